@@ -0,0 +1,7 @@
+-- Optional per-revenda overrides for the copy phase
+-- NULL means "use the global FileCopyConfig value"
+ALTER TABLE fvw_arq_diarios_ext ADD COLUMN IF NOT EXISTS days_back INTEGER;
+ALTER TABLE fvw_arq_diarios_ext ADD COLUMN IF NOT EXISTS overwrite BOOLEAN;
+
+COMMENT ON COLUMN fvw_arq_diarios_ext.days_back IS 'Look-back window in days for copying (overrides the global setting)';
+COMMENT ON COLUMN fvw_arq_diarios_ext.overwrite IS 'Overwrite existing destination files (overrides the global setting)';
@@ -0,0 +1,4 @@
+-- Flag files whose FTR trailer declares a record count different from the counted lines
+ALTER TABLE fvw_file_trace ADD COLUMN IF NOT EXISTS count_mismatch BOOLEAN NOT NULL DEFAULT FALSE;
+
+COMMENT ON COLUMN fvw_file_trace.count_mismatch IS 'TRUE when the FTR trailer count disagrees with total_lines';
@@ -0,0 +1,4 @@
+-- Record how each trace's hash was computed
+ALTER TABLE fvw_file_trace ADD COLUMN IF NOT EXISTS hash_algorithm VARCHAR(16) NOT NULL DEFAULT 'sha256';
+
+COMMENT ON COLUMN fvw_file_trace.hash_algorithm IS 'Hash algorithm: sha256 (full content) or quick (first/last bytes + size)';
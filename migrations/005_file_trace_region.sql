@@ -0,0 +1,4 @@
+-- Optional region code read from the file header next to the DN
+ALTER TABLE fvw_file_trace ADD COLUMN IF NOT EXISTS region VARCHAR(16);
+
+COMMENT ON COLUMN fvw_file_trace.region IS 'Region code extracted from the FHI first line when a region offset is configured';
@@ -0,0 +1,6 @@
+-- Discovery run that registered each trace, for grouping and purging by run
+ALTER TABLE fvw_file_trace ADD COLUMN IF NOT EXISTS run_id VARCHAR(36);
+
+CREATE INDEX IF NOT EXISTS idx_fvw_file_trace_run_id ON fvw_file_trace (run_id);
+
+COMMENT ON COLUMN fvw_file_trace.run_id IS 'UUID shared by every trace registered in the same discovery run';
@@ -0,0 +1,4 @@
+-- Number of delimited fields in the first data line, when a field delimiter is configured
+ALTER TABLE fvw_file_trace ADD COLUMN IF NOT EXISTS first_record_field_count INTEGER;
+
+COMMENT ON COLUMN fvw_file_trace.first_record_field_count IS 'Fields in the first non-header line, split on the configured delimiter (NULL when not counted)';
@@ -0,0 +1,2 @@
+-- Keyed hashes share the hash_algorithm column
+COMMENT ON COLUMN fvw_file_trace.hash_algorithm IS 'Hash algorithm: sha256 (full content), quick (first/last bytes + size) or hmac-sha256 (full content keyed with a secret)';
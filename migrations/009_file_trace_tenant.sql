@@ -0,0 +1,6 @@
+-- Tenant the run registering each trace was started for, separating tenants sharing the table
+ALTER TABLE fvw_file_trace ADD COLUMN IF NOT EXISTS tenant VARCHAR(64);
+
+CREATE INDEX IF NOT EXISTS idx_fvw_file_trace_tenant ON fvw_file_trace (tenant);
+
+COMMENT ON COLUMN fvw_file_trace.tenant IS 'Tenant id given with --tenant to the discovery run that registered the trace (NULL when none)';
@@ -0,0 +1,3 @@
+-- Full-content digests other than SHA-256 share the hash and hash_algorithm columns
+COMMENT ON COLUMN fvw_file_trace.hash IS 'Hex digest of file content, computed with hash_algorithm';
+COMMENT ON COLUMN fvw_file_trace.hash_algorithm IS 'Hash algorithm: sha256, md5 or blake3 (full content), quick (first/last bytes + size) or hmac-sha256 (full content keyed with a secret)';
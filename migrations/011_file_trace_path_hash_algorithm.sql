@@ -0,0 +1,3 @@
+-- Metadata-only runs store the hash of the normalized path instead of a content digest
+COMMENT ON COLUMN fvw_file_trace.hash IS 'Hex digest of file content, computed with hash_algorithm (of the normalized path for path-sha256)';
+COMMENT ON COLUMN fvw_file_trace.hash_algorithm IS 'Hash algorithm: sha256, md5 or blake3 (full content), quick (first/last bytes + size), hmac-sha256 (full content keyed with a secret) or path-sha256 (normalized path, content not read)';
@@ -0,0 +1,4 @@
+-- extensao may hold a list of extensions (".txt,.dat;.fhi"), which outgrows VARCHAR(10)
+ALTER TABLE fvw_arq_diarios_ext ALTER COLUMN extensao TYPE TEXT;
+
+COMMENT ON COLUMN fvw_arq_diarios_ext.extensao IS 'Extension, or comma/semicolon separated list of extensions, of the files to copy and register';
@@ -0,0 +1,9 @@
+-- Opt-in: switch the file trace conflict key to (hash, hash_algorithm) for --conflict-key hash-algorithm,
+-- so a digest stored by one algorithm (e.g. a quick hash of the first and last bytes) never skips
+-- a file hashed by another that happens to produce the same value.
+-- Not applied with the numbered migrations: hash alone stops being unique, which changes
+-- what every run without --conflict-key hash-algorithm considers already registered.
+CREATE UNIQUE INDEX IF NOT EXISTS fvw_file_trace_hash_hash_algorithm_key ON fvw_file_trace (hash, hash_algorithm);
+ALTER TABLE fvw_file_trace DROP CONSTRAINT IF EXISTS fvw_file_trace_hash_key;
+DROP INDEX IF EXISTS fvw_file_trace_tenant_hash_key;
+DROP INDEX IF EXISTS fvw_file_trace_hash_dn_region_key;
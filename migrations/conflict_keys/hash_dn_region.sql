@@ -0,0 +1,9 @@
+-- Opt-in: switch the file trace conflict key to (hash, dn, region) for --conflict-key hash-dn-region,
+-- keeping one trace per content for each DN and region pair read from the header.
+-- Not applied with the numbered migrations: hash alone stops being unique, which changes
+-- what every run without --conflict-key hash-dn-region considers already registered.
+-- NULLS NOT DISTINCT (PostgreSQL 15+) keeps files without a region at one trace per content and DN.
+CREATE UNIQUE INDEX IF NOT EXISTS fvw_file_trace_hash_dn_region_key ON fvw_file_trace (hash, dn, region) NULLS NOT DISTINCT;
+ALTER TABLE fvw_file_trace DROP CONSTRAINT IF EXISTS fvw_file_trace_hash_key;
+DROP INDEX IF EXISTS fvw_file_trace_tenant_hash_key;
+DROP INDEX IF EXISTS fvw_file_trace_hash_hash_algorithm_key;
@@ -0,0 +1,9 @@
+-- Opt-in: switch the file trace conflict key to (tenant, hash) for --conflict-key tenant-hash,
+-- so tenants sharing the table each register their copy of the same content.
+-- Not applied with the numbered migrations: hash alone stops being unique, which changes
+-- what every run without --conflict-key tenant-hash considers already registered.
+-- NULLS NOT DISTINCT (PostgreSQL 15+) keeps untenanted runs at one trace per content.
+CREATE UNIQUE INDEX IF NOT EXISTS fvw_file_trace_tenant_hash_key ON fvw_file_trace (tenant, hash) NULLS NOT DISTINCT;
+ALTER TABLE fvw_file_trace DROP CONSTRAINT IF EXISTS fvw_file_trace_hash_key;
+DROP INDEX IF EXISTS fvw_file_trace_hash_dn_region_key;
+DROP INDEX IF EXISTS fvw_file_trace_hash_hash_algorithm_key;
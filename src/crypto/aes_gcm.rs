@@ -5,44 +5,219 @@ use aes_gcm::{
 use anyhow::{Context, Result};
 use base64::{engine::general_purpose::STANDARD, Engine as _};
 use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// Bytes of the nonce at the start of an encrypted block
+const NONCE_LEN: usize = 12;
+/// Bytes of the authentication tag at the end of an encrypted block
+const TAG_LEN: usize = 16;
+/// Bytes of the random prefix every frame nonce of one stream shares
+const STREAM_PREFIX_LEN: usize = 7;
+/// Last nonce byte of a stream's final frame; every other frame has 0 there
+const STREAM_LAST_FRAME: u8 = 1;
+
+/// Why decryption failed, so a wrong key can be told apart from corrupted cipher text
+#[derive(Debug, thiserror::Error)]
+pub enum CryptoError {
+    #[error("Failed to read key file: {path}")]
+    KeyFile {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("Failed to decode base64 {what}")]
+    Base64Decode {
+        what: &'static str,
+        #[source]
+        source: base64::DecodeError,
+    },
+    #[error("Invalid key length for AES-256-GCM: {0} bytes (need 32)")]
+    InvalidKeyLength(usize),
+    #[error("Encrypted data too short: {0} bytes (need at least 28 bytes for nonce + tag)")]
+    CipherTextTooShort(usize),
+    #[error("Decryption failed: wrong key or corrupted cipher text")]
+    DecryptionFailed,
+    #[error("Decrypted data is not valid UTF-8")]
+    InvalidUtf8(#[from] std::string::FromUtf8Error),
+    #[error("Decrypted canary does not match the expected value")]
+    CanaryMismatch,
+}
 
 /// Decrypts AES-GCM encrypted data using a key from a file
-pub fn decrypt_from_key_file(key_path: &str, cipher_text: &str) -> Result<String> {
-    let key_data = fs::read_to_string(key_path)
-        .with_context(|| format!("Failed to read key file: {}", key_path))?;
+pub fn decrypt_from_key_file(key_path: &str, cipher_text: &str) -> Result<String, CryptoError> {
+    let key_data = fs::read_to_string(key_path).map_err(|source| CryptoError::KeyFile {
+        path: key_path.to_string(),
+        source,
+    })?;
 
-    let key = STANDARD
-        .decode(key_data.trim())
-        .context("Failed to decode base64 key")?;
+    let key = decode_base64(key_data.trim(), "key")?;
 
     decrypt_with_key(&key, cipher_text)
 }
 
 /// Decrypts AES-GCM encrypted data using a base64-encoded key string
-pub fn decrypt_from_base64_key(private_key: &str, cipher_text: &str) -> Result<String> {
-    let key = STANDARD
-        .decode(private_key.trim())
-        .context("Failed to decode base64 private key")?;
+pub fn decrypt_from_base64_key(private_key: &str, cipher_text: &str) -> Result<String, CryptoError> {
+    let key = decode_base64(private_key.trim(), "private key")?;
 
     decrypt_with_key(&key, cipher_text)
 }
 
+/// Check that `encrypted_canary_b64` decrypts to `expected` with the given key
+/// A canary encrypted at provisioning time detects key or configuration drift at startup
+pub fn verify_canary(key_b64: &str, encrypted_canary_b64: &str, expected: &str) -> Result<(), CryptoError> {
+    let canary = decrypt_from_base64_key(key_b64, encrypted_canary_b64)?;
+    if canary != expected {
+        return Err(CryptoError::CanaryMismatch);
+    }
+    Ok(())
+}
+
 /// Core decryption function - pure function that takes key bytes and cipher text
-fn decrypt_with_key(key: &[u8], cipher_text: &str) -> Result<String> {
-    let encrypted_data = STANDARD
-        .decode(cipher_text)
-        .context("Failed to decode base64 cipher text")?;
+fn decrypt_with_key(key: &[u8], cipher_text: &str) -> Result<String, CryptoError> {
+    let encrypted_data = decode_base64(cipher_text, "cipher text")?;
+
+    let plaintext = decrypt_bytes(key, &encrypted_data)?;
+    Ok(String::from_utf8(plaintext)?)
+}
+
+/// Pure function decoding standard base64, naming `what` was being decoded on failure
+fn decode_base64(encoded: &str, what: &'static str) -> Result<Vec<u8>, CryptoError> {
+    STANDARD
+        .decode(encoded)
+        .map_err(|source| CryptoError::Base64Decode { what, source })
+}
+
+/// Largest frame `decrypt_stream` accepts, bounding the memory a corrupt length can claim
+pub const MAX_STREAM_FRAME_LEN: usize = 64 * 1024 * 1024;
 
-    if encrypted_data.len() < 28 {
-        anyhow::bail!("Encrypted data too short (need at least 28 bytes for nonce + tag)");
+/// Decrypt a large base64 payload of framed AES-GCM chunks from `reader` into `writer`
+///
+/// The decoded payload is a sequence of frames, each a 4-byte big-endian length followed
+/// by that many bytes of `nonce || ciphertext || tag`. Base64 is decoded as it is read,
+/// so only one frame is held in memory at a time. Returns the number of plaintext bytes written.
+///
+/// Frames follow the STREAM construction: each nonce is `prefix || counter || last`, with a
+/// 7-byte prefix shared by the whole stream, the 4-byte big-endian frame index, and 1 on the
+/// final frame only (0 before it). As GCM authenticates the nonce, frames that are reordered,
+/// dropped or spliced in from another stream fail, and so does a stream that ends without
+/// its final frame or goes on after it. Each frame is written once authenticated, so on an
+/// error `writer` holds an incomplete prefix of the plaintext that must be discarded;
+/// `decrypt_stream_to_file` does that for files.
+pub fn decrypt_stream<R: Read, W: Write>(key: &[u8], reader: R, mut writer: W) -> Result<u64> {
+    let mut decoded = base64::read::DecoderReader::new(reader, &STANDARD);
+    let mut written = 0;
+    let mut prefix = None;
+    let mut counter: u32 = 0;
+    let mut finished = false;
+
+    loop {
+        let mut len_bytes = [0u8; 4];
+        if !read_frame_length(&mut decoded, &mut len_bytes)? {
+            break;
+        }
+        if finished {
+            anyhow::bail!("Encrypted stream continues after its final frame");
+        }
+
+        let frame_len = u32::from_be_bytes(len_bytes) as usize;
+        if frame_len > MAX_STREAM_FRAME_LEN {
+            anyhow::bail!("Encrypted frame of {} bytes exceeds the {} byte limit", frame_len, MAX_STREAM_FRAME_LEN);
+        }
+
+        let mut frame = vec![0u8; frame_len];
+        decoded
+            .read_exact(&mut frame)
+            .context("Encrypted stream ended inside a frame")?;
+
+        let nonce = frame.get(..NONCE_LEN).ok_or(CryptoError::CipherTextTooShort(frame.len()))?;
+        let prefix = prefix.get_or_insert_with(|| nonce[..STREAM_PREFIX_LEN].to_vec());
+        finished = stream_frame_position(nonce, prefix, counter)?;
+
+        let plaintext = decrypt_bytes(key, &frame)?;
+        writer.write_all(&plaintext).context("Failed to write decrypted data")?;
+        written += plaintext.len() as u64;
+        counter = counter.checked_add(1).context("Encrypted stream has too many frames")?;
+    }
+
+    if !finished {
+        anyhow::bail!("Encrypted stream ended before its final frame: it was truncated");
+    }
+
+    writer.flush().context("Failed to write decrypted data")?;
+    Ok(written)
+}
+
+/// Decrypt a stream as `decrypt_stream` does into its `.part` file, renamed to `destination`
+/// only once the whole stream is authenticated; on failure the `.part` file is removed
+pub fn decrypt_stream_to_file<R: Read>(key: &[u8], reader: R, destination: &Path) -> Result<u64> {
+    let part_path = crate::utils::part_file_path(destination);
+    let decrypted = fs::File::create(&part_path)
+        .with_context(|| format!("Failed to create {:?}", part_path))
+        .and_then(|file| decrypt_stream(key, reader, std::io::BufWriter::new(file)));
+
+    match decrypted {
+        Ok(written) => {
+            fs::rename(&part_path, destination)
+                .with_context(|| format!("Failed to rename {:?} to {:?}", part_path, destination))?;
+            Ok(written)
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&part_path);
+            Err(e)
+        }
+    }
+}
+
+/// Pure function checking a frame nonce is at position `counter` of the stream with `prefix`
+/// Returns whether it marks the final frame
+fn stream_frame_position(nonce: &[u8], prefix: &[u8], counter: u32) -> Result<bool> {
+    let (nonce_prefix, rest) = nonce.split_at(STREAM_PREFIX_LEN);
+    let (nonce_counter, last) = rest.split_at(4);
+
+    if nonce_prefix != prefix {
+        anyhow::bail!("Encrypted frame {} belongs to another stream", counter);
+    }
+    if nonce_counter != counter.to_be_bytes() {
+        anyhow::bail!("Encrypted frame {} is out of order", counter);
+    }
+    match last[0] {
+        0 => Ok(false),
+        STREAM_LAST_FRAME => Ok(true),
+        flag => anyhow::bail!("Encrypted frame {} has an invalid last-frame flag {}", counter, flag),
+    }
+}
+
+/// Read a frame length prefix, returning false at a clean end of stream
+fn read_frame_length<R: Read>(reader: &mut R, len_bytes: &mut [u8; 4]) -> Result<bool> {
+    let mut filled = 0;
+    while filled < len_bytes.len() {
+        let read = reader
+            .read(&mut len_bytes[filled..])
+            .context("Failed to decode base64 cipher text")?;
+        if read == 0 {
+            if filled == 0 {
+                return Ok(false);
+            }
+            anyhow::bail!("Encrypted stream ended inside a frame length");
+        }
+        filled += read;
+    }
+    Ok(true)
+}
+
+/// Decrypt one `nonce || ciphertext || tag` block
+fn decrypt_bytes(key: &[u8], encrypted_data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    if encrypted_data.len() < NONCE_LEN + TAG_LEN {
+        return Err(CryptoError::CipherTextTooShort(encrypted_data.len()));
     }
 
     // Extract components: first 12 bytes (nonce), last 16 bytes (tag), middle (ciphertext)
-    let (nonce_bytes, rest) = encrypted_data.split_at(12);
-    let (ciphertext, tag) = rest.split_at(rest.len() - 16);
+    let (nonce_bytes, rest) = encrypted_data.split_at(NONCE_LEN);
+    let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LEN);
 
     // Create cipher and decrypt
-    let cipher = Aes256Gcm::new_from_slice(key).context("Invalid key length for AES-256-GCM")?;
+    let cipher = Aes256Gcm::new_from_slice(key).map_err(|_| CryptoError::InvalidKeyLength(key.len()))?;
 
     let nonce = Nonce::from_slice(nonce_bytes);
 
@@ -51,11 +226,9 @@ fn decrypt_with_key(key: &[u8], cipher_text: &str) -> Result<String> {
     payload.extend_from_slice(ciphertext);
     payload.extend_from_slice(tag);
 
-    let plaintext = cipher
+    cipher
         .decrypt(nonce, payload.as_slice())
-        .map_err(|e| anyhow::anyhow!("Decryption failed: {:?}", e))?;
-
-    String::from_utf8(plaintext).context("Decrypted data is not valid UTF-8")
+        .map_err(|_| CryptoError::DecryptionFailed)
 }
 
 #[cfg(test)]
@@ -70,4 +243,179 @@ mod tests {
         let result = decrypt_with_key(&key, "invalid_base64");
         assert!(result.is_err());
     }
+
+    /// `nonce || ciphertext || tag` of `plaintext`, base64-encoded
+    fn encrypt_block(key: &[u8], plaintext: &[u8]) -> String {
+        let cipher = Aes256Gcm::new_from_slice(key).unwrap();
+        let nonce = [3u8; 12];
+        let sealed = cipher.encrypt(Nonce::from_slice(&nonce), plaintext).unwrap();
+        STANDARD.encode([nonce.as_slice(), &sealed].concat())
+    }
+
+    #[test]
+    fn test_errors_tell_wrong_key_from_corrupt_data() {
+        let key = [9u8; 32];
+        let base64_key = STANDARD.encode(key);
+        let cipher_text = encrypt_block(&key, b"Server=db;Database=app;");
+
+        assert_eq!(decrypt_from_base64_key(&base64_key, &cipher_text).unwrap(), "Server=db;Database=app;");
+
+        let wrong_key = STANDARD.encode([8u8; 32]);
+        assert!(matches!(decrypt_from_base64_key(&wrong_key, &cipher_text), Err(CryptoError::DecryptionFailed)));
+
+        let short_key = STANDARD.encode([9u8; 16]);
+        assert!(matches!(
+            decrypt_from_base64_key(&short_key, &cipher_text),
+            Err(CryptoError::InvalidKeyLength(16))
+        ));
+
+        assert!(matches!(
+            decrypt_from_base64_key("not base64!", &cipher_text),
+            Err(CryptoError::Base64Decode { what: "private key", .. })
+        ));
+        assert!(matches!(
+            decrypt_from_base64_key(&base64_key, "not base64!"),
+            Err(CryptoError::Base64Decode { what: "cipher text", .. })
+        ));
+        assert!(matches!(
+            decrypt_from_base64_key(&base64_key, &STANDARD.encode([0u8; 27])),
+            Err(CryptoError::CipherTextTooShort(27))
+        ));
+
+        let not_utf8 = encrypt_block(&key, &[0xff, 0xfe]);
+        assert!(matches!(decrypt_from_base64_key(&base64_key, &not_utf8), Err(CryptoError::InvalidUtf8(_))));
+
+        assert!(matches!(
+            decrypt_from_key_file("/nonexistent/key", &cipher_text),
+            Err(CryptoError::KeyFile { .. })
+        ));
+    }
+
+    #[test]
+    fn test_verify_canary() {
+        let key = [5u8; 32];
+        let base64_key = STANDARD.encode(key);
+        let canary = encrypt_block(&key, b"vw-canary-prod");
+
+        assert!(verify_canary(&base64_key, &canary, "vw-canary-prod").is_ok());
+        assert!(matches!(
+            verify_canary(&base64_key, &canary, "vw-canary-staging"),
+            Err(CryptoError::CanaryMismatch)
+        ));
+        assert!(matches!(
+            verify_canary(&STANDARD.encode([6u8; 32]), &canary, "vw-canary-prod"),
+            Err(CryptoError::DecryptionFailed)
+        ));
+    }
+
+    /// Frame `chunk` as `decrypt_stream` expects: length prefix, then nonce || ciphertext || tag
+    fn encrypt_frame(key: &[u8], prefix: [u8; 7], counter: u32, last: bool, chunk: &[u8]) -> Vec<u8> {
+        let cipher = Aes256Gcm::new_from_slice(key).unwrap();
+        let mut nonce = [0u8; 12];
+        nonce[..7].copy_from_slice(&prefix);
+        nonce[7..11].copy_from_slice(&counter.to_be_bytes());
+        nonce[11] = last as u8;
+        let sealed = cipher.encrypt(Nonce::from_slice(&nonce), chunk).unwrap();
+
+        let frame_len = (nonce.len() + sealed.len()) as u32;
+        [frame_len.to_be_bytes().as_slice(), &nonce, &sealed].concat()
+    }
+
+    /// The frames of `chunks` as one stream, the last chunk marked final
+    fn encrypt_frames(key: &[u8], prefix: [u8; 7], chunks: &[&[u8]]) -> Vec<Vec<u8>> {
+        chunks
+            .iter()
+            .enumerate()
+            .map(|(i, chunk)| encrypt_frame(key, prefix, i as u32, i + 1 == chunks.len(), chunk))
+            .collect()
+    }
+
+    fn decrypt_frames(key: &[u8], frames: &[Vec<u8>]) -> Result<Vec<u8>> {
+        let mut output = Vec::new();
+        decrypt_stream(key, STANDARD.encode(frames.concat()).as_bytes(), &mut output)?;
+        Ok(output)
+    }
+
+    #[test]
+    fn test_decrypt_stream_of_large_framed_payload() {
+        let key = [7u8; 32];
+        let plaintext: Vec<u8> = (0..3_000_000u32).map(|i| (i % 251) as u8).collect();
+        let chunks: Vec<&[u8]> = plaintext.chunks(256 * 1024).collect();
+        let encoded = STANDARD.encode(encrypt_frames(&key, [1; 7], &chunks).concat());
+
+        let mut output = Vec::new();
+        let written = decrypt_stream(&key, encoded.as_bytes(), &mut output).unwrap();
+
+        assert_eq!(written, plaintext.len() as u64);
+        assert!(output == plaintext);
+    }
+
+    #[test]
+    fn test_decrypt_stream_rejects_truncated_and_tampered_frames() {
+        let key = [7u8; 32];
+        let frame = encrypt_frame(&key, [1; 7], 0, true, b"connection settings");
+
+        let truncated = STANDARD.encode(&frame[..frame.len() - 1]);
+        assert!(decrypt_stream(&key, truncated.as_bytes(), Vec::new()).is_err());
+
+        let mut tampered = frame.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(decrypt_stream(&key, STANDARD.encode(tampered).as_bytes(), Vec::new()).is_err());
+
+        // Even an empty plaintext is one final frame
+        assert!(decrypt_stream(&key, &b""[..], Vec::new()).is_err());
+        assert_eq!(decrypt_frames(&key, &encrypt_frames(&key, [1; 7], &[b""])).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_decrypt_stream_rejects_truncated_streams() {
+        let key = [7u8; 32];
+        let frames = encrypt_frames(&key, [1; 7], &[b"first ", b"second ", b"third"]);
+        assert_eq!(decrypt_frames(&key, &frames).unwrap(), b"first second third");
+
+        // Dropping the final frame leaves every remaining frame valid, but no end marker
+        let error = decrypt_frames(&key, &frames[..2]).unwrap_err().to_string();
+        assert!(error.contains("truncated"), "{}", error);
+
+        // Nor can a non-final frame be passed off as the last one
+        let mut forged = frames[1].clone();
+        forged[4 + 11] = STREAM_LAST_FRAME;
+        assert!(decrypt_frames(&key, &[frames[0].clone(), forged]).is_err());
+
+        let error = decrypt_frames(&key, &[frames[0].clone(), frames[2].clone(), frames[2].clone()]).unwrap_err();
+        assert!(error.to_string().contains("out of order"), "{}", error);
+    }
+
+    #[test]
+    fn test_decrypt_stream_rejects_reordered_and_spliced_frames() {
+        let key = [7u8; 32];
+        let frames = encrypt_frames(&key, [1; 7], &[b"first ", b"second ", b"third"]);
+        let other = encrypt_frames(&key, [2; 7], &[b"FIRST ", b"SECOND ", b"THIRD"]);
+
+        let reordered = [frames[1].clone(), frames[0].clone(), frames[2].clone()];
+        assert!(decrypt_frames(&key, &reordered).unwrap_err().to_string().contains("out of order"));
+
+        let spliced = [frames[0].clone(), other[1].clone(), frames[2].clone()];
+        assert!(decrypt_frames(&key, &spliced).unwrap_err().to_string().contains("another stream"));
+
+        let extended = [frames.clone(), vec![frames[2].clone()]].concat();
+        assert!(decrypt_frames(&key, &extended).unwrap_err().to_string().contains("after its final frame"));
+    }
+
+    #[test]
+    fn test_decrypt_stream_to_file_only_publishes_authenticated_streams() {
+        let key = [7u8; 32];
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("settings.txt");
+        let frames = encrypt_frames(&key, [1; 7], &[b"first ", b"second"]);
+
+        let truncated = STANDARD.encode(frames[..1].concat());
+        assert!(decrypt_stream_to_file(&key, truncated.as_bytes(), &destination).is_err());
+        assert!(!destination.exists());
+        assert!(!crate::utils::part_file_path(&destination).exists());
+
+        let complete = STANDARD.encode(frames.concat());
+        assert_eq!(decrypt_stream_to_file(&key, complete.as_bytes(), &destination).unwrap(), 12);
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "first second");
+    }
 }
@@ -1,5 +1,9 @@
 pub mod connection;
 pub mod repositories;
+pub mod revenda_cache;
+pub mod schema;
 
 pub use connection::*;
 pub use repositories::*;
+pub use revenda_cache::{RevendaCache, RevendaSource, DEFAULT_REVENDA_CACHE_TTL};
+pub use schema::{ensure_unique_index, health_check, ConflictKey};
@@ -1,55 +1,273 @@
-use crate::database::DbPool;
-use crate::models::{FileTrace, FvwArqDiarioExt};
+use crate::database::{ConflictKey, DbPool};
+use crate::models::{dedup_revendas, FileTrace, FileTraceStatus, FvwArqDiarioExt};
 use anyhow::Result;
 use sqlx::Row;
+use std::io::Write;
 
 /// Functional repository functions for FvwArqDiarioExt
 pub mod arq_vw_ext {
     use super::*;
+    use sqlx::postgres::PgRow;
+
+    /// Query listing every revenda; the columns are read by `map_revenda_row`
+    pub const REVENDAS_QUERY: &str =
+        "SELECT empresa, revenda, extensao, dn, pasta_input, pasta_output, days_back, overwrite FROM fvw_arq_diarios_ext ORDER BY id";
+
+    /// Raw column values of one `fvw_arq_diarios_ext` row, nullable columns still as `Option`
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct RevendaColumns {
+        pub empresa: i32,
+        pub revenda: i32,
+        pub extensao: Option<String>,
+        pub dn: i32,
+        pub pasta_input: Option<String>,
+        pub pasta_output: Option<String>,
+        pub days_back: Option<i32>,
+        pub overwrite: Option<bool>,
+    }
 
     /// Fetch all revendas from the database
+    /// Rows sharing `(empresa, revenda, dn)` are collapsed into the first one (see `dedup_revendas`)
     pub async fn get_revendas(pool: &DbPool) -> Result<Vec<FvwArqDiarioExt>> {
-        let rows = sqlx::query(
-            "SELECT empresa, revenda, extensao, dn, pasta_input, pasta_output FROM fvw_arq_diarios_ext"
-        )
-        .fetch_all(pool)
-        .await?;
+        get_revendas_with_query(pool, REVENDAS_QUERY).await
+    }
+
+    /// Fetch revendas with a caller-supplied query returning the `REVENDAS_QUERY` columns
+    /// Lets tests run the mapping against fixture tables or literal rows
+    pub async fn get_revendas_with_query(pool: &DbPool, query: &str) -> Result<Vec<FvwArqDiarioExt>> {
+        let rows = sqlx::query(query).fetch_all(pool).await?;
 
         let revendas = rows
-            .into_iter()
-            .map(|row| -> Result<FvwArqDiarioExt> {
-                Ok(FvwArqDiarioExt {
-                    empresa: row.try_get("empresa")?,
-                    revenda: row.try_get("revenda")?,
-                    extensao: row.try_get::<Option<String>, _>("extensao")?.unwrap_or_default(),
-                    dn: row.try_get("dn")?,
-                    pasta_input: row.try_get::<Option<String>, _>("pasta_input")?.unwrap_or_default(),
-                    pasta_output: row.try_get::<Option<String>, _>("pasta_output")?.unwrap_or_default(),
-                })
-            })
-            .collect::<Result<Vec<_>, _>>()?;
+            .iter()
+            .map(map_revenda_row)
+            .collect::<Result<Vec<_>>>()?;
+
+        let (revendas, duplicates) = dedup_revendas(revendas);
+        for duplicate in &duplicates {
+            tracing::warn!(
+                "Duplicate revenda row (empresa {}, revenda {}, dn {}) merged into the first occurrence: {}",
+                duplicate.empresa, duplicate.revenda, duplicate.dn, duplicate.pasta_output
+            );
+        }
 
         Ok(revendas)
     }
+
+    /// Map a database row into a FvwArqDiarioExt
+    pub fn map_revenda_row(row: &PgRow) -> Result<FvwArqDiarioExt> {
+        let columns = RevendaColumns {
+            empresa: row.try_get("empresa")?,
+            revenda: row.try_get("revenda")?,
+            extensao: row.try_get("extensao")?,
+            dn: row.try_get("dn")?,
+            pasta_input: row.try_get("pasta_input")?,
+            pasta_output: row.try_get("pasta_output")?,
+            days_back: row.try_get("days_back")?,
+            overwrite: row.try_get("overwrite")?,
+        };
+
+        Ok(revenda_from_columns(columns))
+    }
+
+    /// Pure function building a FvwArqDiarioExt from raw column values
+    /// Missing text columns become empty strings; the copy overrides stay optional
+    pub fn revenda_from_columns(columns: RevendaColumns) -> FvwArqDiarioExt {
+        FvwArqDiarioExt {
+            empresa: columns.empresa,
+            revenda: columns.revenda,
+            extensao: columns.extensao.unwrap_or_default(),
+            dn: columns.dn,
+            pasta_input: columns.pasta_input.unwrap_or_default(),
+            pasta_output: columns.pasta_output.unwrap_or_default(),
+            days_back: columns.days_back.map(i64::from),
+            overwrite: columns.overwrite,
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_revenda_from_columns_defaults_missing_text() {
+            let revenda = revenda_from_columns(RevendaColumns {
+                empresa: 1,
+                revenda: 2,
+                dn: 30,
+                ..RevendaColumns::default()
+            });
+
+            assert_eq!((revenda.empresa, revenda.revenda, revenda.dn), (1, 2, 30));
+            assert_eq!(revenda.extensao, "");
+            assert_eq!(revenda.pasta_input, "");
+            assert_eq!(revenda.pasta_output, "");
+            assert_eq!((revenda.days_back, revenda.overwrite), (None, None));
+
+            let configured = revenda_from_columns(RevendaColumns {
+                extensao: Some(".txt".to_string()),
+                pasta_input: Some("/in".to_string()),
+                pasta_output: Some("/out".to_string()),
+                days_back: Some(45),
+                overwrite: Some(true),
+                ..RevendaColumns::default()
+            });
+
+            assert_eq!(configured.extensions(), vec![".txt"]);
+            assert_eq!((configured.pasta_input.as_str(), configured.pasta_output.as_str()), ("/in", "/out"));
+            assert_eq!((configured.days_back, configured.overwrite), (Some(45), Some(true)));
+        }
+    }
 }
 
 /// Functional repository functions for FileTrace
 pub mod file_trace {
     use super::*;
+    use futures::TryStreamExt;
+    use sqlx::postgres::PgRow;
+    use sqlx::{Postgres, QueryBuilder};
+
+    /// Filter applied when querying or exporting file traces
+    #[derive(Debug, Clone, Default)]
+    pub struct TraceFilter {
+        pub status_fvw: Option<i32>,
+        pub status_fnt: Option<i32>,
+        pub status_fa4: Option<i32>,
+        pub dn: Option<i32>,
+        /// Only traces whose hash is one of these
+        pub hashes: Option<Vec<String>>,
+        /// Only traces registered for this tenant
+        pub tenant: Option<String>,
+    }
+
+    /// A window of query results, in id order
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct Page {
+        /// Maximum number of rows (None returns every row after the offset)
+        pub limit: Option<i64>,
+        /// Rows skipped before the first returned row
+        pub offset: i64,
+    }
 
-    /// Save multiple file traces to database (insert on conflict do nothing)
+    /// Save multiple file traces to database (insert on conflict of the default hash key do nothing)
     /// Pure functional approach - takes pool and data, returns Result
     pub async fn save_batch(pool: &DbPool, file_traces: &[FileTrace]) -> Result<u64> {
         if file_traces.is_empty() {
             return Ok(0);
         }
 
-        let mut query_builder = sqlx::QueryBuilder::new(
+        let result = build_insert_query(file_traces, ConflictKey::Hash).build().execute(pool).await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Save multiple file traces and return the (id, hash, path) of the rows actually inserted
+    /// Traces skipped by the ON CONFLICT clause on `conflict_key` are not part of the result
+    pub async fn save_batch_returning(
+        pool: &DbPool,
+        file_traces: &[FileTrace],
+        conflict_key: ConflictKey,
+    ) -> Result<Vec<(i32, String, String)>> {
+        if file_traces.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut query_builder = build_insert_query(file_traces, conflict_key);
+        query_builder.push(" RETURNING id, hash, path");
+
+        let rows = query_builder.build().fetch_all(pool).await?;
+
+        rows.iter().map(saved_row).collect()
+    }
+
+    /// Like `save_batch_returning`, but with one INSERT per DN so each statement touches a
+    /// single partition of a DN-partitioned table. The inserts share one transaction, so the
+    /// batch is still saved or rejected as a whole.
+    pub async fn save_batch_returning_by_dn(
+        pool: &DbPool,
+        file_traces: &[FileTrace],
+        conflict_key: ConflictKey,
+    ) -> Result<Vec<(i32, String, String)>> {
+        if file_traces.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut transaction = pool.begin().await?;
+        let mut saved = Vec::with_capacity(file_traces.len());
+        for mut query_builder in build_insert_queries_by_dn(file_traces, conflict_key) {
+            let rows = query_builder.build().fetch_all(&mut *transaction).await?;
+            for row in &rows {
+                saved.push(saved_row(row)?);
+            }
+        }
+        transaction.commit().await?;
+
+        Ok(saved)
+    }
+
+    /// One INSERT ... RETURNING statement per DN, in order of each DN's first trace
+    fn build_insert_queries_by_dn(file_traces: &[FileTrace], conflict_key: ConflictKey) -> Vec<QueryBuilder<'_, Postgres>> {
+        partition_by_dn(file_traces)
+            .into_iter()
+            .map(|partition| {
+                let mut query_builder = build_insert_query(partition, conflict_key);
+                query_builder.push(" RETURNING id, hash, path");
+                query_builder
+            })
+            .collect()
+    }
+
+    /// Pure function grouping traces by DN, keeping the order of each DN's first trace
+    /// and the order of traces within a DN
+    pub fn partition_by_dn(file_traces: &[FileTrace]) -> Vec<Vec<&FileTrace>> {
+        let mut partitions: Vec<Vec<&FileTrace>> = Vec::new();
+        for file_trace in file_traces {
+            match partitions.iter_mut().find(|partition| partition[0].dn == file_trace.dn) {
+                Some(partition) => partition.push(file_trace),
+                None => partitions.push(vec![file_trace]),
+            }
+        }
+        partitions
+    }
+
+    /// (id, hash, path) of an inserted row
+    fn saved_row(row: &PgRow) -> Result<(i32, String, String)> {
+        Ok((row.try_get("id")?, row.try_get("hash")?, row.try_get("path")?))
+    }
+
+    /// Reset `status_fvw` from Error to Pending for `tenant`'s traces with the given hashes
+    /// (untenanted traces when None). Returns the ids of the traces that were requeued
+    pub async fn requeue_errored(pool: &DbPool, hashes: &[String], tenant: Option<&str>) -> Result<Vec<i32>> {
+        if hashes.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let ids = sqlx::query_scalar(
+            "UPDATE fvw_file_trace SET status_fvw = $1 \
+             WHERE hash = ANY($2) AND status_fvw = $3 AND tenant IS NOT DISTINCT FROM $4 RETURNING id",
+        )
+        .bind(FileTraceStatus::Pending as i32)
+        .bind(hashes)
+        .bind(FileTraceStatus::Error as i32)
+        .bind(tenant)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(ids)
+    }
+
+    /// Build the batch INSERT statement shared by the save functions, skipping traces that
+    /// conflict on `conflict_key`
+    fn build_insert_query<'a, I>(file_traces: I, conflict_key: ConflictKey) -> QueryBuilder<'a, Postgres>
+    where
+        I: IntoIterator<Item = &'a FileTrace>,
+    {
+        let mut query_builder = QueryBuilder::new(
             r#"
             INSERT INTO fvw_file_trace
                 (name, path, hash, size_bytes, size_mb, total_lines,
                  created_at, modified_at, processed_at,
-                 status_fvw, status_fnt, status_fa4, dn)
+                 status_fvw, status_fnt, status_fa4, dn, count_mismatch, hash_algorithm, region, run_id,
+                 tenant, first_record_field_count)
             "#,
         );
 
@@ -63,17 +281,20 @@ pub mod file_trace {
                 .push_bind(file_trace.created_at)
                 .push_bind(file_trace.modified_at)
                 .push_bind(file_trace.processed_at)
-                .push_bind(file_trace.status_fvw as i32)
-                .push_bind(file_trace.status_fnt as i32)
-                .push_bind(file_trace.status_fa4 as i32)
-                .push_bind(file_trace.dn);
+                .push_bind(file_trace.status_fvw)
+                .push_bind(file_trace.status_fnt)
+                .push_bind(file_trace.status_fa4)
+                .push_bind(file_trace.dn)
+                .push_bind(file_trace.count_mismatch)
+                .push_bind(&file_trace.hash_algorithm)
+                .push_bind(&file_trace.region)
+                .push_bind(&file_trace.run_id)
+                .push_bind(&file_trace.tenant)
+                .push_bind(file_trace.first_record_field_count);
         });
 
-        query_builder.push(" ON CONFLICT (hash) DO NOTHING");
-
-        let result = query_builder.build().execute(pool).await?;
-
-        Ok(result.rows_affected())
+        query_builder.push(format!(" ON CONFLICT ({}) DO NOTHING", conflict_key.columns().join(", ")));
+        query_builder
     }
 
     /// Get file traces by status - functional approach
@@ -83,44 +304,353 @@ pub mod file_trace {
         status_fnt: Option<i32>,
         status_fa4: Option<i32>,
     ) -> Result<Vec<FileTrace>> {
-        let mut query = sqlx::QueryBuilder::new(
-            "SELECT id, name, path, hash, size_bytes, size_mb, total_lines, created_at, modified_at, processed_at, status_fvw, status_fnt, status_fa4, dn FROM fvw_file_trace WHERE 1=1"
+        let filter = TraceFilter {
+            status_fvw,
+            status_fnt,
+            status_fa4,
+            ..TraceFilter::default()
+        };
+
+        get_by_filter(pool, &filter).await
+    }
+
+    /// Get one page of the file traces matching the filter
+    pub async fn get_by_status_paged(pool: &DbPool, filter: &TraceFilter, page: Page) -> Result<Vec<FileTrace>> {
+        let mut query = build_select_query(filter);
+        if let Some(limit) = page.limit {
+            query.push(" LIMIT ").push_bind(limit);
+        }
+        if page.offset > 0 {
+            query.push(" OFFSET ").push_bind(page.offset);
+        }
+
+        let rows = query.build().fetch_all(pool).await?;
+        rows.iter()
+            .map(map_trace_row)
+            .collect::<Result<Vec<_>, sqlx::Error>>()
+            .map_err(Into::into)
+    }
+
+    /// Number of file traces matching the filter
+    pub async fn count_by_status(pool: &DbPool, filter: &TraceFilter) -> Result<i64> {
+        let mut query = QueryBuilder::new("SELECT COUNT(*) FROM fvw_file_trace WHERE 1=1");
+        push_filter_conditions(&mut query, filter);
+
+        let count = query.build_query_scalar().fetch_one(pool).await?;
+        Ok(count)
+    }
+
+    /// Get all file traces matching the filter
+    pub async fn get_by_filter(pool: &DbPool, filter: &TraceFilter) -> Result<Vec<FileTrace>> {
+        let rows = build_select_query(filter).build().fetch_all(pool).await?;
+
+        let file_traces = rows
+            .iter()
+            .map(map_trace_row)
+            .collect::<Result<Vec<_>, sqlx::Error>>()?;
+
+        Ok(file_traces)
+    }
+
+    /// Update the DN of a single trace, returning the number of rows affected
+    pub async fn update_dn(pool: &DbPool, id: i32, dn: i32) -> Result<u64> {
+        let result = sqlx::query("UPDATE fvw_file_trace SET dn = $1 WHERE id = $2")
+            .bind(dn)
+            .bind(id)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Replace the content fields (hash, hash algorithm, size, line count, modification time,
+    /// DN and region) of a single trace by id
+    /// Nothing is updated when another trace already has the new values of `conflict_key`;
+    /// returns the number of rows affected
+    pub async fn update_content(pool: &DbPool, id: i32, content: &FileTrace, conflict_key: ConflictKey) -> Result<u64> {
+        let mut query = QueryBuilder::<Postgres>::new("UPDATE fvw_file_trace t SET hash = ");
+        query
+            .push_bind(&content.hash)
+            .push(", hash_algorithm = ")
+            .push_bind(&content.hash_algorithm)
+            .push(", size_bytes = ")
+            .push_bind(content.size_bytes)
+            .push(", size_mb = ")
+            .push_bind(content.size_mb)
+            .push(", total_lines = ")
+            .push_bind(content.total_lines)
+            .push(", modified_at = ")
+            .push_bind(content.modified_at)
+            .push(", dn = ")
+            .push_bind(content.dn)
+            .push(", region = ")
+            .push_bind(&content.region)
+            .push(" WHERE t.id = ")
+            .push_bind(id)
+            .push(" AND NOT EXISTS (SELECT 1 FROM fvw_file_trace other WHERE other.id <> t.id");
+        for column in conflict_key.columns() {
+            query.push(format!(" AND other.{column} IS NOT DISTINCT FROM "));
+            match *column {
+                "hash" => query.push_bind(&content.hash),
+                "hash_algorithm" => query.push_bind(&content.hash_algorithm),
+                "dn" => query.push_bind(content.dn),
+                "region" => query.push_bind(&content.region),
+                // Key columns the update leaves alone, e.g. tenant, keep the trace's own value
+                _ => query.push(format!("t.{column}")),
+            };
+        }
+        query.push(")");
+
+        let result = query.build().execute(pool).await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Paths of the traces registered after the given discovery run, in registration order
+    /// Runs are ordered by `processed_at`: traces processed after the run's last trace qualify
+    pub async fn paths_since_run(pool: &DbPool, after_run_id: &str) -> Result<Vec<String>> {
+        let run_end: Option<chrono::DateTime<chrono::Utc>> =
+            sqlx::query_scalar("SELECT MAX(processed_at) FROM fvw_file_trace WHERE run_id = $1")
+                .bind(after_run_id)
+                .fetch_one(pool)
+                .await?;
+        let Some(run_end) = run_end else {
+            anyhow::bail!("No traces were registered by run {}", after_run_id);
+        };
+
+        let paths = sqlx::query_scalar(
+            "SELECT path FROM fvw_file_trace WHERE processed_at > $1 AND run_id IS DISTINCT FROM $2 ORDER BY processed_at, id",
+        )
+        .bind(run_end)
+        .bind(after_run_id)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(paths)
+    }
+
+    /// Every DN that has at least one trace, in ascending order
+    pub async fn distinct_dns(pool: &DbPool) -> Result<Vec<i32>> {
+        let dns = sqlx::query_scalar("SELECT DISTINCT dn FROM fvw_file_trace ORDER BY dn")
+            .fetch_all(pool)
+            .await?;
+
+        Ok(dns)
+    }
+
+    /// Which downstream status column an update targets
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum StatusColumn {
+        Fvw,
+        Fnt,
+        Fa4,
+    }
+
+    impl StatusColumn {
+        pub fn column_name(self) -> &'static str {
+            match self {
+                StatusColumn::Fvw => "status_fvw",
+                StatusColumn::Fnt => "status_fnt",
+                StatusColumn::Fa4 => "status_fa4",
+            }
+        }
+    }
+
+    /// Mark the given traces Processed in one status column, returning the number of rows affected
+    /// Idempotent: marking already processed traces again leaves them unchanged
+    pub async fn mark_processed(pool: &DbPool, ids: &[i32], which: StatusColumn) -> Result<u64> {
+        if ids.is_empty() {
+            return Ok(0);
+        }
+
+        let statement = format!("UPDATE fvw_file_trace SET {} = $1 WHERE id = ANY($2)", which.column_name());
+        let result = sqlx::query(&statement)
+            .bind(FileTraceStatus::Processed as i32)
+            .bind(ids)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Stream traces matching the filter into a CSV writer
+    /// Writes a header row followed by one line per trace and returns the number of traces written
+    pub async fn export_csv(pool: &DbPool, filter: &TraceFilter, writer: impl Write) -> Result<usize> {
+        let mut csv_writer = create_csv_writer(writer)?;
+        let mut query = build_select_query(filter);
+        let mut rows = query.build().fetch(pool);
+        let mut written = 0;
+
+        while let Some(row) = rows.try_next().await? {
+            write_csv_record(&mut csv_writer, &map_trace_row(&row)?)?;
+            written += 1;
+        }
+
+        csv_writer.flush()?;
+
+        Ok(written)
+    }
+
+    /// Column order used by the CSV export
+    pub const CSV_HEADER: [&str; 20] = [
+        "id", "name", "path", "hash", "size_bytes", "size_mb", "total_lines",
+        "created_at", "modified_at", "processed_at",
+        "status_fvw", "status_fnt", "status_fa4", "dn", "count_mismatch", "hash_algorithm", "region", "run_id",
+        "tenant", "first_record_field_count",
+    ];
+
+    /// Create a CSV writer with the header row already written
+    pub fn create_csv_writer<W: Write>(writer: W) -> Result<csv::Writer<W>> {
+        let mut csv_writer = csv::Writer::from_writer(writer);
+        csv_writer.write_record(CSV_HEADER)?;
+        Ok(csv_writer)
+    }
+
+    /// Write a single trace as a CSV record following `CSV_HEADER`
+    pub fn write_csv_record<W: Write>(csv_writer: &mut csv::Writer<W>, file_trace: &FileTrace) -> Result<()> {
+        csv_writer.write_record([
+            file_trace.id.map(|id| id.to_string()).unwrap_or_default(),
+            file_trace.name.clone(),
+            file_trace.path.clone(),
+            file_trace.hash.clone(),
+            file_trace.size_bytes.to_string(),
+            file_trace.size_mb.to_string(),
+            file_trace.total_lines.to_string(),
+            file_trace.created_at.to_rfc3339(),
+            file_trace.modified_at.to_rfc3339(),
+            file_trace.processed_at.to_rfc3339(),
+            file_trace.status_fvw.to_string(),
+            file_trace.status_fnt.to_string(),
+            file_trace.status_fa4.to_string(),
+            file_trace.dn.to_string(),
+            file_trace.count_mismatch.to_string(),
+            file_trace.hash_algorithm.clone(),
+            file_trace.region.clone().unwrap_or_default(),
+            file_trace.run_id.clone().unwrap_or_default(),
+            file_trace.tenant.clone().unwrap_or_default(),
+            file_trace.first_record_field_count.map(|count| count.to_string()).unwrap_or_default(),
+        ])?;
+        Ok(())
+    }
+
+    /// Build the SELECT statement for the given filter
+    fn build_select_query(filter: &TraceFilter) -> QueryBuilder<'_, Postgres> {
+        let mut query = QueryBuilder::new(
+            "SELECT id, name, path, hash, size_bytes, size_mb::FLOAT8 AS size_mb, total_lines, created_at, modified_at, processed_at, status_fvw, status_fnt, status_fa4, dn, count_mismatch, hash_algorithm, region, run_id, tenant, first_record_field_count FROM fvw_file_trace WHERE 1=1"
         );
+        push_filter_conditions(&mut query, filter);
+        query.push(" ORDER BY id");
+        query
+    }
 
-        if let Some(status) = status_fvw {
+    /// Append the filter's conditions to a query ending in a WHERE clause
+    fn push_filter_conditions<'a>(query: &mut QueryBuilder<'a, Postgres>, filter: &'a TraceFilter) {
+        if let Some(status) = filter.status_fvw {
             query.push(" AND status_fvw = ").push_bind(status);
         }
-        if let Some(status) = status_fnt {
+        if let Some(status) = filter.status_fnt {
             query.push(" AND status_fnt = ").push_bind(status);
         }
-        if let Some(status) = status_fa4 {
+        if let Some(status) = filter.status_fa4 {
             query.push(" AND status_fa4 = ").push_bind(status);
         }
+        if let Some(dn) = filter.dn {
+            query.push(" AND dn = ").push_bind(dn);
+        }
+        if let Some(hashes) = &filter.hashes {
+            query.push(" AND hash = ANY(").push_bind(hashes).push(")");
+        }
+        if let Some(tenant) = &filter.tenant {
+            query.push(" AND tenant = ").push_bind(tenant);
+        }
+    }
 
-        let rows = query.build().fetch_all(pool).await?;
+    /// Map a database row into a FileTrace
+    fn map_trace_row(row: &PgRow) -> Result<FileTrace, sqlx::Error> {
+        Ok(FileTrace {
+            id: Some(row.try_get("id")?),
+            name: row.try_get("name")?,
+            path: row.try_get("path")?,
+            hash: row.try_get("hash")?,
+            size_bytes: row.try_get("size_bytes")?,
+            size_mb: row.try_get("size_mb")?,
+            total_lines: row.try_get("total_lines")?,
+            created_at: row.try_get("created_at")?,
+            modified_at: row.try_get("modified_at")?,
+            processed_at: row.try_get("processed_at")?,
+            status_fvw: row.try_get("status_fvw")?,
+            status_fnt: row.try_get("status_fnt")?,
+            status_fa4: row.try_get("status_fa4")?,
+            dn: row.try_get("dn")?,
+            count_mismatch: row.try_get("count_mismatch")?,
+            hash_algorithm: row.try_get("hash_algorithm")?,
+            region: row.try_get("region")?,
+            run_id: row.try_get("run_id")?,
+            tenant: row.try_get("tenant")?,
+            first_record_field_count: row.try_get("first_record_field_count")?,
+        })
+    }
 
-        let file_traces = rows
-            .into_iter()
-            .map(|row| {
-                Ok(FileTrace {
-                    id: Some(row.get("id")),
-                    name: row.get("name"),
-                    path: row.get("path"),
-                    hash: row.get("hash"),
-                    size_bytes: row.get("size_bytes"),
-                    size_mb: row.get("size_mb"),
-                    total_lines: row.get("total_lines"),
-                    created_at: row.get("created_at"),
-                    modified_at: row.get("modified_at"),
-                    processed_at: row.get("processed_at"),
-                    status_fvw: row.get("status_fvw"),
-                    status_fnt: row.get("status_fnt"),
-                    status_fa4: row.get("status_fa4"),
-                    dn: row.get("dn"),
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use chrono::{TimeZone, Utc};
+
+        #[test]
+        fn test_partitioned_inserts_group_traces_by_dn() {
+            let timestamp = Utc.with_ymd_and_hms(2024, 1, 15, 10, 30, 0).unwrap();
+            let trace = |hash: &str, dn| {
+                FileTrace::new(format!("{}.txt", hash), format!("/out/{}.txt", hash), hash.into(), 1, 1, timestamp, timestamp, timestamp, dn)
+            };
+            let traces = vec![trace("a", 200), trace("b", 100), trace("c", 200), trace("d", 300), trace("e", 100)];
+
+            let partitions: Vec<Vec<(i32, &str)>> = partition_by_dn(&traces)
+                .iter()
+                .map(|partition| partition.iter().map(|trace| (trace.dn, trace.hash.as_str())).collect())
+                .collect();
+            assert_eq!(
+                partitions,
+                vec![vec![(200, "a"), (200, "c")], vec![(100, "b"), (100, "e")], vec![(300, "d")]]
+            );
+
+            // One statement per DN, each with one VALUES tuple per trace of that DN
+            let rows_per_statement: Vec<usize> = build_insert_queries_by_dn(&traces, ConflictKey::TenantHash)
+                .iter()
+                .map(|query| {
+                    let sql = query.sql();
+                    assert!(sql.trim_end().ends_with("RETURNING id, hash, path"), "{}", sql);
+                    assert!(sql.contains("ON CONFLICT (tenant, hash) DO NOTHING"), "{}", sql);
+                    sql.matches("), (").count() + 1
                 })
-            })
-            .collect::<Result<Vec<_>, sqlx::Error>>()?;
+                .collect();
+            assert_eq!(rows_per_statement, vec![2, 2, 1]);
 
-        Ok(file_traces)
+            assert!(build_insert_queries_by_dn(&[], ConflictKey::Hash).is_empty());
+        }
+
+        #[test]
+        fn test_csv_round_trip() {
+            let timestamp = Utc.with_ymd_and_hms(2024, 1, 15, 10, 30, 0).unwrap();
+            let traces = vec![
+                FileTrace::new("a.txt".into(), "/out/a.txt".into(), "aa".into(), 10, 2, timestamp, timestamp, timestamp, 12345),
+                FileTrace::new("b,c.txt".into(), "/out/b,c.txt".into(), "bb".into(), 20, 3, timestamp, timestamp, timestamp, 0),
+            ];
+
+            let mut csv_writer = create_csv_writer(Vec::new()).unwrap();
+            for trace in &traces {
+                write_csv_record(&mut csv_writer, trace).unwrap();
+            }
+            let output = csv_writer.into_inner().unwrap();
+
+            let mut reader = csv::Reader::from_reader(output.as_slice());
+            assert_eq!(reader.headers().unwrap(), CSV_HEADER.as_slice());
+
+            let records: Vec<csv::StringRecord> = reader.records().map(|r| r.unwrap()).collect();
+            assert_eq!(records.len(), 2);
+            assert_eq!(&records[0][1], "a.txt");
+            assert_eq!(&records[0][13], "12345");
+            assert_eq!(&records[1][1], "b,c.txt");
+            assert_eq!(&records[1][2], "/out/b,c.txt");
+            assert_eq!(&records[1][7], timestamp.to_rfc3339());
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,136 @@
+use crate::database::DbPool;
+use crate::models::FvwArqDiarioExt;
+use anyhow::Result;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+/// How long a run reuses the revenda rows it loaded before querying them again
+pub const DEFAULT_REVENDA_CACHE_TTL: Duration = Duration::from_secs(300);
+
+/// Source of revenda configuration rows
+pub trait RevendaSource {
+    fn load_revendas(&self) -> impl Future<Output = Result<Vec<FvwArqDiarioExt>>> + Send;
+}
+
+impl RevendaSource for DbPool {
+    fn load_revendas(&self) -> impl Future<Output = Result<Vec<FvwArqDiarioExt>>> + Send {
+        crate::database::arq_vw_ext::get_revendas(self)
+    }
+}
+
+/// Revenda rows together with the instant they were loaded
+#[derive(Debug)]
+struct CachedRevendas {
+    loaded_at: Instant,
+    revendas: Vec<FvwArqDiarioExt>,
+}
+
+/// In-process cache of revenda data, refreshed from its source once the TTL expires
+/// Clones share the same cached data.
+#[derive(Debug, Clone)]
+pub struct RevendaCache<S = DbPool> {
+    source: S,
+    ttl: Duration,
+    cached: Arc<RwLock<Option<CachedRevendas>>>,
+}
+
+impl<S: RevendaSource> RevendaCache<S> {
+    pub fn new(source: S, ttl: Duration) -> Self {
+        Self {
+            source,
+            ttl,
+            cached: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    /// Get the revendas, loading them from the source if the cache is empty or expired
+    pub async fn get_revendas(&self) -> Result<Vec<FvwArqDiarioExt>> {
+        if let Some(revendas) = self.fresh(&*self.cached.read().await) {
+            return Ok(revendas);
+        }
+
+        let mut cached = self.cached.write().await;
+
+        // Another task may have refreshed while we waited for the write lock
+        if let Some(revendas) = self.fresh(&cached) {
+            return Ok(revendas);
+        }
+
+        let revendas = self.source.load_revendas().await?;
+        *cached = Some(CachedRevendas {
+            loaded_at: Instant::now(),
+            revendas: revendas.clone(),
+        });
+
+        Ok(revendas)
+    }
+
+    /// Drop the cached data so the next call reloads from the source
+    pub async fn invalidate(&self) {
+        *self.cached.write().await = None;
+    }
+
+    fn fresh(&self, cached: &Option<CachedRevendas>) -> Option<Vec<FvwArqDiarioExt>> {
+        cached
+            .as_ref()
+            .filter(|cached| cached.loaded_at.elapsed() < self.ttl)
+            .map(|cached| cached.revendas.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Source counting how many times it was queried
+    #[derive(Default)]
+    struct CountingSource {
+        loads: AtomicUsize,
+    }
+
+    impl RevendaSource for Arc<CountingSource> {
+        async fn load_revendas(&self) -> Result<Vec<FvwArqDiarioExt>> {
+            let load = self.loads.fetch_add(1, Ordering::SeqCst) as i32;
+            Ok(vec![FvwArqDiarioExt::new(1, load, ".txt".into(), 0, String::new(), String::new())])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_second_call_within_ttl_uses_cache() {
+        let source = Arc::new(CountingSource::default());
+        let cache = RevendaCache::new(Arc::clone(&source), Duration::from_secs(60));
+
+        let first = cache.get_revendas().await.unwrap();
+        let second = cache.get_revendas().await.unwrap();
+
+        assert_eq!(source.loads.load(Ordering::SeqCst), 1);
+        assert_eq!(first[0].revenda, second[0].revenda);
+    }
+
+    #[tokio::test]
+    async fn test_expired_cache_reloads() {
+        let source = Arc::new(CountingSource::default());
+        let cache = RevendaCache::new(Arc::clone(&source), Duration::ZERO);
+
+        cache.get_revendas().await.unwrap();
+        let reloaded = cache.get_revendas().await.unwrap();
+
+        assert_eq!(source.loads.load(Ordering::SeqCst), 2);
+        assert_eq!(reloaded[0].revenda, 1);
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_forces_reload() {
+        let source = Arc::new(CountingSource::default());
+        let cache = RevendaCache::new(Arc::clone(&source), Duration::from_secs(60));
+
+        cache.get_revendas().await.unwrap();
+        cache.invalidate().await;
+        cache.get_revendas().await.unwrap();
+
+        assert_eq!(source.loads.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,164 @@
+use crate::database::DbPool;
+use crate::models::FileTrace;
+use anyhow::{Context, Result};
+
+/// Table holding the file traces
+pub const FILE_TRACE_TABLE: &str = "fvw_file_trace";
+
+/// Unique key targeted by the file trace insert's `ON CONFLICT` clause
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictKey {
+    /// One trace per content: the `UNIQUE (hash)` of the initial schema
+    #[default]
+    Hash,
+    /// One trace per content and tenant, so each tenant sharing the table registers its own
+    /// copy of the same content
+    TenantHash,
+    /// One trace per content, DN and region, for headers whose DN and region together
+    /// identify the dealer
+    HashDnRegion,
+    /// One trace per content and hash algorithm, so a digest computed one way (e.g. a quick
+    /// hash) never counts as a file already registered under another
+    HashAlgorithm,
+}
+
+impl ConflictKey {
+    pub const ALL: [ConflictKey; 4] = [
+        ConflictKey::Hash,
+        ConflictKey::TenantHash,
+        ConflictKey::HashDnRegion,
+        ConflictKey::HashAlgorithm,
+    ];
+
+    /// Name given to `--conflict-key`
+    pub fn name(self) -> &'static str {
+        match self {
+            ConflictKey::Hash => "hash",
+            ConflictKey::TenantHash => "tenant-hash",
+            ConflictKey::HashDnRegion => "hash-dn-region",
+            ConflictKey::HashAlgorithm => "hash-algorithm",
+        }
+    }
+
+    /// Pure function parsing a `--conflict-key` name
+    pub fn from_name(name: &str) -> Option<Self> {
+        ConflictKey::ALL
+            .into_iter()
+            .find(|key| key.name() == name)
+    }
+
+    /// Columns of the unique index the insert conflicts on
+    pub fn columns(self) -> &'static [&'static str] {
+        match self {
+            ConflictKey::Hash => &["hash"],
+            ConflictKey::TenantHash => &["tenant", "hash"],
+            ConflictKey::HashDnRegion => &["hash", "dn", "region"],
+            ConflictKey::HashAlgorithm => &["hash", "hash_algorithm"],
+        }
+    }
+
+    /// Pure function telling whether two traces have the same values for this key's columns,
+    /// i.e. whether inserting one conflicts with the other
+    pub fn same_key(self, a: &FileTrace, b: &FileTrace) -> bool {
+        a.hash == b.hash
+            && match self {
+                ConflictKey::Hash => true,
+                ConflictKey::TenantHash => a.tenant == b.tenant,
+                ConflictKey::HashDnRegion => a.dn == b.dn && a.region == b.region,
+                ConflictKey::HashAlgorithm => a.hash_algorithm == b.hash_algorithm,
+            }
+    }
+
+    /// Opt-in script under `migrations/` switching the table to this key (None for the
+    /// hash key of the numbered migrations)
+    pub fn setup_script(self) -> Option<&'static str> {
+        match self {
+            ConflictKey::Hash => None,
+            ConflictKey::TenantHash => Some("migrations/conflict_keys/tenant_hash.sql"),
+            ConflictKey::HashDnRegion => Some("migrations/conflict_keys/hash_dn_region.sql"),
+            ConflictKey::HashAlgorithm => Some("migrations/conflict_keys/hash_algorithm.sql"),
+        }
+    }
+}
+
+/// Check that the database is reachable and its schema supports the queries we rely on,
+/// with inserts conflicting on `conflict_key`
+pub async fn health_check(pool: &DbPool, conflict_key: ConflictKey) -> Result<()> {
+    sqlx::query("SELECT 1")
+        .execute(pool)
+        .await
+        .context("Database health check query failed")?;
+
+    let setup = |error: anyhow::Error| match conflict_key.setup_script() {
+        Some(script) => anyhow::anyhow!("{error}. --conflict-key {} is set up by {script}", conflict_key.name()),
+        None => error,
+    };
+    ensure_unique_index(pool, FILE_TRACE_TABLE, conflict_key.columns()).await.map_err(setup)?;
+
+    // Another unique key missing some of our columns still rejects rows ours lets in,
+    // failing their whole batch instead of skipping them
+    for other in ConflictKey::ALL {
+        let columns = other.columns();
+        let narrower = !conflict_key.columns().iter().all(|column| columns.contains(column));
+        if narrower && has_unique_index(pool, FILE_TRACE_TABLE, columns).await? {
+            return Err(setup(anyhow::anyhow!(
+                "{FILE_TRACE_TABLE} still has a unique index on ({}), which rejects traces --conflict-key {} accepts",
+                columns.join(", "),
+                conflict_key.name()
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Fail unless `table` has a unique constraint or index on exactly `columns`
+/// Without one, `ON CONFLICT (columns)` errors at runtime and every batch insert is dropped.
+pub async fn ensure_unique_index(pool: &DbPool, table: &str, columns: &[&str]) -> Result<()> {
+    if has_unique_index(pool, table, columns).await? {
+        return Ok(());
+    }
+
+    let column_list = columns.join(", ");
+    // Nullable key columns (e.g. tenant) must compare equal when NULL for the key to hold
+    let nulls = if columns.len() > 1 { " NULLS NOT DISTINCT" } else { "" };
+    anyhow::bail!(
+        "Missing unique constraint on {table}({column_list}): inserts use ON CONFLICT ({column_list}) and would fail for every batch. \
+         Add it with: ALTER TABLE {table} ADD CONSTRAINT {table}_{}_key UNIQUE{nulls} ({column_list})",
+        columns.join("_")
+    )
+}
+
+/// Whether `table` (resolved through the search path) has a non-partial unique index on
+/// exactly `columns`, in any order. An index over nullable columns only counts with
+/// NULLS NOT DISTINCT, since otherwise rows with a NULL key column never conflict
+pub async fn has_unique_index(pool: &DbPool, table: &str, columns: &[&str]) -> Result<bool> {
+    let exists = sqlx::query_scalar(
+        "SELECT EXISTS (
+             SELECT 1
+             FROM pg_index i
+             WHERE i.indrelid = to_regclass($1)
+               AND i.indisunique
+               AND i.indnatts = cardinality($2::text[])
+               AND i.indpred IS NULL
+               AND i.indexprs IS NULL
+               AND (i.indnullsnotdistinct OR NOT EXISTS (
+                   SELECT 1
+                   FROM pg_attribute a
+                   WHERE a.attrelid = i.indrelid AND a.attnum = ANY(i.indkey::int2[]) AND NOT a.attnotnull
+               ))
+               AND (
+                   SELECT array_agg(a.attname::text ORDER BY a.attname)
+                   FROM pg_attribute a
+                   WHERE a.attrelid = i.indrelid AND a.attnum = ANY(i.indkey::int2[])
+               ) = (SELECT array_agg(c ORDER BY c) FROM unnest($2::text[]) c)
+         )",
+    )
+    .bind(table)
+    .bind(columns)
+    .fetch_one(pool)
+    .await
+    .with_context(|| format!("Failed to inspect the indexes of {}", table))?;
+
+    Ok(exists)
+}
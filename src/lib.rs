@@ -9,7 +9,7 @@ pub use database::{create_connection_pool, DbPool};
 pub use models::{FileTrace, FileTraceStatus, FvwArqDiarioExt};
 pub use services::{
     copy_files_for_revendas, discover_and_register_files, FileCopyConfig, FileCopyReport,
-    FileDiscoveryConfig, FileDiscoveryReport,
+    ExitPolicy, FileDiscoveryConfig, FileDiscoveryReport,
 };
 
 // Application configuration
@@ -18,6 +18,45 @@ pub struct AppConfig {
     pub file_copy: FileCopyConfig,
     pub file_discovery: FileDiscoveryConfig,
     pub log_level: String,
+    /// Address for the optional HTTP status server (e.g. "127.0.0.1:9090")
+    pub status_addr: Option<String>,
+    /// Register only the files listed in this file instead of scanning directories
+    pub file_list: Option<std::path::PathBuf>,
+    /// Register only the files registered after this discovery run, like a file list
+    pub since_run: Option<String>,
+    /// Write the run's reports as JSON to this file
+    pub report_output: Option<std::path::PathBuf>,
+    /// Gzip-compress output files, appending `.gz` to their names
+    pub compress_output: bool,
+    /// Also echo WARN/ERROR events as plain text to stderr
+    pub stderr_alerts: bool,
+    /// Skip the copy phase
+    pub skip_copy: bool,
+    /// Skip the discovery phase
+    pub skip_discovery: bool,
+    /// Explicit (input, output) directory pairs copied instead of the revendas' folders
+    pub copy_mappings: Vec<(String, String)>,
+    /// Extensions copied for explicit mappings (empty copies every file)
+    pub copy_extensions: Vec<String>,
+    /// Wall-clock budget of the run: once used up, no new copy starts and no new file is
+    /// queued for discovery, and the reports are flagged as time-limited
+    pub max_run_duration: Option<std::time::Duration>,
+    /// Size and timeouts of the database connection pool
+    pub pool: database::PoolConfig,
+    /// When copy or discovery errors make the process exit non-zero
+    pub exit_policy: ExitPolicy,
+    /// Overlap the phases: discover each output folder as soon as its copies finish
+    pub pipeline_revendas: bool,
+    /// Output folders copied into and discovered at once when `pipeline_revendas` is set
+    pub max_revendas_in_flight: usize,
+}
+
+impl AppConfig {
+    /// Whether any requested phase reads or writes the database
+    pub fn needs_database(&self) -> bool {
+        let copy_uses_database = !self.skip_copy && self.copy_mappings.is_empty();
+        self.file_list.is_some() || self.since_run.is_some() || !self.skip_discovery || copy_uses_database
+    }
 }
 
 impl Default for AppConfig {
@@ -26,6 +65,21 @@ impl Default for AppConfig {
             file_copy: FileCopyConfig::default(),
             file_discovery: FileDiscoveryConfig::default(),
             log_level: "info".to_string(),
+            status_addr: None,
+            file_list: None,
+            since_run: None,
+            report_output: None,
+            compress_output: false,
+            stderr_alerts: false,
+            skip_copy: false,
+            skip_discovery: false,
+            copy_mappings: Vec::new(),
+            copy_extensions: Vec::new(),
+            max_run_duration: None,
+            pool: database::PoolConfig::default(),
+            exit_policy: ExitPolicy::default(),
+            pipeline_revendas: false,
+            max_revendas_in_flight: services::DEFAULT_MAX_REVENDAS_IN_FLIGHT,
         }
     }
 }
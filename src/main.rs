@@ -1,17 +1,76 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::{Arg, Command};
 use std::env;
-use tracing::{info, error, Level};
+use std::fs::File;
+use std::io::BufWriter;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{info, warn, error, Level};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::EnvFilter;
+use vw_file_discover::database::file_trace::{self, Page, TraceFilter};
+use vw_file_discover::database::{
+    create_connection_pool_with, health_check, verify_canary_from_env, ConflictKey, PoolConfig,
+    RevendaCache, DEFAULT_CONNECT_ATTEMPTS, RevendaSource, DEFAULT_REVENDA_CACHE_TTL,
+};
 use vw_file_discover::{
-    create_connection_pool, copy_files_for_revendas, discover_and_register_files,
-    AppConfig, FileCopyConfig, FileDiscoveryConfig,
+    create_connection_pool, AppConfig, ExitPolicy, FileCopyConfig, FileDiscoveryConfig,
+};
+use vw_file_discover::models::{FileProcessingConfig, HashAlgorithm, HashMode, HmacKey, LineCountMode, RegionSpec, TrailerSpec, TrimMode};
+use vw_file_discover::services::{
+    copy_and_discover_pipelined_with, copy_files_for_revendas_with, copy_files_with_mappings, default_hash_concurrency,
+    discover_and_register_files_with, discover_from_file_list, parse_copy_mapping, parse_file_list, reconcile, repair_traces, Metrics,
+    RepairReport, RunPhase, RunReport, RunTimings, StatusServer, EXIT_OK,
+};
+use vw_file_discover::utils::{
+    alert_layer, modified_since_cutoff, parse_look_back, write_json_output, CollisionPolicy, FilenameDatePattern, FutureMtimePolicy, OverwriteMode,
+    RetryPolicy, TransferMode,
+    CancellationToken, Clock, Deadline, SystemClock, DEFAULT_MAX_NAME_LEN, MAX_LOOK_BACK_DAYS,
 };
 
 #[tokio::main]
 async fn main() -> Result<()> {
     // Parse command line arguments
-    let matches = Command::new("VW File Discover")
+    let matches = build_cli().get_matches();
+
+    // Load the .env file first so configuration can read secrets from it
+    let dotenv_found = dotenvy::dotenv().is_ok();
+
+    // Initialize configuration from command line arguments
+    let config = create_app_config(&matches)?;
+
+    // Initialize logging
+    initialize_logging(&config.log_level, config.stderr_alerts)?;
+
+    // Validate environment variables
+    let needs_database = matches.subcommand_name().is_some() || config.needs_database();
+    load_environment_variables(needs_database, dotenv_found)?;
+
+    // Fail fast on key or configuration drift before touching anything
+    if verify_canary_from_env(|key| env::var(key).ok())? {
+        info!("Canary check passed");
+    }
+
+    // Run the requested subcommand or the full application
+    match matches.subcommand() {
+        Some(("export", export_matches)) => run_export(export_matches).await,
+        Some(("status", status_matches)) => run_status(status_matches).await,
+        Some(("revendas", revendas_matches)) => run_revendas(revendas_matches).await,
+        Some(("repair", repair_matches)) => run_repair(repair_matches, &config.file_discovery).await,
+        _ => {
+            let exit_code = run_application(config).await?;
+            if exit_code != EXIT_OK {
+                std::process::exit(exit_code);
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Build the command line interface definition
+fn build_cli() -> Command {
+    Command::new("VW File Discover")
         .version("1.0")
         .author("Your Name")
         .about("Functional file discovery and processing application")
@@ -25,10 +84,17 @@ async fn main() -> Result<()> {
         .arg(
             Arg::new("days-back")
                 .long("days-back")
-                .value_name("DAYS")
-                .help("Number of days back to look for files")
+                .value_name("WINDOW")
+                .help("How far back to look for files: days (15) or a duration such as 90m, 6h or 3d, up to 36500 days")
+                .value_parser(parse_days_back)
                 .default_value("15"),
         )
+        .arg(
+            Arg::new("max-run-duration")
+                .long("max-run-duration")
+                .value_name("DURATION")
+                .help("Stop starting new copies and queuing new files after DURATION (e.g. 90m or 2h); in-flight work completes"),
+        )
         .arg(
             Arg::new("batch-size")
                 .long("batch-size")
@@ -48,19 +114,658 @@ async fn main() -> Result<()> {
                 .help("Skip file discovery phase")
                 .action(clap::ArgAction::SetTrue),
         )
-        .get_matches();
+        .arg(
+            Arg::new("strict")
+                .long("strict")
+                .help("Exit non-zero when any copy or discovery error occurs")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("max-error-rate")
+                .long("max-error-rate")
+                .value_name("PERCENT")
+                .help("Exit non-zero when more than PERCENT (0-100) of a phase's files failed")
+                .value_parser(parse_percent)
+                .default_value("50"),
+        )
+        .arg(
+            Arg::new("copy-mapping")
+                .long("copy-mapping")
+                .value_name("SRC:DST")
+                .help("Copy from SRC to DST without reading the revendas from the database (repeatable)")
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            Arg::new("copy-extension")
+                .long("copy-extension")
+                .value_name("EXT")
+                .help("Extension copied for --copy-mapping (repeatable; default: every file)")
+                .requires("copy-mapping")
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            Arg::new("count-lines-extension")
+                .long("count-lines-extension")
+                .value_name("EXT")
+                .help("Only count lines and read the DN of files with this extension; others are hashed only (repeatable; default: every file)")
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            Arg::new("filename-date-pattern")
+                .long("filename-date-pattern")
+                .value_name("REGEX")
+                .help("Judge --days-back by the date captured from each file name (first group or `date`) instead of its modification time"),
+        )
+        .arg(
+            Arg::new("filename-date-format")
+                .long("filename-date-format")
+                .value_name("FORMAT")
+                .help("chrono format of the date captured by --filename-date-pattern")
+                .requires("filename-date-pattern")
+                .default_value("%Y%m%d"),
+        )
+        .arg(
+            Arg::new("stderr-alerts")
+                .long("stderr-alerts")
+                .help("Also print warnings and errors to stderr as plain text")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("status-addr")
+                .long("status-addr")
+                .value_name("ADDR")
+                .help("Serve GET /status with live progress as JSON on this address (e.g. 127.0.0.1:9090)"),
+        )
+        .arg(
+            Arg::new("hash-truncate-len")
+                .long("hash-truncate-len")
+                .value_name("CHARS")
+                .help("Store only the first CHARS hex characters of each file hash (1 to 64)")
+                .value_parser(clap::builder::RangedU64ValueParser::<usize>::new().range(1..=64)),
+        )
+        .arg(
+            Arg::new("read-buffer-size")
+                .long("read-buffer-size")
+                .value_name("BYTES")
+                .help("Buffer size used when hashing files with buffered reads")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("mmap-threshold")
+                .long("mmap-threshold")
+                .value_name("BYTES")
+                .help("Memory-map files larger than BYTES instead of reading them in chunks")
+                .value_parser(clap::value_parser!(u64)),
+        )
+        .arg(
+            Arg::new("region-offset")
+                .long("region-offset")
+                .value_name("OFFSET")
+                .help("Also capture a region code at this 0-based offset of the FHI header line")
+                .requires("region-length")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("region-length")
+                .long("region-length")
+                .value_name("LENGTH")
+                .help("Number of characters of the region code")
+                .requires("region-offset")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("quick-hash-bytes")
+                .long("quick-hash-bytes")
+                .value_name("BYTES")
+                .help(
+                    "Hash only the first and last BYTES bytes plus the size (fast, weaker change detection; no line counts). \
+                     Same-size files differing only in the middle get the same hash, so only the first of them is registered; \
+                     use --conflict-key hash-algorithm to keep quick hashes apart from full ones",
+                )
+                .value_parser(clap::value_parser!(u64).range(1..)),
+        )
+        .arg(
+            Arg::new("metadata-only")
+                .long("metadata-only")
+                .help("Don't read file contents: key each trace on the SHA-256 of its normalized path, with no lines or DN")
+                .conflicts_with_all(["quick-hash-bytes", "hash-algorithm"])
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("hash-algorithm")
+                .long("hash-algorithm")
+                .value_name("ALGORITHM")
+                .help("Content hash: sha256, md5, blake3, or hmac-sha256 keyed with the HASH_HMAC_KEY environment variable")
+                .value_parser(["sha256", "md5", "blake3", "hmac-sha256"])
+                .default_value("sha256")
+                .conflicts_with("quick-hash-bytes"),
+        )
+        .arg(
+            Arg::new("skip-blank-lines")
+                .long("skip-blank-lines")
+                .help("Leave empty and whitespace-only lines out of each file's line count")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("field-delimiter")
+                .long("field-delimiter")
+                .value_name("CHAR")
+                .help("Count the fields of each file's first data line split on CHAR (e.g. '|' or ';')")
+                .value_parser(clap::value_parser!(char)),
+        )
+        .arg(
+            Arg::new("recursive")
+                .long("recursive")
+                .help("Also discover files in subdirectories of each output folder (symlinked folders are not followed)")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("max-depth")
+                .long("max-depth")
+                .value_name("N")
+                .help("Discover files at most N subdirectory levels below each output folder (implies --recursive)")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("trim-first-line")
+                .long("trim-first-line")
+                .value_name("MODE")
+                .help("Trim whitespace from the header line before reading the DN: none, end or both")
+                .value_parser(["none", "end", "both"])
+                .default_value("none"),
+        )
+        .arg(
+            Arg::new("future-mtime")
+                .long("future-mtime")
+                .value_name("POLICY")
+                .help("Files modified in the future (clock skew): accept, clamp to now, or reject")
+                .value_parser(["accept", "clamp", "reject"])
+                .default_value("accept"),
+        )
+        .arg(
+            Arg::new("read-sidecar-meta")
+                .long("read-sidecar-meta")
+                .help("Take the DN and expected line count from a <file>.meta JSON sidecar when one exists")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("validate-trailer")
+                .long("validate-trailer")
+                .help("Flag files whose FTR trailer record count differs from the counted lines")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("trailer-count-offset")
+                .long("trailer-count-offset")
+                .value_name("CHARS")
+                .help("0-based character offset of the record count in the FTR line")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("3"),
+        )
+        .arg(
+            Arg::new("trailer-count-length")
+                .long("trailer-count-length")
+                .value_name("CHARS")
+                .help("Number of characters holding the record count in the FTR line")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("9"),
+        )
+        .arg(
+            Arg::new("scan-retries")
+                .long("scan-retries")
+                .value_name("ATTEMPTS")
+                .help("Attempts for listing a directory on transient network errors")
+                .value_parser(clap::value_parser!(u32))
+                .default_value("3"),
+        )
+        .arg(
+            Arg::new("scan-retry-delay-ms")
+                .long("scan-retry-delay-ms")
+                .value_name("MS")
+                .help("Delay between directory listing attempts in milliseconds")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("500"),
+        )
+        .arg(
+            Arg::new("file-list")
+                .long("file-list")
+                .value_name("PATH")
+                .help("Register only the files listed in PATH (one per line), skipping copy and directory scanning")
+                .value_parser(clap::value_parser!(std::path::PathBuf)),
+        )
+        .arg(
+            Arg::new("since-run")
+                .long("since-run")
+                .value_name("RUN_ID")
+                .help("Register again only the files registered after discovery run RUN_ID, skipping copy and directory scanning")
+                .conflicts_with("file-list"),
+        )
+        .arg(
+            Arg::new("report-output")
+                .long("report-output")
+                .value_name("PATH")
+                .help("Write the copy and discovery reports as JSON to PATH")
+                .value_parser(clap::value_parser!(std::path::PathBuf)),
+        )
+        .arg(
+            Arg::new("compress-output")
+                .long("compress-output")
+                .help("Gzip-compress output files, appending .gz to their names")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("error-log")
+                .long("error-log")
+                .value_name("PATH")
+                .help("Append one timestamp<TAB>path<TAB>error line per failed copy or file to PATH")
+                .value_parser(clap::value_parser!(std::path::PathBuf)),
+        )
+        .arg(
+            Arg::new("max-copy-errors")
+                .long("max-copy-errors")
+                .value_name("COUNT")
+                .help("Abort the copy phase once more than COUNT copies have failed")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("dest-name-template")
+                .long("dest-name-template")
+                .value_name("TEMPLATE")
+                .help("Name copied files from TEMPLATE using {name}, {stem} and {ext} placeholders"),
+        )
+        .arg(
+            Arg::new("repair-stale-copies")
+                .long("repair-stale-copies")
+                .help("Re-copy files whose existing destination has different content (e.g. an interrupted copy)")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("rename-on-collision")
+                .long("rename-on-collision")
+                .help("Copy to name_1.ext, name_2.ext, ... instead of skipping or overwriting existing files")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("max-name-len")
+                .long("max-name-len")
+                .value_name("BYTES")
+                .help("Shorten renamed files so their names fit in BYTES (default: 255)")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("copy-buffer-size")
+                .long("copy-buffer-size")
+                .value_name("BYTES")
+                .help("Copy through a BYTES buffer instead of the OS copy (can be faster on network mounts)")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("skip-duplicate-content")
+                .long("skip-duplicate-content")
+                .help("Skip files whose content already exists in the destination under another name (hashes destination files)")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("pipeline-revendas")
+                .long("pipeline-revendas")
+                .help("Discover each output folder as soon as the copies into it finish, instead of copying everything first")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("max-revendas-in-flight")
+                .long("max-revendas-in-flight")
+                .value_name("N")
+                .help("Output folders copied into and discovered at once with --pipeline-revendas")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value("4"),
+        )
+        .arg(
+            Arg::new("transfer-mode")
+                .long("transfer-mode")
+                .value_name("MODE")
+                .help("Copy files to the output folders, or move them out of the input folders")
+                .value_parser(["copy", "move"])
+                .default_value("copy"),
+        )
+        .arg(
+            Arg::new("verify-copies")
+                .long("verify-copies")
+                .help("Re-hash each copied file and report a copy error when it differs from the source")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("copy-dry-run")
+                .long("copy-dry-run")
+                .help("Preview the copy phase: report which files would be copied (skip reason \"dry-run\") without writing anything")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("preserve-attributes")
+                .long("preserve-attributes")
+                .help("Copy Windows file attributes (read-only, archive) onto copied files")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("ban-pattern")
+                .long("ban-pattern")
+                .value_name("PATTERN")
+                .help("Register files whose name matches the glob PATTERN (*, ? and [...]) as banned; may be repeated")
+                .value_parser(parse_glob_pattern)
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            Arg::new("requeue-errors")
+                .long("requeue-errors")
+                .help("Reset already-registered traces in error back to pending when their file is found again")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("content-type")
+                .long("content-type")
+                .value_name("TYPE")
+                .help("Also match extension-less files whose magic bytes identify TYPE (MIME type or extension); may be repeated")
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            Arg::new("expand-archives")
+                .long("expand-archives")
+                .help("Register each file inside discovered .zip archives instead of the archive itself")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("delete-after-register")
+                .long("delete-after-register")
+                .help("Delete discovered files once their trace is saved to the database")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("delete-conflicting")
+                .long("delete-conflicting")
+                .help("With --delete-after-register, also delete files already registered by a previous run")
+                .requires("delete-after-register")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("max-db-concurrency")
+                .long("max-db-concurrency")
+                .value_name("COUNT")
+                .help("Maximum number of batch inserts running at once (capped by the pool size)")
+                .default_value("4")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("preflight-dirs")
+                .long("preflight-dirs")
+                .help("Check every output directory is reachable before scanning and skip the unreachable ones")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("preflight-strict")
+                .long("preflight-strict")
+                .help("Like --preflight-dirs, but abort discovery if any output directory is unreachable")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("tenant")
+                .long("tenant")
+                .value_name("ID")
+                .help("Tenant id (at most 64 characters) stored on every trace registered by this run, for separating tenants sharing the table")
+                .value_parser(parse_tenant),
+        )
+        .arg(
+            Arg::new("conflict-key")
+                .long("conflict-key")
+                .value_name("KEY")
+                .help("Unique key skipping already-registered traces: hash, tenant-hash for one trace per content and tenant, hash-dn-region for one per content, DN and region, or hash-algorithm for one per content and hash algorithm")
+                .value_parser(["hash", "tenant-hash", "hash-dn-region", "hash-algorithm"])
+                .default_value("hash"),
+        )
+        .arg(
+            Arg::new("hash-concurrency")
+                .long("hash-concurrency")
+                .value_name("COUNT")
+                .help("Maximum number of files hashed at once, e.g. 4 on slow network storage (default: number of CPUs)")
+                .value_parser(clap::value_parser!(u64).range(1..)),
+        )
+        .arg(
+            Arg::new("db-max-connections")
+                .long("db-max-connections")
+                .value_name("COUNT")
+                .help("Maximum number of database connections in the pool")
+                .default_value("10")
+                .value_parser(clap::value_parser!(u32).range(1..)),
+        )
+        .arg(
+            Arg::new("db-acquire-timeout")
+                .long("db-acquire-timeout")
+                .value_name("SECONDS")
+                .help("Seconds to wait for a free database connection before failing (default: the connection string's Timeout, else 30)")
+                .value_parser(clap::value_parser!(u64)),
+        )
+        .arg(
+            Arg::new("db-connect-attempts")
+                .long("db-connect-attempts")
+                .value_name("COUNT")
+                .help("Connection attempts, with exponential backoff from 500ms, while Postgres is starting or unreachable; 1 fails fast (default: 5)")
+                .value_parser(clap::value_parser!(u32).range(1..)),
+        )
+        .arg(
+            Arg::new("skip-zero-byte")
+                .long("skip-zero-byte")
+                .help("Do not register zero-byte files; they are counted separately in the discovery report")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("partition-inserts-by-dn")
+                .long("partition-inserts-by-dn")
+                .help("Insert each batch with one statement per DN, for DN-partitioned trace tables")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("check-hash-collisions")
+                .long("check-hash-collisions")
+                .help("Report files whose hash matches a registered trace of a different size or line count")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("report-top-n")
+                .long("report-top-n")
+                .value_name("COUNT")
+                .help("Number of largest and oldest files listed in the discovery report")
+                .default_value("10")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("scan-queue-capacity")
+                .long("scan-queue-capacity")
+                .value_name("COUNT")
+                .help("Maximum number of scanned files waiting to be processed before scanning pauses")
+                .default_value("1024")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("recheck-before-save")
+                .long("recheck-before-save")
+                .help("Re-stat each file before saving and skip files modified since they were hashed")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("path-rewrite")
+                .long("path-rewrite")
+                .value_names(["FROM", "TO"])
+                .num_args(2)
+                .help("Store paths scanned under FROM (e.g. a local snapshot) with FROM replaced by TO"),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Export file traces to CSV")
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .value_name("PATH")
+                        .help("Destination CSV file")
+                        .required(true),
+                )
+                .arg(status_filter_arg("status-fvw"))
+                .arg(status_filter_arg("status-fnt"))
+                .arg(status_filter_arg("status-fa4"))
+                .arg(
+                    Arg::new("dn")
+                        .long("dn")
+                        .value_name("DN")
+                        .help("Only export traces for this DN")
+                        .value_parser(clap::value_parser!(i32)),
+                )
+                .arg(tenant_filter_arg()),
+        )
+        .subcommand(
+            Command::new("status")
+                .about("List file traces by status")
+                .arg(status_filter_arg("status-fvw"))
+                .arg(status_filter_arg("status-fnt"))
+                .arg(status_filter_arg("status-fa4"))
+                .arg(
+                    Arg::new("dn")
+                        .long("dn")
+                        .value_name("DN")
+                        .help("Only list traces for this DN")
+                        .value_parser(clap::value_parser!(i32)),
+                )
+                .arg(tenant_filter_arg())
+                .arg(
+                    Arg::new("limit")
+                        .long("limit")
+                        .value_name("N")
+                        .help("List at most N traces")
+                        .value_parser(clap::value_parser!(i64).range(0..)),
+                )
+                .arg(
+                    Arg::new("offset")
+                        .long("offset")
+                        .value_name("M")
+                        .help("Skip the first M matching traces")
+                        .value_parser(clap::value_parser!(i64).range(0..))
+                        .default_value("0"),
+                )
+                .arg(
+                    Arg::new("count-only")
+                        .long("count-only")
+                        .help("Print only the number of matching traces")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with_all(["limit", "offset"]),
+                ),
+        )
+        .subcommand(
+            Command::new("repair")
+                .about("Re-hash the files of file traces and repair traces whose stored hash is stale, with the hashing and --conflict-key options given before the subcommand")
+                .arg(status_filter_arg("status-fvw"))
+                .arg(status_filter_arg("status-fnt"))
+                .arg(status_filter_arg("status-fa4"))
+                .arg(
+                    Arg::new("dn")
+                        .long("dn")
+                        .value_name("DN")
+                        .help("Only repair traces for this DN")
+                        .value_parser(clap::value_parser!(i32)),
+                )
+                .arg(tenant_filter_arg())
+                .arg(
+                    Arg::new("dry-run")
+                        .long("dry-run")
+                        .help("Only report the traces that would be repaired")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("revendas")
+                .about("Print the revenda configuration the tool would act on as JSON")
+                .arg(
+                    Arg::new("only-dn")
+                        .long("only-dn")
+                        .value_name("DN")
+                        .help("Only print revendas with this DN")
+                        .value_parser(clap::value_parser!(i32)),
+                ),
+        )
+}
 
-    // Initialize configuration from command line arguments
-    let config = create_app_config(&matches)?;
+/// Optional status filter argument shared by trace subcommands
+fn status_filter_arg(name: &'static str) -> Arg {
+    Arg::new(name)
+        .long(name)
+        .value_name("STATUS")
+        .help("Only include traces with this status (0=pending, 1=processing, 2=processed, 3=error, 4=banned)")
+        .value_parser(clap::value_parser!(i32))
+}
 
-    // Initialize logging
-    initialize_logging(&config.log_level)?;
+/// Optional tenant filter argument shared by trace subcommands
+fn tenant_filter_arg() -> Arg {
+    Arg::new("tenant")
+        .long("tenant")
+        .value_name("ID")
+        .help("Only include traces registered for this tenant")
+        .value_parser(parse_tenant)
+}
+
+/// Pure function to create a trace filter from subcommand arguments
+fn create_trace_filter(matches: &clap::ArgMatches) -> TraceFilter {
+    TraceFilter {
+        status_fvw: matches.get_one::<i32>("status-fvw").copied(),
+        status_fnt: matches.get_one::<i32>("status-fnt").copied(),
+        status_fa4: matches.get_one::<i32>("status-fa4").copied(),
+        dn: matches.get_one::<i32>("dn").copied(),
+        hashes: None,
+        tenant: matches.get_one::<String>("tenant").cloned(),
+    }
+}
+
+/// Environment variable holding the secret key for `--hash-algorithm hmac-sha256`
+const HMAC_KEY_ENV: &str = "HASH_HMAC_KEY";
+
+/// Width of the `tenant` column (VARCHAR(64))
+const MAX_TENANT_LEN: usize = 64;
 
-    // Load environment variables
-    load_environment_variables()?;
+/// Pure function validating a tenant id against the width of the `tenant` column
+fn parse_tenant(raw: &str) -> Result<String, String> {
+    match raw.chars().count() {
+        0 => Err("tenant id is empty".to_string()),
+        len if len > MAX_TENANT_LEN => Err(format!("tenant id is {} characters, at most {} allowed", len, MAX_TENANT_LEN)),
+        _ => Ok(raw.to_string()),
+    }
+}
+
+/// Pure function parsing a `--max-error-rate` percentage, rejecting values outside 0-100
+fn parse_percent(raw: &str) -> Result<f64, String> {
+    match raw.parse::<f64>() {
+        Ok(percent) if (0.0..=100.0).contains(&percent) => Ok(percent),
+        Ok(_) => Err(format!("{} is not between 0 and 100", raw)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Pure function parsing `--days-back`, bounded to `MAX_LOOK_BACK_DAYS`
+fn parse_days_back(raw: &str) -> Result<chrono::Duration, String> {
+    parse_look_back(raw).ok_or_else(|| {
+        format!("expected a number of days or a duration such as 90m, 6h or 3d, up to {MAX_LOOK_BACK_DAYS} days")
+    })
+}
+
+/// Pure function rejecting a glob pattern that could never match
+fn parse_glob_pattern(raw: &str) -> Result<String, String> {
+    glob::Pattern::new(raw)
+        .map(|_| raw.to_string())
+        .map_err(|e| format!("invalid glob pattern: {e}"))
+}
 
-    // Run the application
-    run_application(config).await
+/// Pure function to create the content hash mode from `--hash-algorithm` and the HMAC key
+fn create_hash_mode(algorithm: Option<&str>, hmac_key: Option<String>) -> Result<HashMode> {
+    match algorithm {
+        Some("hmac-sha256") => match hmac_key.filter(|key| !key.is_empty()) {
+            Some(key) => Ok(HashMode::HmacSha256 { key: HmacKey::new(key) }),
+            None => anyhow::bail!("--hash-algorithm hmac-sha256 requires the {} environment variable", HMAC_KEY_ENV),
+        },
+        _ => Ok(HashMode::Full),
+    }
 }
 
 /// Pure function to create application configuration from CLI arguments
@@ -70,11 +775,25 @@ fn create_app_config(matches: &clap::ArgMatches) -> Result<AppConfig> {
         .unwrap()
         .clone();
 
-    let days_back: i64 = matches
-        .get_one::<String>("days-back")
-        .unwrap_or(&"15".to_string())
-        .parse()
-        .map_err(|_| anyhow::anyhow!("Invalid days-back value"))?;
+    let look_back = *matches.get_one::<chrono::Duration>("days-back").unwrap();
+
+    let max_run_duration = matches
+        .get_one::<String>("max-run-duration")
+        .map(|raw| {
+            parse_look_back(raw)
+                .and_then(|duration| duration.to_std().ok())
+                .with_context(|| format!("Invalid max-run-duration value: {}", raw))
+        })
+        .transpose()?;
+
+    let hash_mode = match matches.get_one::<u64>("quick-hash-bytes") {
+        Some(bytes) => HashMode::Quick { bytes: *bytes },
+        None if matches.get_flag("metadata-only") => HashMode::MetadataOnly,
+        None => create_hash_mode(
+            matches.get_one::<String>("hash-algorithm").map(String::as_str),
+            env::var(HMAC_KEY_ENV).ok(),
+        )?,
+    };
 
     let batch_size: usize = matches
         .get_one::<String>("batch-size")
@@ -82,21 +801,190 @@ fn create_app_config(matches: &clap::ArgMatches) -> Result<AppConfig> {
         .parse()
         .map_err(|_| anyhow::anyhow!("Invalid batch-size value"))?;
 
+    let error_log = matches.get_one::<std::path::PathBuf>("error-log").cloned();
+
+    let filename_date_pattern = matches
+        .get_one::<String>("filename-date-pattern")
+        .map(|pattern| FilenameDatePattern::new(pattern, matches.get_one::<String>("filename-date-format").unwrap()))
+        .transpose()?;
+
+    // One clock for every time-dependent decision of the run
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+
     Ok(AppConfig {
         file_copy: FileCopyConfig {
-            days_back,
-            overwrite: false,
+            look_back,
+            overwrite: if matches.get_flag("repair-stale-copies") {
+                OverwriteMode::IfDestinationHashDiffers
+            } else {
+                OverwriteMode::Never
+            },
+            preserve_attributes: matches.get_flag("preserve-attributes"),
+            error_log: error_log.clone(),
+            max_copy_errors: matches.get_one::<usize>("max-copy-errors").copied(),
+            name_template: matches.get_one::<String>("dest-name-template").cloned(),
+            collision: if matches.get_flag("rename-on-collision") {
+                CollisionPolicy::RenameWithSuffix
+            } else {
+                CollisionPolicy::Keep
+            },
+            max_name_len: matches
+                .get_one::<usize>("max-name-len")
+                .copied()
+                .unwrap_or(DEFAULT_MAX_NAME_LEN),
+            // Custom predicates are a library extension point with no CLI equivalent
+            file_filters: Vec::new(),
+            filename_date_pattern: filename_date_pattern.clone(),
+            copy_buffer_bytes: matches.get_one::<usize>("copy-buffer-size").copied(),
+            skip_duplicate_content: matches.get_flag("skip-duplicate-content"),
+            // Set from `max_run_duration` when the run starts
+            deadline: None,
+            cancel: CancellationToken::new(),
+            metrics: Arc::new(Metrics::default()),
+            clock: Arc::clone(&clock),
+            dry_run: matches.get_flag("copy-dry-run"),
+            verify: matches.get_flag("verify-copies"),
+            transfer: match matches.get_one::<String>("transfer-mode").map(String::as_str) {
+                Some("move") => TransferMode::Move,
+                _ => TransferMode::Copy,
+            },
         },
         file_discovery: FileDiscoveryConfig {
             batch_size,
             parallel_processing: true,
+            hash_concurrency: matches
+                .get_one::<u64>("hash-concurrency")
+                .map(|count| *count as usize)
+                .unwrap_or_else(default_hash_concurrency),
+            delete_after_register: matches.get_flag("delete-after-register"),
+            delete_conflicting: matches.get_flag("delete-conflicting"),
+            recursive: matches.get_flag("recursive") || matches.contains_id("max-depth"),
+            max_depth: matches.get_one::<usize>("max-depth").copied(),
+            deadline: None,
+            cancel: CancellationToken::new(),
+            metrics: Arc::new(Metrics::default()),
+            clock: Arc::clone(&clock),
+            preflight_dirs: matches.get_flag("preflight-dirs") || matches.get_flag("preflight-strict"),
+            preflight_strict: matches.get_flag("preflight-strict"),
+            tenant: matches.get_one::<String>("tenant").cloned(),
+            conflict_key: matches
+                .get_one::<String>("conflict-key")
+                .and_then(|name| ConflictKey::from_name(name))
+                .unwrap_or_default(),
+            processing: FileProcessingConfig {
+                hash_truncate_len: matches.get_one::<usize>("hash-truncate-len").copied(),
+                read_buffer_size: matches.get_one::<usize>("read-buffer-size").copied(),
+                use_mmap_threshold: matches.get_one::<u64>("mmap-threshold").copied(),
+                count_mode: if matches.get_flag("skip-blank-lines") {
+                    LineCountMode::NonBlank
+                } else {
+                    LineCountMode::All
+                },
+                field_delimiter: matches.get_one::<char>("field-delimiter").copied(),
+                read_sidecar_meta: matches.get_flag("read-sidecar-meta"),
+                trim_first_line: match matches.get_one::<String>("trim-first-line").map(String::as_str) {
+                    Some("end") => TrimMode::End,
+                    Some("both") => TrimMode::Both,
+                    _ => TrimMode::None,
+                },
+                future_mtime_policy: match matches.get_one::<String>("future-mtime").map(String::as_str) {
+                    Some("clamp") => FutureMtimePolicy::ClampToNow,
+                    Some("reject") => FutureMtimePolicy::Reject,
+                    _ => FutureMtimePolicy::Accept,
+                },
+                hash_mode,
+                hash_algorithm: matches
+                    .get_one::<String>("hash-algorithm")
+                    .and_then(|name| HashAlgorithm::from_name(name))
+                    .unwrap_or_default(),
+                count_lines_extensions: matches
+                    .get_many::<String>("count-lines-extension")
+                    .map(|extensions| extensions.cloned().collect()),
+                region: matches.get_one::<usize>("region-offset").map(|offset| RegionSpec {
+                    prefix: "FHI".to_string(),
+                    offset: *offset,
+                    length: *matches.get_one::<usize>("region-length").unwrap(),
+                }),
+                trailer: matches.get_flag("validate-trailer").then(|| TrailerSpec {
+                    offset: *matches.get_one::<usize>("trailer-count-offset").unwrap(),
+                    length: *matches.get_one::<usize>("trailer-count-length").unwrap(),
+                    ..TrailerSpec::default()
+                }),
+            },
+            expand_archives: matches.get_flag("expand-archives"),
+            banned_patterns: matches
+                .get_many::<String>("ban-pattern")
+                .map(|patterns| patterns.cloned().collect())
+                .unwrap_or_default(),
+            requeue_errors: matches.get_flag("requeue-errors"),
+            recheck_before_save: matches.get_flag("recheck-before-save"),
+            max_db_concurrency: *matches.get_one::<usize>("max-db-concurrency").unwrap(),
+            partition_inserts_by_dn: matches.get_flag("partition-inserts-by-dn"),
+            skip_zero_byte: matches.get_flag("skip-zero-byte"),
+            scan_queue_capacity: *matches.get_one::<usize>("scan-queue-capacity").unwrap(),
+            report_top_n: *matches.get_one::<usize>("report-top-n").unwrap(),
+            check_hash_collisions: matches.get_flag("check-hash-collisions"),
+            // Discovery applies the copy window only to dated file names
+            file_filters: filename_date_pattern
+                .iter()
+                .zip(modified_since_cutoff(Some(look_back), clock.as_ref()))
+                .map(|(pattern, cutoff)| pattern.since(cutoff.date_naive()))
+                .collect(),
+            path_rewrite: matches
+                .get_many::<String>("path-rewrite")
+                .map(|values| values.cloned().collect::<Vec<_>>())
+                .map(|values| (values[0].clone(), values[1].clone())),
+            error_log,
+            content_types: matches
+                .get_many::<String>("content-type")
+                .map(|types| types.cloned().collect())
+                .unwrap_or_default(),
+            scan_retry: RetryPolicy {
+                attempts: *matches.get_one::<u32>("scan-retries").unwrap(),
+                delay: std::time::Duration::from_millis(*matches.get_one::<u64>("scan-retry-delay-ms").unwrap()),
+            },
         },
         log_level,
+        status_addr: matches.get_one::<String>("status-addr").cloned(),
+        file_list: matches.get_one::<std::path::PathBuf>("file-list").cloned(),
+        since_run: matches.get_one::<String>("since-run").cloned(),
+        report_output: matches.get_one::<std::path::PathBuf>("report-output").cloned(),
+        compress_output: matches.get_flag("compress-output"),
+        stderr_alerts: matches.get_flag("stderr-alerts"),
+        skip_copy: matches.get_flag("skip-copy"),
+        skip_discovery: matches.get_flag("skip-discovery"),
+        max_run_duration,
+        pool: PoolConfig {
+            max_connections: *matches.get_one::<u32>("db-max-connections").unwrap(),
+            acquire_timeout: matches.get_one::<u64>("db-acquire-timeout").copied().map(std::time::Duration::from_secs),
+            connect_attempts: matches
+                .get_one::<u32>("db-connect-attempts")
+                .copied()
+                .unwrap_or(DEFAULT_CONNECT_ATTEMPTS),
+            ..PoolConfig::default()
+        },
+        pipeline_revendas: matches.get_flag("pipeline-revendas"),
+        max_revendas_in_flight: *matches.get_one::<u64>("max-revendas-in-flight").unwrap() as usize,
+        exit_policy: ExitPolicy {
+            strict: matches.get_flag("strict"),
+            max_error_percent: *matches.get_one::<f64>("max-error-rate").unwrap(),
+        },
+        copy_mappings: matches
+            .get_many::<String>("copy-mapping")
+            .into_iter()
+            .flatten()
+            .map(|raw| parse_copy_mapping(raw).with_context(|| format!("Invalid --copy-mapping (expected SRC:DST): {}", raw)))
+            .collect::<Result<_>>()?,
+        copy_extensions: matches
+            .get_many::<String>("copy-extension")
+            .map(|extensions| extensions.cloned().collect())
+            .unwrap_or_default(),
     })
 }
 
 /// Initialize structured logging with tracing
-fn initialize_logging(log_level: &str) -> Result<()> {
+/// With `stderr_alerts`, WARN/ERROR events are also echoed to stderr as plain text
+fn initialize_logging(log_level: &str, stderr_alerts: bool) -> Result<()> {
     let level = match log_level.to_lowercase().as_str() {
         "trace" => Level::TRACE,
         "debug" => Level::DEBUG,
@@ -112,24 +1000,47 @@ fn initialize_logging(log_level: &str) -> Result<()> {
         .add_directive("hyper=warn".parse()?)
         .add_directive("rustls=warn".parse()?);
 
-    tracing_subscriber::fmt()
-        .with_env_filter(filter)
+    let stdout_layer = tracing_subscriber::fmt::layer()
         .with_target(false)
         .with_thread_ids(false)
         .with_file(false)
-        .with_line_number(false)
+        .with_line_number(false);
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(stdout_layer)
+        .with(stderr_alerts.then(|| alert_layer(std::io::stderr)))
         .init();
 
     Ok(())
 }
 
-/// Load and validate environment variables
-fn load_environment_variables() -> Result<()> {
-    // Load .env file if it exists
-    if let Err(_) = dotenvy::dotenv() {
+/// Validate environment variables
+/// Database settings are only required when a phase uses the database
+fn load_environment_variables(needs_database: bool, dotenv_found: bool) -> Result<()> {
+    if !dotenv_found {
         info!("No .env file found, using system environment variables");
     }
 
+    if !needs_database {
+        info!("No database access needed for this run");
+        return Ok(());
+    }
+
+    // DATABASE_URL bypasses the encrypted connection string entirely
+    if env::var("DATABASE_URL").is_ok_and(|value| !value.trim().is_empty()) {
+        info!("Environment variable DATABASE_URL is set, skipping encrypted connection settings");
+        return Ok(());
+    }
+
+    // A secrets file replaces the encrypted connection env vars; its fields are validated on connect
+    if let Ok(path) = env::var("VW_SECRETS_FILE").map(|value| value.trim().to_string()) {
+        if !path.is_empty() {
+            info!("Environment variable VW_SECRETS_FILE is set, reading connection secrets from {}", path);
+            return Ok(());
+        }
+    }
+
     // Validate required environment variables
     let required_vars = ["SECRET_KEY1", "PG_API_CONNECTION"];
     
@@ -148,37 +1059,340 @@ fn load_environment_variables() -> Result<()> {
 }
 
 /// Main application logic with functional composition
-async fn run_application(config: AppConfig) -> Result<()> {
+/// Returns the process exit code chosen by the configured exit policy
+async fn run_application(config: AppConfig) -> Result<i32> {
     info!("Starting VW File Discover application");
     info!("Configuration: {:#?}", config);
 
-    // Create database connection pool
-    let pool = create_connection_pool().await?;
-    info!("Database connection established");
+    let metrics = Arc::new(Metrics::default());
+    let status_server = match &config.status_addr {
+        Some(addr) => Some(StatusServer::start(addr, Arc::clone(&metrics)).await?),
+        None => None,
+    };
 
-    // Phase 1: File copying (if not skipped)
-    let copy_report = copy_files_for_revendas(&pool, config.file_copy).await?;
-    print_copy_report(&copy_report);
+    let exit_policy = config.exit_policy;
+    let result = run_phases(config, &metrics).await;
 
-    // Phase 2: File discovery and registration (if not skipped)  
-    let discovery_report = discover_and_register_files(&pool, config.file_discovery).await?;
-    print_discovery_report(&discovery_report);
+    if let Some(server) = status_server {
+        server.shutdown().await;
+    }
+
+    let exit_code = result?.exit_code(&exit_policy);
+    if exit_code == EXIT_OK {
+        info!("Application completed successfully");
+    } else {
+        error!("Application completed with too many errors, exiting with code {}", exit_code);
+    }
+    Ok(exit_code)
+}
+
+/// Cancel `cancel` on the first Ctrl-C, so the phases start no new work and report what they did
+fn cancel_on_ctrl_c(cancel: CancellationToken) {
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            warn!("Interrupted: finishing the work in progress and starting no new work");
+            cancel.cancel();
+        }
+    });
+}
+
+/// Run the copy and discovery phases, recording progress in the shared metrics
+async fn run_phases(mut config: AppConfig, metrics: &Arc<Metrics>) -> Result<RunReport> {
+    let run_started = Instant::now();
+    let deadline = config.max_run_duration.map(Deadline::after);
+    config.file_copy.deadline = deadline;
+    config.file_discovery.deadline = deadline;
+    cancel_on_ctrl_c(config.file_copy.cancel.clone());
+    config.file_discovery.cancel = config.file_copy.cancel.clone();
+    // The phases count each file as they go, so /status shows progress mid-phase
+    config.file_copy.metrics = Arc::clone(metrics);
+    config.file_discovery.metrics = Arc::clone(metrics);
+
+    // Create database connection pool, unless no phase needs it
+    let pool = if config.needs_database() {
+        let pool = create_connection_pool_with(config.pool.clone()).await?;
+        health_check(&pool, config.file_discovery.conflict_key).await?;
+        info!("Database connection established");
+        Some(pool)
+    } else {
+        None
+    };
+    // One load of the revenda rows serves the readiness check and every phase of the run
+    let revendas = pool.clone().map(|pool| RevendaCache::new(pool, DEFAULT_REVENDA_CACHE_TTL));
+    let revendas = || revendas.as_ref().context("Database connection was not established");
+    let pool = || pool.as_ref().context("Database connection was not established");
+
+    let listed_paths = match (&config.file_list, &config.since_run) {
+        (Some(file_list), _) => Some(read_file_list(file_list)?),
+        (None, Some(run_id)) => {
+            let paths = file_trace::paths_since_run(pool()?, run_id).await?;
+            info!("Found {} files registered after run {}", paths.len(), run_id);
+            Some(paths.into_iter().map(std::path::PathBuf::from).collect())
+        }
+        (None, None) => None,
+    };
+
+    if let Some(paths) = listed_paths {
+        let discovery_report = run_file_list(pool()?, paths, config.file_discovery, metrics).await?;
+        let report = RunReport::new(None, Some(discovery_report)).with_timings(None, run_started.elapsed());
+        print_timings(&report.timings);
+        write_run_report(config.report_output.as_deref(), config.compress_output, &report)?;
+        return Ok(report);
+    }
+
+    // Report revendas that cannot be copied or scanned before either phase starts
+    if (!config.skip_copy && config.copy_mappings.is_empty()) || !config.skip_discovery {
+        print_revenda_readiness(&revendas()?.get_revendas().await?);
+    }
+
+    // Overlap copy and discovery per output folder when pipelining, else run them in turn
+    let pipelined = config.pipeline_revendas && !config.skip_copy && !config.skip_discovery && config.copy_mappings.is_empty();
+    let (copy_report, copy_elapsed, discovery_report) = if pipelined {
+        metrics.set_phase(RunPhase::Pipelined);
+        let (copy_report, discovery_report) = copy_and_discover_pipelined_with(
+            pool()?,
+            revendas()?,
+            config.file_copy,
+            config.file_discovery,
+            config.max_revendas_in_flight,
+        )
+        .await?;
+        print_copy_report(&copy_report);
+        print_discovery_report(&discovery_report);
+        // The phases overlap, so the copy has no duration of its own
+        (Some(copy_report), None, Some(discovery_report))
+    } else {
+        // Phase 1: File copying (if not skipped)
+        let mut copy_elapsed = None;
+        let copy_report = if config.skip_copy {
+            info!("Skipping copy phase");
+            None
+        } else {
+            metrics.set_phase(RunPhase::Copying);
+            let copy_started = Instant::now();
+            let copy_report = if config.copy_mappings.is_empty() {
+                copy_files_for_revendas_with(revendas()?, config.file_copy).await?
+            } else {
+                let (mappings, extensions) = (config.copy_mappings.clone(), config.copy_extensions.clone());
+                let copy_config = config.file_copy;
+                tokio::task::spawn_blocking(move || copy_files_with_mappings(&mappings, &extensions, &copy_config)).await?
+            };
+            copy_elapsed = Some(copy_started.elapsed());
+            print_copy_report(&copy_report);
+            Some(copy_report)
+        };
+
+        // Phase 2: File discovery and registration (if not skipped)  
+        let discovery_report = if config.skip_discovery {
+            info!("Skipping discovery phase");
+            None
+        } else {
+            metrics.set_phase(RunPhase::Discovering);
+            let discovery_report = discover_and_register_files_with(pool()?, revendas()?, config.file_discovery).await?;
+            print_discovery_report(&discovery_report);
+            Some(discovery_report)
+        };
+
+        (copy_report, copy_elapsed, discovery_report)
+    };
 
     // Final summary
-    print_final_summary(&copy_report, &discovery_report);
+    metrics.set_phase(RunPhase::Finished);
+    if let (Some(copy_report), Some(discovery_report)) = (&copy_report, &discovery_report) {
+        print_final_summary(copy_report, discovery_report);
+        print_reconciliation(&reconcile(copy_report, discovery_report));
+    }
+
+    let report = RunReport::new(copy_report, discovery_report).with_timings(copy_elapsed, run_started.elapsed());
+    print_timings(&report.timings);
+    write_run_report(config.report_output.as_deref(), config.compress_output, &report)?;
+    Ok(report)
+}
+
+/// Write the run report as JSON when `--report-output` is set
+fn write_run_report(path: Option<&std::path::Path>, compress: bool, report: &RunReport) -> Result<()> {
+    if let Some(path) = path {
+        let written = write_json_output(path, compress, report)?;
+        info!("Run report written to {}", written.display());
+    }
+    Ok(())
+}
+
+/// Register only the files named in a file list, skipping copy and directory scanning
+async fn run_file_list(
+    pool: &vw_file_discover::DbPool,
+    paths: Vec<std::path::PathBuf>,
+    config: FileDiscoveryConfig,
+    metrics: &Metrics,
+) -> Result<vw_file_discover::FileDiscoveryReport> {
+    info!("Skipping copy phase: registering {} listed files", paths.len());
+
+    metrics.set_phase(RunPhase::Discovering);
+    let discovery_report = discover_from_file_list(pool, paths, config).await?;
+    print_discovery_report(&discovery_report);
+
+    metrics.set_phase(RunPhase::Finished);
+    Ok(discovery_report)
+}
+
+/// Read the paths of a `--file-list` file
+fn read_file_list(file_list: &std::path::Path) -> Result<Vec<std::path::PathBuf>> {
+    let contents = std::fs::read_to_string(file_list)
+        .with_context(|| format!("Failed to read file list: {}", file_list.display()))?;
+    let paths = parse_file_list(&contents);
+    info!("Read {} files from {}", paths.len(), file_list.display());
+    Ok(paths)
+}
+
+/// Export file traces matching the subcommand filters to a CSV file
+async fn run_export(matches: &clap::ArgMatches) -> Result<()> {
+    let output = matches.get_one::<String>("output").unwrap();
+    let filter = create_trace_filter(matches);
+
+    let pool = create_connection_pool().await?;
+    let file = File::create(output)
+        .with_context(|| format!("Failed to create export file: {}", output))?;
+
+    let written = file_trace::export_csv(&pool, &filter, BufWriter::new(file)).await?;
+    info!("Exported {} file traces to {}", written, output);
+
+    Ok(())
+}
+
+/// List file traces matching the subcommand filters, or only count them
+async fn run_status(matches: &clap::ArgMatches) -> Result<()> {
+    let filter = create_trace_filter(matches);
+    let pool = create_connection_pool().await?;
+
+    let output = if matches.get_flag("count-only") {
+        format_trace_count(file_trace::count_by_status(&pool, &filter).await?)
+    } else {
+        let traces = file_trace::get_by_status_paged(&pool, &filter, create_page(matches)).await?;
+        format_trace_lines(&traces)
+    };
+    print!("{}", output);
+
+    Ok(())
+}
+
+/// Repair the traces matching the subcommand filters whose hash no longer matches their file,
+/// re-hashing with the processing settings and conflict key of a discovery run
+async fn run_repair(matches: &clap::ArgMatches, discovery: &FileDiscoveryConfig) -> Result<()> {
+    let filter = create_trace_filter(matches);
+    let pool = create_connection_pool().await?;
+
+    let report = repair_traces(
+        &pool,
+        &filter,
+        &discovery.processing,
+        discovery.conflict_key,
+        matches.get_flag("dry-run"),
+    )
+    .await?;
+    for (path, e) in &report.errors {
+        error!("Failed to repair trace for {}: {}", path, e);
+    }
+    print!("{}", format_repair_report(&report));
+
+    Ok(())
+}
+
+/// Pure function formatting the counts of a repair pass, one per line
+fn format_repair_report(report: &RepairReport) -> String {
+    let repaired = if report.dry_run { "Would repair" } else { "Repaired" };
+    format!(
+        "Examined: {}\n{}: {}\nUnchanged: {}\nSkipped: {}\nMissing: {}\nErrors: {}\n",
+        report.traces_examined,
+        repaired,
+        report.repaired.len(),
+        report.traces_unchanged,
+        report.traces_skipped,
+        report.missing_files.len(),
+        report.errors.len()
+    )
+}
+
+/// Print the revendas loaded from the database as JSON
+async fn run_revendas(matches: &clap::ArgMatches) -> Result<()> {
+    let pool = create_connection_pool().await?;
+    let output = revendas_json(&pool, matches.get_one::<i32>("only-dn").copied()).await?;
+    println!("{}", output);
 
-    info!("Application completed successfully");
     Ok(())
 }
 
+/// Load the revendas from `source`, optionally keeping only those with DN `only_dn`, as pretty JSON
+async fn revendas_json<S: RevendaSource>(source: &S, only_dn: Option<i32>) -> Result<String> {
+    let revendas: Vec<_> = source
+        .load_revendas()
+        .await?
+        .into_iter()
+        .filter(|revenda| only_dn.is_none_or(|dn| revenda.dn == dn))
+        .collect();
+
+    serde_json::to_string_pretty(&revendas).context("Failed to serialize revendas")
+}
+
+/// Pure function to create the page of traces requested by `--limit` and `--offset`
+fn create_page(matches: &clap::ArgMatches) -> Page {
+    Page {
+        limit: matches.get_one::<i64>("limit").copied(),
+        offset: matches.get_one::<i64>("offset").copied().unwrap_or(0),
+    }
+}
+
+/// Pure function formatting a trace count as a single line, for scripts
+fn format_trace_count(count: i64) -> String {
+    format!("{}\n", count)
+}
+
+/// Pure function formatting one tab-separated line per trace: id, statuses, DN and path
+fn format_trace_lines(traces: &[vw_file_discover::FileTrace]) -> String {
+    traces
+        .iter()
+        .map(|trace| {
+            format!(
+                "{}\t{}\t{}\t{}\t{}\t{}\n",
+                trace.id.unwrap_or_default(),
+                trace.status_fvw,
+                trace.status_fnt,
+                trace.status_fa4,
+                trace.dn,
+                trace.path
+            )
+        })
+        .collect()
+}
+
+/// Pure function counting skipped files per reason, in reason order
+fn count_skip_reasons(report: &vw_file_discover::FileCopyReport) -> std::collections::BTreeMap<&str, usize> {
+    report.skipped.iter().fold(std::collections::BTreeMap::new(), |mut counts, skip| {
+        *counts.entry(skip.reason.as_str()).or_insert(0) += 1;
+        counts
+    })
+}
+
 /// Print file copy report in a functional manner
 fn print_copy_report(report: &vw_file_discover::FileCopyReport) {
     info!("=== FILE COPY REPORT ===");
     info!("Total files processed: {}", report.total_processed());
     info!("Successfully copied: {}", report.successful_copies);
-    info!("Skipped files: {}", report.skipped_files);
+    info!("  Overwritten existing files: {}", report.overwritten_files);
+    info!("Skipped files: {}", report.skipped_files());
+    for (reason, count) in count_skip_reasons(report) {
+        info!("  {}: {}", reason, count);
+    }
     info!("Copy errors: {}", report.errors.len());
     info!("Success rate: {:.2}%", report.success_rate() * 100.0);
+    if report.aborted {
+        error!("Copy phase aborted early: too many copy errors");
+    }
+    if report.time_limited {
+        warn!("Copy phase stopped early: --max-run-duration was reached");
+    }
+    if report.cancelled {
+        warn!("Copy phase stopped early: the run was interrupted");
+    }
 
     if !report.errors.is_empty() {
         error!("Copy errors encountered:");
@@ -191,12 +1405,107 @@ fn print_copy_report(report: &vw_file_discover::FileCopyReport) {
 /// Print file discovery report in a functional manner
 fn print_discovery_report(report: &vw_file_discover::FileDiscoveryReport) {
     info!("=== FILE DISCOVERY REPORT ===");
+    if let Some(run_id) = &report.run_id {
+        info!("Run id: {} ({} ms)", run_id, report.run_duration_ms);
+    }
     info!("Files discovered: {}", report.files_discovered);
+    if report.zero_byte_skipped > 0 {
+        info!("Zero-byte files skipped: {}", report.zero_byte_skipped);
+    }
+    if report.time_limited {
+        warn!("Discovery stopped early: --max-run-duration was reached");
+    }
+    if report.cancelled {
+        warn!("Discovery stopped early: the run was interrupted");
+    }
+    if !report.unreachable_directories.is_empty() {
+        warn!("Unreachable directories skipped: {}", report.unreachable_directories.len());
+    }
     info!("Files processed: {}", report.files_processed);
     info!("Files saved to database: {}", report.files_saved);
     info!("Processing errors: {}", report.processing_errors);
+    for (kind, count) in &report.errors_by_kind {
+        info!("  {:?}: {}", kind, count);
+    }
     info!("Processing success rate: {:.2}%", report.success_rate() * 100.0);
     info!("Database save rate: {:.2}%", report.save_rate() * 100.0);
+    if report.files_deleted > 0 {
+        info!("Source files deleted after registration: {}", report.files_deleted);
+    }
+    if report.files_filtered_out > 0 {
+        info!("Files filtered out by date, size or custom filters: {}", report.files_filtered_out);
+    }
+    for collision in &report.hash_collisions {
+        warn!(
+            "  Possible hash collision: {} vs registered {} (hash {})",
+            collision.path, collision.existing_path, collision.hash
+        );
+    }
+    for (path, size) in &report.top_largest {
+        info!("  Largest: {} ({} bytes)", path, size);
+    }
+    for (path, modified_at) in &report.oldest {
+        info!("  Oldest: {} (modified {})", path, modified_at.to_rfc3339());
+    }
+    if report.files_changed_before_save > 0 {
+        info!("Files skipped because they changed before save: {}", report.files_changed_before_save);
+    }
+    if report.files_failed_to_save > 0 {
+        error!("Files not registered because their batch failed to save: {}", report.files_failed_to_save);
+    }
+    if report.files_banned > 0 {
+        info!("Files registered as banned: {}", report.files_banned);
+    }
+    for (id, old_status, new_status) in &report.status_changes {
+        info!("  Trace {}: status {} -> {}", id, old_status, new_status);
+    }
+    for directory in &report.scanned_directories {
+        match &directory.error {
+            Some(error) => info!("  Directory {}: scan failed: {}", directory.path, error),
+            None => info!(
+                "  Directory {}: {} files ({} filtered out)",
+                directory.path, directory.file_count, directory.filtered_out
+            ),
+        }
+    }
+}
+
+/// Print how many revendas are ready to copy and scan, and why the others are not
+fn print_revenda_readiness(revendas: &[vw_file_discover::models::FvwArqDiarioExt]) {
+    let mut ready = 0;
+    for revenda in revendas {
+        let readiness = revenda.readiness();
+        if readiness.is_ready() {
+            ready += 1;
+        } else {
+            warn!(
+                "Revenda {}/{} (DN {}) is not ready: {}",
+                revenda.empresa,
+                revenda.revenda,
+                revenda.dn,
+                readiness.problems().join(", ")
+            );
+        }
+    }
+    info!("Revendas ready to copy and scan: {}/{}", ready, revendas.len());
+}
+
+/// Print how long each phase of the run took
+fn print_timings(timings: &RunTimings) {
+    info!("=== PHASE TIMINGS ===");
+    info!("Copy: {:.1} ms", timings.copy_ms);
+    info!("Discovery scan: {:.1} ms", timings.discovery_scan_ms);
+    info!("Discovery hash: {:.1} ms", timings.discovery_hash_ms);
+    info!("Discovery save: {:.1} ms", timings.discovery_save_ms);
+    info!("Total: {:.1} ms", timings.total_ms);
+}
+
+/// Warn about copied files that discovery never saw
+fn print_reconciliation(report: &vw_file_discover::services::ReconciliationReport) {
+    for path in &report.copied_not_discovered {
+        warn!("Copied file was not discovered (is its output directory scanned?): {}", path);
+    }
+    info!("Discovered files not copied by this run: {}", report.discovered_not_copied.len());
 }
 
 /// Print final application summary
@@ -212,3 +1521,166 @@ fn print_final_summary(
     let total_success = copy_report.successful_copies + discovery_report.files_saved;
     info!("Total successful operations: {}", total_success);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vw_file_discover::services::RepairedTrace;
+
+    fn status_matches(args: &[&str]) -> clap::ArgMatches {
+        let matches = build_cli()
+            .try_get_matches_from(["vw-file-discover", "status"].iter().chain(args))
+            .unwrap();
+        matches.subcommand_matches("status").unwrap().clone()
+    }
+
+    #[test]
+    fn test_status_pagination_arguments() {
+        let matches = status_matches(&["--status-fvw", "3", "--limit", "50", "--offset", "100"]);
+        assert_eq!(create_page(&matches), Page { limit: Some(50), offset: 100 });
+        assert_eq!(create_trace_filter(&matches).status_fvw, Some(3));
+
+        assert_eq!(create_page(&status_matches(&[])), Page { limit: None, offset: 0 });
+
+        let rejected = |args: &[&str]| {
+            build_cli()
+                .try_get_matches_from(["vw-file-discover", "status"].iter().chain(args))
+                .is_err()
+        };
+        assert!(rejected(&["--limit", "-1"]));
+        assert!(rejected(&["--count-only", "--limit", "5"]));
+    }
+
+    /// Revenda source returning a fixed list, standing in for the database
+    struct StaticRevendas(Vec<vw_file_discover::FvwArqDiarioExt>);
+
+    impl RevendaSource for StaticRevendas {
+        async fn load_revendas(&self) -> Result<Vec<vw_file_discover::FvwArqDiarioExt>> {
+            Ok(self.0.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_revendas_subcommand_prints_filtered_json() {
+        let source = StaticRevendas(vec![
+            vw_file_discover::FvwArqDiarioExt::new(1, 10, ".txt".into(), 111, "/in/10".into(), "/out/10".into()),
+            vw_file_discover::FvwArqDiarioExt::new(1, 20, ".csv".into(), 222, "/in/20".into(), "/out/20".into()),
+        ]);
+        let matches = build_cli()
+            .try_get_matches_from(["vw-file-discover", "revendas", "--only-dn", "222"])
+            .unwrap();
+        let only_dn = matches.subcommand_matches("revendas").unwrap().get_one::<i32>("only-dn").copied();
+
+        let output = revendas_json(&source, only_dn).await.unwrap();
+        let printed: serde_json::Value = serde_json::from_str(&output).unwrap();
+
+        assert_eq!(printed.as_array().unwrap().len(), 1);
+        assert_eq!(printed[0]["revenda"], 20);
+        assert_eq!(printed[0]["extensao"], ".csv");
+        assert_eq!(printed[0]["pasta_output"], "/out/20");
+
+        let all: serde_json::Value = serde_json::from_str(&revendas_json(&source, None).await.unwrap()).unwrap();
+        assert_eq!(all.as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_hmac_hash_mode_requires_a_key() {
+        assert_eq!(create_hash_mode(Some("sha256"), None).unwrap(), HashMode::Full);
+        assert_eq!(
+            create_hash_mode(Some("hmac-sha256"), Some("secret".to_string())).unwrap(),
+            HashMode::HmacSha256 { key: HmacKey::new("secret") }
+        );
+        assert!(create_hash_mode(Some("hmac-sha256"), None).is_err());
+        assert!(create_hash_mode(Some("hmac-sha256"), Some(String::new())).is_err());
+    }
+
+    #[test]
+    fn test_format_repair_report_prints_every_count() {
+        let report = RepairReport {
+            traces_examined: 5,
+            repaired: vec![RepairedTrace {
+                id: 1,
+                path: "/out/a.txt".to_string(),
+                stored_hash: "old".to_string(),
+                file_hash: "new".to_string(),
+            }],
+            traces_unchanged: 2,
+            traces_skipped: 1,
+            missing_files: Vec::new(),
+            errors: vec![("/out/b.txt".to_string(), "unreadable".to_string())],
+            dry_run: true,
+        };
+
+        assert_eq!(
+            format_repair_report(&report),
+            "Examined: 5\nWould repair: 1\nUnchanged: 2\nSkipped: 1\nMissing: 0\nErrors: 1\n"
+        );
+        let applied = RepairReport { dry_run: false, ..report };
+        assert!(format_repair_report(&applied).contains("\nRepaired: 1\n"));
+    }
+
+    #[test]
+    fn test_tenant_fits_the_tenant_column() {
+        let longest = "t".repeat(MAX_TENANT_LEN);
+        let matches = build_cli().try_get_matches_from(["vw-file-discover", "--tenant", &longest]).unwrap();
+        assert_eq!(matches.get_one::<String>("tenant"), Some(&longest));
+
+        let too_long = "t".repeat(MAX_TENANT_LEN + 1);
+        assert!(build_cli().try_get_matches_from(["vw-file-discover", "--tenant", &too_long]).is_err());
+        assert!(build_cli().try_get_matches_from(["vw-file-discover", "--tenant", ""]).is_err());
+        assert!(build_cli().try_get_matches_from(["vw-file-discover", "status", "--tenant", &too_long]).is_err());
+    }
+
+    #[test]
+    fn test_hash_truncate_len_keeps_at_least_one_char() {
+        let parse = |len: &str| build_cli().try_get_matches_from(["vw-file-discover", "--hash-truncate-len", len]);
+        assert_eq!(parse("1").unwrap().get_one::<usize>("hash-truncate-len"), Some(&1));
+        assert_eq!(parse("64").unwrap().get_one::<usize>("hash-truncate-len"), Some(&64));
+        assert!(parse("0").is_err());
+        assert!(parse("65").is_err());
+    }
+
+    #[test]
+    fn test_days_back_is_bounded() {
+        let parse = |window: &str| build_cli().try_get_matches_from(["vw-file-discover", "--days-back", window]);
+        let days_back = |window: &str| *parse(window).unwrap().get_one::<chrono::Duration>("days-back").unwrap();
+        assert_eq!(days_back("36500"), chrono::Duration::days(MAX_LOOK_BACK_DAYS));
+        assert_eq!(days_back("6h"), chrono::Duration::hours(6));
+        assert!(parse("36501").is_err());
+        assert!(parse("1e300").is_err());
+        assert!(parse("9223372036854775807d").is_err());
+    }
+
+    #[test]
+    fn test_ban_pattern_must_be_a_valid_glob() {
+        let parse = |pattern: &str| build_cli().try_get_matches_from(["vw-file-discover", "--ban-pattern", pattern]);
+        assert!(parse("skip_[0-9]*").is_ok());
+        assert!(parse("skip_[").is_err());
+    }
+
+    #[test]
+    fn test_quick_hash_reads_at_least_one_byte() {
+        let parse = |bytes: &str| build_cli().try_get_matches_from(["vw-file-discover", "--quick-hash-bytes", bytes]);
+        assert_eq!(parse("1").unwrap().get_one::<u64>("quick-hash-bytes"), Some(&1));
+        assert!(parse("0").is_err());
+    }
+
+    #[test]
+    fn test_max_error_rate_is_a_percentage() {
+        let parse = |rate: &str| build_cli().try_get_matches_from(["vw-file-discover", "--max-error-rate", rate]);
+
+        assert_eq!(parse("0").unwrap().get_one::<f64>("max-error-rate"), Some(&0.0));
+        assert_eq!(parse("100").unwrap().get_one::<f64>("max-error-rate"), Some(&100.0));
+        assert!(parse("150").is_err());
+        assert!(parse("-1").is_err());
+        assert!(parse("NaN").is_err());
+    }
+
+    #[test]
+    fn test_count_only_prints_just_the_number() {
+        let matches = status_matches(&["--count-only", "--dn", "12345"]);
+        assert!(matches.get_flag("count-only"));
+        assert_eq!(create_trace_filter(&matches).dn, Some(12345));
+        assert_eq!(format_trace_count(42), "42\n");
+    }
+}
@@ -0,0 +1,167 @@
+use crate::models::{
+    hash_reader, path_hash_result, process_reader, resolve_file_times, FileProcessingConfig, FileTrace, HashMode,
+};
+use crate::utils::{Clock, SystemClock};
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::path::Path;
+
+/// Separator between the archive path and the entry name in synthetic trace paths
+pub const ARCHIVE_ENTRY_SEPARATOR: &str = "!";
+
+/// Check whether a path points at a zip archive (by extension)
+/// Pure function
+pub fn is_zip_archive(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+}
+
+/// Build the synthetic path of an archive entry, e.g. `daily.zip!data/file.txt`
+/// Pure function
+pub fn archive_entry_path(archive_path: &Path, entry_name: &str) -> String {
+    format!("{}{}{}", archive_path.to_string_lossy(), ARCHIVE_ENTRY_SEPARATOR, entry_name)
+}
+
+/// Check whether a trace path refers to an entry inside a zip archive
+/// Pure function
+pub fn is_archive_entry_path(path: &str) -> bool {
+    path.to_lowercase().contains(&format!(".zip{}", ARCHIVE_ENTRY_SEPARATOR))
+}
+
+/// Create one FileTrace per file entry in a zip archive
+/// Each entry is hashed and line-counted over its decompressed content;
+/// timestamps are taken from the archive file itself.
+pub fn create_file_traces_from_zip<P: AsRef<Path>>(
+    archive_path: P,
+    config: &FileProcessingConfig,
+) -> Result<Vec<FileTrace>> {
+    create_file_traces_from_zip_with(&SystemClock, archive_path, config)
+}
+
+/// `create_file_traces_from_zip` with `processed_at` taken from `clock`
+pub fn create_file_traces_from_zip_with<C: Clock + ?Sized, P: AsRef<Path>>(
+    clock: &C,
+    archive_path: P,
+    config: &FileProcessingConfig,
+) -> Result<Vec<FileTrace>> {
+    let path = archive_path.as_ref();
+    let file = File::open(path).with_context(|| format!("Failed to open archive: {:?}", path))?;
+    let metadata = file
+        .metadata()
+        .with_context(|| format!("Failed to read metadata for: {:?}", path))?;
+    let mut archive = zip::ZipArchive::new(file)
+        .with_context(|| format!("Failed to read zip archive: {:?}", path))?;
+
+    let (created_at, modified_at) = resolve_file_times(metadata.created().ok(), metadata.modified().ok());
+    let processed_at = clock.now();
+
+    let mut traces = Vec::with_capacity(archive.len());
+
+    for index in 0..archive.len() {
+        let entry = archive
+            .by_index(index)
+            .with_context(|| format!("Failed to read entry {} of {:?}", index, path))?;
+
+        if entry.is_dir() {
+            continue;
+        }
+
+        let entry_name = entry.name().to_string();
+        let size_bytes = entry.size() as i64;
+        let name = Path::new(&entry_name)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("unknown")
+            .to_string();
+
+        // Entries cannot be sampled without decompressing them, so they are always fully hashed
+        let full_hash = match config.hash_mode {
+            HashMode::Quick { .. } => FileProcessingConfig {
+                hash_mode: HashMode::Full,
+                ..config.clone()
+            },
+            _ => config.clone(),
+        };
+        let entry_path = archive_entry_path(path, &entry_name);
+        let processing_result = if config.hash_mode == HashMode::MetadataOnly {
+            Ok(path_hash_result(&entry_path, config))
+        } else if config.counts_lines_for(Path::new(&entry_name)) {
+            process_reader(entry, &full_hash)
+        } else {
+            hash_reader(entry, &full_hash)
+        }
+        .with_context(|| format!("Failed to process {} in {:?}", entry_name, path))?;
+
+        traces.push(FileTrace {
+            count_mismatch: processing_result.count_mismatch,
+            hash_algorithm: processing_result.hash_algorithm.to_string(),
+            region: processing_result.region,
+            first_record_field_count: processing_result.first_record_field_count,
+            ..FileTrace::new(
+                name,
+                entry_path,
+                processing_result.hash,
+                size_bytes,
+                processing_result.total_lines,
+                created_at,
+                modified_at,
+                processed_at,
+                processing_result.dn,
+            )
+        });
+    }
+
+    Ok(traces)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::process_file_one_pass;
+    use std::io::Write;
+
+    fn write_zip(path: &Path, entries: &[(&str, &str)]) {
+        let mut writer = zip::ZipWriter::new(File::create(path).unwrap());
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated);
+
+        for (name, content) in entries {
+            writer.start_file(*name, options).unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        writer.add_directory("nested/", options).unwrap();
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn test_zip_with_two_entries_produces_two_traces() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("daily.zip");
+        write_zip(&archive, &[("a.txt", "one\ntwo\n"), ("nested/b.txt", "three\n")]);
+
+        let traces = create_file_traces_from_zip(&archive, &FileProcessingConfig::default()).unwrap();
+
+        assert_eq!(traces.len(), 2);
+        assert_eq!(traces[0].name, "a.txt");
+        assert_eq!(traces[0].path, format!("{}!a.txt", archive.display()));
+        assert_eq!(traces[0].total_lines, 2);
+        assert_eq!(traces[0].size_bytes, 8);
+        assert_eq!(traces[1].name, "b.txt");
+        assert_eq!(traces[1].path, format!("{}!nested/b.txt", archive.display()));
+        assert_eq!(traces[1].total_lines, 1);
+
+        // Entry hashes match hashing the same content as a plain file
+        let plain = dir.path().join("a.txt");
+        std::fs::write(&plain, "one\ntwo\n").unwrap();
+        assert_eq!(traces[0].hash, process_file_one_pass(&plain).unwrap().hash);
+    }
+
+    #[test]
+    fn test_archive_path_helpers() {
+        assert!(is_zip_archive(Path::new("/in/DAILY.ZIP")));
+        assert!(!is_zip_archive(Path::new("/in/daily.txt")));
+        assert!(is_archive_entry_path("/in/daily.zip!a.txt"));
+        assert!(!is_archive_entry_path("/in/hello!.txt"));
+    }
+}
@@ -1,10 +1,13 @@
 use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use md5::Md5;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::fs::File;
-use std::io::Read;
-use std::path::Path;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
 use anyhow::{Context, Result};
+use crate::utils::{matches_extensions, Clock, FileSystem, FutureMtimePolicy, OsFileSystem, SystemClock};
 
 /// File trace status enumeration
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -46,10 +49,25 @@ pub struct FileTrace {
     pub status_fnt: i32,
     pub status_fa4: i32,
     pub dn: i32,
+    /// The FTR trailer declared a record count different from `total_lines`
+    pub count_mismatch: bool,
+    /// How `hash` was computed (`HASH_ALGORITHM_SHA256`, `HASH_ALGORITHM_QUICK`, `HASH_ALGORITHM_HMAC_SHA256`
+    /// or `HASH_ALGORITHM_PATH`)
+    pub hash_algorithm: String,
+    /// Secondary header field forming the key together with `dn`, when a `RegionSpec` is configured
+    pub region: Option<String>,
+    /// Discovery run that registered this trace (see `discover_and_register_files`)
+    pub run_id: Option<String>,
+    /// Tenant the registering run was started for (see `FileDiscoveryConfig::tenant`)
+    pub tenant: Option<String>,
+    /// Fields in the first data line after the header, when a field delimiter is configured
+    pub first_record_field_count: Option<i32>,
 }
 
 impl FileTrace {
     /// Create a new FileTrace with default values
+    /// `processed_at` comes from the caller's `Clock`
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         name: String,
         path: String,
@@ -58,6 +76,7 @@ impl FileTrace {
         total_lines: i32,
         created_at: DateTime<Utc>,
         modified_at: DateTime<Utc>,
+        processed_at: DateTime<Utc>,
         dn: i32,
     ) -> Self {
         Self {
@@ -70,11 +89,253 @@ impl FileTrace {
             total_lines,
             created_at,
             modified_at,
-            processed_at: Utc::now(),
+            processed_at,
             status_fvw: FileTraceStatus::Pending as i32,
             status_fnt: FileTraceStatus::Pending as i32,
             status_fa4: FileTraceStatus::Pending as i32,
             dn,
+            count_mismatch: false,
+            hash_algorithm: HASH_ALGORITHM_SHA256.to_string(),
+            region: None,
+            run_id: None,
+            tenant: None,
+            first_record_field_count: None,
+        }
+    }
+}
+
+/// SHA-256 over the full file content
+pub const HASH_ALGORITHM_SHA256: &str = "sha256";
+/// MD5 over the full file content, for systems keyed on legacy MD5 digests
+pub const HASH_ALGORITHM_MD5: &str = "md5";
+/// BLAKE3 over the full file content
+pub const HASH_ALGORITHM_BLAKE3: &str = "blake3";
+/// SHA-256 over the first and last bytes plus the size (see `HashMode::Quick`)
+pub const HASH_ALGORITHM_QUICK: &str = "quick";
+/// HMAC-SHA256 over the full file content with a secret key (see `HashMode::HmacSha256`)
+pub const HASH_ALGORITHM_HMAC_SHA256: &str = "hmac-sha256";
+/// SHA-256 of the normalized path, the content is not read (see `HashMode::MetadataOnly`)
+pub const HASH_ALGORITHM_PATH: &str = "path-sha256";
+
+/// Minimum truncated hash length (hex chars) considered safe against collisions
+pub const MIN_SAFE_HASH_LEN: usize = 16;
+
+/// Default read buffer size for buffered file processing
+pub const DEFAULT_READ_BUFFER_SIZE: usize = 128 * 1024;
+
+/// Configuration for single-pass file processing
+#[derive(Debug, Clone, Default)]
+pub struct FileProcessingConfig {
+    /// Truncate the hex digest to this many characters before storing (None keeps the full digest)
+    pub hash_truncate_len: Option<usize>,
+    /// Buffer size for buffered reads (None uses `DEFAULT_READ_BUFFER_SIZE`)
+    pub read_buffer_size: Option<usize>,
+    /// Memory-map files larger than this many bytes instead of reading them in chunks
+    pub use_mmap_threshold: Option<u64>,
+    /// Validate the declared record count of a trailer line against `total_lines`
+    pub trailer: Option<TrailerSpec>,
+    /// Full content hashing (default) or a quick partial hash for huge files
+    pub hash_mode: HashMode,
+    /// Digest of the full content with `HashMode::Full`; quick and HMAC hashes are always SHA-256
+    pub hash_algorithm: HashAlgorithm,
+    /// Also capture a region code from the header line (None keeps DN-only extraction)
+    pub region: Option<RegionSpec>,
+    /// Which lines count toward `total_lines`
+    pub count_mode: LineCountMode,
+    /// Count the fields of the first data line split on this delimiter (None skips the count)
+    pub field_delimiter: Option<char>,
+    /// Take the DN and expected line count from a `<file>.meta` sidecar when one exists
+    pub read_sidecar_meta: bool,
+    /// Whitespace trimmed from the header line before the DN and region are extracted
+    pub trim_first_line: TrimMode,
+    /// How a modification time in the future is stored: as is, clamped to now, or rejected
+    pub future_mtime_policy: FutureMtimePolicy,
+    /// Only count lines and read the header (DN, region, trailer) of files with these
+    /// extensions; other files are hashed only (None processes every file fully)
+    pub count_lines_extensions: Option<Vec<String>>,
+}
+
+impl FileProcessingConfig {
+    /// Whether `path` is line-counted and its header read, per `count_lines_extensions`
+    /// Pure function
+    pub fn counts_lines_for(&self, path: &Path) -> bool {
+        self.count_lines_extensions
+            .as_ref()
+            .is_none_or(|extensions| matches_extensions(path, extensions))
+    }
+}
+
+/// Whitespace trimmed from the header line; a trailing carriage return is always dropped
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TrimMode {
+    /// Keep the line as read
+    #[default]
+    None,
+    /// Trim trailing whitespace only, keeping the DN offsets
+    End,
+    /// Also trim leading whitespace, so the header prefix and the offsets after it are
+    /// found from the first non-blank character of padded headers
+    Both,
+}
+
+impl TrimMode {
+    /// Pure function applying the mode to a header line
+    pub fn apply(self, line: &str) -> &str {
+        match self {
+            TrimMode::None => line,
+            TrimMode::End => line.trim_end(),
+            TrimMode::Both => line.trim(),
+        }
+    }
+}
+
+/// Which lines count toward `total_lines`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineCountMode {
+    /// Every line break
+    #[default]
+    All,
+    /// Only lines holding something other than whitespace
+    NonBlank,
+}
+
+/// How file content is hashed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum HashMode {
+    /// SHA-256 over the whole content
+    #[default]
+    Full,
+    /// SHA-256 over `first_n || last_n || size.to_le_bytes()`
+    ///
+    /// Much faster for multi-GB files, but only a change-detection heuristic: files that
+    /// differ only outside the sampled bytes and have the same size get the same hash.
+    /// Lines are not counted (`total_lines` is 0), trailers are not validated and
+    /// the first record's fields are not counted.
+    /// Archive entries cannot be sampled and are always fully hashed.
+    Quick { bytes: u64 },
+    /// HMAC-SHA256 over the whole content, so hashes cannot be forged without the key
+    HmacSha256 { key: HmacKey },
+    /// SHA-256 of the normalized path (see `path_hash`) instead of the content, which is
+    /// never read: a deterministic key for deduplicating by location only. Lines, DN,
+    /// region and trailer are left unset, though a sidecar can still supply the DN.
+    MetadataOnly,
+}
+
+/// Digest computed over the full content by `HashMode::Full`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    /// 32 hex chars; only for downstream systems keyed on MD5, it is not collision resistant
+    Md5,
+    /// Same length as SHA-256 and much faster on large files
+    Blake3,
+}
+
+impl HashAlgorithm {
+    /// Name stored in `FileTrace::hash_algorithm`
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => HASH_ALGORITHM_SHA256,
+            HashAlgorithm::Md5 => HASH_ALGORITHM_MD5,
+            HashAlgorithm::Blake3 => HASH_ALGORITHM_BLAKE3,
+        }
+    }
+
+    /// Pure function parsing a stored or command line name (None for quick and keyed hashes)
+    pub fn from_name(name: &str) -> Option<Self> {
+        [HashAlgorithm::Sha256, HashAlgorithm::Md5, HashAlgorithm::Blake3]
+            .into_iter()
+            .find(|algorithm| algorithm.name() == name)
+    }
+}
+
+/// Secret key for `HashMode::HmacSha256`, redacted from debug output
+#[derive(Clone, PartialEq, Eq)]
+pub struct HmacKey(Vec<u8>);
+
+impl HmacKey {
+    pub fn new(key: impl Into<Vec<u8>>) -> Self {
+        Self(key.into())
+    }
+}
+
+impl std::fmt::Debug for HmacKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("HmacKey(<redacted>)")
+    }
+}
+
+/// Hasher of the full content in the single read pass
+enum ContentHasher {
+    Sha256(Sha256),
+    Md5(Md5),
+    Blake3(Box<blake3::Hasher>),
+    HmacSha256(Hmac<Sha256>),
+}
+
+impl ContentHasher {
+    fn new(config: &FileProcessingConfig) -> Self {
+        match (&config.hash_mode, config.hash_algorithm) {
+            (HashMode::HmacSha256 { key }, _) => ContentHasher::HmacSha256(
+                Hmac::new_from_slice(&key.0).expect("HMAC accepts keys of any length"),
+            ),
+            (HashMode::Full, HashAlgorithm::Md5) => ContentHasher::Md5(Md5::new()),
+            (HashMode::Full, HashAlgorithm::Blake3) => ContentHasher::Blake3(Box::default()),
+            (HashMode::Full, HashAlgorithm::Sha256) | (HashMode::Quick { .. } | HashMode::MetadataOnly, _) => {
+                ContentHasher::Sha256(Sha256::new())
+            }
+        }
+    }
+
+    fn update(&mut self, chunk: &[u8]) {
+        match self {
+            ContentHasher::Sha256(hasher) => Digest::update(hasher, chunk),
+            ContentHasher::Md5(hasher) => Digest::update(hasher, chunk),
+            ContentHasher::Blake3(hasher) => {
+                hasher.update(chunk);
+            }
+            ContentHasher::HmacSha256(mac) => Mac::update(mac, chunk),
+        }
+    }
+
+    fn algorithm(&self) -> &'static str {
+        match self {
+            ContentHasher::Sha256(_) => HASH_ALGORITHM_SHA256,
+            ContentHasher::Md5(_) => HASH_ALGORITHM_MD5,
+            ContentHasher::Blake3(_) => HASH_ALGORITHM_BLAKE3,
+            ContentHasher::HmacSha256(_) => HASH_ALGORITHM_HMAC_SHA256,
+        }
+    }
+
+    /// Hex digest of everything fed so far
+    fn finalize_hex(self) -> String {
+        match self {
+            ContentHasher::Sha256(hasher) => format!("{:x}", hasher.finalize()),
+            ContentHasher::Md5(hasher) => format!("{:x}", hasher.finalize()),
+            ContentHasher::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+            ContentHasher::HmacSha256(mac) => format!("{:x}", mac.finalize().into_bytes()),
+        }
+    }
+}
+
+/// Where to find the declared record count inside the last line of a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrailerSpec {
+    /// Required prefix of the trailer line (e.g. "FTR")
+    pub prefix: String,
+    /// 0-based character offset of the record count
+    pub offset: usize,
+    /// Number of characters holding the record count
+    pub length: usize,
+}
+
+impl Default for TrailerSpec {
+    fn default() -> Self {
+        Self {
+            prefix: "FTR".to_string(),
+            offset: 3,
+            length: 9,
         }
     }
 }
@@ -85,15 +346,59 @@ pub struct FileProcessingResult {
     pub hash: String,
     pub dn: i32,
     pub total_lines: i32,
+    /// Set when a trailer spec is configured and the declared count disagrees with `total_lines`
+    pub count_mismatch: bool,
+    /// How `hash` was computed
+    pub hash_algorithm: &'static str,
+    /// Region code from the first line, when a region spec is configured
+    pub region: Option<String>,
+    /// Fields in the first non-blank line after the header, when a field delimiter is configured
+    pub first_record_field_count: Option<i32>,
 }
 
 /// Pure functional approach to create FileTrace from file path
 pub fn create_file_trace_from_path<P: AsRef<Path>>(file_path: P) -> Result<FileTrace> {
+    create_file_trace_from_path_with(file_path, &FileProcessingConfig::default())
+}
+
+/// Create a FileTrace from a file path using the given processing configuration
+pub fn create_file_trace_from_path_with<P: AsRef<Path>>(
+    file_path: P,
+    config: &FileProcessingConfig,
+) -> Result<FileTrace> {
+    create_file_trace_with_fs(&OsFileSystem, &SystemClock, file_path, config)
+}
+
+/// Create a FileTrace reading size and timestamps through the given filesystem
+/// `processed_at` is taken from `clock`
+pub fn create_file_trace_with_fs<F: FileSystem + ?Sized, C: Clock + ?Sized, P: AsRef<Path>>(
+    file_system: &F,
+    clock: &C,
+    file_path: P,
+    config: &FileProcessingConfig,
+) -> Result<FileTrace> {
     let path = file_path.as_ref();
-    let metadata = std::fs::metadata(path)
+    let stat = file_system
+        .stat(path)
         .with_context(|| format!("Failed to read metadata for: {:?}", path))?;
+
+    let (created_at, modified_at) = resolve_file_times(stat.created, stat.modified);
+    let now = clock.now();
+    let Some(modified_at) = config.future_mtime_policy.apply(modified_at, now) else {
+        anyhow::bail!("Modification time {} of {:?} is in the future", modified_at, path);
+    };
+    let created_at = config.future_mtime_policy.apply(created_at, now).unwrap_or(created_at);
     
-    let processing_result = process_file_one_pass(path)?;
+    let processing_result = process_file_one_pass_with(path, config)?;
+    let sidecar = if config.read_sidecar_meta { read_sidecar_meta(file_system, path)? } else { None };
+    let processing_result = match sidecar {
+        // Hash-only and metadata-only files have no line count to check the expected lines against
+        Some(meta) if !config.counts_lines_for(path) || config.hash_mode == HashMode::MetadataOnly => {
+            apply_sidecar_meta(processing_result, &SidecarMeta { expected_lines: None, ..meta })
+        }
+        Some(meta) => apply_sidecar_meta(processing_result, &meta),
+        None => processing_result,
+    };
     
     let name = path
         .file_name()
@@ -103,95 +408,556 @@ pub fn create_file_trace_from_path<P: AsRef<Path>>(file_path: P) -> Result<FileT
     
     let path_str = path.to_string_lossy().to_string();
     
-    let created_at = metadata_to_datetime(metadata.created().ok());
-    let modified_at = metadata_to_datetime(metadata.modified().ok());
-    
-    Ok(FileTrace::new(
-        name,
-        path_str,
-        processing_result.hash,
-        metadata.len() as i64,
-        processing_result.total_lines,
-        created_at,
-        modified_at,
-        processing_result.dn,
-    ))
+    Ok(FileTrace {
+        count_mismatch: processing_result.count_mismatch,
+        hash_algorithm: processing_result.hash_algorithm.to_string(),
+        region: processing_result.region,
+        first_record_field_count: processing_result.first_record_field_count,
+        ..FileTrace::new(
+            name,
+            path_str,
+            processing_result.hash,
+            stat.len as i64,
+            processing_result.total_lines,
+            created_at,
+            modified_at,
+            now,
+            processing_result.dn,
+        )
+    })
 }
 
 /// Process file in one pass to get hash, DN from first line, and line count
 /// Pure function with no side effects
 pub fn process_file_one_pass<P: AsRef<Path>>(file_path: P) -> Result<FileProcessingResult> {
-    let mut file = File::open(file_path.as_ref())
+    process_file_one_pass_with(file_path, &FileProcessingConfig::default())
+}
+
+/// Process file in one pass using the given processing configuration
+/// Files above `use_mmap_threshold` are memory-mapped, falling back to buffered reads if mapping fails
+pub fn process_file_one_pass_with<P: AsRef<Path>>(
+    file_path: P,
+    config: &FileProcessingConfig,
+) -> Result<FileProcessingResult> {
+    if config.hash_mode == HashMode::MetadataOnly {
+        return Ok(path_hash_result(&file_path.as_ref().to_string_lossy(), config));
+    }
+
+    let file = File::open(file_path.as_ref())
         .with_context(|| format!("Failed to open file: {:?}", file_path.as_ref()))?;
-    
+
+    if !config.counts_lines_for(file_path.as_ref()) {
+        return match config.hash_mode {
+            HashMode::Quick { bytes } => process_file_quick(file, bytes, config).map(|result| FileProcessingResult {
+                dn: 0,
+                region: None,
+                ..result
+            }),
+            _ => hash_reader(file, config),
+        };
+    }
+
+    if let HashMode::Quick { bytes } = config.hash_mode {
+        return process_file_quick(file, bytes, config);
+    }
+
+    if let Some(threshold) = config.use_mmap_threshold {
+        let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+        if len > threshold {
+            // SAFETY: the map is read-only and dropped before returning; files being
+            // modified concurrently are already unsupported by the buffered path.
+            match unsafe { memmap2::Mmap::map(&file) } {
+                Ok(mmap) => return Ok(process_bytes(&mmap, config)),
+                Err(e) => tracing::debug!(
+                    "Memory map failed for {:?}, using buffered read: {}",
+                    file_path.as_ref(),
+                    e
+                ),
+            }
+        }
+    }
+
+    process_reader(file, config)
+}
+
+/// Quick hash of the first and last `bytes` bytes plus the size; DN comes from the sampled head
+fn process_file_quick(mut file: File, bytes: u64, config: &FileProcessingConfig) -> Result<FileProcessingResult> {
+    let len = file.metadata().context("Failed to read file metadata")?.len();
+
+    let mut head = Vec::new();
+    Read::by_ref(&mut file).take(bytes).read_to_end(&mut head)
+        .context("Failed to read from file")?;
+
+    let mut tail = Vec::new();
+    file.seek(SeekFrom::Start(len.saturating_sub(bytes)))
+        .context("Failed to seek in file")?;
+    file.take(bytes).read_to_end(&mut tail)
+        .context("Failed to read from file")?;
+
+    Ok(quick_hash_result(&head, &tail, len, config))
+}
+
+/// Pure function building a quick-hash result from the sampled head and tail
+fn quick_hash_result(head: &[u8], tail: &[u8], len: u64, config: &FileProcessingConfig) -> FileProcessingResult {
     let mut hasher = Sha256::new();
-    let mut buffer = vec![0; 128 * 1024]; // 128KB buffer
-    let mut total_lines = 0;
-    let mut first_line = String::new();
-    let mut first_line_read = false;
-    let mut line_buffer = Vec::new();
+    hasher.update(head);
+    hasher.update(tail);
+    hasher.update(len.to_le_bytes());
+
+    let first_line_end = head.iter().position(|&byte| byte == b'\n').unwrap_or(head.len());
+    let first_line = decode_line(&head[..first_line_end]);
+    let first_line = config.trim_first_line.apply(&first_line);
+
+    FileProcessingResult {
+        hash: truncate_hash(format!("{:x}", hasher.finalize()), config.hash_truncate_len),
+        dn: extract_dn_from_fhi_first_line(first_line),
+        total_lines: 0,
+        count_mismatch: false,
+        hash_algorithm: HASH_ALGORITHM_QUICK,
+        region: config.region.as_ref().and_then(|spec| extract_region(first_line, spec)),
+        first_record_field_count: None,
+    }
+}
+
+/// Pure function building the `HashMode::MetadataOnly` result of the file at `path`
+pub fn path_hash_result(path: &str, config: &FileProcessingConfig) -> FileProcessingResult {
+    FileProcessingResult {
+        hash: truncate_hash(path_hash(path), config.hash_truncate_len),
+        dn: 0,
+        total_lines: 0,
+        count_mismatch: false,
+        hash_algorithm: HASH_ALGORITHM_PATH,
+        region: None,
+        first_record_field_count: None,
+    }
+}
+
+/// Process any byte stream in one pass to get hash, DN from first line, and line count
+/// Shared by regular files and archive entries so both produce identical results
+pub fn process_reader<R: Read>(
+    mut reader: R,
+    config: &FileProcessingConfig,
+) -> Result<FileProcessingResult> {
+    let mut state = OnePassState::new(config);
+    let mut buffer = vec![0; config.read_buffer_size.unwrap_or(DEFAULT_READ_BUFFER_SIZE).max(1)];
     
     loop {
-        let bytes_read = file.read(&mut buffer)
+        let bytes_read = reader.read(&mut buffer)
             .context("Failed to read from file")?;
         
         if bytes_read == 0 {
             break;
         }
         
-        // Update hash
+        state.update(&buffer[..bytes_read]);
+    }
+    
+    Ok(state.finish(config))
+}
+
+/// Hash a byte stream without counting lines or reading its header
+/// For files outside `count_lines_extensions`: the DN is 0 and no lines are counted
+pub fn hash_reader<R: Read>(
+    mut reader: R,
+    config: &FileProcessingConfig,
+) -> Result<FileProcessingResult> {
+    let mut hasher = ContentHasher::new(config);
+    let mut buffer = vec![0; config.read_buffer_size.unwrap_or(DEFAULT_READ_BUFFER_SIZE).max(1)];
+
+    loop {
+        let bytes_read = reader.read(&mut buffer)
+            .context("Failed to read from file")?;
+
+        if bytes_read == 0 {
+            break;
+        }
+
         hasher.update(&buffer[..bytes_read]);
-        
-        // Process bytes for line counting and first line extraction
-        for &byte in &buffer[..bytes_read] {
-            if !first_line_read {
-                if byte == b'\n' {
-                    first_line = String::from_utf8_lossy(&line_buffer).trim_end_matches('\r').to_string();
-                    first_line_read = true;
-                    line_buffer.clear();
-                } else {
-                    line_buffer.push(byte);
-                }
+    }
+
+    Ok(FileProcessingResult {
+        hash_algorithm: hasher.algorithm(),
+        hash: truncate_hash(hasher.finalize_hex(), config.hash_truncate_len),
+        dn: 0,
+        total_lines: 0,
+        count_mismatch: false,
+        region: None,
+        first_record_field_count: None,
+    })
+}
+
+/// Process an in-memory (or memory-mapped) byte slice in one pass
+/// Pure function - yields the same result as `process_reader` over the same bytes
+pub fn process_bytes(bytes: &[u8], config: &FileProcessingConfig) -> FileProcessingResult {
+    let mut state = OnePassState::new(config);
+    state.update(bytes);
+    state.finish(config)
+}
+
+/// Running state of a single pass: hash, line count, first line and optionally the last line
+struct OnePassState {
+    hasher: ContentHasher,
+    total_lines: i32,
+    count_mode: LineCountMode,
+    /// Whether the line being read has a non-whitespace byte so far (`NonBlank` mode)
+    line_has_content: bool,
+    first_line: Option<String>,
+    line_buffer: Vec<u8>,
+    /// First non-blank line after the header, only captured when a field delimiter is configured
+    track_first_record: bool,
+    first_record: Option<String>,
+    record_buffer: Vec<u8>,
+    /// Last line tracking, only enabled when a trailer must be validated
+    track_last_line: bool,
+    last_line: Vec<u8>,
+    partial_line: Vec<u8>,
+}
+
+impl OnePassState {
+    fn new(config: &FileProcessingConfig) -> Self {
+        Self {
+            hasher: ContentHasher::new(config),
+            total_lines: 0,
+            count_mode: config.count_mode,
+            line_has_content: false,
+            first_line: None,
+            line_buffer: Vec::new(),
+            track_first_record: config.field_delimiter.is_some(),
+            first_record: None,
+            record_buffer: Vec::new(),
+            track_last_line: config.trailer.is_some(),
+            last_line: Vec::new(),
+            partial_line: Vec::new(),
+        }
+    }
+
+    /// Keep the last complete line and the bytes after it, without buffering the whole file
+    fn update_last_line(&mut self, chunk: &[u8]) {
+        let Some(last_newline) = chunk.iter().rposition(|&byte| byte == b'\n') else {
+            self.partial_line.extend_from_slice(chunk);
+            return;
+        };
+
+        let before = &chunk[..last_newline];
+        match before.iter().rposition(|&byte| byte == b'\n') {
+            Some(previous_newline) => self.last_line = before[previous_newline + 1..].to_vec(),
+            None => {
+                self.partial_line.extend_from_slice(before);
+                self.last_line = std::mem::take(&mut self.partial_line);
+            }
+        }
+        self.partial_line = chunk[last_newline + 1..].to_vec();
+    }
+
+    /// Capture the first non-blank line from the bytes following the header line
+    fn capture_first_record(&mut self, mut rest: &[u8]) {
+        while self.first_record.is_none() {
+            let Some(end) = rest.iter().position(|&byte| byte == b'\n') else {
+                self.record_buffer.extend_from_slice(rest);
+                return;
+            };
+
+            self.record_buffer.extend_from_slice(&rest[..end]);
+            let line = decode_line(&std::mem::take(&mut self.record_buffer));
+            if !line.trim().is_empty() {
+                self.first_record = Some(line);
             }
-            
+            rest = &rest[end + 1..];
+        }
+    }
+
+    /// Count the non-blank lines ending in this chunk, carrying the state of an unfinished line
+    fn count_non_blank_lines(&mut self, chunk: &[u8]) -> i32 {
+        let mut lines = 0;
+        for &byte in chunk {
             if byte == b'\n' {
-                total_lines += 1;
+                lines += i32::from(self.line_has_content);
+                self.line_has_content = false;
+            } else if !byte.is_ascii_whitespace() {
+                self.line_has_content = true;
             }
         }
+        lines
     }
-    
-    // Handle case where file doesn't end with newline
-    if !line_buffer.is_empty() && !first_line_read {
-        first_line = String::from_utf8_lossy(&line_buffer).trim_end_matches('\r').to_string();
-        total_lines = total_lines.max(1);
+
+    /// Feed the next chunk of bytes
+    fn update(&mut self, chunk: &[u8]) {
+        // Update hash
+        self.hasher.update(chunk);
+
+        // Capture the first line until its line break is seen
+        let mut after_header: &[u8] = chunk;
+        if self.first_line.is_none() {
+            match chunk.iter().position(|&byte| byte == b'\n') {
+                Some(end) => {
+                    self.line_buffer.extend_from_slice(&chunk[..end]);
+                    self.first_line = Some(decode_line(&self.line_buffer));
+                    self.line_buffer.clear();
+                    after_header = &chunk[end + 1..];
+                }
+                None => {
+                    self.line_buffer.extend_from_slice(chunk);
+                    after_header = &[];
+                }
+            }
+        }
+
+        if self.track_first_record && self.first_record.is_none() {
+            self.capture_first_record(after_header);
+        }
+
+        self.total_lines += match self.count_mode {
+            LineCountMode::All => chunk.iter().filter(|&&byte| byte == b'\n').count() as i32,
+            LineCountMode::NonBlank => self.count_non_blank_lines(chunk),
+        };
+
+        if self.track_last_line {
+            self.update_last_line(chunk);
+        }
     }
-    
-    let hash = format!("{:x}", hasher.finalize());
-    let dn = extract_dn_from_fhi_first_line(&first_line);
-    
-    Ok(FileProcessingResult {
-        hash,
-        dn,
-        total_lines,
-    })
+
+    /// Finalize the pass into a processing result
+    fn finish(self, config: &FileProcessingConfig) -> FileProcessingResult {
+        // Handle case where file doesn't end with newline
+        let (first_line, total_lines) = match self.first_line {
+            Some(line) => (line, self.total_lines),
+            None if self.line_buffer.is_empty() => (String::new(), self.total_lines),
+            None if self.count_mode == LineCountMode::NonBlank && !self.line_has_content => {
+                (decode_line(&self.line_buffer), self.total_lines)
+            }
+            None => (decode_line(&self.line_buffer), self.total_lines.max(1)),
+        };
+
+        let count_mismatch = config.trailer.as_ref().is_some_and(|spec| {
+            let last_line = if self.partial_line.is_empty() { &self.last_line } else { &self.partial_line };
+            extract_trailer_count(&decode_line(last_line), spec).is_some_and(|expected| expected != total_lines)
+        });
+
+        let first_line = config.trim_first_line.apply(&first_line);
+
+        // A last record without a trailing newline still counts
+        let first_record = self.first_record.or_else(|| {
+            Some(decode_line(&self.record_buffer)).filter(|line| !line.trim().is_empty())
+        });
+
+        FileProcessingResult {
+            hash_algorithm: self.hasher.algorithm(),
+            hash: truncate_hash(self.hasher.finalize_hex(), config.hash_truncate_len),
+            dn: extract_dn_from_fhi_first_line(first_line),
+            total_lines,
+            count_mismatch,
+            region: config.region.as_ref().and_then(|spec| extract_region(first_line, spec)),
+            first_record_field_count: config
+                .field_delimiter
+                .zip(first_record)
+                .map(|(delimiter, line)| count_fields(&line, delimiter)),
+        }
+    }
+}
+
+/// Extract the declared record count from a trailer line
+/// Pure function - returns None when the line is not a trailer or the count is not numeric
+pub fn extract_trailer_count(last_line: &str, spec: &TrailerSpec) -> Option<i32> {
+    if !last_line.starts_with(&spec.prefix) {
+        return None;
+    }
+
+    last_line
+        .chars()
+        .skip(spec.offset)
+        .take(spec.length)
+        .collect::<String>()
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Number of fields in a delimited line: one more than the delimiter occurrences
+/// Pure function
+pub fn count_fields(line: &str, delimiter: char) -> i32 {
+    line.matches(delimiter).count() as i32 + 1
+}
+
+/// Decode a raw first line, dropping a trailing carriage return
+fn decode_line(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).trim_end_matches('\r').to_string()
+}
+
+/// Truncate a hex digest to the configured length, keeping its prefix
+/// Pure function
+pub fn truncate_hash(mut hash: String, truncate_len: Option<usize>) -> String {
+    if let Some(len) = truncate_len {
+        hash.truncate(len);
+    }
+    hash
+}
+
+/// SHA-256 of a normalized path, a deterministic key for files whose content is not hashed
+/// Paths compare case-insensitively on Windows, so they are lowercased there
+pub fn path_hash(path: &str) -> String {
+    let normalized = normalize_path_key(path, cfg!(windows));
+    format!("{:x}", Sha256::digest(normalized.as_bytes()))
+}
+
+/// Pure function normalizing a path for hashing
+/// Separators become '/', repeated separators collapse and a trailing one is dropped;
+/// `case_insensitive` also lowercases the path
+pub fn normalize_path_key(path: &str, case_insensitive: bool) -> String {
+    let mut normalized = String::with_capacity(path.len());
+    for c in path.chars().map(|c| if c == '\\' { '/' } else { c }) {
+        if !(c == '/' && normalized.ends_with('/')) {
+            normalized.push(c);
+        }
+    }
+    if normalized.len() > 1 && normalized.ends_with('/') {
+        normalized.pop();
+    }
+
+    if case_insensitive {
+        normalized.to_lowercase()
+    } else {
+        normalized
+    }
+}
+
+/// Where to find the DN inside the first line of a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DnExtractionSpec {
+    /// Required prefix of the first line (e.g. "FHI")
+    pub prefix: String,
+    /// 0-based character offset of the DN
+    pub offset: usize,
+    /// Number of characters holding the DN
+    pub length: usize,
+}
+
+impl Default for DnExtractionSpec {
+    fn default() -> Self {
+        Self {
+            prefix: "FHI".to_string(),
+            offset: 39,
+            length: 5,
+        }
+    }
+}
+
+/// Extract DN from a first line according to the given spec
+/// Pure function - returns 0 when the prefix does not match or the line is too short
+pub fn extract_dn(first_line: &str, spec: &DnExtractionSpec) -> i32 {
+    if !first_line.starts_with(&spec.prefix) || first_line.chars().count() < spec.offset + spec.length {
+        return 0;
+    }
+
+    first_line
+        .chars()
+        .skip(spec.offset)
+        .take(spec.length)
+        .collect::<String>()
+        .trim()
+        .parse()
+        .unwrap_or(0)
+}
+
+/// Where to find the region code inside the first line of a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionSpec {
+    /// Required prefix of the first line (e.g. "FHI")
+    pub prefix: String,
+    /// 0-based character offset of the region code
+    pub offset: usize,
+    /// Number of characters holding the region code
+    pub length: usize,
+}
+
+/// Extract the region code from a first line according to the given spec
+/// Pure function - returns None when the prefix does not match, the line is too short or the field is blank
+pub fn extract_region(first_line: &str, spec: &RegionSpec) -> Option<String> {
+    if !first_line.starts_with(&spec.prefix) || first_line.chars().count() < spec.offset + spec.length {
+        return None;
+    }
+
+    let region: String = first_line.chars().skip(spec.offset).take(spec.length).collect();
+    let region = region.trim();
+    (!region.is_empty()).then(|| region.to_string())
 }
 
 /// Extract DN from FHI first line (positions 39-44, 0-based)
 /// Pure function
 fn extract_dn_from_fhi_first_line(first_line: &str) -> i32 {
-    if first_line.starts_with("FHI") && first_line.len() >= 45 {
-        first_line
-            .chars()
-            .skip(39)
-            .take(5)
-            .collect::<String>()
-            .parse()
-            .unwrap_or(0)
-    } else {
-        0
+    extract_dn(first_line, &DnExtractionSpec::default())
+}
+
+/// Attributes a revenda declares in a JSON sidecar next to a data file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct SidecarMeta {
+    /// Takes precedence over the DN parsed from the header line
+    pub dn: Option<i32>,
+    /// Compared against the counted lines, like a trailer record count
+    pub expected_lines: Option<i32>,
+}
+
+/// Sidecar location for a data file: `x.txt` -> `x.txt.meta`
+/// Pure function
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".meta");
+    PathBuf::from(sidecar)
+}
+
+/// Read the sidecar of a data file through the given filesystem, or None when it has no sidecar
+pub fn read_sidecar_meta<F: FileSystem + ?Sized>(file_system: &F, path: &Path) -> Result<Option<SidecarMeta>> {
+    let sidecar = sidecar_path(path);
+    let contents = match file_system.read_to_string(&sidecar) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read sidecar: {:?}", sidecar)),
+    };
+
+    serde_json::from_str(&contents)
+        .map(Some)
+        .with_context(|| format!("Invalid sidecar metadata: {:?}", sidecar))
+}
+
+/// Pure function overriding a processing result with the values declared in a sidecar
+/// The expected line count is not checked for quick hashes, which do not count lines
+pub fn apply_sidecar_meta(result: FileProcessingResult, meta: &SidecarMeta) -> FileProcessingResult {
+    let counted = result.hash_algorithm != HASH_ALGORITHM_QUICK;
+    let count_mismatch = match meta.expected_lines {
+        Some(expected) if counted => expected != result.total_lines,
+        _ => result.count_mismatch,
+    };
+
+    FileProcessingResult {
+        dn: meta.dn.unwrap_or(result.dn),
+        count_mismatch,
+        ..result
     }
 }
 
+/// Read only the first line of a file, without the trailing line break
+pub fn read_first_line<P: AsRef<Path>>(file_path: P) -> Result<String> {
+    let file = File::open(file_path.as_ref())
+        .with_context(|| format!("Failed to open file: {:?}", file_path.as_ref()))?;
+    let mut line = Vec::new();
+    std::io::BufRead::read_until(&mut std::io::BufReader::new(file), b'\n', &mut line)
+        .context("Failed to read from file")?;
+
+    Ok(String::from_utf8_lossy(&line).trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Resolve (created_at, modified_at) from optional file timestamps
+/// Pure function - a missing created time (common on Linux) falls back to the modified time,
+/// and only when both are missing does either fall back to now
+pub fn resolve_file_times(
+    created: Option<std::time::SystemTime>,
+    modified: Option<std::time::SystemTime>,
+) -> (DateTime<Utc>, DateTime<Utc>) {
+    let created = created.or(modified);
+    let modified = modified.or(created);
+
+    (metadata_to_datetime(created), metadata_to_datetime(modified))
+}
+
 /// Convert system time to UTC DateTime
 fn metadata_to_datetime(system_time: Option<std::time::SystemTime>) -> DateTime<Utc> {
     system_time
@@ -219,6 +985,542 @@ mod tests {
         assert_eq!(dn, 0);
     }
 
+    #[test]
+    fn test_hash_truncation_is_prefix_of_full_hash() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, b"FHI header\nrecord\n").unwrap();
+
+        let full = process_file_one_pass(file.path()).unwrap();
+        let config = FileProcessingConfig {
+            hash_truncate_len: Some(40),
+            ..FileProcessingConfig::default()
+        };
+        let truncated = process_file_one_pass_with(file.path(), &config).unwrap();
+
+        assert_eq!(full.hash.len(), 64);
+        assert_eq!(truncated.hash.len(), 40);
+        assert!(full.hash.starts_with(&truncated.hash));
+
+        let trace = create_file_trace_from_path_with(file.path(), &config).unwrap();
+        assert_eq!(trace.hash, truncated.hash);
+    }
+
+    #[test]
+    fn test_count_lines_extensions_hash_other_files_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let content = format!("FHI{}54321 header\nrecord 1\nrecord 2\n", "0".repeat(36));
+        let text = dir.path().join("revenda.txt");
+        let binary = dir.path().join("revenda.bin");
+        std::fs::write(&text, &content).unwrap();
+        std::fs::write(&binary, &content).unwrap();
+
+        let config = FileProcessingConfig {
+            count_lines_extensions: Some(vec!["txt".to_string()]),
+            ..FileProcessingConfig::default()
+        };
+
+        let counted = process_file_one_pass_with(&text, &config).unwrap();
+        assert_eq!(counted.total_lines, 3);
+        assert_eq!(counted.dn, 54321);
+
+        let hashed = process_file_one_pass_with(&binary, &config).unwrap();
+        assert_eq!(hashed.total_lines, 0);
+        assert_eq!(hashed.dn, 0);
+        assert_eq!(hashed.hash, counted.hash);
+
+        // Without the list every file is fully processed
+        let full = process_file_one_pass(&binary).unwrap();
+        assert_eq!((full.total_lines, full.dn), (3, 54321));
+    }
+
+    #[test]
+    fn test_mmap_and_buffered_results_match() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        let header = format!("FHI{}54321 header\r\n", "0".repeat(36));
+        std::io::Write::write_all(&mut file, header.as_bytes()).unwrap();
+        for i in 0..50_000 {
+            std::io::Write::write_all(&mut file, format!("record {:08}\n", i).as_bytes()).unwrap();
+        }
+        std::io::Write::write_all(&mut file, b"no trailing newline").unwrap();
+
+        // A tiny buffer forces the first line to span several chunks
+        let buffered = process_file_one_pass_with(file.path(), &FileProcessingConfig {
+            read_buffer_size: Some(7),
+            ..FileProcessingConfig::default()
+        })
+        .unwrap();
+        let mapped = process_file_one_pass_with(file.path(), &FileProcessingConfig {
+            use_mmap_threshold: Some(1024),
+            ..FileProcessingConfig::default()
+        })
+        .unwrap();
+
+        assert_eq!(buffered.hash, mapped.hash);
+        assert_eq!(buffered.total_lines, 50_001);
+        assert_eq!(buffered.total_lines, mapped.total_lines);
+        assert_eq!(buffered.dn, 54321);
+        assert_eq!(buffered.dn, mapped.dn);
+    }
+
+    #[test]
+    fn test_non_blank_line_count_skips_whitespace_lines() {
+        let content = b"FHI header\n\nrecord 1\n   \n\t\r\nrecord 2\n\n";
+        let non_blank = FileProcessingConfig { count_mode: LineCountMode::NonBlank, ..FileProcessingConfig::default() };
+
+        assert_eq!(process_bytes(content, &FileProcessingConfig::default()).total_lines, 7);
+        assert_eq!(process_bytes(content, &non_blank).total_lines, 3);
+
+        // Lines split across read chunks are still judged as a whole
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, content).unwrap();
+        let chunked = FileProcessingConfig { read_buffer_size: Some(3), ..non_blank.clone() };
+        assert_eq!(process_file_one_pass_with(file.path(), &chunked).unwrap().total_lines, 3);
+
+        // A whitespace-only file without line breaks has no records
+        assert_eq!(process_bytes(b"   ", &non_blank).total_lines, 0);
+        assert_eq!(process_bytes(b"record", &non_blank).total_lines, 1);
+    }
+
+    #[test]
+    fn test_sidecar_dn_takes_precedence_over_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let header = format!("FHI{}54321 header\nrecord 1\nrecord 2\n", "0".repeat(36));
+        let with_sidecar = dir.path().join("with.txt");
+        let without_sidecar = dir.path().join("without.txt");
+        std::fs::write(&with_sidecar, &header).unwrap();
+        std::fs::write(&without_sidecar, &header).unwrap();
+        std::fs::write(sidecar_path(&with_sidecar), r#"{ "dn": 777, "expected_lines": 5 }"#).unwrap();
+
+        let config = FileProcessingConfig { read_sidecar_meta: true, ..FileProcessingConfig::default() };
+        let from_sidecar = create_file_trace_from_path_with(&with_sidecar, &config).unwrap();
+        let from_header = create_file_trace_from_path_with(&without_sidecar, &config).unwrap();
+        let sidecar_ignored = create_file_trace_from_path_with(&with_sidecar, &FileProcessingConfig::default()).unwrap();
+
+        assert_eq!((from_sidecar.dn, from_sidecar.count_mismatch), (777, true));
+        assert_eq!((from_header.dn, from_header.count_mismatch), (54321, false));
+        assert_eq!((sidecar_ignored.dn, sidecar_ignored.count_mismatch), (54321, false));
+    }
+
+    #[test]
+    fn test_sidecar_fields_are_optional_and_invalid_json_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.txt");
+        std::fs::write(&path, "FHI header\nrecord\n").unwrap();
+        let config = FileProcessingConfig { read_sidecar_meta: true, ..FileProcessingConfig::default() };
+
+        std::fs::write(sidecar_path(&path), r#"{ "expected_lines": 2 }"#).unwrap();
+        let trace = create_file_trace_from_path_with(&path, &config).unwrap();
+        assert_eq!((trace.dn, trace.count_mismatch), (0, false));
+
+        std::fs::write(sidecar_path(&path), "dn=777").unwrap();
+        let error = create_file_trace_from_path_with(&path, &config).unwrap_err();
+        assert!(format!("{:#}", error).contains("Invalid sidecar metadata"));
+    }
+
+    #[test]
+    fn test_sidecar_is_read_through_the_file_system() {
+        /// Filesystem holding a single sidecar in memory
+        struct SidecarOnly;
+
+        impl FileSystem for SidecarOnly {
+            fn read_dir(&self, _directory: &Path) -> std::io::Result<crate::utils::DirEntries> {
+                Err(std::io::Error::from(std::io::ErrorKind::Unsupported))
+            }
+
+            fn stat(&self, _path: &Path) -> std::io::Result<crate::utils::FileStat> {
+                Err(std::io::Error::from(std::io::ErrorKind::Unsupported))
+            }
+
+            fn read_to_string(&self, path: &Path) -> std::io::Result<String> {
+                if path == Path::new("/share/data.txt.meta") {
+                    Ok(r#"{ "dn": 777 }"#.to_string())
+                } else {
+                    Err(std::io::Error::from(std::io::ErrorKind::NotFound))
+                }
+            }
+        }
+
+        let meta = read_sidecar_meta(&SidecarOnly, Path::new("/share/data.txt")).unwrap().unwrap();
+        assert_eq!(meta.dn, Some(777));
+        assert!(read_sidecar_meta(&SidecarOnly, Path::new("/share/other.txt")).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_path_hash_is_stable_across_separators() {
+        let hash = path_hash("/data/in/export.txt");
+
+        assert_eq!(hash.len(), 64);
+        assert_eq!(path_hash("/data/in/export.txt"), hash);
+        assert_eq!(path_hash("\\data\\in\\export.txt"), hash);
+        assert_eq!(path_hash("/data//in/export.txt"), hash);
+        assert_ne!(path_hash("/data/in/export2.txt"), hash);
+    }
+
+    #[test]
+    fn test_metadata_only_keys_traces_on_the_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.txt");
+        let config = FileProcessingConfig { hash_mode: HashMode::MetadataOnly, ..FileProcessingConfig::default() };
+
+        std::fs::write(&path, format!("FHI{}12345\nline\n", "0".repeat(36))).unwrap();
+        let trace = create_file_trace_from_path_with(&path, &config).unwrap();
+        assert_eq!(trace.hash, path_hash(&path.to_string_lossy()));
+        assert_eq!(trace.hash_algorithm, HASH_ALGORITHM_PATH);
+        // The content is not read: no lines and no DN from the header
+        assert_eq!((trace.total_lines, trace.dn), (0, 0));
+
+        std::fs::write(&path, "other content\n").unwrap();
+        assert_eq!(create_file_trace_from_path_with(&path, &config).unwrap().hash, trace.hash);
+    }
+
+    #[test]
+    fn test_normalize_path_key_case_handling() {
+        assert_eq!(normalize_path_key(r"C:\Data\In\Export.TXT", true), "c:/data/in/export.txt");
+        assert_eq!(normalize_path_key(r"C:\Data\In\", false), "C:/Data/In");
+        assert_eq!(normalize_path_key("/", false), "/");
+        assert_eq!(
+            normalize_path_key(r"C:\DATA\in\export.txt", true),
+            normalize_path_key("c:/data/IN/Export.txt", true)
+        );
+    }
+
+    #[test]
+    fn test_count_fields_of_delimited_lines() {
+        assert_eq!(count_fields("123|ABC|2024-01-15|9.99", '|'), 4);
+        assert_eq!(count_fields("123;ABC;;9.99;", ';'), 5);
+        assert_eq!(count_fields("no delimiter here", ';'), 1);
+    }
+
+    #[test]
+    fn test_first_record_field_count_skips_header_and_blank_lines() {
+        let pipe = FileProcessingConfig { field_delimiter: Some('|'), ..FileProcessingConfig::default() };
+        let semicolon = FileProcessingConfig { field_delimiter: Some(';'), ..FileProcessingConfig::default() };
+        let content = b"FHI|header|line\n\r\n1|ACME|2024-01-15|10\n2|ACME\n";
+
+        assert_eq!(process_bytes(content, &pipe).first_record_field_count, Some(4));
+        assert_eq!(process_bytes(b"FHI header\r\n1;ACME;10", &semicolon).first_record_field_count, Some(3));
+        assert_eq!(process_bytes(b"FHI header\n", &pipe).first_record_field_count, None);
+        assert_eq!(process_bytes(content, &FileProcessingConfig::default()).first_record_field_count, None);
+
+        // The record line may be split across read chunks
+        let chunked = process_reader(&content[..], &FileProcessingConfig { read_buffer_size: Some(3), ..pipe.clone() }).unwrap();
+        assert_eq!(chunked.first_record_field_count, Some(4));
+    }
+
+    fn trailer_config() -> FileProcessingConfig {
+        FileProcessingConfig {
+            trailer: Some(TrailerSpec::default()),
+            ..FileProcessingConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_trailer_count_matches() {
+        let content = b"FHI header\nrecord 1\nrecord 2\nFTR000000004\n";
+        let result = process_bytes(content, &trailer_config());
+        assert_eq!(result.total_lines, 4);
+        assert!(!result.count_mismatch);
+    }
+
+    #[test]
+    fn test_trailer_count_mismatch() {
+        let content = b"FHI header\nrecord 1\nFTR000000010\r\n";
+        let result = process_bytes(content, &trailer_config());
+        assert!(result.count_mismatch);
+
+        // Without a trailer spec the count is not validated
+        assert!(!process_bytes(content, &FileProcessingConfig::default()).count_mismatch);
+    }
+
+    #[test]
+    fn test_trailer_found_across_chunks_and_without_final_newline() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, b"FHI header\nrecord\nFTR000000005").unwrap();
+
+        let config = FileProcessingConfig {
+            read_buffer_size: Some(4),
+            ..trailer_config()
+        };
+        let result = process_file_one_pass_with(file.path(), &config).unwrap();
+        assert_eq!(result.total_lines, 2);
+        assert!(result.count_mismatch);
+
+        let trace = create_file_trace_from_path_with(file.path(), &config).unwrap();
+        assert!(trace.count_mismatch);
+    }
+
+    #[test]
+    fn test_file_without_trailer_is_not_flagged() {
+        let result = process_bytes(b"FHI header\nrecord\n", &trailer_config());
+        assert!(!result.count_mismatch);
+    }
+
+    #[test]
+    fn test_missing_created_time_falls_back_to_modified() {
+        /// Filesystem whose platform does not report creation times
+        struct NoCreatedTime;
+
+        impl FileSystem for NoCreatedTime {
+            fn read_dir(&self, directory: &Path) -> std::io::Result<crate::utils::DirEntries> {
+                OsFileSystem.read_dir(directory)
+            }
+
+            fn stat(&self, path: &Path) -> std::io::Result<crate::utils::FileStat> {
+                Ok(crate::utils::FileStat {
+                    created: None,
+                    ..OsFileSystem.stat(path)?
+                })
+            }
+
+            fn read_to_string(&self, path: &Path) -> std::io::Result<String> {
+                OsFileSystem.read_to_string(path)
+            }
+        }
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, b"content\n").unwrap();
+        let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        file.as_file().set_modified(modified).unwrap();
+
+        let trace = create_file_trace_with_fs(&NoCreatedTime, &SystemClock, file.path(), &FileProcessingConfig::default()).unwrap();
+
+        assert_eq!(trace.created_at, trace.modified_at);
+        assert_eq!(trace.modified_at.timestamp(), 1_700_000_000);
+    }
+
+    #[test]
+    fn test_processed_at_comes_from_clock() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, b"content\n").unwrap();
+        let now = DateTime::parse_from_rfc3339("2024-03-15T12:00:00Z").unwrap().with_timezone(&Utc);
+
+        let trace = create_file_trace_with_fs(
+            &OsFileSystem,
+            &crate::utils::FixedClock(now),
+            file.path(),
+            &FileProcessingConfig::default(),
+        )
+        .unwrap();
+
+        assert_eq!(trace.processed_at, now);
+    }
+
+    #[test]
+    fn test_future_mtime_policies_in_trace_creation() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, b"content\n").unwrap();
+        let now = DateTime::parse_from_rfc3339("2024-03-15T12:00:00Z").unwrap().with_timezone(&Utc);
+        let in_an_hour = std::time::UNIX_EPOCH + std::time::Duration::from_secs(now.timestamp() as u64 + 3600);
+        file.as_file().set_modified(in_an_hour).unwrap();
+
+        let create = |future_mtime_policy| {
+            let config = FileProcessingConfig {
+                future_mtime_policy,
+                ..FileProcessingConfig::default()
+            };
+            create_file_trace_with_fs(&OsFileSystem, &crate::utils::FixedClock(now), file.path(), &config)
+        };
+
+        let accepted = create(FutureMtimePolicy::Accept).unwrap();
+        assert_eq!(accepted.modified_at.timestamp(), now.timestamp() + 3600);
+
+        let clamped = create(FutureMtimePolicy::ClampToNow).unwrap();
+        assert_eq!(clamped.modified_at, now);
+        assert!(clamped.created_at <= now);
+
+        let rejected = create(FutureMtimePolicy::Reject).unwrap_err();
+        assert!(rejected.to_string().contains("is in the future"), "{}", rejected);
+    }
+
+    #[test]
+    fn test_resolve_file_times_when_both_missing() {
+        let before = Utc::now();
+        let (created_at, modified_at) = resolve_file_times(None, None);
+        assert!(created_at >= before && modified_at >= before);
+    }
+
+    #[test]
+    fn test_extract_dn_and_region() {
+        let spec = RegionSpec { prefix: "FHI".to_string(), offset: 44, length: 2 };
+        let config = FileProcessingConfig { region: Some(spec.clone()), ..FileProcessingConfig::default() };
+        let dn_only = format!("FHI{}12345", "0".repeat(36));
+        let with_region = format!("{}07", dn_only);
+
+        let both = process_bytes(format!("{}\nrecord\n", with_region).as_bytes(), &config);
+        assert_eq!((both.dn, both.region.as_deref()), (12345, Some("07")));
+
+        let only_dn = process_bytes(format!("{}\nrecord\n", dn_only).as_bytes(), &config);
+        assert_eq!((only_dn.dn, only_dn.region), (12345, None));
+
+        // Region extraction is off by default
+        let default = process_bytes(format!("{}\n", with_region).as_bytes(), &FileProcessingConfig::default());
+        assert_eq!((default.dn, default.region), (12345, None));
+
+        assert_eq!(extract_region(&format!("HDR{}", &with_region[3..]), &spec), None);
+        assert_eq!(extract_region(&format!("{}  ", dn_only), &spec), None);
+    }
+
+    #[test]
+    fn test_trim_modes_on_padded_header() {
+        let header = format!("FHI{}12345", "0".repeat(36));
+        let padded = format!("   {}  \t\r\nrecord\n", header);
+        let region_spec = RegionSpec { prefix: "FHI".to_string(), offset: 44, length: 2 };
+        let process = |content: &str, trim_first_line| {
+            let config = FileProcessingConfig {
+                trim_first_line,
+                region: Some(region_spec.clone()),
+                ..FileProcessingConfig::default()
+            };
+            let result = process_bytes(content.as_bytes(), &config);
+            (result.dn, result.region)
+        };
+
+        // Leading padding hides the FHI prefix unless it is trimmed
+        assert_eq!(process(&padded, TrimMode::None), (0, None));
+        assert_eq!(process(&padded, TrimMode::End), (0, None));
+        assert_eq!(process(&padded, TrimMode::Both), (12345, None));
+
+        // Trailing padding alone reads as a blank region field, whatever the mode
+        let trailing = format!("{}  \nrecord\n", header);
+        assert_eq!(process(&trailing, TrimMode::None), (12345, None));
+        assert_eq!(process(&trailing, TrimMode::End), (12345, None));
+
+        // Quick hashing reads the header from the sampled head with the same trimming
+        let quick = FileProcessingConfig { trim_first_line: TrimMode::Both, ..quick_config(128) };
+        assert_eq!(quick_hash_result(padded.as_bytes(), b"", padded.len() as u64, &quick).dn, 12345);
+
+        assert_eq!(TrimMode::End.apply(" FHI \t"), " FHI");
+        assert_eq!(TrimMode::None.apply(" FHI "), " FHI ");
+    }
+
+    fn quick_config(bytes: u64) -> FileProcessingConfig {
+        FileProcessingConfig {
+            hash_mode: HashMode::Quick { bytes },
+            ..FileProcessingConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_quick_hash_ignores_middle_but_not_size() {
+        let write = |content: &[u8]| {
+            let mut file = tempfile::NamedTempFile::new().unwrap();
+            std::io::Write::write_all(&mut file, content).unwrap();
+            file
+        };
+        let header = format!("FHI{}12345\n", "0".repeat(36));
+        let padding = "-".repeat(100);
+        let body = |middle: &str| format!("{}{}{}{}\ntail\n", header, padding, middle, padding);
+
+        let a = write(body(&"a".repeat(1000)).as_bytes());
+        let b = write(body(&"b".repeat(1000)).as_bytes());
+        let longer = write(body(&"a".repeat(1001)).as_bytes());
+
+        let config = quick_config(64);
+        let quick_a = process_file_one_pass_with(a.path(), &config).unwrap();
+        let quick_b = process_file_one_pass_with(b.path(), &config).unwrap();
+        let quick_longer = process_file_one_pass_with(longer.path(), &config).unwrap();
+
+        // Same head, tail and size: the weaker guarantee in action
+        assert_eq!(quick_a.hash, quick_b.hash);
+        assert_ne!(quick_a.hash, quick_longer.hash);
+        assert_eq!(quick_a.hash_algorithm, HASH_ALGORITHM_QUICK);
+        assert_eq!(quick_a.dn, 12345);
+
+        // Full hashing still tells them apart
+        assert_ne!(process_file_one_pass(a.path()).unwrap().hash, process_file_one_pass(b.path()).unwrap().hash);
+
+        let trace = create_file_trace_from_path_with(a.path(), &config).unwrap();
+        assert_eq!(trace.hash_algorithm, "quick");
+        assert_eq!(create_file_trace_from_path(a.path()).unwrap().hash_algorithm, "sha256");
+    }
+
+    #[test]
+    fn test_quick_hash_of_small_file() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, b"short").unwrap();
+
+        let result = process_file_one_pass_with(file.path(), &quick_config(1024)).unwrap();
+        assert_eq!(result.hash, quick_hash_result(b"short", b"short", 5, &quick_config(1024)).hash);
+    }
+
+    #[test]
+    fn test_hmac_sha256_matches_known_vector() {
+        // RFC 4231, test case 2
+        let content = b"what do ya want for nothing?";
+        let config = FileProcessingConfig {
+            hash_mode: HashMode::HmacSha256 { key: HmacKey::new("Jefe") },
+            ..FileProcessingConfig::default()
+        };
+
+        let keyed = process_bytes(content, &config);
+        let plain = process_bytes(content, &FileProcessingConfig::default());
+
+        assert_eq!(keyed.hash, "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+        assert_eq!(keyed.hash_algorithm, "hmac-sha256");
+        assert_ne!(keyed.hash, plain.hash);
+        assert_eq!(plain.hash_algorithm, HASH_ALGORITHM_SHA256);
+        assert_eq!(format!("{:?}", config.hash_mode), "HmacSha256 { key: HmacKey(<redacted>) }");
+    }
+
+    #[test]
+    fn test_hash_algorithms_match_known_vectors_and_keep_line_counts() {
+        let header = format!("FHI{}{}", "0".repeat(36), "12345");
+        let content = format!("{}\nrecord 1\nrecord 2\n", header);
+        let with_algorithm = |hash_algorithm| FileProcessingConfig {
+            hash_algorithm,
+            read_buffer_size: Some(7),
+            ..FileProcessingConfig::default()
+        };
+
+        for (algorithm, abc_digest) in [
+            (HashAlgorithm::Sha256, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+            (HashAlgorithm::Md5, "900150983cd24fb0d6963f7d28e17f72"),
+            (HashAlgorithm::Blake3, "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"),
+        ] {
+            let config = with_algorithm(algorithm);
+            assert_eq!(process_bytes(b"abc", &config).hash, abc_digest);
+
+            let streamed = process_reader(content.as_bytes(), &config).unwrap();
+            assert_eq!(streamed.hash, process_bytes(content.as_bytes(), &config).hash);
+            assert_eq!(streamed.hash_algorithm, algorithm.name());
+            assert_eq!(HashAlgorithm::from_name(streamed.hash_algorithm), Some(algorithm));
+            assert_eq!((streamed.total_lines, streamed.dn), (3, 12345));
+        }
+
+        // The algorithm only applies to full-content hashes
+        let quick = FileProcessingConfig {
+            hash_mode: HashMode::Quick { bytes: 4 },
+            ..with_algorithm(HashAlgorithm::Md5)
+        };
+        assert_eq!(ContentHasher::new(&quick).algorithm(), HASH_ALGORITHM_SHA256);
+    }
+
+    #[test]
+    fn test_single_line_without_newline() {
+        let result = process_bytes(b"only line", &FileProcessingConfig::default());
+        assert_eq!(result.total_lines, 1);
+        assert_eq!(process_bytes(b"", &FileProcessingConfig::default()).total_lines, 0);
+    }
+
+    #[test]
+    fn test_truncate_hash_longer_than_digest() {
+        let hash = "ab".repeat(32);
+        assert_eq!(truncate_hash(hash.clone(), Some(100)), hash);
+        assert_eq!(truncate_hash(hash.clone(), None), hash);
+    }
+
+    #[test]
+    fn test_extract_dn_with_custom_spec() {
+        let spec = DnExtractionSpec {
+            prefix: "HDR".to_string(),
+            offset: 3,
+            length: 4,
+        };
+        assert_eq!(extract_dn("HDR0042rest", &spec), 42);
+        assert_eq!(extract_dn("FHI0042rest", &spec), 0);
+        assert_eq!(extract_dn("HDR00", &spec), 0);
+    }
+
     #[test]
     fn test_extract_dn_from_short_line() {
         let line = "FHI123";
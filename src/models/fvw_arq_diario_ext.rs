@@ -1,4 +1,6 @@
+use crate::utils::{FileSystem, OsFileSystem, SftpTarget};
 use serde::{Deserialize, Serialize};
+use std::path::Path;
 
 /// Configuration for file processing by revenda
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -9,6 +11,10 @@ pub struct FvwArqDiarioExt {
     pub dn: i32,
     pub pasta_input: String,
     pub pasta_output: String,
+    /// Per-revenda look-back window for copying, overriding the global config
+    pub days_back: Option<i64>,
+    /// Per-revenda overwrite behavior for copying, overriding the global config
+    pub overwrite: Option<bool>,
 }
 
 impl FvwArqDiarioExt {
@@ -37,6 +43,231 @@ impl FvwArqDiarioExt {
             dn,
             pasta_input,
             pasta_output,
+            days_back: None,
+            overwrite: None,
         }
     }
+
+    /// Return a copy with the given copy-phase overrides
+    pub fn with_overrides(self, days_back: Option<i64>, overwrite: Option<bool>) -> Self {
+        Self {
+            days_back,
+            overwrite,
+            ..self
+        }
+    }
+
+    /// Normalized list of extensions configured for this revenda
+    pub fn extensions(&self) -> Vec<String> {
+        parse_extensions(&self.extensao)
+    }
+
+    /// Check whether this revenda can be copied and scanned
+    pub fn readiness(&self) -> RevendaReadiness {
+        self.readiness_with_fs(&OsFileSystem)
+    }
+
+    /// Check readiness with an injected filesystem
+    /// A folder exists when it can be listed; an `sftp://` output folder is on another host,
+    /// so it is not listed and only needs to be a valid URL
+    pub fn readiness_with_fs<F: FileSystem + ?Sized>(&self, file_system: &F) -> RevendaReadiness {
+        let dir_exists = |dir: &str| !dir.is_empty() && file_system.read_dir(Path::new(dir)).is_ok();
+        let output_exists = match SftpTarget::parse(&self.pasta_output) {
+            Some(target) => target.is_ok(),
+            None => dir_exists(&self.pasta_output),
+        };
+
+        RevendaReadiness {
+            input_exists: dir_exists(&self.pasta_input),
+            output_exists,
+            has_extension: !self.extensions().is_empty(),
+            has_valid_dn: self.dn > 0,
+        }
+    }
+}
+
+/// Outcome of the checks a revenda needs to pass before copying and scanning
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct RevendaReadiness {
+    pub input_exists: bool,
+    pub output_exists: bool,
+    pub has_extension: bool,
+    pub has_valid_dn: bool,
+}
+
+impl RevendaReadiness {
+    /// Whether every check passed
+    pub fn is_ready(&self) -> bool {
+        self.problems().is_empty()
+    }
+
+    /// Pure function describing each failed check
+    pub fn problems(&self) -> Vec<&'static str> {
+        [
+            (self.input_exists, "input folder missing"),
+            (self.output_exists, "output folder missing"),
+            (self.has_extension, "no extension"),
+            (self.has_valid_dn, "invalid DN"),
+        ]
+        .into_iter()
+        .filter_map(|(passed, problem)| (!passed).then_some(problem))
+        .collect()
+    }
+}
+
+/// Pure function collapsing revendas that share `(empresa, revenda, dn)`
+/// The first occurrence is kept and absorbs the extensions of its duplicates, so no extension is
+/// lost while each directory is scanned once. Returns `(unique, duplicates)`.
+pub fn dedup_revendas(revendas: Vec<FvwArqDiarioExt>) -> (Vec<FvwArqDiarioExt>, Vec<FvwArqDiarioExt>) {
+    let mut unique: Vec<FvwArqDiarioExt> = Vec::with_capacity(revendas.len());
+    let mut duplicates = Vec::new();
+
+    for revenda in revendas {
+        let key = (revenda.empresa, revenda.revenda, revenda.dn);
+        match unique.iter_mut().find(|kept| (kept.empresa, kept.revenda, kept.dn) == key) {
+            Some(kept) => {
+                let mut extensions = kept.extensions();
+                extensions.extend(revenda.extensions().into_iter().filter(|ext| !kept.extensions().contains(ext)));
+                kept.extensao = extensions.join(",");
+                duplicates.push(revenda);
+            }
+            None => unique.push(revenda),
+        }
+    }
+
+    (unique, duplicates)
+}
+
+/// Split an `extensao` value into normalized extensions
+/// Accepts single values (".txt") as well as lists such as ".txt,.dat;fhi"
+pub fn parse_extensions(raw: &str) -> Vec<String> {
+    raw.split([',', ';'])
+        .filter_map(normalize_extension)
+        .collect()
+}
+
+/// Normalize an extension to lowercase with a leading dot
+/// Pure function - returns None for blank entries
+pub fn normalize_extension(ext: &str) -> Option<String> {
+    let ext = ext.trim().trim_start_matches('.');
+
+    if ext.is_empty() {
+        None
+    } else {
+        Some(format!(".{}", ext.to_lowercase()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{DirEntries, FileStat};
+    use std::io;
+    use std::path::PathBuf;
+
+    /// Lists only the given directories
+    struct DirsFileSystem(Vec<&'static str>);
+
+    impl FileSystem for DirsFileSystem {
+        fn read_dir(&self, directory: &Path) -> io::Result<DirEntries> {
+            if self.0.iter().any(|dir| Path::new(dir) == directory) {
+                Ok(Box::new(std::iter::empty::<io::Result<PathBuf>>()))
+            } else {
+                Err(io::Error::from(io::ErrorKind::NotFound))
+            }
+        }
+
+        fn stat(&self, _path: &Path) -> io::Result<FileStat> {
+            Err(io::Error::from(io::ErrorKind::NotFound))
+        }
+
+        fn read_to_string(&self, _path: &Path) -> io::Result<String> {
+            Err(io::Error::from(io::ErrorKind::NotFound))
+        }
+    }
+
+    #[test]
+    fn test_readiness_checks_folders_extension_and_dn() {
+        let file_system = DirsFileSystem(vec!["/in/1", "/out/1", "/out/2"]);
+        let revenda = |extensao: &str, dn: i32, pasta_input: &str, pasta_output: &str| {
+            FvwArqDiarioExt::new(1, 1, extensao.to_string(), dn, pasta_input.to_string(), pasta_output.to_string())
+                .readiness_with_fs(&file_system)
+        };
+
+        let ready = revenda(".txt", 12345, "/in/1", "/out/1");
+        assert!(ready.is_ready());
+        assert_eq!(ready, RevendaReadiness {
+            input_exists: true,
+            output_exists: true,
+            has_extension: true,
+            has_valid_dn: true,
+        });
+
+        let missing_input = revenda(".txt", 12345, "/in/2", "/out/2");
+        assert_eq!(missing_input.problems(), vec!["input folder missing"]);
+
+        // Blank folders are never listed, even when the filesystem would accept them
+        let blank = revenda(" ; ", 0, "", "");
+        assert_eq!(
+            blank.problems(),
+            vec!["input folder missing", "output folder missing", "no extension", "invalid DN"]
+        );
+
+        let negative_dn = revenda(".fhi", -1, "/in/1", "/out/3");
+        assert_eq!(negative_dn.problems(), vec!["output folder missing", "invalid DN"]);
+    }
+
+    #[test]
+    fn test_readiness_does_not_list_remote_outputs() {
+        let file_system = DirsFileSystem(vec!["/in/1"]);
+        let revenda = |pasta_output: &str| {
+            FvwArqDiarioExt::new(1, 1, ".txt".to_string(), 12345, "/in/1".to_string(), pasta_output.to_string())
+                .readiness_with_fs(&file_system)
+        };
+
+        assert!(revenda("sftp://uploader@files.example.com/inbox").is_ready());
+        assert_eq!(revenda("sftp://").problems(), vec!["output folder missing"]);
+    }
+
+    #[test]
+    fn test_parse_single_extension() {
+        assert_eq!(parse_extensions(".txt"), vec![".txt"]);
+        assert_eq!(parse_extensions("TXT"), vec![".txt"]);
+    }
+
+    #[test]
+    fn test_parse_comma_separated_extensions() {
+        assert_eq!(
+            parse_extensions(".txt,.dat, .FHI"),
+            vec![".txt", ".dat", ".fhi"]
+        );
+    }
+
+    #[test]
+    fn test_dedup_revendas_keeps_first_and_merges_extensions() {
+        let revenda = |revenda: i32, extensao: &str, pasta_output: &str| {
+            FvwArqDiarioExt::new(1, revenda, extensao.to_string(), 10, String::new(), pasta_output.to_string())
+        };
+
+        let (unique, duplicates) = dedup_revendas(vec![
+            revenda(1, ".txt", "/out/first"),
+            revenda(2, ".txt", "/out/other"),
+            revenda(1, ".TXT,.dat", "/out/second"),
+        ]);
+
+        assert_eq!(unique.len(), 2);
+        assert_eq!(unique[0].pasta_output, "/out/first");
+        assert_eq!(unique[0].extensions(), vec![".txt", ".dat"]);
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].pasta_output, "/out/second");
+    }
+
+    #[test]
+    fn test_parse_mixed_separators_and_blanks() {
+        assert_eq!(
+            parse_extensions(" .txt ; dat,, ;."),
+            vec![".txt", ".dat"]
+        );
+        assert!(parse_extensions("").is_empty());
+    }
 }
\ No newline at end of file
@@ -1,5 +1,7 @@
+pub mod archive;
 pub mod file_trace;
 pub mod fvw_arq_diario_ext;
 
+pub use archive::*;
 pub use file_trace::*;
 pub use fvw_arq_diario_ext::*;
\ No newline at end of file
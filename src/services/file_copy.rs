@@ -1,22 +1,81 @@
-use crate::database::DbPool;
+use crate::database::{DbPool, RevendaCache, RevendaSource, DEFAULT_REVENDA_CACHE_TTL};
 use crate::models::FvwArqDiarioExt;
-use crate::utils::{copy_files_batch, CopyResult};
+use crate::services::Metrics;
+use crate::utils::{copy_jobs_batch_until, CancellationToken, Clock, CollisionPolicy, CopyJob, CopyResult, Deadline, DestOptions, ErrorLog, FilePredicate, FilenameDatePattern, OverwriteMode, SystemClock, TransferMode, DEFAULT_MAX_NAME_LEN, MAX_LOOK_BACK_DAYS};
 use anyhow::Result;
-use std::path::Path;
+use chrono::Duration;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::Arc;
 use tracing::{info, warn, error};
 
 /// Configuration for file copying operations
 #[derive(Debug, Clone)]
 pub struct FileCopyConfig {
-    pub days_back: i64,
-    pub overwrite: bool,
+    /// Only copy files modified within this window
+    pub look_back: Duration,
+    pub overwrite: OverwriteMode,
+    /// Copy Windows file attributes onto copied files (no-op on other platforms)
+    pub preserve_attributes: bool,
+    /// Append one line per failed copy to this file, in addition to tracing
+    pub error_log: Option<PathBuf>,
+    /// Stop copying once more than this many copies have failed
+    pub max_copy_errors: Option<usize>,
+    /// Destination file name template with `{name}`, `{stem}` and `{ext}` placeholders
+    pub name_template: Option<String>,
+    /// How an already existing destination file is handled
+    pub collision: CollisionPolicy,
+    /// Maximum destination file name length in bytes when renaming on collision
+    pub max_name_len: usize,
+    /// Custom predicates every copied file must pass, alongside the extension and date filters
+    pub file_filters: Vec<FilePredicate>,
+    /// Select files by the date in their name instead of their modification time
+    pub filename_date_pattern: Option<FilenameDatePattern>,
+    /// Copy through a buffer of this many bytes instead of `fs::copy` (for slow network mounts)
+    pub copy_buffer_bytes: Option<usize>,
+    /// Skip files whose content is already in the destination directory under another name
+    /// Hashes same-size destination files, so it is off by default
+    pub skip_duplicate_content: bool,
+    /// Start no new copy once this passes (see `AppConfig::max_run_duration`)
+    pub deadline: Option<Deadline>,
+    /// Start no new copy once this is cancelled, e.g. on Ctrl-C
+    pub cancel: CancellationToken,
+    /// Live counters updated as each copy finishes, e.g. for the status server
+    pub metrics: Arc<Metrics>,
+    /// Clock the look-back windows and future modification times are measured against
+    pub clock: Arc<dyn Clock>,
+    /// Only preview the copies: files that would be copied are reported as skipped
+    /// with reason `SKIP_REASON_DRY_RUN` and nothing is written
+    pub dry_run: bool,
+    /// Copy files, or move them out of the input folder; moves count as successful copies
+    pub transfer: TransferMode,
+    /// Re-hash each copied file and report it as an error when its SHA-256 differs from
+    /// the source's; the mismatching copy is left in place
+    pub verify: bool,
 }
 
 impl Default for FileCopyConfig {
     fn default() -> Self {
         Self {
-            days_back: 15,
-            overwrite: false,
+            look_back: Duration::days(15),
+            overwrite: OverwriteMode::Never,
+            preserve_attributes: false,
+            error_log: None,
+            max_copy_errors: None,
+            name_template: None,
+            collision: CollisionPolicy::Keep,
+            max_name_len: DEFAULT_MAX_NAME_LEN,
+            file_filters: Vec::new(),
+            filename_date_pattern: None,
+            copy_buffer_bytes: None,
+            skip_duplicate_content: false,
+            deadline: None,
+            cancel: CancellationToken::new(),
+            metrics: Arc::new(Metrics::default()),
+            clock: Arc::new(SystemClock),
+            dry_run: false,
+            transfer: TransferMode::Copy,
+            verify: false,
         }
     }
 }
@@ -25,9 +84,7 @@ impl Default for FileCopyConfig {
 pub fn extract_file_extensions(revendas: &[FvwArqDiarioExt]) -> Vec<String> {
     revendas
         .iter()
-        .map(|revenda| &revenda.extensao)
-        .filter(|ext| !ext.is_empty())
-        .cloned()
+        .flat_map(|revenda| revenda.extensions())
         .collect()
 }
 
@@ -40,15 +97,82 @@ pub fn create_copy_mappings(revendas: &[FvwArqDiarioExt]) -> Vec<(String, String
         .collect()
 }
 
+/// Pure function to create one copy job per revenda
+/// Per-revenda `days_back`/`overwrite` values take precedence over the global config;
+/// `days_back` is clamped to `0..=MAX_LOOK_BACK_DAYS`
+pub fn create_copy_jobs(revendas: &[FvwArqDiarioExt], config: &FileCopyConfig) -> Vec<CopyJob> {
+    revendas
+        .iter()
+        .filter(|revenda| !revenda.pasta_input.is_empty() && !revenda.pasta_output.is_empty())
+        .map(|revenda| CopyJob {
+            look_back: Some(revenda.days_back.map(|days| Duration::days(days.clamp(0, MAX_LOOK_BACK_DAYS))).unwrap_or(config.look_back)),
+            overwrite: revenda.overwrite.map(OverwriteMode::from).unwrap_or(config.overwrite),
+            ..create_copy_job(&revenda.pasta_input, &revenda.pasta_output, config)
+        })
+        .collect()
+}
+
+/// Pure function to create a copy job using the global config
+fn create_copy_job(source_dir: &str, dest_dir: &str, config: &FileCopyConfig) -> CopyJob {
+    CopyJob {
+        look_back: Some(config.look_back),
+        overwrite: config.overwrite,
+        preserve_attributes: config.preserve_attributes,
+        dest_options: DestOptions {
+            // Files found below the input directory keep their subdirectories in the output
+            source_root: Some(PathBuf::from(source_dir)),
+            name_template: config.name_template.clone(),
+            collision: config.collision,
+            max_name_len: config.max_name_len,
+        },
+        filters: config.file_filters.clone(),
+        filename_date: config.filename_date_pattern.clone(),
+        copy_buffer_bytes: config.copy_buffer_bytes,
+        skip_duplicate_content: config.skip_duplicate_content,
+        dry_run: config.dry_run,
+        transfer: config.transfer,
+        verify: config.verify,
+        ..CopyJob::new(source_dir, dest_dir)
+    }
+}
+
+/// Pure function parsing a `source:destination` copy mapping
+/// Windows drive letters (`C:\in:D:\out`) are not taken as the separator
+pub fn parse_copy_mapping(raw: &str) -> Option<(String, String)> {
+    let is_drive_colon = |index: usize| {
+        let segment_start = index.checked_sub(1)?;
+        let starts_segment = segment_start == 0 || raw[..segment_start].ends_with(':');
+        let letter = raw[segment_start..index].chars().all(|c| c.is_ascii_alphabetic());
+        let rooted = raw[index + 1..].starts_with(['\\', '/']);
+        Some(starts_segment && letter && rooted)
+    };
+
+    let separator = raw
+        .match_indices(':')
+        .map(|(index, _)| index)
+        .find(|&index| is_drive_colon(index) != Some(true))?;
+    let (source, destination) = (raw[..separator].trim(), raw[separator + 1..].trim());
+
+    (!source.is_empty() && !destination.is_empty()).then(|| (source.to_string(), destination.to_string()))
+}
+
 /// Main file copy operation - functional composition
 pub async fn copy_files_for_revendas(
     pool: &DbPool,
     config: FileCopyConfig,
+) -> Result<FileCopyReport> {
+    copy_files_for_revendas_with(&RevendaCache::new(pool.clone(), DEFAULT_REVENDA_CACHE_TTL), config).await
+}
+
+/// Copy the files of the revendas served by `revendas`, shared with the other phases of the run
+pub async fn copy_files_for_revendas_with<S: RevendaSource>(
+    revendas: &RevendaCache<S>,
+    config: FileCopyConfig,
 ) -> Result<FileCopyReport> {
     info!("Starting file copy process...");
 
     // Get revendas data
-    let revendas = crate::database::arq_vw_ext::get_revendas(pool).await?;
+    let revendas = revendas.get_revendas().await?;
     
     if revendas.is_empty() {
         warn!("No revendas found in database");
@@ -57,63 +181,131 @@ pub async fn copy_files_for_revendas(
 
     // Extract configuration data functionally
     let extensions = extract_file_extensions(&revendas);
-    let mappings = create_copy_mappings(&revendas);
 
     info!("Found {} revendas with {} unique extensions", revendas.len(), extensions.len());
-    info!("Processing {} directory mappings", mappings.len());
 
-    // Convert string pairs to Path references for the copy function
-    let path_mappings: Vec<(&Path, &Path)> = mappings
+    // Copies block on the filesystem, pauses between listing retries included
+    let report = tokio::task::spawn_blocking(move || copy_files_in_revendas(&revendas, &extensions, &config)).await?;
+    Ok(report)
+}
+
+/// Copy the files of the given revendas, selecting them by `extensions`
+/// Shared by the whole-run copy and the per-revenda units of a pipelined run
+pub fn copy_files_in_revendas(
+    revendas: &[FvwArqDiarioExt],
+    extensions: &[String],
+    config: &FileCopyConfig,
+) -> FileCopyReport {
+    let jobs = create_copy_jobs(revendas, config);
+    info!("Processing {} directory mappings", jobs.len());
+
+    run_copy_jobs(&jobs, extensions, config)
+}
+
+/// Copy between explicit (input, output) directory pairs without querying the database
+/// Useful for one-off copies or when the database is unavailable
+pub fn copy_files_with_mappings(
+    mappings: &[(String, String)],
+    extensions: &[String],
+    config: &FileCopyConfig,
+) -> FileCopyReport {
+    info!("Starting file copy for {} explicit mappings...", mappings.len());
+
+    let jobs: Vec<CopyJob> = mappings
         .iter()
-        .map(|(input, output)| (Path::new(input), Path::new(output)))
+        .filter(|(input, output)| !input.is_empty() && !output.is_empty())
+        .map(|(input, output)| create_copy_job(input, output, config))
         .collect();
 
+    run_copy_jobs(&jobs, extensions, config)
+}
+
+/// Run the copy jobs and build the report, logging failures and an early abort
+fn run_copy_jobs(jobs: &[CopyJob], extensions: &[String], config: &FileCopyConfig) -> FileCopyReport {
     // Perform batch copy operation
-    let copy_results = copy_files_batch(
-        &path_mappings,
-        &extensions,
-        Some(config.days_back),
-        config.overwrite,
-    )?;
+    let batch = copy_jobs_batch_until(
+        jobs,
+        extensions,
+        config.max_copy_errors,
+        config.deadline,
+        &config.cancel,
+        config.clock.as_ref(),
+        |result| config.metrics.record_copy_result(result),
+    );
 
     // Create report from results
-    let report = create_copy_report(copy_results);
+    let error_log = ErrorLog::open(config.error_log.as_deref());
+    let report = FileCopyReport {
+        aborted: batch.aborted,
+        time_limited: batch.time_limited,
+        cancelled: batch.cancelled,
+        ..create_copy_report(batch.results, &error_log)
+    };
+
+    if config.dry_run {
+        info!("Dry run: no files were copied or overwritten");
+    }
+    if report.time_limited {
+        warn!("File copy stopped early: the run's time budget was used up");
+    }
+    if report.cancelled {
+        warn!("File copy stopped early: the run was cancelled");
+    }
+    if report.aborted {
+        error!(
+            "File copy aborted after {} errors (limit: {:?})",
+            report.errors.len(),
+            config.max_copy_errors
+        );
+    }
     
     info!(
         "File copy completed. Success: {}, Skipped: {}, Errors: {}",
         report.successful_copies,
-        report.skipped_files,
+        report.skipped_files(),
         report.errors.len()
     );
 
-    Ok(report)
+    report
 }
 
 /// Create a comprehensive report from copy results
-fn create_copy_report(results: Vec<CopyResult>) -> FileCopyReport {
+fn create_copy_report(results: Vec<CopyResult>, error_log: &ErrorLog) -> FileCopyReport {
     let mut successful_copies = 0;
-    let mut skipped_files = 0;
+    let mut overwritten_files = 0;
+    let mut skipped = Vec::new();
     let mut errors = Vec::new();
     let mut copied_files = Vec::new();
 
     for result in results {
         match result {
-            CopyResult::Success { source, destination } => {
+            CopyResult::Created { source, destination } => {
                 successful_copies += 1;
                 copied_files.push(CopiedFile {
                     source: source.to_string_lossy().to_string(),
                     destination: destination.to_string_lossy().to_string(),
                 });
             }
+            CopyResult::Overwritten { source, destination } => {
+                successful_copies += 1;
+                overwritten_files += 1;
+                tracing::debug!("Overwrote {} with {}", destination.display(), source.display());
+                copied_files.push(CopiedFile {
+                    source: source.to_string_lossy().to_string(),
+                    destination: destination.to_string_lossy().to_string(),
+                });
+            }
             CopyResult::Skipped { source, destination, reason } => {
-                skipped_files += 1;
-                // Optionally log skipped files
                 tracing::debug!(
                     "Skipped copying {} to {}: {}",
                     source.display(),
                     destination.display(),
                     reason
                 );
+                skipped.push(SkippedFile {
+                    path: source.to_string_lossy().to_string(),
+                    reason,
+                });
             }
             CopyResult::Error { source, destination, error } => {
                 let error_info = CopyError {
@@ -121,6 +313,7 @@ fn create_copy_report(results: Vec<CopyResult>) -> FileCopyReport {
                     destination: destination.to_string_lossy().to_string(),
                     error: error.clone(),
                 };
+                error_log.record(&error_info.source, &error_info.error);
                 errors.push(error_info);
                 error!(
                     "Failed to copy {} to {}: {}",
@@ -134,33 +327,68 @@ fn create_copy_report(results: Vec<CopyResult>) -> FileCopyReport {
 
     FileCopyReport {
         successful_copies,
-        skipped_files,
+        overwritten_files,
+        skipped,
         copied_files,
         errors,
+        aborted: false,
+        time_limited: false,
+        cancelled: false,
     }
 }
 
 /// Report structure for file copy operations
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct FileCopyReport {
     pub successful_copies: usize,
-    pub skipped_files: usize,
+    /// Successful copies that replaced an existing destination file
+    pub overwritten_files: usize,
+    /// Files that were not copied, each with the reason it was skipped
+    pub skipped: Vec<SkippedFile>,
     pub copied_files: Vec<CopiedFile>,
     pub errors: Vec<CopyError>,
+    /// Copying stopped early because `max_copy_errors` was exceeded
+    pub aborted: bool,
+    /// Copying stopped early because the run's time budget was used up
+    pub time_limited: bool,
+    /// Copying stopped early because the run was cancelled
+    pub cancelled: bool,
 }
 
 impl FileCopyReport {
     pub fn empty() -> Self {
         Self {
             successful_copies: 0,
-            skipped_files: 0,
+            overwritten_files: 0,
+            skipped: Vec::new(),
             copied_files: Vec::new(),
             errors: Vec::new(),
+            aborted: false,
+            time_limited: false,
+            cancelled: false,
         }
     }
 
+    /// Combine the reports of two copy units of the same run
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            successful_copies: self.successful_copies + other.successful_copies,
+            overwritten_files: self.overwritten_files + other.overwritten_files,
+            skipped: [self.skipped, other.skipped].concat(),
+            copied_files: [self.copied_files, other.copied_files].concat(),
+            errors: [self.errors, other.errors].concat(),
+            aborted: self.aborted || other.aborted,
+            time_limited: self.time_limited || other.time_limited,
+            cancelled: self.cancelled || other.cancelled,
+        }
+    }
+
+    pub fn skipped_files(&self) -> usize {
+        self.skipped.len()
+    }
+
     pub fn total_processed(&self) -> usize {
-        self.successful_copies + self.skipped_files + self.errors.len()
+        self.successful_copies + self.skipped_files() + self.errors.len()
     }
 
     pub fn success_rate(&self) -> f64 {
@@ -173,13 +401,19 @@ impl FileCopyReport {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct CopiedFile {
     pub source: String,
     pub destination: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedFile {
+    pub path: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct CopyError {
     pub source: String,
     pub destination: String,
@@ -194,8 +428,13 @@ pub mod functional {
     pub fn create_copy_filter(
         allowed_extensions: Vec<String>,
     ) -> impl Fn(&FvwArqDiarioExt) -> bool {
+        let allowed: Vec<String> = allowed_extensions
+            .iter()
+            .filter_map(|ext| crate::models::normalize_extension(ext))
+            .collect();
+
         move |revenda: &FvwArqDiarioExt| -> bool {
-            allowed_extensions.contains(&revenda.extensao)
+            revenda.extensions().iter().any(|ext| allowed.contains(ext))
         }
     }
 
@@ -211,10 +450,349 @@ pub mod functional {
     pub fn reduce_copy_results(results: Vec<CopyResult>) -> (usize, usize, usize) {
         results.into_iter().fold((0, 0, 0), |(success, skip, error), result| {
             match result {
-                CopyResult::Success { .. } => (success + 1, skip, error),
+                CopyResult::Created { .. } | CopyResult::Overwritten { .. } => (success + 1, skip, error),
                 CopyResult::Skipped { .. } => (success, skip + 1, error),
                 CopyResult::Error { .. } => (success, skip, error + 1),
             }
         })
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{SKIP_REASON_COLLISION, SKIP_REASON_DRY_RUN, SKIP_REASON_EXISTS};
+    use std::fs;
+
+    fn revenda(input: &std::path::Path, output: &std::path::Path) -> FvwArqDiarioExt {
+        FvwArqDiarioExt::new(
+            1,
+            1,
+            ".txt".to_string(),
+            0,
+            input.to_string_lossy().to_string(),
+            output.to_string_lossy().to_string(),
+        )
+    }
+
+    #[test]
+    fn test_create_copy_jobs_uses_overrides() {
+        let config = FileCopyConfig::default();
+        let revendas = vec![
+            revenda("in-a".as_ref(), "out-a".as_ref()).with_overrides(Some(90), Some(true)),
+            revenda("in-b".as_ref(), "out-b".as_ref()),
+            revenda("".as_ref(), "out-c".as_ref()),
+        ];
+
+        let jobs = create_copy_jobs(&revendas, &config);
+
+        assert_eq!(jobs.len(), 2);
+        assert_eq!((jobs[0].look_back, jobs[0].overwrite), (Some(Duration::days(90)), OverwriteMode::Always));
+        assert_eq!((jobs[1].look_back, jobs[1].overwrite), (Some(Duration::days(15)), OverwriteMode::Never));
+    }
+
+    #[test]
+    fn test_copy_jobs_keep_paths_relative_to_the_input_directory() {
+        let revendas = vec![revenda("/in/a".as_ref(), "/out/a".as_ref())];
+        let job = &create_copy_jobs(&revendas, &FileCopyConfig::default())[0];
+
+        assert_eq!(job.dest_options.source_root.as_deref(), Some(std::path::Path::new("/in/a")));
+        assert_eq!(
+            crate::utils::compute_destination(std::path::Path::new("/in/a/2024/x.txt"), &job.dest_dir, &job.dest_options),
+            PathBuf::from("/out/a/2024/x.txt")
+        );
+    }
+
+    #[test]
+    fn test_copy_jobs_honor_per_revenda_overrides() {
+        let root = tempfile::tempdir().unwrap();
+        let (in_a, out_a) = (root.path().join("in-a"), root.path().join("out-a"));
+        let (in_b, out_b) = (root.path().join("in-b"), root.path().join("out-b"));
+        for dir in [&in_a, &out_a, &in_b, &out_b] {
+            fs::create_dir(dir).unwrap();
+        }
+
+        // Both destinations already hold a stale copy of the file
+        for (input, output) in [(&in_a, &out_a), (&in_b, &out_b)] {
+            fs::write(input.join("data.txt"), "fresh").unwrap();
+            fs::write(output.join("data.txt"), "stale").unwrap();
+        }
+
+        // An old file only inside the longer look-back window of revenda A
+        let old_file = in_a.join("old.txt");
+        fs::write(&old_file, "old").unwrap();
+        let thirty_days_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(30 * 24 * 3600);
+        fs::File::options().write(true).open(&old_file).unwrap().set_modified(thirty_days_ago).unwrap();
+
+        let revendas = vec![
+            revenda(&in_a, &out_a).with_overrides(Some(60), Some(true)),
+            revenda(&in_b, &out_b),
+        ];
+        let config = FileCopyConfig::default();
+        let results = crate::utils::copy_jobs_batch(&create_copy_jobs(&revendas, &config), &extract_file_extensions(&revendas));
+        let report = create_copy_report(results, &ErrorLog::disabled());
+
+        assert_eq!(report.successful_copies, 2);
+        assert_eq!(report.skipped_files(), 1);
+        assert_eq!(fs::read_to_string(out_a.join("data.txt")).unwrap(), "fresh");
+        assert!(out_a.join("old.txt").exists());
+        assert_eq!(fs::read_to_string(out_b.join("data.txt")).unwrap(), "stale");
+    }
+
+    #[tokio::test]
+    async fn test_copy_runs_share_the_cached_revendas() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        struct CountingSource(Arc<AtomicUsize>, FvwArqDiarioExt);
+
+        impl RevendaSource for CountingSource {
+            async fn load_revendas(&self) -> Result<Vec<FvwArqDiarioExt>> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                Ok(vec![self.1.clone()])
+            }
+        }
+
+        let root = tempfile::tempdir().unwrap();
+        let (input, output) = (root.path().join("in"), root.path().join("out"));
+        fs::create_dir(&input).unwrap();
+        fs::write(input.join("a.txt"), "a").unwrap();
+
+        let loads = Arc::new(AtomicUsize::new(0));
+        let source = CountingSource(Arc::clone(&loads), revenda(&input, &output));
+        let revendas = RevendaCache::new(source, DEFAULT_REVENDA_CACHE_TTL);
+
+        let first = copy_files_for_revendas_with(&revendas, FileCopyConfig::default()).await.unwrap();
+        copy_files_for_revendas_with(&revendas, FileCopyConfig::default()).await.unwrap();
+
+        assert_eq!(first.successful_copies, 1);
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_parse_copy_mapping() {
+        let mapping = |source: &str, destination: &str| Some((source.to_string(), destination.to_string()));
+
+        assert_eq!(parse_copy_mapping("/in/a:/out/a"), mapping("/in/a", "/out/a"));
+        assert_eq!(parse_copy_mapping(r"C:\in:D:\out"), mapping(r"C:\in", r"D:\out"));
+        assert_eq!(parse_copy_mapping(r"C:\in:/mnt/out"), mapping(r"C:\in", "/mnt/out"));
+        assert_eq!(parse_copy_mapping(r"\\server\share\in:E:/out"), mapping(r"\\server\share\in", "E:/out"));
+        assert_eq!(parse_copy_mapping("relative:out"), mapping("relative", "out"));
+        assert_eq!(parse_copy_mapping("/in/only"), None);
+        assert_eq!(parse_copy_mapping("/in:"), None);
+    }
+
+    #[test]
+    fn test_copy_files_with_mappings_needs_no_pool() {
+        let root = tempfile::tempdir().unwrap();
+        let (input, output) = (root.path().join("in"), root.path().join("out"));
+        fs::create_dir(&input).unwrap();
+        fs::write(input.join("a.txt"), "a").unwrap();
+        fs::write(input.join("b.dat"), "b").unwrap();
+
+        let mappings = vec![(input.to_string_lossy().to_string(), output.to_string_lossy().to_string())];
+        let report = copy_files_with_mappings(&mappings, &[".txt".to_string()], &FileCopyConfig::default());
+
+        assert_eq!(report.successful_copies, 1);
+        assert!(output.join("a.txt").exists());
+        assert!(!output.join("b.dat").exists());
+
+        // Running again skips the already copied file
+        let again = copy_files_with_mappings(&mappings, &[], &FileCopyConfig::default());
+        assert_eq!((again.successful_copies, again.skipped_files()), (1, 1));
+        assert!(output.join("b.dat").exists());
+    }
+
+    #[test]
+    fn test_copies_are_counted_in_the_shared_metrics() {
+        let root = tempfile::tempdir().unwrap();
+        let (input, output) = (root.path().join("in"), root.path().join("out"));
+        fs::create_dir(&input).unwrap();
+        fs::write(input.join("a.txt"), "a").unwrap();
+        fs::write(input.join("b.txt"), "b").unwrap();
+
+        let metrics = Arc::new(Metrics::default());
+        let config = FileCopyConfig { metrics: Arc::clone(&metrics), ..FileCopyConfig::default() };
+        let mappings = vec![(input.to_string_lossy().to_string(), output.to_string_lossy().to_string())];
+        copy_files_with_mappings(&mappings, &[], &config);
+
+        let snapshot = metrics.snapshot();
+        assert_eq!((snapshot.files_copied, snapshot.copy_errors), (2, 0));
+    }
+
+    #[test]
+    fn test_dry_run_reports_copies_without_writing() {
+        let root = tempfile::tempdir().unwrap();
+        let (input, output) = (root.path().join("in"), root.path().join("out"));
+        fs::create_dir(&input).unwrap();
+        fs::write(input.join("a.txt"), "a").unwrap();
+        fs::write(input.join("b.txt"), "b").unwrap();
+        fs::write(input.join("c.dat"), "c").unwrap();
+        fs::create_dir(&output).unwrap();
+        fs::write(output.join("b.txt"), "b").unwrap();
+
+        let mappings = vec![(input.to_string_lossy().to_string(), output.to_string_lossy().to_string())];
+        let config = FileCopyConfig { dry_run: true, ..FileCopyConfig::default() };
+
+        let report = copy_files_with_mappings(&mappings, &[".txt".to_string()], &config);
+        let reasons: Vec<&str> = report.skipped.iter().map(|skip| skip.reason.as_str()).collect();
+
+        assert_eq!((report.successful_copies, report.errors.len()), (0, 0));
+        assert_eq!(reasons, vec![SKIP_REASON_DRY_RUN, SKIP_REASON_EXISTS]);
+        assert!(!output.join("a.txt").exists());
+
+        // The real run copies exactly what the dry run previewed
+        let report = copy_files_with_mappings(&mappings, &[".txt".to_string()], &FileCopyConfig::default());
+        assert_eq!((report.successful_copies, report.skipped_files()), (1, 1));
+    }
+
+    #[test]
+    fn test_move_mode_empties_the_input_folder() {
+        let root = tempfile::tempdir().unwrap();
+        let (input, output) = (root.path().join("in"), root.path().join("out"));
+        fs::create_dir(&input).unwrap();
+        fs::write(input.join("a.txt"), "a").unwrap();
+        fs::write(input.join("b.txt"), "b").unwrap();
+
+        let mappings = vec![(input.to_string_lossy().to_string(), output.to_string_lossy().to_string())];
+        let config = FileCopyConfig { transfer: TransferMode::Move, ..FileCopyConfig::default() };
+        let report = copy_files_with_mappings(&mappings, &[], &config);
+
+        assert_eq!((report.successful_copies, report.errors.len()), (2, 0));
+        assert_eq!(fs::read_dir(&input).unwrap().count(), 0);
+        assert_eq!(fs::read_to_string(output.join("b.txt")).unwrap(), "b");
+    }
+
+    #[test]
+    fn test_copies_into_empty_dir_are_all_created() {
+        let root = tempfile::tempdir().unwrap();
+        let (input, output) = (root.path().join("in"), root.path().join("out"));
+        fs::create_dir(&input).unwrap();
+        fs::write(input.join("a.txt"), "a").unwrap();
+        fs::write(input.join("b.txt"), "b").unwrap();
+
+        let mappings = vec![(input.to_string_lossy().to_string(), output.to_string_lossy().to_string())];
+        let report = copy_files_with_mappings(&mappings, &[], &FileCopyConfig::default());
+
+        assert_eq!((report.successful_copies, report.overwritten_files), (2, 0));
+    }
+
+    #[test]
+    fn test_copies_over_existing_files_count_as_overwritten() {
+        let root = tempfile::tempdir().unwrap();
+        let (input, output) = (root.path().join("in"), root.path().join("out"));
+        fs::create_dir(&input).unwrap();
+        fs::create_dir(&output).unwrap();
+        fs::write(input.join("a.txt"), "new a").unwrap();
+        fs::write(input.join("b.txt"), "new b").unwrap();
+        fs::write(output.join("a.txt"), "old a").unwrap();
+
+        let mappings = vec![(input.to_string_lossy().to_string(), output.to_string_lossy().to_string())];
+        let config = FileCopyConfig { overwrite: OverwriteMode::Always, ..FileCopyConfig::default() };
+        let report = copy_files_with_mappings(&mappings, &[], &config);
+
+        assert_eq!((report.successful_copies, report.overwritten_files), (2, 1));
+        assert_eq!(fs::read_to_string(output.join("a.txt")).unwrap(), "new a");
+    }
+
+    #[test]
+    fn test_skipped_files_carry_their_reason() {
+        let root = tempfile::tempdir().unwrap();
+        let (input, output) = (root.path().join("in"), root.path().join("out"));
+        fs::create_dir(&input).unwrap();
+        fs::create_dir(&output).unwrap();
+
+        // a.txt was copied by an earlier run; b.txt is an unrelated file with the same name
+        fs::write(input.join("a.txt"), "same").unwrap();
+        fs::write(output.join("a.txt"), "same").unwrap();
+        fs::write(input.join("b.txt"), "new content").unwrap();
+        fs::write(output.join("b.txt"), "other").unwrap();
+
+        let mappings = vec![(input.to_string_lossy().to_string(), output.to_string_lossy().to_string())];
+        let report = copy_files_with_mappings(&mappings, &[], &FileCopyConfig::default());
+        let reasons: Vec<(String, String)> = report
+            .skipped
+            .iter()
+            .map(|skip| (skip.path.clone(), skip.reason.clone()))
+            .collect();
+
+        assert_eq!(report.skipped_files(), 2);
+        assert_eq!(
+            reasons,
+            vec![
+                (input.join("a.txt").to_string_lossy().to_string(), SKIP_REASON_EXISTS.to_string()),
+                (input.join("b.txt").to_string_lossy().to_string(), SKIP_REASON_COLLISION.to_string()),
+            ]
+        );
+        assert_eq!(fs::read_to_string(output.join("b.txt")).unwrap(), "other");
+    }
+
+    #[test]
+    fn test_error_log_receives_one_line_per_failed_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        let error_path = dir.path().join("errors.tsv");
+        let failure = |name: &str| CopyResult::Error {
+            source: PathBuf::from(format!("/in/{}", name)),
+            destination: PathBuf::from(format!("/out/{}", name)),
+            error: "Permission denied".to_string(),
+        };
+        let results = vec![
+            failure("a.txt"),
+            CopyResult::Created {
+                source: PathBuf::from("/in/b.txt"),
+                destination: PathBuf::from("/out/b.txt"),
+            },
+            failure("c.txt"),
+        ];
+
+        let report = create_copy_report(results, &ErrorLog::open(Some(&error_path)));
+
+        let contents = fs::read_to_string(&error_path).unwrap();
+        let lines: Vec<Vec<&str>> = contents.lines().map(|line| line.split('\t').collect()).collect();
+        assert_eq!(report.errors.len(), 2);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0][1..], ["/in/a.txt", "Permission denied"]);
+        assert_eq!(lines[1][1..], ["/in/c.txt", "Permission denied"]);
+    }
+
+    #[test]
+    fn test_copy_stops_once_the_deadline_passes() {
+        let dir = tempfile::tempdir().unwrap();
+        let (input, output) = (dir.path().join("in"), dir.path().join("out"));
+        fs::create_dir(&input).unwrap();
+        for name in ["a.txt", "b.txt"] {
+            fs::write(input.join(name), name).unwrap();
+        }
+        let mappings = vec![(input.to_string_lossy().to_string(), output.to_string_lossy().to_string())];
+        let with_budget = |budget| FileCopyConfig {
+            deadline: Some(Deadline::after(budget)),
+            ..FileCopyConfig::default()
+        };
+
+        let report = copy_files_with_mappings(&mappings, &[], &with_budget(std::time::Duration::ZERO));
+        assert!(report.time_limited);
+        assert_eq!(report.total_processed(), 0);
+        assert!(!output.join("a.txt").exists());
+
+        let report = copy_files_with_mappings(&mappings, &[], &with_budget(std::time::Duration::from_secs(3600)));
+        assert!(!report.time_limited);
+        assert_eq!(report.successful_copies, 2);
+    }
+
+    #[test]
+    fn test_copy_stops_once_cancelled() {
+        let dir = tempfile::tempdir().unwrap();
+        let (input, output) = (dir.path().join("in"), dir.path().join("out"));
+        fs::create_dir(&input).unwrap();
+        fs::write(input.join("a.txt"), "a").unwrap();
+        let mappings = vec![(input.to_string_lossy().to_string(), output.to_string_lossy().to_string())];
+        let config = FileCopyConfig::default();
+        config.cancel.cancel();
+
+        let report = copy_files_with_mappings(&mappings, &[], &config);
+        assert!(report.cancelled);
+        assert!(!report.time_limited);
+        assert_eq!(report.total_processed(), 0);
+        assert!(!output.join("a.txt").exists());
+    }
+}
@@ -1,15 +1,94 @@
-use crate::database::DbPool;
-use crate::models::{create_file_trace_from_path, FileTrace, FvwArqDiarioExt};
-use crate::utils::list_files_with_extensions;
-use anyhow::Result;
-use std::path::PathBuf;
+use crate::database::{ConflictKey, DbPool, RevendaCache, RevendaSource, DEFAULT_REVENDA_CACHE_TTL};
+use crate::models::{
+    create_file_trace_with_fs, create_file_traces_from_zip_with, is_archive_entry_path,
+    is_zip_archive, path_hash, resolve_file_times, truncate_hash, FileProcessingConfig, FileTrace, FileTraceStatus,
+    FvwArqDiarioExt, HASH_ALGORITHM_PATH, MIN_SAFE_HASH_LEN,
+};
+use crate::services::Metrics;
+use crate::utils::{stop_reason, CancellationToken, Clock, Deadline, ErrorLog, StopReason, FilePredicate, FileScanner, FileStat, FileSystem, OsFileSystem, RetryPolicy, ScanOptions, SystemClock};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::{mpsc, Semaphore};
 use tracing::{info, warn, error};
 
 /// Configuration for file discovery operations
 #[derive(Debug, Clone)]
 pub struct FileDiscoveryConfig {
     pub batch_size: usize,
+    /// Hash up to `hash_concurrency` files at once; when false files are hashed one at a time
     pub parallel_processing: bool,
+    /// Maximum number of files being hashed at once, bounding open file descriptors and disk
+    /// contention on slow storage (default: the number of CPUs; 0 is treated as 1)
+    pub hash_concurrency: usize,
+    /// Delete each source file once its trace has been inserted into the database
+    pub delete_after_register: bool,
+    /// Also delete files whose trace already existed (hash conflict); requires `delete_after_register`
+    pub delete_conflicting: bool,
+    /// Options for hashing and reading each discovered file
+    pub processing: FileProcessingConfig,
+    /// Register each file inside discovered `.zip` archives instead of the archive itself
+    pub expand_archives: bool,
+    /// Retry policy for listing output directories on transient errors
+    pub scan_retry: RetryPolicy,
+    /// Also scan the subdirectories of each output directory (e.g. per-day folders)
+    pub recursive: bool,
+    /// With `recursive`, how many levels of subdirectories are scanned: `Some(0)` is the
+    /// output directory only and None the whole tree
+    pub max_depth: Option<usize>,
+    /// File name patterns (`*` and `?` wildcards, case-insensitive) registered as Banned
+    pub banned_patterns: Vec<String>,
+    /// Reset already-registered traces in Error back to Pending when their file is seen again
+    pub requeue_errors: bool,
+    /// Content types detected from magic bytes that match extension-less files (empty disables detection)
+    pub content_types: Vec<String>,
+    /// Append one line per file that failed processing to this file, in addition to tracing
+    pub error_log: Option<PathBuf>,
+    /// (from_prefix, to_prefix): store paths scanned under `from_prefix` (e.g. a local snapshot)
+    /// as if they were under `to_prefix` (the canonical production path)
+    pub path_rewrite: Option<(String, String)>,
+    /// Re-stat each file just before saving and drop traces whose file changed since hashing
+    pub recheck_before_save: bool,
+    /// Maximum number of batch inserts running at once (capped by the pool's max connections)
+    pub max_db_concurrency: usize,
+    /// Insert each batch with one statement per DN, so each statement touches a single
+    /// partition of a DN-partitioned table
+    pub partition_inserts_by_dn: bool,
+    /// Custom predicates every discovered file must pass, alongside the extension filter
+    pub file_filters: Vec<FilePredicate>,
+    /// Leave zero-byte files out of processing, counting them in `zero_byte_skipped`
+    pub skip_zero_byte: bool,
+    /// Paths the scan stage may queue ahead of processing; scanning waits once the queue is full
+    pub scan_queue_capacity: usize,
+    /// How many of the largest and oldest files the report lists
+    pub report_top_n: usize,
+    /// Compare traces skipped by the hash conflict against the stored row and report
+    /// those whose size or line count differs as potential hash collisions
+    pub check_hash_collisions: bool,
+    /// Queue no new file once this passes; files already queued are still registered
+    pub deadline: Option<Deadline>,
+    /// Queue no new file once this is cancelled, e.g. on Ctrl-C; queued files are still registered
+    pub cancel: CancellationToken,
+    /// Live counters updated as each file is processed and each batch saved, e.g. for the status server
+    pub metrics: Arc<Metrics>,
+    /// Clock future modification times and each trace's `processed_at` are taken from
+    pub clock: Arc<dyn Clock>,
+    /// Probe every output directory before scanning and leave the unreachable ones out,
+    /// with one consolidated warning
+    pub preflight_dirs: bool,
+    /// With `preflight_dirs`, abort the discovery instead when any directory is unreachable
+    pub preflight_strict: bool,
+    /// Tenant stamped on every trace registered by the run, next to its run id
+    pub tenant: Option<String>,
+    /// Unique key a trace must not share with a registered one to be inserted
+    pub conflict_key: ConflictKey,
 }
 
 impl Default for FileDiscoveryConfig {
@@ -17,6 +96,35 @@ impl Default for FileDiscoveryConfig {
         Self {
             batch_size: 1000,
             parallel_processing: true,
+            hash_concurrency: default_hash_concurrency(),
+            delete_after_register: false,
+            delete_conflicting: false,
+            processing: FileProcessingConfig::default(),
+            expand_archives: false,
+            scan_retry: RetryPolicy::default(),
+            recursive: false,
+            max_depth: None,
+            banned_patterns: Vec::new(),
+            requeue_errors: false,
+            content_types: Vec::new(),
+            error_log: None,
+            path_rewrite: None,
+            recheck_before_save: false,
+            max_db_concurrency: 4,
+            partition_inserts_by_dn: false,
+            file_filters: Vec::new(),
+            skip_zero_byte: false,
+            scan_queue_capacity: 1024,
+            report_top_n: 10,
+            check_hash_collisions: false,
+            deadline: None,
+            cancel: CancellationToken::new(),
+            metrics: Arc::new(Metrics::default()),
+            clock: Arc::new(SystemClock),
+            preflight_dirs: false,
+            preflight_strict: false,
+            tenant: None,
+            conflict_key: ConflictKey::default(),
         }
     }
 }
@@ -35,9 +143,7 @@ pub fn extract_output_directories(revendas: &[FvwArqDiarioExt]) -> Vec<String> {
 pub fn extract_unique_extensions(revendas: &[FvwArqDiarioExt]) -> Vec<String> {
     let mut extensions: Vec<String> = revendas
         .iter()
-        .map(|revenda| &revenda.extensao)
-        .filter(|ext| !ext.is_empty())
-        .cloned()
+        .flat_map(|revenda| revenda.extensions())
         .collect();
     
     extensions.sort();
@@ -46,14 +152,29 @@ pub fn extract_unique_extensions(revendas: &[FvwArqDiarioExt]) -> Vec<String> {
 }
 
 /// Main file discovery operation - functional composition
+/// Every trace saved by one call shares the run id generated when it starts
 pub async fn discover_and_register_files(
     pool: &DbPool,
     config: FileDiscoveryConfig,
 ) -> Result<FileDiscoveryReport> {
-    info!("Starting file discovery and registration...");
+    let revendas = RevendaCache::new(pool.clone(), DEFAULT_REVENDA_CACHE_TTL);
+    discover_and_register_files_with(pool, &revendas, config).await
+}
+
+/// Discover and register the files of the revendas served by `revendas`, shared with the
+/// other phases of the run
+pub async fn discover_and_register_files_with<S: RevendaSource>(
+    pool: &DbPool,
+    revendas: &RevendaCache<S>,
+    config: FileDiscoveryConfig,
+) -> Result<FileDiscoveryReport> {
+    let run = ScanRun::start();
+    info!("Starting file discovery and registration (run {})...", run.id);
+
+    warn_on_unsafe_hash_truncation(&config);
 
     // Get revendas data
-    let revendas = crate::database::arq_vw_ext::get_revendas(pool).await?;
+    let revendas = revendas.get_revendas().await?;
     
     if revendas.is_empty() {
         warn!("No revendas found in database");
@@ -64,45 +185,368 @@ pub async fn discover_and_register_files(
     let output_directories = extract_output_directories(&revendas);
     let extensions = extract_unique_extensions(&revendas);
 
+    let report = discover_in_directories(pool, output_directories, extensions, &config, &run.id).await?;
+    Ok(run.finish(report))
+}
+
+/// Scan `output_directories` for `extensions` and register what is found under `run_id`
+/// Shared by the whole-run discovery and the per-revenda units of a pipelined run
+pub async fn discover_in_directories(
+    pool: &DbPool,
+    output_directories: Vec<String>,
+    extensions: Vec<String>,
+    config: &FileDiscoveryConfig,
+    run_id: &str,
+) -> Result<FileDiscoveryReport> {
+    let (output_directories, unreachable_directories) = if config.preflight_dirs {
+        // Probing an unreachable share can block for a long time; keep it off the async threads
+        let preflight = tokio::task::spawn_blocking(move || preflight_directories(&OsFileSystem, output_directories))
+            .await
+            .context("Preflight task failed")?;
+        apply_preflight(preflight, config.preflight_strict)?
+    } else {
+        (output_directories, Vec::new())
+    };
+
     info!("Scanning {} directories for {} file extensions", 
           output_directories.len(), extensions.len());
     info!("Extensions: {:?}", extensions);
 
-    // Discover files across all directories
-    let discovered_files = discover_files_in_directories(&output_directories, &extensions)?;
-    
-    info!("Discovered {} files", discovered_files.len());
+    // Scan directories while processing what was already found; the bounded queue between
+    // the two stages makes scanning wait whenever processing falls behind
+    let (queue, scan) = spawn_scan_stage(output_directories, extensions, config);
+    let report = register_files(pool, queue, config, run_id).await?;
+    let scan = scan.await?;
+    let scanned_directories = scan.directories;
+    warn_on_early_stop(scan.stopped);
 
-    if discovered_files.is_empty() {
+    info!("Discovered {} files", report.files_discovered);
+
+    let files_filtered_out = scanned_directories.iter().map(|directory| directory.filtered_out).sum();
+    if report.files_discovered == 0 && files_filtered_out > 0 {
+        warn!("All {} matching files were filtered out by the date, size or custom filters", files_filtered_out);
+    } else if report.files_discovered == 0 {
         info!("No files found for processing");
-        return Ok(FileDiscoveryReport::empty());
     }
 
-    let discovered_count = discovered_files.len();
+    Ok(FileDiscoveryReport {
+        scanned_directories,
+        unreachable_directories,
+        files_filtered_out,
+        time_limited: scan.stopped == Some(StopReason::Deadline),
+        cancelled: scan.stopped == Some(StopReason::Cancelled),
+        timings: DiscoveryTimings {
+            scan_ms: elapsed_ms(scan.elapsed),
+            ..report.timings
+        },
+        ..report
+    })
+}
 
-    // Process files to create FileTrace objects
-    let file_traces = process_files_to_traces(discovered_files).await;
-    let successful_traces: Vec<FileTrace> = file_traces
+/// Identity and start time of one discovery run
+pub(crate) struct ScanRun {
+    pub(crate) id: String,
+    started_at: DateTime<Utc>,
+}
+
+impl ScanRun {
+    pub(crate) fn start() -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            started_at: Utc::now(),
+        }
+    }
+
+    /// Stamp the run's id, start time and duration onto its report
+    pub(crate) fn finish(self, report: FileDiscoveryReport) -> FileDiscoveryReport {
+        let duration_ms = (Utc::now() - self.started_at).num_milliseconds().max(0) as u64;
+        info!("Run {} finished in {} ms", self.id, duration_ms);
+        FileDiscoveryReport {
+            run_id: Some(self.id),
+            run_started_at: Some(self.started_at),
+            run_duration_ms: duration_ms,
+            ..report
+        }
+    }
+}
+
+/// Result of probing the output directories before scanning
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PreflightSummary {
+    pub reachable: Vec<String>,
+    /// (directory, error) for every directory that could not be listed
+    pub unreachable: Vec<(String, String)>,
+}
+
+/// Check that every directory can be listed, with one cheap `read_dir` probe each
+/// No retries: the point is to fail fast on unreachable shares
+pub fn preflight_directories<F: FileSystem + ?Sized>(file_system: &F, directories: Vec<String>) -> PreflightSummary {
+    directories
         .into_iter()
-        .filter_map(|result| match result {
-            Ok(trace) => Some(trace),
-            Err(e) => {
-                error!("Failed to process file: {}", e);
-                None
+        .fold(PreflightSummary::default(), |mut summary, directory| {
+            match file_system.read_dir(Path::new(&directory)) {
+                Ok(_) => summary.reachable.push(directory),
+                Err(e) => summary.unreachable.push((directory, e.to_string())),
             }
+            summary
         })
-        .collect();
+}
+
+/// Keep the reachable directories, or fail when `strict` and any is unreachable
+/// Returns the directories to scan and the unreachable ones left out
+fn apply_preflight(preflight: PreflightSummary, strict: bool) -> Result<(Vec<String>, Vec<String>)> {
+    if preflight.unreachable.is_empty() {
+        return Ok((preflight.reachable, Vec::new()));
+    }
+
+    let details = preflight
+        .unreachable
+        .iter()
+        .map(|(directory, e)| format!("{} ({})", directory, e))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let total = preflight.reachable.len() + preflight.unreachable.len();
+
+    if strict {
+        anyhow::bail!("{} of {} output directories are unreachable: {}", preflight.unreachable.len(), total, details);
+    }
+
+    warn!(
+        "Skipping {} of {} output directories that are unreachable: {}",
+        preflight.unreachable.len(),
+        total,
+        details
+    );
+    let unreachable = preflight.unreachable.into_iter().map(|(directory, _)| directory).collect();
+    Ok((preflight.reachable, unreachable))
+}
+
+/// Register exactly the listed files, bypassing directory scanning
+/// Paths that do not exist (or are not regular files) are skipped with a warning
+pub async fn discover_from_file_list(
+    pool: &DbPool,
+    paths: Vec<PathBuf>,
+    config: FileDiscoveryConfig,
+) -> Result<FileDiscoveryReport> {
+    let run = ScanRun::start();
+    info!("Registering {} listed files (run {})...", paths.len(), run.id);
+
+    warn_on_unsafe_hash_truncation(&config);
+
+    let (existing, missing): (Vec<PathBuf>, Vec<PathBuf>) = paths.into_iter().partition(|path| path.is_file());
+
+    for path in &missing {
+        warn!("Listed file does not exist, skipping: {}", path.display());
+    }
+
+    if existing.is_empty() {
+        info!("No listed files found for processing");
+        return Ok(run.finish(FileDiscoveryReport::empty()));
+    }
+
+    let (queue, listing) = spawn_list_stage(existing, &config);
+    let report = register_files(pool, queue, &config, &run.id).await?;
+    let stopped = listing.await?;
+    warn_on_early_stop(stopped);
+
+    Ok(run.finish(FileDiscoveryReport {
+        time_limited: stopped == Some(StopReason::Deadline),
+        cancelled: stopped == Some(StopReason::Cancelled),
+        ..report
+    }))
+}
+
+/// List stage of a file list: queue the listed files for processing, queueing no further
+/// file once the deadline passes or the run is cancelled
+/// Returns why queueing stopped early, if it did
+fn spawn_list_stage(
+    paths: Vec<PathBuf>,
+    config: &FileDiscoveryConfig,
+) -> (mpsc::Receiver<PathBuf>, tokio::task::JoinHandle<Option<StopReason>>) {
+    let (sender, receiver) = mpsc::channel(config.scan_queue_capacity.max(1));
+    let (deadline, cancel) = (config.deadline, config.cancel.clone());
+
+    let listing = tokio::spawn(async move {
+        for path in paths {
+            if let Some(reason) = stop_reason(deadline, &cancel) {
+                return Some(reason);
+            }
+            // Stop queueing if processing has gone away
+            if sender.send(path).await.is_err() {
+                break;
+            }
+        }
+        None
+    });
+
+    (receiver, listing)
+}
+
+/// Log why discovery queued no further files, if it stopped early
+fn warn_on_early_stop(stopped: Option<StopReason>) {
+    match stopped {
+        Some(StopReason::Deadline) => warn!("File discovery stopped early: the run's time budget was used up"),
+        Some(StopReason::Cancelled) => warn!("File discovery stopped early: the run was cancelled"),
+        None => {}
+    }
+}
+
+/// Pure function parsing a file list: one path per line, ignoring blank lines and `#` comments
+pub fn parse_file_list(contents: &str) -> Vec<PathBuf> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(PathBuf::from)
+        .collect()
+}
+
+pub(crate) fn warn_on_unsafe_hash_truncation(config: &FileDiscoveryConfig) {
+    if let Some(len) = config.processing.hash_truncate_len.filter(|len| *len < MIN_SAFE_HASH_LEN) {
+        warn!(
+            "Hash truncation to {} hex chars is below the recommended minimum of {}; collisions become likely",
+            len, MIN_SAFE_HASH_LEN
+        );
+    }
+}
+
+/// Process, save and post-process the files arriving on the queue
+async fn register_files(
+    pool: &DbPool,
+    discovered_files: mpsc::Receiver<PathBuf>,
+    config: &FileDiscoveryConfig,
+    run_id: &str,
+) -> Result<FileDiscoveryReport> {
+    // Process files to create FileTrace objects
+    let error_log = ErrorLog::open(config.error_log.as_deref());
+    let hash_started = Instant::now();
+    let ProcessedFiles { results: file_traces, discovered_paths, zero_byte_skipped } =
+        process_files_to_traces(
+            discovered_files,
+            &config.processing,
+            ProcessOptions {
+                expand_archives: config.expand_archives,
+                skip_zero_byte: config.skip_zero_byte,
+                concurrency: effective_hash_concurrency(config),
+                metrics: Arc::clone(&config.metrics),
+                clock: Arc::clone(&config.clock),
+            },
+            &error_log,
+        )
+        .await;
+    let hash_ms = elapsed_ms(hash_started.elapsed());
+    if zero_byte_skipped > 0 {
+        info!("Skipped {} zero-byte files", zero_byte_skipped);
+    }
+    let discovered_count = discovered_paths.len();
+    if discovered_count == 0 {
+        return Ok(FileDiscoveryReport {
+            zero_byte_skipped,
+            ..FileDiscoveryReport::empty()
+        });
+    }
+    let (successful_traces, processing_errors) = functional::reduce_processing_results(file_traces);
+
+    for e in &processing_errors {
+        error!("Failed to process file: {:#}", e);
+    }
+    let errors_by_kind = count_errors_by_kind(&processing_errors);
 
     info!("Successfully processed {} files", successful_traces.len());
 
+    for file_trace in successful_traces.iter().filter(|file_trace| file_trace.count_mismatch) {
+        warn!(
+            "Trailer record count does not match {} counted lines: {}",
+            file_trace.total_lines, file_trace.path
+        );
+    }
+
+    let successful_traces = apply_banned_patterns(successful_traces, &config.banned_patterns);
+    let (successful_traces, files_changed) = if config.recheck_before_save {
+        drop_changed_traces(&OsFileSystem, successful_traces)
+    } else {
+        (successful_traces, 0)
+    };
+    let successful_traces = rewrite_trace_paths(successful_traces, config.path_rewrite.as_ref());
+    let successful_traces: Vec<FileTrace> = successful_traces
+        .into_iter()
+        .map(|file_trace| FileTrace {
+            run_id: Some(run_id.to_string()),
+            tenant: config.tenant.clone(),
+            ..file_trace
+        })
+        .collect();
+
     // Save to database in batches
-    let saved_count = save_file_traces_in_batches(pool, &successful_traces, config.batch_size).await?;
+    let save_started = Instant::now();
+    let save_outcome = save_file_traces_in_batches(pool, &successful_traces, config).await?;
+    config.metrics.record_files_saved(save_outcome.saved.len());
+    let save_ms = elapsed_ms(save_started.elapsed());
+
+    let banned_changes = banned_status_changes(&successful_traces, &save_outcome);
+    let requeued_changes = if config.requeue_errors {
+        requeue_errored_traces(pool, &successful_traces, &save_outcome, config.tenant.as_deref()).await
+    } else {
+        Vec::new()
+    };
+    let hash_collisions = if config.check_hash_collisions {
+        check_hash_collisions(pool, &successful_traces, &save_outcome, config.conflict_key).await
+    } else {
+        Vec::new()
+    };
+
+    let files_deleted = if config.delete_after_register {
+        // Delete the scanned copies, never the production paths they were rewritten to
+        let to_delete: Vec<String> = select_files_to_delete(&successful_traces, &save_outcome, config.delete_conflicting)
+            .into_iter()
+            .map(|path| match &config.path_rewrite {
+                Some((from, to)) => rewrite_path_prefix(path, to, from).unwrap_or_else(|| path.to_string()),
+                None => path.to_string(),
+            })
+            .collect();
+        delete_source_files(&to_delete)
+    } else {
+        0
+    };
+
+    let top_largest = functional::top_n_by_key(&successful_traces, config.report_top_n, |file_trace| file_trace.size_bytes)
+        .into_iter()
+        .map(|file_trace| (file_trace.path.clone(), file_trace.size_bytes))
+        .collect();
+    let oldest = functional::top_n_by_key(&successful_traces, config.report_top_n, |file_trace| std::cmp::Reverse(file_trace.modified_at))
+        .into_iter()
+        .map(|file_trace| (file_trace.path.clone(), file_trace.modified_at))
+        .collect();
 
     let report = FileDiscoveryReport {
         files_discovered: discovered_count,
         files_processed: successful_traces.len(),
-        files_saved: saved_count,
-        processing_errors: discovered_count - successful_traces.len(),
+        files_saved: save_outcome.saved.len(),
+        processing_errors: processing_errors.len(),
+        errors_by_kind,
+        files_deleted,
+        files_banned: banned_changes.len(),
+        status_changes: banned_changes.into_iter().chain(requeued_changes).collect(),
+        scanned_directories: Vec::new(),
+        unreachable_directories: Vec::new(),
+        discovered_paths,
+        files_changed_before_save: files_changed,
+        files_failed_to_save: save_outcome.failed.len(),
+        files_filtered_out: 0,
+        zero_byte_skipped,
+        run_id: None,
+        run_started_at: None,
+        run_duration_ms: 0,
+        top_largest,
+        oldest,
+        hash_collisions,
+        timings: DiscoveryTimings {
+            scan_ms: 0.0,
+            hash_ms,
+            save_ms,
+        },
+        time_limited: false,
+        cancelled: false,
     };
 
     info!(
@@ -117,79 +561,693 @@ pub async fn discover_and_register_files(
 }
 
 /// Discover files in multiple directories
+/// Returns the files found plus one summary per directory attempted, including failed ones
+#[cfg(test)]
 fn discover_files_in_directories(
     directories: &[String],
     extensions: &[String],
-) -> Result<Vec<PathBuf>> {
+    config: &FileDiscoveryConfig,
+) -> (Vec<PathBuf>, Vec<DirectoryScanSummary>) {
     let mut all_files = Vec::new();
+    let mut summaries = Vec::with_capacity(directories.len());
 
     for directory in directories {
-        match list_files_with_extensions(directory, extensions, None) {
-            Ok(mut files) => {
-                info!("Found {} files in directory: {}", files.len(), directory);
-                all_files.append(&mut files);
+        let (summary, _) = scan_directory(directory, extensions, config, |file| {
+            all_files.push(file);
+            Ok(())
+        });
+        summaries.push(summary);
+    }
+
+    (all_files, summaries)
+}
+
+/// What the scan stage did
+struct ScanOutcome {
+    /// One summary per directory scanned
+    directories: Vec<DirectoryScanSummary>,
+    elapsed: std::time::Duration,
+    /// Why scanning stopped before the last directory, if it did
+    stopped: Option<StopReason>,
+}
+
+/// Scan stage: list each directory on a blocking thread and queue the files found
+/// Sending waits while the queue holds `scan_queue_capacity` paths, so scanning never runs
+/// further ahead of processing than that. Once the deadline passes or the run is cancelled
+/// no further file is queued.
+fn spawn_scan_stage(
+    directories: Vec<String>,
+    extensions: Vec<String>,
+    config: &FileDiscoveryConfig,
+) -> (mpsc::Receiver<PathBuf>, tokio::task::JoinHandle<ScanOutcome>) {
+    let (sender, receiver) = mpsc::channel(config.scan_queue_capacity.max(1));
+    let config = config.clone();
+
+    let scan = tokio::task::spawn_blocking(move || {
+        let started = Instant::now();
+        let mut summaries = Vec::with_capacity(directories.len());
+        let mut stopped = None;
+        for directory in &directories {
+            stopped = stop_reason(config.deadline, &config.cancel);
+            if stopped.is_some() {
+                break;
             }
-            Err(e) => {
-                warn!("Failed to scan directory {}: {}", directory, e);
-                // Continue processing other directories
+            let (summary, stop) = scan_directory(directory, &extensions, &config, |file| {
+                if let Some(reason) = stop_reason(config.deadline, &config.cancel) {
+                    return Err(ScanStop::Limit(reason));
+                }
+                // Stop scanning if processing has gone away
+                sender.blocking_send(file).map_err(|_| ScanStop::Closed)
+            });
+            summaries.push(summary);
+
+            match stop {
+                Some(ScanStop::Limit(reason)) => {
+                    stopped = Some(reason);
+                    break;
+                }
+                Some(ScanStop::Closed) => break,
+                None => {}
             }
         }
+        ScanOutcome {
+            directories: summaries,
+            elapsed: started.elapsed(),
+            stopped,
+        }
+    });
+
+    (receiver, scan)
+}
+
+/// Why a directory scan stopped queueing files before the end of the directory
+enum ScanStop {
+    /// The run's deadline passed or it was cancelled
+    Limit(StopReason),
+    /// The process stage is gone
+    Closed,
+}
+
+/// Scan one directory lazily, handing each file that passes the filters to `queue` as soon as
+/// it is found, so no more paths are held than `queue` buffers
+/// A directory that cannot be listed queues no files and records the error
+fn scan_directory<F>(
+    directory: &str,
+    extensions: &[String],
+    config: &FileDiscoveryConfig,
+    mut queue: F,
+) -> (DirectoryScanSummary, Option<ScanStop>)
+where
+    F: FnMut(PathBuf) -> Result<(), ScanStop>,
+{
+    let options = ScanOptions {
+        extensions: extensions.to_vec(),
+        retry: config.scan_retry.clone(),
+        content_types: config.content_types.clone(),
+        filters: config.file_filters.clone(),
+        recursive: config.recursive,
+        max_depth: config.max_depth,
+        future_mtime_policy: config.processing.future_mtime_policy,
+        now: Some(config.clock.now()),
+        ..ScanOptions::default()
+    };
+
+    let mut summary = DirectoryScanSummary {
+        path: directory.to_string(),
+        file_count: 0,
+        filtered_out: 0,
+        error: None,
+    };
+
+    let scanner = match FileScanner::new(directory, options.selection()) {
+        Ok(scanner) => scanner,
+        Err(e) => {
+            warn!("Failed to scan directory {}: {}", directory, e);
+            summary.error = Some(format!("{:#}", e));
+            return (summary, None);
+        }
+    };
+
+    let mut stop = None;
+    for path in scanner.filter_map(|entry| entry.ok()) {
+        if !options.passes_filters(&path) {
+            summary.filtered_out += 1;
+            continue;
+        }
+        if let Err(reason) = queue(path) {
+            stop = Some(reason);
+            break;
+        }
+        summary.file_count += 1;
     }
 
-    Ok(all_files)
+    info!("Found {} files in directory: {} ({} filtered out)", summary.file_count, directory, summary.filtered_out);
+    (summary, stop)
 }
 
-/// Process discovered files into FileTrace objects
-async fn process_files_to_traces(files: Vec<PathBuf>) -> Vec<Result<FileTrace>> {
-    // In a real-world scenario, you might want to use tokio::task::spawn_blocking
-    // for CPU-intensive file processing to avoid blocking the async runtime
-    
-    let mut results = Vec::with_capacity(files.len());
-    
-    for file_path in files {
-        let result = tokio::task::spawn_blocking(move || {
-            create_file_trace_from_path(file_path)
+/// Default number of files hashed at once: the available parallelism, or 1 if unknown
+pub fn default_hash_concurrency() -> usize {
+    std::thread::available_parallelism().map(usize::from).unwrap_or(1)
+}
+
+/// Pure function to get how many files are hashed at once: 1 without `parallel_processing`
+fn effective_hash_concurrency(config: &FileDiscoveryConfig) -> usize {
+    if config.parallel_processing {
+        config.hash_concurrency.max(1)
+    } else {
+        1
+    }
+}
+
+/// How the process stage treats each queued file
+#[derive(Debug, Clone)]
+struct ProcessOptions {
+    /// Register each file inside a zip archive instead of the archive itself
+    expand_archives: bool,
+    /// Leave zero-byte files out, only counting them
+    skip_zero_byte: bool,
+    /// Files hashed at once
+    concurrency: usize,
+    /// Counters updated as each file is processed
+    metrics: Arc<Metrics>,
+    /// Clock each trace's `processed_at` is taken from
+    clock: Arc<dyn Clock>,
+}
+
+/// What the process stage produced
+struct ProcessedFiles {
+    results: Vec<Result<FileTrace>>,
+    /// Every file received, except skipped zero-byte files
+    discovered_paths: Vec<String>,
+    zero_byte_skipped: usize,
+}
+
+/// Pure function (except for file system access) to check a file is empty
+/// Unreadable metadata is left for processing to report
+fn is_zero_byte_file(path: &Path) -> bool {
+    std::fs::metadata(path).is_ok_and(|metadata| metadata.len() == 0)
+}
+
+/// Process stage: turn queued files into FileTrace objects until the queue closes
+/// Up to `concurrency` files are hashed at once; results keep the order files were queued in.
+/// With `expand_archives`, a zip archive yields one result per contained file
+/// Each failure is also appended to the error log. Also returns the path of every file processed.
+async fn process_files_to_traces(
+    files: mpsc::Receiver<PathBuf>,
+    processing: &FileProcessingConfig,
+    options: ProcessOptions,
+    error_log: &ErrorLog,
+) -> ProcessedFiles {
+    let ProcessOptions { expand_archives, skip_zero_byte, concurrency, metrics, clock } = options;
+    let processing = Arc::new(processing.clone());
+    let mut results = Vec::new();
+    let mut discovered_paths = Vec::new();
+    let mut zero_byte_skipped = 0;
+
+    let queued = futures::stream::unfold(files, |mut files| async move {
+        files.recv().await.map(|file_path| (file_path, files))
+    });
+    let processed = queued
+        .map(|file_path| {
+            let processing = Arc::clone(&processing);
+            let clock = Arc::clone(&clock);
+            let display_path = file_path.to_string_lossy().to_string();
+            async move {
+                // None when the file is a skipped zero-byte file
+                let file_results: Option<Vec<Result<FileTrace>>> = tokio::task::spawn_blocking(move || {
+                    if skip_zero_byte && is_zero_byte_file(&file_path) {
+                        None
+                    } else if expand_archives && is_zip_archive(&file_path) {
+                        match create_file_traces_from_zip_with(clock.as_ref(), &file_path, &processing) {
+                            Ok(traces) => Some(traces.into_iter().map(Ok).collect()),
+                            Err(e) => Some(vec![Err(e)]),
+                        }
+                    } else {
+                        Some(vec![create_file_trace_with_fs(&OsFileSystem, clock.as_ref(), file_path, &processing)])
+                    }
+                })
+                .await
+                .unwrap_or_else(|e| Some(vec![Err(anyhow::anyhow!("Task join error: {}", e))]));
+                (display_path, file_results)
+            }
         })
-        .await
-        .unwrap_or_else(|e| Err(anyhow::anyhow!("Task join error: {}", e)));
-        
-        results.push(result);
+        .buffered(concurrency.max(1));
+    let mut processed = std::pin::pin!(processed);
+
+    while let Some((display_path, file_results)) = processed.next().await {
+        let Some(file_results) = file_results else {
+            zero_byte_skipped += 1;
+            continue;
+        };
+        let mut errors = 0;
+        for e in file_results.iter().filter_map(|result| result.as_ref().err()) {
+            error_log.record(&display_path, &format!("{:#}", e));
+            errors += 1;
+        }
+        metrics.record_file_processed(file_results.len() - errors, errors);
+
+        discovered_paths.push(display_path);
+        results.extend(file_results);
     }
-    
-    results
+
+    ProcessedFiles {
+        results,
+        discovered_paths,
+        zero_byte_skipped,
+    }
+}
+
+/// Classification of per-file processing errors, used for alerting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub enum ProcessingErrorKind {
+    PermissionDenied,
+    NotFound,
+    IoError,
+    DecodeError,
+    Timeout,
+    Other,
+}
+
+impl ProcessingErrorKind {
+    /// Classify an error by inspecting its cause chain
+    pub fn classify(error: &anyhow::Error) -> Self {
+        for cause in error.chain() {
+            if let Some(io_error) = cause.downcast_ref::<io::Error>() {
+                return match io_error.kind() {
+                    io::ErrorKind::PermissionDenied => ProcessingErrorKind::PermissionDenied,
+                    io::ErrorKind::NotFound => ProcessingErrorKind::NotFound,
+                    io::ErrorKind::TimedOut => ProcessingErrorKind::Timeout,
+                    io::ErrorKind::InvalidData => ProcessingErrorKind::DecodeError,
+                    _ => ProcessingErrorKind::IoError,
+                };
+            }
+            if cause.is::<std::string::FromUtf8Error>() || cause.is::<std::str::Utf8Error>() {
+                return ProcessingErrorKind::DecodeError;
+            }
+            if cause.is::<tokio::time::error::Elapsed>() {
+                return ProcessingErrorKind::Timeout;
+            }
+        }
+
+        ProcessingErrorKind::Other
+    }
+}
+
+/// Pure function to count processing errors per kind
+pub fn count_errors_by_kind(errors: &[anyhow::Error]) -> BTreeMap<ProcessingErrorKind, usize> {
+    errors.iter().fold(BTreeMap::new(), |mut counts, error| {
+        *counts.entry(ProcessingErrorKind::classify(error)).or_insert(0) += 1;
+        counts
+    })
+}
+
+/// Keys (hash, path) of traces grouped by the outcome of their batch insert
+#[derive(Debug, Default)]
+struct SaveOutcome {
+    /// Traces inserted by this run, with their new id
+    saved: HashMap<(String, String), i32>,
+    /// Traces whose batch failed, so their database state is unknown
+    failed: HashSet<(String, String)>,
 }
 
 /// Save file traces to database in batches
+/// At most `max_db_concurrency` batch inserts run at once, capped by the pool's connection limit
+/// With `partition_inserts_by_dn`, each batch is inserted with one statement per DN
 async fn save_file_traces_in_batches(
     pool: &DbPool,
     file_traces: &[FileTrace],
+    config: &FileDiscoveryConfig,
+) -> Result<SaveOutcome> {
+    let permits = effective_db_concurrency(config.max_db_concurrency, pool.options().get_max_connections());
+    let semaphore = Semaphore::new(permits);
+
+    let outcome = if config.partition_inserts_by_dn {
+        save_batches_with(file_traces, config.batch_size, &semaphore, |batch| {
+            crate::database::file_trace::save_batch_returning_by_dn(pool, batch, config.conflict_key)
+        })
+        .await
+    } else {
+        save_batches_with(file_traces, config.batch_size, &semaphore, |batch| {
+            crate::database::file_trace::save_batch_returning(pool, batch, config.conflict_key)
+        })
+        .await
+    };
+
+    Ok(outcome)
+}
+
+/// Pure function bounding the configured save concurrency to `1..=pool_max_connections`
+fn effective_db_concurrency(configured: usize, pool_max_connections: u32) -> usize {
+    configured.clamp(1, (pool_max_connections as usize).max(1))
+}
+
+/// Run `save` for every batch concurrently, each holding a semaphore permit while it runs
+async fn save_batches_with<'a, F, Fut>(
+    file_traces: &'a [FileTrace],
     batch_size: usize,
-) -> Result<usize> {
-    let mut total_saved = 0;
+    semaphore: &Semaphore,
+    save: F,
+) -> SaveOutcome
+where
+    F: Fn(&'a [FileTrace]) -> Fut,
+    Fut: Future<Output = Result<Vec<(i32, String, String)>>>,
+{
+    let saves = file_traces.chunks(batch_size).map(|batch| {
+        let save = &save;
+        async move {
+            let _permit = semaphore.acquire().await.expect("save semaphore is never closed");
+            (batch, save(batch).await)
+        }
+    });
 
-    for batch in file_traces.chunks(batch_size) {
-        match crate::database::file_trace::save_batch(pool, batch).await {
+    let mut outcome = SaveOutcome::default();
+    for (batch, result) in futures::future::join_all(saves).await {
+        match result {
             Ok(saved) => {
-                total_saved += saved as usize;
-                info!("Saved batch of {} file traces to database", saved);
+                info!("Saved batch of {} file traces to database", saved.len());
+                outcome.saved.extend(saved.into_iter().map(|(id, hash, path)| ((hash, path), id)));
             }
             Err(e) => {
                 error!("Failed to save batch to database: {}", e);
+                outcome.failed.extend(batch.iter().map(trace_key));
                 // Continue with next batch
             }
         }
     }
 
-    Ok(total_saved)
+    outcome
+}
+
+/// Key identifying a trace row as returned by the insert
+fn trace_key(file_trace: &FileTrace) -> (String, String) {
+    (file_trace.hash.clone(), file_trace.path.clone())
+}
+
+/// Pure function selecting the source files that are safe to delete after saving
+/// Saved traces always qualify; conflicting traces only when `include_conflicting` is set.
+/// Traces from failed batches and entries inside archives never qualify.
+fn select_files_to_delete<'a>(
+    file_traces: &'a [FileTrace],
+    outcome: &SaveOutcome,
+    include_conflicting: bool,
+) -> Vec<&'a str> {
+    file_traces
+        .iter()
+        .filter(|file_trace| !is_archive_entry_path(&file_trace.path))
+        .filter(|file_trace| {
+            let key = trace_key(file_trace);
+            outcome.saved.contains_key(&key) || (include_conflicting && !outcome.failed.contains(&key))
+        })
+        .map(|file_trace| file_trace.path.as_str())
+        .collect()
+}
+
+/// Re-stat each traced file and drop the traces whose size or modification time changed since
+/// hashing, so no stale hash is saved; the file is picked up again by the next run.
+/// Files that can no longer be read are dropped too. Archive entries are kept unchecked.
+/// Returns the kept traces and the number dropped.
+fn drop_changed_traces<F: FileSystem + ?Sized>(file_system: &F, file_traces: Vec<FileTrace>) -> (Vec<FileTrace>, usize) {
+    let (kept, changed): (Vec<FileTrace>, Vec<FileTrace>) = file_traces.into_iter().partition(|file_trace| {
+        if is_archive_entry_path(&file_trace.path) {
+            return true;
+        }
+        match file_system.stat(Path::new(&file_trace.path)) {
+            Ok(stat) => !trace_changed_since_hashing(file_trace, &stat),
+            Err(e) => {
+                warn!("File vanished before save, dropping its trace: {}: {}", file_trace.path, e);
+                false
+            }
+        }
+    });
+
+    for file_trace in &changed {
+        warn!("File changed after hashing, dropping its trace for re-processing: {}", file_trace.path);
+    }
+
+    (kept, changed.len())
+}
+
+/// Pure function comparing a trace's size and modification time against a fresh stat
+/// The modification time is only compared when the platform reports one
+fn trace_changed_since_hashing(file_trace: &FileTrace, stat: &FileStat) -> bool {
+    let size_changed = i64::try_from(stat.len).ok() != Some(file_trace.size_bytes);
+    let modified_changed = stat
+        .modified
+        .is_some_and(|modified| resolve_file_times(None, Some(modified)).1 != file_trace.modified_at);
+
+    size_changed || modified_changed
+}
+
+/// Pure function replacing a leading `from_prefix` path with `to_prefix`
+/// Prefixes match whole path components; returns None when `path` is not under `from_prefix`
+pub fn rewrite_path_prefix(path: &str, from_prefix: &str, to_prefix: &str) -> Option<String> {
+    let rest = Path::new(path).strip_prefix(from_prefix).ok()?;
+    Some(Path::new(to_prefix).join(rest).to_string_lossy().to_string())
+}
+
+/// Pure function applying the configured path rewrite to the traces' stored paths
+/// Path hashes follow the rewrite, so a file keeps its key whichever prefix it was scanned under
+fn rewrite_trace_paths(file_traces: Vec<FileTrace>, path_rewrite: Option<&(String, String)>) -> Vec<FileTrace> {
+    let Some((from, to)) = path_rewrite else {
+        return file_traces;
+    };
+
+    file_traces
+        .into_iter()
+        .map(|file_trace| match rewrite_path_prefix(&file_trace.path, from, to) {
+            Some(path) if file_trace.hash_algorithm == HASH_ALGORITHM_PATH => FileTrace {
+                hash: truncate_hash(path_hash(&path), Some(file_trace.hash.len())),
+                path,
+                ..file_trace
+            },
+            Some(path) => FileTrace { path, ..file_trace },
+            None => file_trace,
+        })
+        .collect()
+}
+
+/// Pure function checking a file name against a glob pattern (`*` any run, `?` any char,
+/// `[...]` a character class), ignoring case
+/// Invalid patterns never match
+pub fn matches_name_pattern(name: &str, pattern: &str) -> bool {
+    let options = glob::MatchOptions {
+        case_sensitive: false,
+        ..glob::MatchOptions::new()
+    };
+
+    glob::Pattern::new(pattern).is_ok_and(|pattern| pattern.matches_with(name, options))
+}
+
+/// Pure function marking traces whose file name matches a banned pattern as Banned
+fn apply_banned_patterns(file_traces: Vec<FileTrace>, patterns: &[String]) -> Vec<FileTrace> {
+    file_traces
+        .into_iter()
+        .map(|file_trace| {
+            if patterns.iter().any(|pattern| matches_name_pattern(&file_trace.name, pattern)) {
+                FileTrace {
+                    status_fvw: FileTraceStatus::Banned as i32,
+                    status_fnt: FileTraceStatus::Banned as i32,
+                    status_fa4: FileTraceStatus::Banned as i32,
+                    ..file_trace
+                }
+            } else {
+                file_trace
+            }
+        })
+        .collect()
+}
+
+/// Pure function listing (id, old, new) status changes for banned traces inserted by this run
+fn banned_status_changes(file_traces: &[FileTrace], outcome: &SaveOutcome) -> Vec<(i32, i32, i32)> {
+    file_traces
+        .iter()
+        .filter(|file_trace| file_trace.status_fvw == FileTraceStatus::Banned as i32)
+        .filter_map(|file_trace| outcome.saved.get(&trace_key(file_trace)))
+        .map(|id| (*id, FileTraceStatus::Pending as i32, FileTraceStatus::Banned as i32))
+        .collect()
+}
+
+/// Requeue the run tenant's already-registered traces in Error whose file was discovered again
+/// Returns (id, old, new) status changes; failures are logged and yield no changes
+async fn requeue_errored_traces(
+    pool: &DbPool,
+    file_traces: &[FileTrace],
+    outcome: &SaveOutcome,
+    tenant: Option<&str>,
+) -> Vec<(i32, i32, i32)> {
+    let conflicting_hashes: Vec<String> = conflicting_traces(file_traces, outcome)
+        .filter(|file_trace| file_trace.status_fvw != FileTraceStatus::Banned as i32)
+        .map(|file_trace| file_trace.hash.clone())
+        .collect();
+
+    match crate::database::file_trace::requeue_errored(pool, &conflicting_hashes, tenant).await {
+        Ok(ids) => ids
+            .into_iter()
+            .map(|id| (id, FileTraceStatus::Error as i32, FileTraceStatus::Pending as i32))
+            .collect(),
+        Err(e) => {
+            error!("Failed to requeue errored traces: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Pure function selecting the traces skipped by the ON CONFLICT clause
+/// (neither inserted nor part of a failed batch)
+fn conflicting_traces<'a>(file_traces: &'a [FileTrace], outcome: &'a SaveOutcome) -> impl Iterator<Item = &'a FileTrace> {
+    file_traces.iter().filter(|file_trace| {
+        let key = trace_key(file_trace);
+        !outcome.saved.contains_key(&key) && !outcome.failed.contains(&key)
+    })
+}
+
+/// Look up the stored rows behind conflicting traces and report those that cannot be the same file
+/// Failures are logged and yield no collisions
+async fn check_hash_collisions(
+    pool: &DbPool,
+    file_traces: &[FileTrace],
+    outcome: &SaveOutcome,
+    conflict_key: ConflictKey,
+) -> Vec<HashCollision> {
+    let conflicting: Vec<&FileTrace> = conflicting_traces(file_traces, outcome).collect();
+    if conflicting.is_empty() {
+        return Vec::new();
+    }
+
+    let filter = crate::database::file_trace::TraceFilter {
+        hashes: Some(conflicting.iter().map(|file_trace| file_trace.hash.clone()).collect()),
+        ..Default::default()
+    };
+    match crate::database::file_trace::get_by_filter(pool, &filter).await {
+        Ok(existing) => {
+            let collisions = find_hash_collisions(conflict_key, &conflicting, &existing);
+            for collision in &collisions {
+                warn!(
+                    "Possible hash collision: {} has hash {} but differs in size or line count from registered {}",
+                    collision.path, collision.hash, collision.existing_path
+                );
+            }
+            collisions
+        }
+        Err(e) => {
+            error!("Failed to check for hash collisions: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Pure function pairing each conflicting trace with the stored trace it conflicted with on
+/// `conflict_key`, keeping the pairs whose size or line count differ
+pub fn find_hash_collisions(conflict_key: ConflictKey, conflicting: &[&FileTrace], existing: &[FileTrace]) -> Vec<HashCollision> {
+    conflicting
+        .iter()
+        .filter_map(|incoming| {
+            let stored = existing.iter().find(|stored| conflict_key.same_key(stored, incoming))?;
+            let differs = stored.size_bytes != incoming.size_bytes || stored.total_lines != incoming.total_lines;
+            differs.then(|| HashCollision {
+                hash: incoming.hash.clone(),
+                path: incoming.path.clone(),
+                existing_path: stored.path.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Delete registered source files, logging failures without aborting
+fn delete_source_files(paths: &[String]) -> usize {
+    paths
+        .iter()
+        .filter(|path| match std::fs::remove_file(path) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("Failed to delete registered file {}: {}", path, e);
+                false
+            }
+        })
+        .count()
 }
 
 /// Report structure for file discovery operations
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct FileDiscoveryReport {
     pub files_discovered: usize,
     pub files_processed: usize,
     pub files_saved: usize,
     pub processing_errors: usize,
+    pub errors_by_kind: BTreeMap<ProcessingErrorKind, usize>,
+    pub files_deleted: usize,
+    /// Traces registered as Banned by this run
+    pub files_banned: usize,
+    /// Audit trail of (trace id, old status_fvw, new status_fvw) set by this run
+    pub status_changes: Vec<(i32, i32, i32)>,
+    /// Every directory scanning attempted, so "empty" can be told apart from "not scanned"
+    pub scanned_directories: Vec<DirectoryScanSummary>,
+    /// Output directories left out of the scan by the preflight check
+    pub unreachable_directories: Vec<String>,
+    /// Paths of every file found for registration, as scanned (left out of JSON reports)
+    #[serde(skip)]
+    pub discovered_paths: Vec<String>,
+    /// Traces dropped because their file changed between hashing and saving
+    pub files_changed_before_save: usize,
+    /// Traces of batches the database rejected, so they were not registered
+    pub files_failed_to_save: usize,
+    /// Files of a scanned extension rejected by the date, size or custom filters,
+    /// so "everything filtered" can be told apart from "nothing there"
+    pub files_filtered_out: usize,
+    /// Zero-byte files left out of processing by `skip_zero_byte`
+    pub zero_byte_skipped: usize,
+    /// Id stored on every trace saved by this run
+    pub run_id: Option<String>,
+    pub run_started_at: Option<DateTime<Utc>>,
+    pub run_duration_ms: u64,
+    /// (path, size in bytes) of the largest processed files, largest first
+    pub top_largest: Vec<(String, i64)>,
+    /// (path, modification time) of the oldest processed files, oldest first
+    pub oldest: Vec<(String, DateTime<Utc>)>,
+    /// Files not registered because their hash was taken by a different-looking file
+    pub hash_collisions: Vec<HashCollision>,
+    /// Wall-clock time spent in each discovery stage
+    pub timings: DiscoveryTimings,
+    /// Scanning stopped early because the run's time budget was used up
+    pub time_limited: bool,
+    /// Scanning stopped early because the run was cancelled
+    pub cancelled: bool,
+}
+
+/// Wall-clock milliseconds of each discovery stage
+/// Scanning and hashing run concurrently, so their durations overlap
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct DiscoveryTimings {
+    /// Listing the directories (0 for file lists)
+    pub scan_ms: f64,
+    /// Hashing and reading the queued files, until the queue closed
+    pub hash_ms: f64,
+    /// Saving the traces to the database
+    pub save_ms: f64,
+}
+
+/// Milliseconds in a duration, with sub-millisecond precision
+pub fn elapsed_ms(duration: std::time::Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// A file whose hash matched a registered trace of a different size or line count
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HashCollision {
+    pub hash: String,
+    pub path: String,
+    pub existing_path: String,
+}
+
+/// Outcome of scanning a single directory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DirectoryScanSummary {
+    pub path: String,
+    pub file_count: usize,
+    /// Files of a scanned extension rejected by the date, size or custom filters
+    pub filtered_out: usize,
+    /// Set when the directory could not be listed
+    pub error: Option<String>,
 }
 
 impl FileDiscoveryReport {
@@ -199,14 +1257,92 @@ impl FileDiscoveryReport {
             files_processed: 0,
             files_saved: 0,
             processing_errors: 0,
+            errors_by_kind: BTreeMap::new(),
+            files_deleted: 0,
+            files_banned: 0,
+            status_changes: Vec::new(),
+            scanned_directories: Vec::new(),
+            unreachable_directories: Vec::new(),
+            discovered_paths: Vec::new(),
+            files_changed_before_save: 0,
+            files_failed_to_save: 0,
+            files_filtered_out: 0,
+            zero_byte_skipped: 0,
+            run_id: None,
+            run_started_at: None,
+            run_duration_ms: 0,
+            top_largest: Vec::new(),
+            oldest: Vec::new(),
+            hash_collisions: Vec::new(),
+            timings: DiscoveryTimings::default(),
+            time_limited: false,
+            cancelled: false,
+        }
+    }
+
+    /// Combine the reports of two discovery units of the same run
+    /// Counts and stage timings add up, lists are concatenated, and the top lists keep
+    /// the `top_n` largest and oldest files of both
+    pub fn merge(self, other: Self, top_n: usize) -> Self {
+        let mut errors_by_kind = self.errors_by_kind;
+        for (kind, count) in other.errors_by_kind {
+            *errors_by_kind.entry(kind).or_insert(0) += count;
+        }
+        let top_largest: Vec<(String, i64)> = self.top_largest.into_iter().chain(other.top_largest).collect();
+        let oldest: Vec<(String, DateTime<Utc>)> = self.oldest.into_iter().chain(other.oldest).collect();
+
+        Self {
+            files_discovered: self.files_discovered + other.files_discovered,
+            files_processed: self.files_processed + other.files_processed,
+            files_saved: self.files_saved + other.files_saved,
+            processing_errors: self.processing_errors + other.processing_errors,
+            errors_by_kind,
+            files_deleted: self.files_deleted + other.files_deleted,
+            files_banned: self.files_banned + other.files_banned,
+            status_changes: [self.status_changes, other.status_changes].concat(),
+            scanned_directories: [self.scanned_directories, other.scanned_directories].concat(),
+            unreachable_directories: [self.unreachable_directories, other.unreachable_directories].concat(),
+            discovered_paths: [self.discovered_paths, other.discovered_paths].concat(),
+            files_changed_before_save: self.files_changed_before_save + other.files_changed_before_save,
+            files_failed_to_save: self.files_failed_to_save + other.files_failed_to_save,
+            files_filtered_out: self.files_filtered_out + other.files_filtered_out,
+            zero_byte_skipped: self.zero_byte_skipped + other.zero_byte_skipped,
+            run_id: self.run_id.or(other.run_id),
+            run_started_at: self.run_started_at.or(other.run_started_at),
+            run_duration_ms: self.run_duration_ms.max(other.run_duration_ms),
+            top_largest: functional::top_n_by_key(&top_largest, top_n, |(_, size)| *size).into_iter().cloned().collect(),
+            oldest: functional::top_n_by_key(&oldest, top_n, |(_, modified)| std::cmp::Reverse(*modified))
+                .into_iter()
+                .cloned()
+                .collect(),
+            hash_collisions: [self.hash_collisions, other.hash_collisions].concat(),
+            timings: DiscoveryTimings {
+                scan_ms: self.timings.scan_ms + other.timings.scan_ms,
+                hash_ms: self.timings.hash_ms + other.timings.hash_ms,
+                save_ms: self.timings.save_ms + other.timings.save_ms,
+            },
+            time_limited: self.time_limited || other.time_limited,
+            cancelled: self.cancelled || other.cancelled,
         }
     }
 
+    /// Number of processing errors classified as the given kind
+    pub fn errors_of_kind(&self, kind: ProcessingErrorKind) -> usize {
+        self.errors_by_kind.get(&kind).copied().unwrap_or(0)
+    }
+
+    /// Traces whose processing was attempted, successfully or not
+    /// Exceeds `files_discovered` when archives are expanded into one trace per entry
+    pub fn files_attempted(&self) -> usize {
+        self.files_processed + self.processing_errors
+    }
+
     pub fn success_rate(&self) -> f64 {
-        if self.files_discovered == 0 {
+        let attempted = self.files_attempted();
+        if attempted == 0 {
             0.0
         } else {
-            self.files_processed as f64 / self.files_discovered as f64
+            self.files_processed as f64 / attempted as f64
         }
     }
 
@@ -260,6 +1396,34 @@ pub mod functional {
             .collect()
     }
 
+    /// Select the `n` items with the greatest keys, greatest first
+    /// Keeps a min-heap of at most `n` entries instead of sorting every item
+    pub fn top_n_by_key<T, K, F>(items: &[T], n: usize, key: F) -> Vec<&T>
+    where
+        K: Ord,
+        F: Fn(&T) -> K,
+    {
+        use std::cmp::Reverse;
+        use std::collections::BinaryHeap;
+
+        if n == 0 {
+            return Vec::new();
+        }
+
+        // Entries are (key, index); the index breaks ties in favor of earlier items
+        let mut heap: BinaryHeap<Reverse<(K, Reverse<usize>)>> = BinaryHeap::with_capacity(n + 1);
+        for (index, item) in items.iter().enumerate() {
+            heap.push(Reverse((key(item), Reverse(index))));
+            if heap.len() > n {
+                heap.pop();
+            }
+        }
+
+        let mut selected: Vec<(K, Reverse<usize>)> = heap.into_iter().map(|Reverse(entry)| entry).collect();
+        selected.sort_by(|a, b| b.cmp(a));
+        selected.into_iter().map(|(_, Reverse(index))| &items[index]).collect()
+    }
+
     /// Reduce file processing results to summary statistics
     pub fn reduce_processing_results<T, E>(
         results: Vec<Result<T, E>>,
@@ -275,4 +1439,643 @@ pub mod functional {
             },
         )
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn revenda(extensao: &str) -> FvwArqDiarioExt {
+        FvwArqDiarioExt::new(1, 1, extensao.to_string(), 0, String::new(), String::new())
+    }
+
+    /// Queue an already known list of files for processing
+    fn queue_paths(paths: Vec<PathBuf>) -> mpsc::Receiver<PathBuf> {
+        let (sender, receiver) = mpsc::channel(paths.len().max(1));
+        for path in paths {
+            // Cannot fail: the channel holds every path and the receiver is alive
+            let _ = sender.try_send(path);
+        }
+        receiver
+    }
+
+    #[test]
+    fn test_extract_unique_extensions_expands_lists() {
+        let revendas = vec![revenda(".txt,.dat;.fhi"), revenda(".TXT"), revenda("")];
+        let extensions = extract_unique_extensions(&revendas);
+        assert_eq!(extensions, vec![".dat", ".fhi", ".txt"]);
+    }
+
+    #[test]
+    fn test_comma_separated_extensions_match_files() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a.txt", "b.dat", "c.fhi", "d.csv"] {
+            std::fs::write(dir.path().join(name), "content").unwrap();
+        }
+
+        let extensions = extract_unique_extensions(&[revenda(".txt,.dat,.fhi")]);
+        let mut files = crate::utils::list_files_with_extensions(dir.path(), &extensions, None).unwrap();
+        files.sort();
+
+        let names: Vec<_> = files
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, vec!["a.txt", "b.dat", "c.fhi"]);
+    }
+
+    fn trace(name: &str, hash: &str) -> FileTrace {
+        let now = chrono::Utc::now();
+        FileTrace::new(name.into(), format!("/out/{}", name), hash.into(), 1, 1, now, now, now, 0)
+    }
+
+    #[test]
+    fn test_success_rate_counts_archive_entries_once() {
+        // One archive expanded into three registered entries and one unreadable entry
+        let report = FileDiscoveryReport {
+            files_discovered: 1,
+            files_processed: 3,
+            processing_errors: 1,
+            ..FileDiscoveryReport::empty()
+        };
+
+        assert_eq!(report.files_attempted(), 4);
+        assert_eq!(report.success_rate(), 0.75);
+        assert_eq!(FileDiscoveryReport::empty().success_rate(), 0.0);
+    }
+
+    #[test]
+    fn test_hash_collisions_compare_against_the_conflict_key_row() {
+        let tenant_trace = |name: &str, tenant: &str, size_bytes| FileTrace {
+            tenant: Some(tenant.to_string()),
+            size_bytes,
+            ..trace(name, "h1")
+        };
+        // Another tenant registered different content under the same hash
+        let existing = vec![tenant_trace("other.txt", "a", 99), tenant_trace("mine.txt", "b", 1)];
+        let incoming = tenant_trace("incoming.txt", "b", 1);
+
+        assert!(find_hash_collisions(ConflictKey::TenantHash, &[&incoming], &existing).is_empty());
+        let collisions = find_hash_collisions(ConflictKey::Hash, &[&incoming], &existing);
+        assert_eq!(collisions.len(), 1);
+        assert_eq!(collisions[0].existing_path, "/out/other.txt");
+    }
+
+    #[tokio::test]
+    async fn test_expand_archives_registers_contained_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("daily.zip");
+        let mut writer = zip::ZipWriter::new(std::fs::File::create(&archive).unwrap());
+        for (name, content) in [("a.txt", "one\n"), ("b.txt", "two\nthree\n")] {
+            writer.start_file(name, zip::write::SimpleFileOptions::default()).unwrap();
+            std::io::Write::write_all(&mut writer, content.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let processing = FileProcessingConfig::default();
+        let expanded = process_files_to_traces(queue_paths(vec![archive.clone()]), &processing, process_options(true, 1), &ErrorLog::disabled())
+            .await
+            .results;
+        let plain = process_files_to_traces(queue_paths(vec![archive]), &processing, process_options(false, 1), &ErrorLog::disabled())
+            .await
+            .results;
+
+        assert_eq!(expanded.len(), 2);
+        assert!(expanded.iter().all(|result| result.is_ok()));
+        assert_eq!(plain.len(), 1);
+    }
+
+    #[test]
+    fn test_scanned_directories_summarize_every_attempt() {
+        let root = tempfile::tempdir().unwrap();
+        let empty = root.path().join("empty");
+        let populated = root.path().join("populated");
+        std::fs::create_dir(&empty).unwrap();
+        std::fs::create_dir(&populated).unwrap();
+        std::fs::write(populated.join("a.txt"), "a").unwrap();
+        std::fs::write(populated.join("b.txt"), "b").unwrap();
+        // Listing a regular file as a directory fails
+        let failing = root.path().join("not-a-dir.txt");
+        std::fs::write(&failing, "x").unwrap();
+
+        let directories: Vec<String> = [&empty, &populated, &failing]
+            .iter()
+            .map(|dir| dir.to_string_lossy().to_string())
+            .collect();
+        let (files, summaries) =
+            discover_files_in_directories(&directories, &["txt".to_string()], &FileDiscoveryConfig::default());
+
+        assert_eq!(files.len(), 2);
+        assert_eq!(summaries.len(), 3);
+        assert_eq!(summaries[0], DirectoryScanSummary { path: directories[0].clone(), file_count: 0, filtered_out: 0, error: None });
+        assert_eq!(summaries[1], DirectoryScanSummary { path: directories[1].clone(), file_count: 2, filtered_out: 0, error: None });
+        assert_eq!((summaries[2].path.as_str(), summaries[2].file_count), (directories[2].as_str(), 0));
+        assert!(summaries[2].error.as_deref().unwrap().contains("Failed to read directory"));
+    }
+
+    /// Filesystem reporting a fixed stat for every file
+    struct FixedStatFileSystem(FileStat);
+
+    impl FileSystem for FixedStatFileSystem {
+        fn read_dir(&self, _directory: &std::path::Path) -> io::Result<crate::utils::DirEntries> {
+            Err(io::Error::from(io::ErrorKind::Unsupported))
+        }
+
+        fn stat(&self, _path: &std::path::Path) -> io::Result<FileStat> {
+            Ok(self.0)
+        }
+
+        fn read_to_string(&self, _path: &std::path::Path) -> io::Result<String> {
+            Err(io::Error::from(io::ErrorKind::Unsupported))
+        }
+    }
+
+    #[test]
+    fn test_drop_changed_traces_detects_changes_after_hashing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("growing.txt");
+        std::fs::write(&path, "first\n").unwrap();
+        let hashed = crate::models::create_file_trace_from_path(&path).unwrap();
+
+        let (kept, dropped) = drop_changed_traces(&OsFileSystem, vec![hashed.clone()]);
+        assert_eq!((kept.len(), dropped), (1, 0));
+
+        // Same size, newer modification time
+        let original = OsFileSystem.stat(&path).unwrap();
+        let touched = FixedStatFileSystem(FileStat {
+            modified: original.modified.map(|modified| modified + std::time::Duration::from_secs(5)),
+            ..original
+        });
+        assert_eq!(drop_changed_traces(&touched, vec![hashed.clone()]).1, 1);
+
+        // Appended to on disk between hashing and saving
+        std::fs::write(&path, "first\nsecond\n").unwrap();
+        let (kept, dropped) = drop_changed_traces(&OsFileSystem, vec![hashed.clone()]);
+        assert_eq!((kept.len(), dropped), (0, 1));
+
+        // Platforms without modification times compare the size only
+        let no_mtime = FixedStatFileSystem(FileStat { len: hashed.size_bytes as u64, created: None, modified: None });
+        assert_eq!(drop_changed_traces(&no_mtime, vec![hashed]).1, 0);
+    }
+
+    #[test]
+    fn test_effective_db_concurrency() {
+        assert_eq!(effective_db_concurrency(4, 10), 4);
+        assert_eq!(effective_db_concurrency(50, 10), 10);
+        assert_eq!(effective_db_concurrency(0, 10), 1);
+        assert_eq!(effective_db_concurrency(4, 0), 1);
+    }
+
+    #[tokio::test]
+    async fn test_batch_saves_respect_semaphore() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let traces: Vec<FileTrace> = (0..8).map(|i| trace(&format!("{}.txt", i), &format!("hash-{}", i))).collect();
+
+        async fn max_in_flight(traces: &[FileTrace], permits: usize) -> (usize, SaveOutcome) {
+            let in_flight = AtomicUsize::new(0);
+            let max_seen = AtomicUsize::new(0);
+            let semaphore = Semaphore::new(permits);
+
+            let outcome = save_batches_with(traces, 2, &semaphore, |batch| {
+                let (in_flight, max_seen) = (&in_flight, &max_seen);
+                async move {
+                    let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_seen.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    Ok(batch.iter().map(|t| (1, t.hash.clone(), t.path.clone())).collect())
+                }
+            })
+            .await;
+
+            (max_seen.load(Ordering::SeqCst), outcome)
+        }
+
+        let (serialized, outcome) = max_in_flight(&traces, 1).await;
+        assert_eq!(serialized, 1);
+        assert_eq!(outcome.saved.len(), 8);
+
+        let (concurrent, _) = max_in_flight(&traces, 4).await;
+        assert_eq!(concurrent, 4);
+    }
+
+    #[test]
+    fn test_custom_predicate_applied_to_discovery() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("keep.txt"), "a").unwrap();
+        std::fs::write(dir.path().join("skip.txt"), "b").unwrap();
+        let config = FileDiscoveryConfig {
+            file_filters: vec![FilePredicate::new(|path| !path.ends_with("skip.txt"))],
+            ..FileDiscoveryConfig::default()
+        };
+
+        let directories = vec![dir.path().to_string_lossy().to_string()];
+        let (files, _) = discover_files_in_directories(&directories, &["txt".to_string()], &config);
+
+        assert_eq!(files, vec![dir.path().join("keep.txt")]);
+    }
+
+    #[test]
+    fn test_files_outside_date_window_counted_as_filtered_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let thirty_days_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(30 * 24 * 3600);
+        for name in ["a.txt", "b.txt"] {
+            let path = dir.path().join(name);
+            std::fs::write(&path, name).unwrap();
+            std::fs::File::options().write(true).open(&path).unwrap().set_modified(thirty_days_ago).unwrap();
+        }
+        // Files of other extensions were never candidates, so they are not counted
+        std::fs::write(dir.path().join("notes.log"), "x").unwrap();
+
+        let cutoff = std::time::SystemTime::now() - std::time::Duration::from_secs(15 * 24 * 3600);
+        let config = FileDiscoveryConfig {
+            file_filters: vec![FilePredicate::new(move |path| {
+                std::fs::metadata(path).and_then(|meta| meta.modified()).map(|modified| modified >= cutoff).unwrap_or(false)
+            })],
+            ..FileDiscoveryConfig::default()
+        };
+
+        let directories = vec![dir.path().to_string_lossy().to_string()];
+        let (files, summaries) = discover_files_in_directories(&directories, &["txt".to_string()], &config);
+
+        assert!(files.is_empty());
+        assert_eq!((summaries[0].file_count, summaries[0].filtered_out), (0, 2));
+    }
+
+    #[test]
+    fn test_top_n_largest_and_oldest() {
+        let base = chrono::Utc::now();
+        let traces: Vec<FileTrace> = [("a", 300, 5), ("b", 100, 40), ("c", 900, 1), ("d", 500, 30), ("e", 700, 10)]
+            .iter()
+            .map(|(name, size, days_old)| FileTrace {
+                size_bytes: *size,
+                modified_at: base - chrono::Duration::days(*days_old),
+                ..trace(name, name)
+            })
+            .collect();
+
+        let largest: Vec<(&str, i64)> = functional::top_n_by_key(&traces, 3, |t| t.size_bytes)
+            .iter()
+            .map(|t| (t.name.as_str(), t.size_bytes))
+            .collect();
+        let oldest: Vec<&str> = functional::top_n_by_key(&traces, 2, |t| std::cmp::Reverse(t.modified_at))
+            .iter()
+            .map(|t| t.name.as_str())
+            .collect();
+
+        assert_eq!(largest, vec![("c", 900), ("e", 700), ("d", 500)]);
+        assert_eq!(oldest, vec!["b", "d"]);
+        assert_eq!(functional::top_n_by_key(&traces, 10, |t| t.size_bytes).len(), 5);
+        assert!(functional::top_n_by_key(&traces, 0, |t| t.size_bytes).is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_scan_queue_never_exceeds_capacity() {
+        let dir = tempfile::tempdir().unwrap();
+        for index in 0..20 {
+            std::fs::write(dir.path().join(format!("{:02}.txt", index)), "x").unwrap();
+        }
+        let config = FileDiscoveryConfig { scan_queue_capacity: 3, ..FileDiscoveryConfig::default() };
+
+        let directories = vec![dir.path().to_string_lossy().to_string()];
+        let (mut queue, scan) = spawn_scan_stage(directories, vec!["txt".to_string()], &config);
+
+        // A slow consumer gives the scanner time to fill the queue; it must never hold more than 3
+        let mut received = 0;
+        let mut peak = 0;
+        loop {
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            peak = peak.max(queue.len());
+            if queue.recv().await.is_none() {
+                break;
+            }
+            received += 1;
+        }
+
+        assert_eq!(received, 20);
+        assert!((1..=3).contains(&peak), "queue held {} files", peak);
+        assert_eq!(scan.await.unwrap().directories[0].file_count, 20);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_scan_of_a_large_directory_stays_ahead_by_at_most_the_capacity() {
+        let dir = tempfile::tempdir().unwrap();
+        for index in 0..500 {
+            std::fs::write(dir.path().join(format!("{:03}.txt", index)), "x").unwrap();
+        }
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        // Counts the files the scanner has looked at so far
+        let examined = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&examined);
+        let config = FileDiscoveryConfig {
+            scan_queue_capacity: 3,
+            file_filters: vec![FilePredicate::new(move |_| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                true
+            })],
+            ..FileDiscoveryConfig::default()
+        };
+
+        let directories = vec![dir.path().to_string_lossy().to_string()];
+        let (mut queue, scan) = spawn_scan_stage(directories, vec!["txt".to_string()], &config);
+
+        // The scanner may hold one path it is waiting to send, beyond the queued ones
+        let mut received = 0;
+        while received < 50 {
+            tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+            assert!(examined.load(Ordering::SeqCst) <= received + 3 + 1);
+            queue.recv().await.unwrap();
+            received += 1;
+        }
+        while queue.recv().await.is_some() {
+            received += 1;
+        }
+
+        assert_eq!(received, 500);
+        assert_eq!(examined.load(Ordering::SeqCst), 500);
+        assert_eq!(scan.await.unwrap().directories[0].file_count, 500);
+    }
+
+    #[test]
+    fn test_rewrite_path_prefix() {
+        assert_eq!(
+            rewrite_path_prefix("/snapshot/vw/out/a.txt", "/snapshot/vw", "/mnt/prod/vw").as_deref(),
+            Some("/mnt/prod/vw/out/a.txt")
+        );
+        assert_eq!(
+            rewrite_path_prefix("/snapshot/vw/a.txt", "/snapshot/vw/", "/mnt/prod/vw").as_deref(),
+            Some("/mnt/prod/vw/a.txt")
+        );
+        // Prefixes only match whole components
+        assert_eq!(rewrite_path_prefix("/snapshot/vw2/a.txt", "/snapshot/vw", "/mnt/prod/vw"), None);
+        assert_eq!(rewrite_path_prefix("/elsewhere/a.txt", "/snapshot/vw", "/mnt/prod/vw"), None);
+    }
+
+    #[test]
+    fn test_rewrite_trace_paths_rehashes_path_keys() {
+        let scanned = |hash_algorithm: &str| FileTrace {
+            path: "/snapshot/vw/out/a.txt".to_string(),
+            hash: path_hash("/snapshot/vw/out/a.txt"),
+            hash_algorithm: hash_algorithm.to_string(),
+            ..trace("a.txt", "")
+        };
+        let rewrite = ("/snapshot/vw".to_string(), "/mnt/prod/vw".to_string());
+
+        let rewritten = rewrite_trace_paths(vec![scanned(HASH_ALGORITHM_PATH), scanned("sha256")], Some(&rewrite));
+
+        assert_eq!(rewritten[0].path, "/mnt/prod/vw/out/a.txt");
+        assert_eq!(rewritten[0].hash, path_hash("/mnt/prod/vw/out/a.txt"));
+        // Content hashes do not depend on where the file was scanned
+        assert_eq!(rewritten[1].path, "/mnt/prod/vw/out/a.txt");
+        assert_eq!(rewritten[1].hash, path_hash("/snapshot/vw/out/a.txt"));
+    }
+
+    #[test]
+    fn test_parse_file_list() {
+        let contents = "/in/a.txt\n\n  # comment\n  /in/b.txt  \r\n";
+        assert_eq!(parse_file_list(contents), vec![PathBuf::from("/in/a.txt"), PathBuf::from("/in/b.txt")]);
+    }
+
+    #[test]
+    fn test_matches_name_pattern() {
+        assert!(matches_name_pattern("SKIP_20240115.txt", "skip_*"));
+        assert!(matches_name_pattern("report.tmp", "*.TMP"));
+        assert!(matches_name_pattern("a1.txt", "a?.txt"));
+        assert!(!matches_name_pattern("a12.txt", "a?.txt"));
+        assert!(!matches_name_pattern("keep.txt", "skip_*"));
+        assert!(matches_name_pattern("a1.txt", "a[0-9].txt"));
+        assert!(!matches_name_pattern("a1.txt", "a[.txt"));
+    }
+
+    #[test]
+    fn test_matches_name_pattern_runs_in_linear_time() {
+        // Backtracking over every `*` took exponential time on names like this one
+        let name = "a".repeat(64);
+        let pattern = format!("{}b", "*a".repeat(32));
+        assert!(!matches_name_pattern(&name, &pattern));
+    }
+
+    #[test]
+    fn test_select_files_to_delete() {
+        let mut traces = vec![trace("saved.txt", "a"), trace("conflict.txt", "b"), trace("failed.txt", "c")];
+        let mut archive_entry = trace("entry.txt", "d");
+        archive_entry.path = "/out/daily.zip!entry.txt".to_string();
+        traces.push(archive_entry);
+        let outcome = SaveOutcome {
+            saved: HashMap::from([(trace_key(&traces[0]), 1), (trace_key(&traces[3]), 4)]),
+            failed: HashSet::from([trace_key(&traces[2])]),
+        };
+
+        assert_eq!(select_files_to_delete(&traces, &outcome, false), vec!["/out/saved.txt"]);
+        assert_eq!(
+            select_files_to_delete(&traces, &outcome, true),
+            vec!["/out/saved.txt", "/out/conflict.txt"]
+        );
+    }
+
+    fn process_options(expand_archives: bool, concurrency: usize) -> ProcessOptions {
+        ProcessOptions {
+            expand_archives,
+            skip_zero_byte: false,
+            concurrency,
+            metrics: Arc::new(Metrics::default()),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_zero_byte_files_are_skipped_and_counted() {
+        let dir = tempfile::tempdir().unwrap();
+        let empty = dir.path().join("empty.txt");
+        let filled = dir.path().join("filled.txt");
+        std::fs::write(&empty, "").unwrap();
+        std::fs::write(&filled, "line\n").unwrap();
+        let paths = vec![empty.clone(), filled.clone()];
+
+        let skipping = ProcessOptions {
+            skip_zero_byte: true,
+            ..process_options(false, 2)
+        };
+        let processed = process_files_to_traces(
+            queue_paths(paths.clone()),
+            &FileProcessingConfig::default(),
+            skipping,
+            &ErrorLog::disabled(),
+        )
+        .await;
+
+        assert_eq!(processed.zero_byte_skipped, 1);
+        assert_eq!(processed.discovered_paths, vec![filled.to_string_lossy().to_string()]);
+        assert_eq!(processed.results.len(), 1);
+        assert_eq!(processed.results[0].as_ref().unwrap().name, "filled.txt");
+
+        // Without the flag the empty file is hashed and registered like any other
+        let processed = process_files_to_traces(
+            queue_paths(paths),
+            &FileProcessingConfig::default(),
+            process_options(false, 2),
+            &ErrorLog::disabled(),
+        )
+        .await;
+        assert_eq!(processed.zero_byte_skipped, 0);
+        assert_eq!(processed.results.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_traces_are_stamped_by_the_injected_clock() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        std::fs::write(&file, "line\n").unwrap();
+        let frozen = DateTime::parse_from_rfc3339("2030-01-01T00:00:00Z").unwrap().with_timezone(&Utc);
+
+        let options = ProcessOptions {
+            clock: Arc::new(crate::utils::FixedClock(frozen)),
+            ..process_options(false, 1)
+        };
+        let processed =
+            process_files_to_traces(queue_paths(vec![file]), &FileProcessingConfig::default(), options, &ErrorLog::disabled())
+                .await;
+
+        assert_eq!(processed.results[0].as_ref().unwrap().processed_at, frozen);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_hashing_keeps_queue_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths: Vec<PathBuf> = (0..20)
+            .map(|index| {
+                let path = dir.path().join(format!("file-{}.txt", index));
+                if index % 5 != 3 {
+                    std::fs::write(&path, "x\n".repeat(index + 1)).unwrap();
+                }
+                path
+            })
+            .collect();
+
+        let ProcessedFiles { results, discovered_paths: discovered, .. } = process_files_to_traces(
+            queue_paths(paths.clone()),
+            &FileProcessingConfig::default(),
+            process_options(false, 4),
+            &ErrorLog::disabled(),
+        )
+        .await;
+
+        let expected: Vec<String> = paths.iter().map(|path| path.to_string_lossy().to_string()).collect();
+        assert_eq!(discovered, expected);
+        assert_eq!(results.iter().filter(|result| result.is_err()).count(), 4);
+        let line_counts: Vec<i32> = results.iter().flatten().map(|trace| trace.total_lines).collect();
+        let expected_counts: Vec<i32> = (0..20).filter(|index| index % 5 != 3).map(|index| index + 1).collect();
+        assert_eq!(line_counts, expected_counts);
+
+        let sequential = FileDiscoveryConfig {
+            parallel_processing: false,
+            ..FileDiscoveryConfig::default()
+        };
+        assert_eq!(effective_hash_concurrency(&sequential), 1);
+        assert_eq!(effective_hash_concurrency(&FileDiscoveryConfig::default()), default_hash_concurrency());
+        let capped = |hash_concurrency| FileDiscoveryConfig {
+            hash_concurrency,
+            ..FileDiscoveryConfig::default()
+        };
+        assert_eq!(effective_hash_concurrency(&capped(4)), 4);
+        assert_eq!(effective_hash_concurrency(&capped(0)), 1);
+    }
+
+    #[test]
+    fn test_preflight_splits_reachable_and_unreachable_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let reachable = dir.path().join("share-a").to_string_lossy().to_string();
+        let also_reachable = dir.path().join("share-c").to_string_lossy().to_string();
+        let unreachable = dir.path().join("share-b").to_string_lossy().to_string();
+        std::fs::create_dir(&reachable).unwrap();
+        std::fs::create_dir(&also_reachable).unwrap();
+
+        let directories = vec![reachable.clone(), unreachable.clone(), also_reachable.clone()];
+        let preflight = preflight_directories(&OsFileSystem, directories);
+
+        assert_eq!(preflight.reachable, vec![reachable.clone(), also_reachable.clone()]);
+        assert_eq!(preflight.unreachable.len(), 1);
+        assert_eq!(preflight.unreachable[0].0, unreachable);
+
+        let strict = apply_preflight(preflight.clone(), true).unwrap_err().to_string();
+        assert!(strict.starts_with("1 of 3 output directories are unreachable: "), "{}", strict);
+        assert!(strict.contains(&unreachable), "{}", strict);
+
+        let (to_scan, skipped) = apply_preflight(preflight, false).unwrap();
+        assert_eq!(to_scan, vec![reachable, also_reachable]);
+        assert_eq!(skipped, vec![unreachable]);
+    }
+
+    #[test]
+    fn test_classify_processing_errors() {
+        let permission = anyhow::Error::from(io::Error::from(io::ErrorKind::PermissionDenied))
+            .context("Failed to open file");
+        let timeout = anyhow::Error::from(io::Error::from(io::ErrorKind::TimedOut));
+        let io_error = anyhow::Error::from(io::Error::from(io::ErrorKind::UnexpectedEof));
+        let decode = anyhow::Error::from(String::from_utf8(vec![0xff]).unwrap_err());
+        let other = anyhow::anyhow!("Task join error");
+
+        assert_eq!(ProcessingErrorKind::classify(&permission), ProcessingErrorKind::PermissionDenied);
+        assert_eq!(ProcessingErrorKind::classify(&timeout), ProcessingErrorKind::Timeout);
+        assert_eq!(ProcessingErrorKind::classify(&io_error), ProcessingErrorKind::IoError);
+        assert_eq!(ProcessingErrorKind::classify(&decode), ProcessingErrorKind::DecodeError);
+        assert_eq!(ProcessingErrorKind::classify(&other), ProcessingErrorKind::Other);
+    }
+
+    #[tokio::test]
+    async fn test_count_errors_by_kind() {
+        let dir = tempfile::tempdir().unwrap();
+        let existing = dir.path().join("ok.txt");
+        std::fs::write(&existing, "line\n").unwrap();
+
+        let results = process_files_to_traces(queue_paths(vec![
+            existing,
+            dir.path().join("missing-1.txt"),
+            dir.path().join("missing-2.txt"),
+        ]), &FileProcessingConfig::default(), process_options(false, 2), &ErrorLog::disabled())
+        .await
+        .results;
+        let (traces, mut errors) = functional::reduce_processing_results(results);
+        errors.push(anyhow::Error::from(io::Error::from(io::ErrorKind::PermissionDenied)));
+
+        let counts = count_errors_by_kind(&errors);
+        let report = FileDiscoveryReport {
+            errors_by_kind: counts,
+            ..FileDiscoveryReport::empty()
+        };
+
+        assert_eq!(traces.len(), 1);
+        assert_eq!(report.errors_of_kind(ProcessingErrorKind::NotFound), 2);
+        assert_eq!(report.errors_of_kind(ProcessingErrorKind::PermissionDenied), 1);
+        assert_eq!(report.errors_of_kind(ProcessingErrorKind::Timeout), 0);
+    }
+
+    #[tokio::test]
+    async fn test_error_log_receives_one_line_per_failed_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let existing = dir.path().join("ok.txt");
+        std::fs::write(&existing, "line\n").unwrap();
+        let error_path = dir.path().join("errors.tsv");
+
+        process_files_to_traces(queue_paths(vec![
+            existing,
+            dir.path().join("missing-1.txt"),
+            dir.path().join("missing-2.txt"),
+        ]), &FileProcessingConfig::default(), process_options(false, 2), &ErrorLog::open(Some(&error_path)))
+        .await;
+
+        let mut error_lines: Vec<String> = std::fs::read_to_string(&error_path)
+            .unwrap()
+            .lines()
+            .map(|line| line.split('\t').nth(1).unwrap().to_string())
+            .collect();
+        error_lines.sort();
+        assert_eq!(error_lines, vec![
+            dir.path().join("missing-1.txt").to_string_lossy().to_string(),
+            dir.path().join("missing-2.txt").to_string_lossy().to_string(),
+        ]);
+    }
+}
@@ -0,0 +1,232 @@
+use crate::database::file_trace::{self, TraceFilter};
+use crate::database::{ConflictKey, DbPool};
+use crate::models::{
+    create_file_trace_from_path_with, extract_dn, read_first_line, DnExtractionSpec, FileProcessingConfig, HashAlgorithm,
+    HashMode,
+};
+use anyhow::Result;
+use std::path::Path;
+use tracing::{info, warn};
+
+/// Report of a DN backfill pass
+#[derive(Debug, Clone, Default)]
+pub struct BackfillReport {
+    /// Traces found with `dn = 0`
+    pub traces_examined: usize,
+    /// Traces whose DN was recomputed and saved
+    pub traces_updated: usize,
+    /// Traces whose file was read but still yields no DN
+    pub traces_unchanged: usize,
+    /// Paths of traces whose file no longer exists
+    pub missing_files: Vec<String>,
+    /// (path, error) pairs for files that could not be read or updated
+    pub errors: Vec<(String, String)>,
+}
+
+/// Recompute the DN of traces saved with `dn = 0` by re-reading the first line of each file
+pub async fn backfill_dns(pool: &DbPool, spec: DnExtractionSpec) -> Result<BackfillReport> {
+    info!("Starting DN backfill...");
+
+    let filter = TraceFilter {
+        dn: Some(0),
+        ..TraceFilter::default()
+    };
+    let traces = file_trace::get_by_filter(pool, &filter).await?;
+
+    let mut report = BackfillReport {
+        traces_examined: traces.len(),
+        ..BackfillReport::default()
+    };
+
+    for trace in traces {
+        let Some(id) = trace.id else {
+            continue;
+        };
+
+        if !Path::new(&trace.path).exists() {
+            warn!("File for trace {} no longer exists: {}", id, trace.path);
+            report.missing_files.push(trace.path);
+            continue;
+        }
+
+        let path = trace.path.clone();
+        let first_line = tokio::task::spawn_blocking(move || read_first_line(path))
+            .await
+            .unwrap_or_else(|e| Err(anyhow::anyhow!("Task join error: {}", e)));
+
+        let dn = match first_line {
+            Ok(line) => extract_dn(&line, &spec),
+            Err(e) => {
+                report.errors.push((trace.path, e.to_string()));
+                continue;
+            }
+        };
+
+        if dn == 0 {
+            report.traces_unchanged += 1;
+            continue;
+        }
+
+        match file_trace::update_dn(pool, id, dn).await {
+            Ok(_) => report.traces_updated += 1,
+            Err(e) => report.errors.push((trace.path, e.to_string())),
+        }
+    }
+
+    info!(
+        "DN backfill completed. Examined: {}, Updated: {}, Missing: {}, Errors: {}",
+        report.traces_examined,
+        report.traces_updated,
+        report.missing_files.len(),
+        report.errors.len()
+    );
+
+    Ok(report)
+}
+
+/// A trace whose stored hash differed from its file on disk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepairedTrace {
+    pub id: i32,
+    pub path: String,
+    /// Hash the trace had before the repair
+    pub stored_hash: String,
+    /// Hash of the file on disk, now stored in the trace (unless a dry run)
+    pub file_hash: String,
+}
+
+/// Report of a trace repair pass
+#[derive(Debug, Clone, Default)]
+pub struct RepairReport {
+    /// Traces matching the filter
+    pub traces_examined: usize,
+    /// Traces whose hash differed from their file, updated unless `dry_run`
+    pub repaired: Vec<RepairedTrace>,
+    /// Traces whose stored hash matches their file
+    pub traces_unchanged: usize,
+    /// Quick or keyed hashes, which are left alone
+    pub traces_skipped: usize,
+    /// Paths of traces whose file no longer exists
+    pub missing_files: Vec<String>,
+    /// (path, error) pairs for files that could not be read or traces that could not be updated
+    pub errors: Vec<(String, String)>,
+    /// Nothing was written: `repaired` lists the traces that would have been updated
+    pub dry_run: bool,
+}
+
+/// Re-hash the files of the traces matching `filter` and repair the traces whose stored hash
+/// differs from their file, replacing the content fields in place by id
+///
+/// Files are read with `processing`, the settings of the runs that registered the traces
+/// (hash truncation, line counting, DN and region extraction), hashing the full content with
+/// the `HashAlgorithm` each trace was stored with; quick and keyed hashes are skipped.
+/// A hash of another length than the stored one means `processing` does not match the
+/// registering run, and a trace whose new `conflict_key` values already belong to another
+/// trace would break the key: both are reported as errors and the trace is left unchanged.
+pub async fn repair_traces(
+    pool: &DbPool,
+    filter: &TraceFilter,
+    processing: &FileProcessingConfig,
+    conflict_key: ConflictKey,
+    dry_run: bool,
+) -> Result<RepairReport> {
+    info!("Starting trace repair{}...", if dry_run { " (dry run)" } else { "" });
+
+    let traces = file_trace::get_by_filter(pool, filter).await?;
+
+    let mut report = RepairReport {
+        traces_examined: traces.len(),
+        dry_run,
+        ..RepairReport::default()
+    };
+
+    for trace in traces {
+        let Some(id) = trace.id else {
+            continue;
+        };
+
+        let Some(hash_algorithm) = HashAlgorithm::from_name(&trace.hash_algorithm) else {
+            report.traces_skipped += 1;
+            continue;
+        };
+
+        if !Path::new(&trace.path).exists() {
+            warn!("File for trace {} no longer exists: {}", id, trace.path);
+            report.missing_files.push(trace.path);
+            continue;
+        }
+
+        let path = trace.path.clone();
+        let config = FileProcessingConfig {
+            hash_mode: HashMode::Full,
+            hash_algorithm,
+            ..processing.clone()
+        };
+        let rehashed = tokio::task::spawn_blocking(move || create_file_trace_from_path_with(path, &config))
+            .await
+            .unwrap_or_else(|e| Err(anyhow::anyhow!("Task join error: {}", e)));
+
+        let rehashed = match rehashed {
+            Ok(rehashed) => rehashed,
+            Err(e) => {
+                report.errors.push((trace.path, e.to_string()));
+                continue;
+            }
+        };
+
+        if rehashed.hash == trace.hash {
+            report.traces_unchanged += 1;
+            continue;
+        }
+
+        if rehashed.hash.len() != trace.hash.len() {
+            let error = format!(
+                "Stored hash has {} characters but the file hashes to {}: repair with the hash settings the trace was registered with",
+                trace.hash.len(),
+                rehashed.hash.len()
+            );
+            report.errors.push((trace.path, error));
+            continue;
+        }
+
+        if !dry_run {
+            match file_trace::update_content(pool, id, &rehashed, conflict_key).await {
+                Ok(0) => {
+                    let error = format!(
+                        "Another trace already has hash {} under the {} conflict key",
+                        rehashed.hash,
+                        conflict_key.name()
+                    );
+                    report.errors.push((trace.path, error));
+                    continue;
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    report.errors.push((trace.path, e.to_string()));
+                    continue;
+                }
+            }
+        }
+
+        info!("Stale hash for trace {} ({}): stored {}, file {}", id, trace.path, trace.hash, rehashed.hash);
+        report.repaired.push(RepairedTrace {
+            id,
+            path: trace.path,
+            stored_hash: trace.hash,
+            file_hash: rehashed.hash,
+        });
+    }
+
+    info!(
+        "Trace repair completed{}. Examined: {}, Repaired: {}, Unchanged: {}, Skipped: {}, Missing: {}, Errors: {}",
+        if dry_run { " (dry run, nothing updated)" } else { "" },
+        report.traces_examined,
+        report.repaired.len(),
+        report.traces_unchanged,
+        report.traces_skipped,
+        report.missing_files.len(),
+        report.errors.len()
+    );
+
+    Ok(report)
+}
@@ -0,0 +1,99 @@
+use crate::utils::CopyResult;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Phase the application is currently in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunPhase {
+    Starting,
+    Copying,
+    Discovering,
+    /// Copying and discovering at once, output folder by output folder
+    Pipelined,
+    Finished,
+}
+
+/// Live progress counters shared between the pipeline and the status server
+#[derive(Debug)]
+pub struct Metrics {
+    started_at: DateTime<Utc>,
+    phase: Mutex<RunPhase>,
+    files_copied: AtomicUsize,
+    copy_errors: AtomicUsize,
+    files_discovered: AtomicUsize,
+    files_processed: AtomicUsize,
+    files_saved: AtomicUsize,
+    processing_errors: AtomicUsize,
+}
+
+/// Point-in-time copy of the metrics, serialized by the status endpoint
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MetricsSnapshot {
+    pub started_at: DateTime<Utc>,
+    pub phase: RunPhase,
+    pub files_copied: usize,
+    pub copy_errors: usize,
+    pub files_discovered: usize,
+    pub files_processed: usize,
+    pub files_saved: usize,
+    pub processing_errors: usize,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            started_at: Utc::now(),
+            phase: Mutex::new(RunPhase::Starting),
+            files_copied: AtomicUsize::new(0),
+            copy_errors: AtomicUsize::new(0),
+            files_discovered: AtomicUsize::new(0),
+            files_processed: AtomicUsize::new(0),
+            files_saved: AtomicUsize::new(0),
+            processing_errors: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Metrics {
+    pub fn set_phase(&self, phase: RunPhase) {
+        *self.phase.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = phase;
+    }
+
+    /// Count one finished copy, as soon as it finishes
+    pub fn record_copy_result(&self, result: &CopyResult) {
+        if result.is_success() {
+            self.files_copied.fetch_add(1, Ordering::Relaxed);
+        } else if result.is_error() {
+            self.copy_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Count one discovered file with the traces it produced (several for an expanded archive)
+    /// and the errors processing it raised
+    pub fn record_file_processed(&self, traces: usize, errors: usize) {
+        self.files_discovered.fetch_add(1, Ordering::Relaxed);
+        self.files_processed.fetch_add(traces, Ordering::Relaxed);
+        self.processing_errors.fetch_add(errors, Ordering::Relaxed);
+    }
+
+    /// Count traces saved to the database
+    pub fn record_files_saved(&self, saved: usize) {
+        self.files_saved.fetch_add(saved, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            started_at: self.started_at,
+            phase: *self.phase.lock().unwrap_or_else(|poisoned| poisoned.into_inner()),
+            files_copied: self.files_copied.load(Ordering::Relaxed),
+            copy_errors: self.copy_errors.load(Ordering::Relaxed),
+            files_discovered: self.files_discovered.load(Ordering::Relaxed),
+            files_processed: self.files_processed.load(Ordering::Relaxed),
+            files_saved: self.files_saved.load(Ordering::Relaxed),
+            processing_errors: self.processing_errors.load(Ordering::Relaxed),
+        }
+    }
+}
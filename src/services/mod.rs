@@ -1,11 +1,31 @@
 pub mod file_copy;
 pub mod file_discovery;
+pub mod maintenance;
+pub mod metrics;
+pub mod pipeline;
+pub mod reconciliation;
+pub mod run_report;
+pub mod status_server;
 
 pub use file_copy::{
-    copy_files_for_revendas, extract_file_extensions, create_copy_mappings,
-    FileCopyConfig, FileCopyReport, CopiedFile, CopyError
+    copy_files_for_revendas, copy_files_for_revendas_with, copy_files_in_revendas, copy_files_with_mappings, extract_file_extensions, create_copy_mappings,
+    parse_copy_mapping,
+    FileCopyConfig, FileCopyReport, CopiedFile, CopyError, SkippedFile
 };
 pub use file_discovery::{
-    discover_and_register_files, extract_output_directories, extract_unique_extensions,
-    FileDiscoveryConfig, FileDiscoveryReport
-};
\ No newline at end of file
+    default_hash_concurrency, discover_and_register_files, discover_and_register_files_with, discover_from_file_list, discover_in_directories, elapsed_ms, extract_output_directories,
+    extract_unique_extensions, parse_file_list, preflight_directories, rewrite_path_prefix,
+    DirectoryScanSummary, DiscoveryTimings, FileDiscoveryConfig, FileDiscoveryReport, HashCollision, PreflightSummary,
+    ProcessingErrorKind
+};
+pub use maintenance::{backfill_dns, repair_traces, BackfillReport, RepairReport, RepairedTrace};
+pub use metrics::{Metrics, MetricsSnapshot, RunPhase};
+pub use pipeline::{
+    copy_and_discover_pipelined, copy_and_discover_pipelined_with, group_by_output_directory,
+    DEFAULT_MAX_REVENDAS_IN_FLIGHT,
+};
+pub use reconciliation::{reconcile, ReconciliationReport};
+pub use run_report::{
+    ExitPolicy, RunReport, RunTimings, EXIT_COPY_AND_DISCOVERY_FAILED, EXIT_COPY_FAILED, EXIT_DISCOVERY_FAILED, EXIT_OK,
+};
+pub use status_server::StatusServer;
@@ -0,0 +1,130 @@
+use crate::database::{DbPool, RevendaCache, RevendaSource, DEFAULT_REVENDA_CACHE_TTL};
+use crate::models::FvwArqDiarioExt;
+use crate::services::file_copy::{copy_files_in_revendas, extract_file_extensions, FileCopyConfig, FileCopyReport};
+use crate::services::file_discovery::{
+    discover_in_directories, extract_unique_extensions, warn_on_unsafe_hash_truncation, FileDiscoveryConfig,
+    FileDiscoveryReport, ScanRun,
+};
+use anyhow::Result;
+use futures::{StreamExt, TryStreamExt};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Default number of output folders copied into and discovered at once by a pipelined run
+pub const DEFAULT_MAX_REVENDAS_IN_FLIGHT: usize = 4;
+
+/// Pure function grouping revendas by output folder, in order of first appearance
+/// Each group is one pipeline unit: its copies all land in the folder it then scans
+pub fn group_by_output_directory(revendas: Vec<FvwArqDiarioExt>) -> Vec<(String, Vec<FvwArqDiarioExt>)> {
+    revendas
+        .into_iter()
+        .filter(|revenda| !revenda.pasta_output.is_empty())
+        .fold(Vec::new(), |mut units: Vec<(String, Vec<FvwArqDiarioExt>)>, revenda| {
+            match units.iter_mut().find(|(directory, _)| *directory == revenda.pasta_output) {
+                Some((_, group)) => group.push(revenda),
+                None => units.push((revenda.pasta_output.clone(), vec![revenda])),
+            }
+            units
+        })
+}
+
+/// Copy and discover output folder by output folder instead of in two whole-run phases
+///
+/// As soon as the copies into one output folder finish, that folder is scanned and
+/// registered while other folders are still being copied; at most `max_in_flight`
+/// folders are in progress at once. Files are selected by the extensions of every
+/// revenda, as in the sequential phases, and all traces share one run id.
+/// `max_copy_errors` applies to each folder on its own.
+pub async fn copy_and_discover_pipelined(
+    pool: &DbPool,
+    copy_config: FileCopyConfig,
+    discovery_config: FileDiscoveryConfig,
+    max_in_flight: usize,
+) -> Result<(FileCopyReport, FileDiscoveryReport)> {
+    let revendas = RevendaCache::new(pool.clone(), DEFAULT_REVENDA_CACHE_TTL);
+    copy_and_discover_pipelined_with(pool, &revendas, copy_config, discovery_config, max_in_flight).await
+}
+
+/// Pipelined copy and discovery of the revendas served by `revendas`, shared with the
+/// other phases of the run
+pub async fn copy_and_discover_pipelined_with<S: RevendaSource>(
+    pool: &DbPool,
+    revendas: &RevendaCache<S>,
+    copy_config: FileCopyConfig,
+    discovery_config: FileDiscoveryConfig,
+    max_in_flight: usize,
+) -> Result<(FileCopyReport, FileDiscoveryReport)> {
+    let run = ScanRun::start();
+    info!("Starting pipelined copy and discovery (run {})...", run.id);
+
+    warn_on_unsafe_hash_truncation(&discovery_config);
+
+    let revendas = revendas.get_revendas().await?;
+
+    if revendas.is_empty() {
+        warn!("No revendas found in database");
+        return Ok((FileCopyReport::empty(), FileDiscoveryReport::empty()));
+    }
+
+    let copy_extensions = Arc::new(extract_file_extensions(&revendas));
+    let discovery_extensions = extract_unique_extensions(&revendas);
+    let units = group_by_output_directory(revendas);
+    let max_in_flight = max_in_flight.max(1);
+    info!("Pipelining {} output folders, {} at a time", units.len(), max_in_flight);
+
+    let copy_config = Arc::new(copy_config);
+    let (discovery_config, run_id) = (&discovery_config, run.id.as_str());
+
+    // `buffered` runs the units concurrently but yields them in order, so the merged
+    // report lists copies and directories in the same order on every run
+    let (copy_report, discovery_report) = futures::stream::iter(units)
+        .map(|(output_directory, revendas)| {
+            let (copy_config, copy_extensions) = (Arc::clone(&copy_config), Arc::clone(&copy_extensions));
+            let discovery_extensions = discovery_extensions.clone();
+            async move {
+                let copy_report = tokio::task::spawn_blocking(move || {
+                    copy_files_in_revendas(&revendas, &copy_extensions, &copy_config)
+                })
+                .await?;
+                let discovery_report =
+                    discover_in_directories(pool, vec![output_directory], discovery_extensions, discovery_config, run_id)
+                        .await?;
+                Ok::<_, anyhow::Error>((copy_report, discovery_report))
+            }
+        })
+        .buffered(max_in_flight)
+        .try_fold(
+            (FileCopyReport::empty(), FileDiscoveryReport::empty()),
+            |(copy, discovery), (unit_copy, unit_discovery)| async move {
+                Ok((copy.merge(unit_copy), discovery.merge(unit_discovery, discovery_config.report_top_n)))
+            },
+        )
+        .await?;
+
+    Ok((copy_report, run.finish(discovery_report)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn revenda(revenda: i32, pasta_output: &str) -> FvwArqDiarioExt {
+        FvwArqDiarioExt::new(1, revenda, ".txt".to_string(), 0, format!("/in/{}", revenda), pasta_output.to_string())
+    }
+
+    #[test]
+    fn test_group_by_output_directory_keeps_first_appearance_order() {
+        let units = group_by_output_directory(vec![
+            revenda(1, "/out/b"),
+            revenda(2, "/out/a"),
+            revenda(3, ""),
+            revenda(4, "/out/b"),
+        ]);
+
+        let grouped: Vec<(&str, Vec<i32>)> = units
+            .iter()
+            .map(|(directory, revendas)| (directory.as_str(), revendas.iter().map(|revenda| revenda.revenda).collect()))
+            .collect();
+        assert_eq!(grouped, vec![("/out/b", vec![1, 4]), ("/out/a", vec![2])]);
+    }
+}
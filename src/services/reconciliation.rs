@@ -0,0 +1,92 @@
+use crate::services::{FileCopyReport, FileDiscoveryReport};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+/// Copied files that discovery did not see, and discovered files that were not copied this run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReconciliationReport {
+    /// Copied destinations never found by discovery, usually a copy output outside the scanned directories
+    pub copied_not_discovered: Vec<String>,
+    /// Discovered files that this run did not copy (e.g. copied by an earlier run or placed manually)
+    pub discovered_not_copied: Vec<String>,
+}
+
+impl ReconciliationReport {
+    /// Every copied file was discovered
+    pub fn all_copies_discovered(&self) -> bool {
+        self.copied_not_discovered.is_empty()
+    }
+}
+
+/// Match copied destination paths against discovered paths
+/// Pure function - paths are compared component-wise, so redundant separators do not matter
+pub fn reconcile(copy_report: &FileCopyReport, discovery_report: &FileDiscoveryReport) -> ReconciliationReport {
+    let copied: BTreeSet<PathBuf> = copy_report
+        .copied_files
+        .iter()
+        .map(|file| PathBuf::from(&file.destination))
+        .collect();
+    let discovered: BTreeSet<PathBuf> = discovery_report
+        .discovered_paths
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+
+    let to_strings = |paths: std::collections::btree_set::Difference<'_, PathBuf>| {
+        paths.map(|path| path.to_string_lossy().to_string()).collect()
+    };
+
+    ReconciliationReport {
+        copied_not_discovered: to_strings(copied.difference(&discovered)),
+        discovered_not_copied: to_strings(discovered.difference(&copied)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::CopiedFile;
+
+    fn copy_report(destinations: &[&str]) -> FileCopyReport {
+        FileCopyReport {
+            successful_copies: destinations.len(),
+            copied_files: destinations
+                .iter()
+                .map(|destination| CopiedFile {
+                    source: format!("/in/{}", destination.rsplit('/').next().unwrap()),
+                    destination: destination.to_string(),
+                })
+                .collect(),
+            ..FileCopyReport::empty()
+        }
+    }
+
+    fn discovery_report(paths: &[&str]) -> FileDiscoveryReport {
+        FileDiscoveryReport {
+            files_discovered: paths.len(),
+            discovered_paths: paths.iter().map(|path| path.to_string()).collect(),
+            ..FileDiscoveryReport::empty()
+        }
+    }
+
+    #[test]
+    fn test_reconcile_reports_both_directions() {
+        let report = reconcile(
+            &copy_report(&["/out/a/1.txt", "/out/a/2.txt", "/misconfigured/3.txt"]),
+            &discovery_report(&["/out/a/1.txt", "/out/a//2.txt", "/out/a/old.txt"]),
+        );
+
+        assert_eq!(report.copied_not_discovered, vec!["/misconfigured/3.txt"]);
+        assert_eq!(report.discovered_not_copied, vec!["/out/a/old.txt"]);
+        assert!(!report.all_copies_discovered());
+    }
+
+    #[test]
+    fn test_reconcile_consistent_run() {
+        let report = reconcile(&copy_report(&["/out/1.txt"]), &discovery_report(&["/out/1.txt"]));
+
+        assert_eq!(report, ReconciliationReport::default());
+        assert!(report.all_copies_discovered());
+        assert!(reconcile(&FileCopyReport::empty(), &FileDiscoveryReport::empty()).all_copies_discovered());
+    }
+}
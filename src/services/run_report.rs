@@ -0,0 +1,208 @@
+use crate::services::{elapsed_ms, DiscoveryTimings, FileCopyReport, FileDiscoveryReport};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::time::Duration;
+
+/// Exit code of a run within the failure thresholds
+pub const EXIT_OK: i32 = 0;
+/// Exit code when the copy phase exceeded the failure threshold
+pub const EXIT_COPY_FAILED: i32 = 3;
+/// Exit code when the discovery phase exceeded the failure threshold
+pub const EXIT_DISCOVERY_FAILED: i32 = 4;
+/// Exit code when both phases exceeded the failure threshold
+pub const EXIT_COPY_AND_DISCOVERY_FAILED: i32 = 5;
+
+/// Combined outcome of one run, as written to `--report-output`
+#[derive(Debug, Clone, Serialize)]
+pub struct RunReport {
+    pub generated_at: DateTime<Utc>,
+    /// None when the copy phase did not run (e.g. with `--file-list` or `--skip-copy`)
+    pub copy: Option<FileCopyReport>,
+    /// None when the discovery phase did not run
+    pub discovery: Option<FileDiscoveryReport>,
+    pub timings: RunTimings,
+}
+
+impl RunReport {
+    pub fn new(copy: Option<FileCopyReport>, discovery: Option<FileDiscoveryReport>) -> Self {
+        Self {
+            generated_at: Utc::now(),
+            copy,
+            discovery,
+            timings: RunTimings::default(),
+        }
+    }
+
+    /// Record the run's phase timings, taking the discovery stages from its report
+    pub fn with_timings(self, copy: Option<Duration>, total: Duration) -> Self {
+        let discovery = self.discovery.as_ref().map(|discovery| &discovery.timings);
+        Self {
+            timings: RunTimings::new(copy, discovery, total),
+            ..self
+        }
+    }
+
+    /// Pure function choosing the process exit code for this run
+    /// Phases that did not run never fail it; discovery counts files it could not save as errors
+    pub fn exit_code(&self, policy: &ExitPolicy) -> i32 {
+        let copy_failed = self.copy.as_ref().is_some_and(|copy| {
+            copy.aborted || policy.exceeded(copy.errors.len(), copy.total_processed())
+        });
+        let discovery_failed = self.discovery.as_ref().is_some_and(|discovery| {
+            let errors = discovery.processing_errors + discovery.files_failed_to_save;
+            policy.exceeded(errors, discovery.files_discovered)
+        });
+
+        match (copy_failed, discovery_failed) {
+            (false, false) => EXIT_OK,
+            (true, false) => EXIT_COPY_FAILED,
+            (false, true) => EXIT_DISCOVERY_FAILED,
+            (true, true) => EXIT_COPY_AND_DISCOVERY_FAILED,
+        }
+    }
+}
+
+/// Wall-clock milliseconds of each phase of a run; phases that did not run stay at 0
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct RunTimings {
+    pub copy_ms: f64,
+    pub discovery_scan_ms: f64,
+    pub discovery_hash_ms: f64,
+    pub discovery_save_ms: f64,
+    pub total_ms: f64,
+}
+
+impl RunTimings {
+    /// Pure function combining the measured phase durations
+    pub fn new(copy: Option<Duration>, discovery: Option<&DiscoveryTimings>, total: Duration) -> Self {
+        let discovery = discovery.copied().unwrap_or_default();
+        Self {
+            copy_ms: copy.map(elapsed_ms).unwrap_or(0.0),
+            discovery_scan_ms: discovery.scan_ms,
+            discovery_hash_ms: discovery.hash_ms,
+            discovery_save_ms: discovery.save_ms,
+            total_ms: elapsed_ms(total),
+        }
+    }
+}
+
+/// When errors make a run exit non-zero
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExitPolicy {
+    /// Any error fails the phase
+    pub strict: bool,
+    /// Otherwise a phase fails when more than this percentage of its files errored
+    pub max_error_percent: f64,
+}
+
+impl Default for ExitPolicy {
+    fn default() -> Self {
+        Self {
+            strict: false,
+            max_error_percent: 50.0,
+        }
+    }
+}
+
+impl ExitPolicy {
+    /// Whether `errors` out of `total` files exceed the policy's threshold
+    pub fn exceeded(&self, errors: usize, total: usize) -> bool {
+        if errors == 0 || total == 0 {
+            return false;
+        }
+        self.strict || errors as f64 * 100.0 / total as f64 > self.max_error_percent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::CopyError;
+
+    fn copy_report(successes: usize, errors: usize) -> FileCopyReport {
+        let error = CopyError {
+            source: "/in/a.txt".to_string(),
+            destination: "/out/a.txt".to_string(),
+            error: "Permission denied".to_string(),
+        };
+        FileCopyReport {
+            successful_copies: successes,
+            errors: vec![error; errors],
+            ..FileCopyReport::empty()
+        }
+    }
+
+    fn discovery_report(discovered: usize, errors: usize) -> FileDiscoveryReport {
+        FileDiscoveryReport {
+            files_discovered: discovered,
+            files_processed: discovered - errors,
+            processing_errors: errors,
+            ..FileDiscoveryReport::empty()
+        }
+    }
+
+    #[test]
+    fn test_clean_and_tolerated_runs_exit_zero() {
+        let policy = ExitPolicy::default();
+
+        assert_eq!(RunReport::new(Some(copy_report(10, 0)), Some(discovery_report(10, 0))).exit_code(&policy), EXIT_OK);
+        assert_eq!(RunReport::new(Some(copy_report(6, 4)), Some(discovery_report(10, 5))).exit_code(&policy), EXIT_OK);
+        assert_eq!(RunReport::new(None, None).exit_code(&policy), EXIT_OK);
+    }
+
+    #[test]
+    fn test_exit_code_names_the_failed_phase() {
+        let policy = ExitPolicy::default();
+        let exit_code = |copy, discovery| RunReport::new(copy, discovery).exit_code(&policy);
+
+        assert_eq!(exit_code(Some(copy_report(0, 3)), Some(discovery_report(10, 0))), EXIT_COPY_FAILED);
+        assert_eq!(exit_code(Some(copy_report(3, 0)), Some(discovery_report(10, 6))), EXIT_DISCOVERY_FAILED);
+        assert_eq!(exit_code(Some(copy_report(1, 2)), Some(discovery_report(4, 4))), EXIT_COPY_AND_DISCOVERY_FAILED);
+        assert_eq!(exit_code(None, Some(discovery_report(1, 1))), EXIT_DISCOVERY_FAILED);
+
+        // An aborted copy fails regardless of its error rate
+        let aborted = FileCopyReport { aborted: true, ..copy_report(10, 1) };
+        assert_eq!(exit_code(Some(aborted), None), EXIT_COPY_FAILED);
+    }
+
+    #[test]
+    fn test_failed_saves_fail_the_discovery_phase() {
+        let policy = ExitPolicy::default();
+        let unsaved = |failed| FileDiscoveryReport { files_failed_to_save: failed, ..discovery_report(10, 0) };
+
+        assert_eq!(RunReport::new(None, Some(unsaved(10))).exit_code(&policy), EXIT_DISCOVERY_FAILED);
+        assert_eq!(RunReport::new(None, Some(unsaved(2))).exit_code(&policy), EXIT_OK);
+        let strict = ExitPolicy { strict: true, ..policy };
+        assert_eq!(RunReport::new(None, Some(unsaved(1))).exit_code(&strict), EXIT_DISCOVERY_FAILED);
+    }
+
+    #[test]
+    fn test_timings_combine_copy_and_discovery_stages() {
+        let discovery = FileDiscoveryReport {
+            timings: DiscoveryTimings { scan_ms: 12.5, hash_ms: 40.0, save_ms: 7.25 },
+            ..discovery_report(3, 0)
+        };
+        let report = RunReport::new(None, Some(discovery))
+            .with_timings(Some(Duration::from_millis(30)), Duration::from_millis(90));
+
+        assert_eq!(report.timings, RunTimings {
+            copy_ms: 30.0,
+            discovery_scan_ms: 12.5,
+            discovery_hash_ms: 40.0,
+            discovery_save_ms: 7.25,
+            total_ms: 90.0,
+        });
+        assert_eq!(RunTimings::new(None, None, Duration::ZERO), RunTimings::default());
+    }
+
+    #[test]
+    fn test_strict_policy_fails_on_any_error() {
+        let strict = ExitPolicy { strict: true, ..ExitPolicy::default() };
+
+        assert_eq!(RunReport::new(Some(copy_report(99, 1)), None).exit_code(&strict), EXIT_COPY_FAILED);
+        assert_eq!(RunReport::new(Some(copy_report(99, 0)), Some(discovery_report(5, 0))).exit_code(&strict), EXIT_OK);
+
+        let zero_tolerance = ExitPolicy { max_error_percent: 0.0, ..ExitPolicy::default() };
+        assert_eq!(RunReport::new(None, Some(discovery_report(100, 1))).exit_code(&zero_tolerance), EXIT_DISCOVERY_FAILED);
+    }
+}
@@ -0,0 +1,184 @@
+use crate::services::Metrics;
+use anyhow::{Context, Result};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+/// Maximum request size accepted by the status server
+const MAX_REQUEST_BYTES: usize = 8 * 1024;
+/// How long a client may take to send its request before the connection is dropped
+const REQUEST_READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Minimal HTTP server exposing `GET /status` with the current metrics as JSON
+pub struct StatusServer {
+    local_addr: SocketAddr,
+    shutdown: oneshot::Sender<()>,
+    handle: JoinHandle<()>,
+}
+
+impl StatusServer {
+    /// Bind the address and start serving in the background
+    pub async fn start(addr: &str, metrics: Arc<Metrics>) -> Result<Self> {
+        let listener = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("Failed to bind status server to {}", addr))?;
+        let local_addr = listener.local_addr()?;
+        let (shutdown, mut shutdown_rx) = oneshot::channel();
+
+        let handle = tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = &mut shutdown_rx => break,
+                    accepted = listener.accept() => match accepted {
+                        Ok((stream, peer)) => {
+                            let metrics = Arc::clone(&metrics);
+                            tokio::spawn(async move {
+                                if let Err(e) = handle_connection(stream, &metrics, REQUEST_READ_TIMEOUT).await {
+                                    debug!("Status request from {} failed: {}", peer, e);
+                                }
+                            });
+                        }
+                        Err(e) => warn!("Status server failed to accept connection: {}", e),
+                    },
+                }
+            }
+        });
+
+        info!("Status server listening on http://{}/status", local_addr);
+
+        Ok(Self {
+            local_addr,
+            shutdown,
+            handle,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Stop accepting connections and wait for the server task to finish
+    pub async fn shutdown(self) {
+        let _ = self.shutdown.send(());
+        let _ = self.handle.await;
+    }
+}
+
+/// Read a single request and write the response
+/// A client that sends nothing for `read_timeout` is dropped, so idle connections do not
+/// pile up
+async fn handle_connection(mut stream: TcpStream, metrics: &Metrics, read_timeout: Duration) -> Result<()> {
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 1024];
+
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") && request.len() < MAX_REQUEST_BYTES {
+        let bytes_read = tokio::time::timeout(read_timeout, stream.read(&mut buffer))
+            .await
+            .context("Timed out reading the request")??;
+        if bytes_read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..bytes_read]);
+    }
+
+    let request_line = String::from_utf8_lossy(&request)
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_string();
+
+    let response = route_request(&request_line, metrics);
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+
+    Ok(())
+}
+
+/// Pure routing: build the full HTTP response for a request line
+fn route_request(request_line: &str, metrics: &Metrics) -> String {
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+
+    match (method, path) {
+        ("GET", "/status") => match serde_json::to_string(&metrics.snapshot()) {
+            Ok(body) => http_response("200 OK", "application/json", &body),
+            Err(e) => http_response("500 Internal Server Error", "text/plain", &e.to_string()),
+        },
+        (_, "/status") => http_response("405 Method Not Allowed", "text/plain", "Method Not Allowed"),
+        _ => http_response("404 Not Found", "text/plain", "Not Found"),
+    }
+}
+
+fn http_response(status: &str, content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::RunPhase;
+    use crate::utils::CopyResult;
+    use std::path::PathBuf;
+
+    async fn request(addr: SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_status_endpoint_returns_metrics_json() {
+        let metrics = Arc::new(Metrics::default());
+        metrics.set_phase(RunPhase::Copying);
+        for _ in 0..3 {
+            metrics.record_copy_result(&CopyResult::Created {
+                source: PathBuf::from("/in/a.txt"),
+                destination: PathBuf::from("/out/a.txt"),
+            });
+        }
+
+        let server = StatusServer::start("127.0.0.1:0", Arc::clone(&metrics)).await.unwrap();
+        let response = request(server.local_addr(), "GET /status HTTP/1.1\r\nHost: localhost\r\n\r\n").await;
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        let body = response.split("\r\n\r\n").nth(1).unwrap();
+        let json: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(json["phase"], "copying");
+        assert_eq!(json["files_copied"], 3);
+        assert_eq!(json["files_saved"], 0);
+        assert!(json["started_at"].is_string());
+
+        let not_found = request(server.local_addr(), "GET /other HTTP/1.1\r\n\r\n").await;
+        assert!(not_found.starts_with("HTTP/1.1 404"));
+
+        server.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_silent_client_is_dropped_after_the_read_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+
+        let handled = tokio::time::timeout(
+            Duration::from_secs(5),
+            handle_connection(stream, &Metrics::default(), Duration::from_millis(50)),
+        )
+        .await
+        .expect("the connection handler waited past its read timeout");
+        assert!(handled.unwrap_err().to_string().contains("Timed out"));
+    }
+}
@@ -0,0 +1,89 @@
+use chrono::{DateTime, Utc};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Source of the current time, abstracted so time-dependent logic can be tested
+/// Shared between threads as `Arc<dyn Clock>` in the run configs
+pub trait Clock: std::fmt::Debug + Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The system clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Wall-clock budget of a run: no new work is started once it has passed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deadline(pub Instant);
+
+impl Deadline {
+    /// A deadline `budget` from now
+    pub fn after(budget: Duration) -> Self {
+        Self(Instant::now() + budget)
+    }
+
+    pub fn has_passed(&self) -> bool {
+        Instant::now() >= self.0
+    }
+}
+
+/// Whether an optional deadline has passed; without one, work never stops
+pub fn deadline_passed(deadline: Option<Deadline>) -> bool {
+    deadline.is_some_and(|deadline| deadline.has_passed())
+}
+
+/// Shared flag asking a run to start no new work, e.g. on Ctrl-C
+/// Clones share the flag; a default token is never cancelled unless `cancel` is called
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Why a run stopped starting new work
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The run's deadline passed
+    Deadline,
+    /// The run's cancellation token was cancelled
+    Cancelled,
+}
+
+/// Why no new work may start, if the run was cancelled or its deadline passed
+pub fn stop_reason(deadline: Option<Deadline>, cancel: &CancellationToken) -> Option<StopReason> {
+    if cancel.is_cancelled() {
+        Some(StopReason::Cancelled)
+    } else if deadline_passed(deadline) {
+        Some(StopReason::Deadline)
+    } else {
+        None
+    }
+}
+
+/// A clock frozen at a given instant
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedClock(pub DateTime<Utc>);
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
@@ -0,0 +1,97 @@
+use chrono::Utc;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use tracing::warn;
+
+/// Dedicated sink for per-file errors, one `timestamp\tpath\terror` line each
+/// Write failures (e.g. a full disk) disable the sink and leave tracing as the only output.
+#[derive(Debug, Default)]
+pub struct ErrorLog {
+    file: Mutex<Option<File>>,
+}
+
+impl ErrorLog {
+    /// Open the error file for appending, or a disabled sink when no path is configured
+    pub fn open(path: Option<&Path>) -> Self {
+        let file = path.and_then(|path| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| warn!("Failed to open error log {:?}, errors go to the log only: {}", path, e))
+                .ok()
+        });
+
+        Self {
+            file: Mutex::new(file),
+        }
+    }
+
+    /// Sink that discards every error
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.lock().is_some()
+    }
+
+    /// Append one error line and flush it
+    pub fn record(&self, path: &str, error: &str) {
+        let mut file = self.lock();
+        let Some(handle) = file.as_mut() else {
+            return;
+        };
+
+        let line = format_error_line(&Utc::now().to_rfc3339(), path, error);
+        if let Err(e) = handle.write_all(line.as_bytes()).and_then(|_| handle.flush()) {
+            warn!("Failed to write error log, errors go to the log only from now on: {}", e);
+            *file = None;
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<File>> {
+        self.file.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Format a single error line, keeping every field on one line
+/// Pure function
+pub fn format_error_line(timestamp: &str, path: &str, error: &str) -> String {
+    let clean = |field: &str| field.replace(['\t', '\r', '\n'], " ");
+    format!("{}\t{}\t{}\n", timestamp, clean(path), clean(error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_error_line_escapes_separators() {
+        assert_eq!(
+            format_error_line("2024-01-15T10:30:00+00:00", "/in/a\tb.txt", "line 1\nline 2"),
+            "2024-01-15T10:30:00+00:00\t/in/a b.txt\tline 1 line 2\n"
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_full_disk_degrades_to_log_only() {
+        let error_log = ErrorLog::open(Some(Path::new("/dev/full")));
+        assert!(error_log.is_enabled());
+
+        error_log.record("/in/a.txt", "boom");
+
+        assert!(!error_log.is_enabled());
+    }
+
+    #[test]
+    fn test_unopenable_path_is_disabled() {
+        let dir = tempfile::tempdir().unwrap();
+        let error_log = ErrorLog::open(Some(&dir.path().join("missing").join("errors.tsv")));
+        assert!(!error_log.is_enabled());
+        error_log.record("/in/a.txt", "boom");
+    }
+}
@@ -1,7 +1,14 @@
 use anyhow::{Context, Result};
 use chrono::{DateTime, Duration, Utc};
+use crate::utils::clock::{stop_reason, CancellationToken, Clock, Deadline, StopReason, SystemClock};
+use crate::utils::filename_date::FilenameDatePattern;
+use crate::utils::filesystem::{read_dir_with_retry, DirEntries, FileSystem, OsFileSystem, RetryPolicy};
+use crate::utils::sftp::{SftpCredentials, SftpTarget, SftpUploader};
+use std::collections::HashMap;
 use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 /// List files in a directory matching given extensions
 /// Pure function that returns a Result<Vec<PathBuf>>
@@ -10,51 +17,370 @@ pub fn list_files_with_extensions<P: AsRef<Path>>(
     extensions: &[String],
     modified_since: Option<DateTime<Utc>>,
 ) -> Result<Vec<PathBuf>> {
-    let dir_path = directory.as_ref();
-    
-    if !dir_path.exists() {
-        return Ok(Vec::new());
-    }
+    let options = ScanOptions {
+        extensions: extensions.to_vec(),
+        modified_since,
+        ..ScanOptions::default()
+    };
+
+    list_files(directory, options)
+}
+
+/// List files matching the given extensions in a directory and all its subdirectories
+/// Subdirectories are walked depth-first; symlinked directories are not followed
+pub fn list_files_recursive<P: AsRef<Path>>(
+    directory: P,
+    extensions: &[String],
+    modified_since: Option<DateTime<Utc>>,
+) -> Result<Vec<PathBuf>> {
+    list_files_recursive_with_depth(directory, extensions, modified_since, None)
+}
+
+/// List files matching the given extensions in a directory and its subdirectories, down to
+/// `max_depth` levels: `Some(0)` lists the directory only, `Some(2)` descends two levels and
+/// None walks the whole tree
+pub fn list_files_recursive_with_depth<P: AsRef<Path>>(
+    directory: P,
+    extensions: &[String],
+    modified_since: Option<DateTime<Utc>>,
+    max_depth: Option<usize>,
+) -> Result<Vec<PathBuf>> {
+    let options = ScanOptions {
+        extensions: extensions.to_vec(),
+        modified_since,
+        recursive: true,
+        max_depth,
+        ..ScanOptions::default()
+    };
 
-    let entries = fs::read_dir(dir_path)
-        .with_context(|| format!("Failed to read directory: {:?}", dir_path))?;
+    list_files(directory, options)
+}
+
+/// List files passing the scan options, also counting the files of a selected extension or
+/// content type that the date, size or custom filters then rejected
+pub fn list_files_with_filtered_count<P: AsRef<Path>>(directory: P, options: ScanOptions) -> Result<(Vec<PathBuf>, usize)> {
+    let (files, filtered): (Vec<PathBuf>, Vec<PathBuf>) = list_files(directory, options.selection())?
+        .into_iter()
+        .partition(|path| options.passes_filters(path));
+
+    Ok((files, filtered.len()))
+}
 
-    let files: Vec<PathBuf> = entries
+/// List files in a directory passing the given scan options
+pub fn list_files<P: AsRef<Path>>(directory: P, options: ScanOptions) -> Result<Vec<PathBuf>> {
+    let files = FileScanner::new(directory, options)?
         .filter_map(|entry| entry.ok())
-        .map(|entry| entry.path())
-        .filter(|path| path.is_file())
-        .filter(|path| matches_extensions(path, extensions))
-        .filter(|path| matches_modification_date(path, modified_since).unwrap_or(true))
         .collect();
 
     Ok(files)
 }
 
+/// Filters applied to each entry while scanning a directory
+#[derive(Debug, Clone, Default)]
+pub struct ScanOptions {
+    pub extensions: Vec<String>,
+    pub modified_since: Option<DateTime<Utc>>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    /// Retry policy for listing the directory on transient errors
+    pub retry: RetryPolicy,
+    /// Content types (MIME type or canonical extension, e.g. "application/zip" or "zip")
+    /// detected from magic bytes that also match files without an extension
+    pub content_types: Vec<String>,
+    /// Custom predicates every file must also pass
+    pub filters: Vec<FilePredicate>,
+    /// Also scan subdirectories, depth-first, without following symlinked directories
+    pub recursive: bool,
+    /// With `recursive`, how many levels of subdirectories are scanned (None is unlimited)
+    pub max_depth: Option<usize>,
+    /// How modification times later than now are treated by the date filter
+    pub future_mtime_policy: FutureMtimePolicy,
+    /// Now, as read from the caller's clock when the scan starts, for `future_mtime_policy`
+    /// (None reads `SystemClock` at each check)
+    pub now: Option<DateTime<Utc>>,
+}
+
+/// How a modification time in the future (e.g. clock skew on an upload server) is treated
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FutureMtimePolicy {
+    /// Use the timestamp as is
+    #[default]
+    Accept,
+    /// Treat the timestamp as `now`
+    ClampToNow,
+    /// Exclude the file
+    Reject,
+}
+
+impl FutureMtimePolicy {
+    /// Pure function to get the modification time to use, or None when the file is rejected
+    pub fn apply(self, modified: DateTime<Utc>, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        if modified <= now {
+            return Some(modified);
+        }
+        match self {
+            FutureMtimePolicy::Accept => Some(modified),
+            FutureMtimePolicy::ClampToNow => Some(now),
+            FutureMtimePolicy::Reject => None,
+        }
+    }
+}
+
+impl ScanOptions {
+    /// Check whether a path is a regular file passing every configured filter
+    pub fn matches(&self, path: &Path) -> bool {
+        path.is_file()
+            && (matches_extensions(path, &self.extensions) || matches_content_type(path, &self.content_types))
+            && self.passes_filters(path)
+    }
+
+    /// Pure function keeping only the extension and content type selection (and how the
+    /// directory is walked), so files the other filters reject can be counted
+    pub fn selection(&self) -> ScanOptions {
+        ScanOptions {
+            extensions: self.extensions.clone(),
+            retry: self.retry.clone(),
+            content_types: self.content_types.clone(),
+            recursive: self.recursive,
+            max_depth: self.max_depth,
+            ..ScanOptions::default()
+        }
+    }
+
+    /// Check the date, size and custom filters, leaving out the extension selection
+    pub fn passes_filters(&self, path: &Path) -> bool {
+        self.exclusion_reason(path).is_none() && self.filters.iter().all(|filter| filter.matches(path))
+    }
+
+    /// Skip reason of the date or size filter rejecting a path, or None when both accept it
+    /// (custom filters are not checked)
+    pub fn exclusion_reason(&self, path: &Path) -> Option<&'static str> {
+        let now = self.now.unwrap_or_else(|| SystemClock.now());
+        if !matches_modification_date(path, self.modified_since, self.future_mtime_policy, now).unwrap_or(true) {
+            return Some(SKIP_REASON_DATE);
+        }
+        if !matches_size(path, self.min_size, self.max_size).unwrap_or(true) {
+            return Some(SKIP_REASON_SIZE);
+        }
+        None
+    }
+}
+
+/// Custom file filter injected by callers, e.g. "only files whose name contains today's date"
+#[derive(Clone)]
+pub struct FilePredicate(Arc<dyn Fn(&Path) -> bool + Send + Sync>);
+
+impl FilePredicate {
+    pub fn new<F>(predicate: F) -> Self
+    where
+        F: Fn(&Path) -> bool + Send + Sync + 'static,
+    {
+        Self(Arc::new(predicate))
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        (self.0)(path)
+    }
+}
+
+impl std::fmt::Debug for FilePredicate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("FilePredicate(..)")
+    }
+}
+
+/// Predicates compare equal only when they are the same instance
+impl PartialEq for FilePredicate {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for FilePredicate {}
+
+/// Lazy directory scanner yielding the files that pass the scan filters
+/// Entries are only read and filtered as the iterator is advanced, so callers can stop early
+pub struct FileScanner<I = DirEntries> {
+    entries: I,
+    options: ScanOptions,
+    /// Subdirectories being walked when scanning recursively, innermost last
+    subdirectories: Vec<DirEntries>,
+}
+
+impl FileScanner {
+    /// Start scanning a directory; a missing directory yields no entries
+    pub fn new<P: AsRef<Path>>(directory: P, options: ScanOptions) -> Result<Self> {
+        Self::with_file_system(&OsFileSystem, directory, options)
+    }
+
+    /// Start scanning a directory through the given filesystem
+    /// Transient listing errors are retried according to `options.retry`
+    pub fn with_file_system<F, P>(file_system: &F, directory: P, options: ScanOptions) -> Result<Self>
+    where
+        F: FileSystem + ?Sized,
+        P: AsRef<Path>,
+    {
+        let dir_path = directory.as_ref();
+
+        match read_dir_with_retry(file_system, dir_path, &options.retry) {
+            Ok(entries) => Ok(Self::from_entries(entries, options)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                Ok(Self::from_entries(Box::new(std::iter::empty()), options))
+            }
+            Err(e) => Err(anyhow::Error::from(e).context(format!("Failed to read directory: {:?}", dir_path))),
+        }
+    }
+}
+
+impl<I> FileScanner<I>
+where
+    I: Iterator<Item = io::Result<PathBuf>>,
+{
+    /// Scan an arbitrary source of directory entries
+    /// Subdirectories found among the entries are listed from the real filesystem
+    pub fn from_entries(entries: I, options: ScanOptions) -> Self {
+        Self {
+            entries,
+            options,
+            subdirectories: Vec::new(),
+        }
+    }
+
+    /// Whether subdirectories found at the current depth are scanned
+    fn descends(&self) -> bool {
+        self.options.recursive && self.options.max_depth.is_none_or(|max| self.subdirectories.len() < max)
+    }
+
+    /// Next entry of the innermost subdirectory being walked, else of the scanned directory
+    fn next_entry(&mut self) -> Option<io::Result<PathBuf>> {
+        while let Some(subdirectory) = self.subdirectories.last_mut() {
+            match subdirectory.next() {
+                Some(entry) => return Some(entry),
+                None => {
+                    self.subdirectories.pop();
+                }
+            }
+        }
+        self.entries.next()
+    }
+}
+
+impl<I> Iterator for FileScanner<I>
+where
+    I: Iterator<Item = io::Result<PathBuf>>,
+{
+    type Item = Result<PathBuf>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(entry) = self.next_entry() {
+            match entry {
+                Ok(path) if self.descends() && is_directory_not_symlink(&path) => {
+                    match read_dir_with_retry(&OsFileSystem, &path, &self.options.retry) {
+                        Ok(entries) => self.subdirectories.push(entries),
+                        Err(e) => {
+                            let error = anyhow::Error::from(e).context(format!("Failed to read directory: {:?}", path));
+                            return Some(Err(error));
+                        }
+                    }
+                }
+                Ok(path) if self.options.recursive && is_directory_not_symlink(&path) => {
+                    tracing::info!(
+                        "Not scanning {:?}: deeper than the maximum scan depth of {}",
+                        path,
+                        self.subdirectories.len()
+                    );
+                }
+                Ok(path) if self.options.matches(&path) => return Some(Ok(path)),
+                Ok(_) => continue,
+                Err(e) => return Some(Err(anyhow::Error::from(e).context("Failed to read directory entry"))),
+            }
+        }
+        None
+    }
+}
+
+/// Check whether a path is a directory itself rather than a symlink to one
+/// Symlinked directories are skipped so recursive scans cannot loop
+fn is_directory_not_symlink(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_dir())
+}
+
 /// Check if file matches any of the given extensions
+/// Entries containing `*` or `?` are glob patterns matched against the whole file name,
+/// e.g. `*.txt.gz` or `report.*.csv`; the others are compared to the last extension
 /// Pure function
-fn matches_extensions(path: &Path, extensions: &[String]) -> bool {
+pub fn matches_extensions(path: &Path, extensions: &[String]) -> bool {
     if extensions.is_empty() {
         return true;
     }
 
-    path.extension()
+    let (patterns, plain): (Vec<&String>, Vec<&String>) =
+        extensions.iter().partition(|target_ext| is_glob_pattern(target_ext));
+
+    let matches_plain = path.extension()
         .and_then(|ext| ext.to_str())
         .map(|ext| {
             let ext_with_dot = format!(".{}", ext);
-            extensions.iter().any(|target_ext| {
+            plain.iter().any(|target_ext| {
                 target_ext.eq_ignore_ascii_case(&ext_with_dot) || 
                 target_ext.eq_ignore_ascii_case(ext)
             })
         })
-        .unwrap_or(false)
+        .unwrap_or(false);
+
+    matches_plain || (!patterns.is_empty() && matches_glob_patterns(path, &patterns))
+}
+
+/// Pure function to check if an extension entry is a glob pattern
+fn is_glob_pattern(extension: &str) -> bool {
+    extension.contains(['*', '?'])
+}
+
+/// Check if the file name matches any of the glob patterns, ignoring case
+/// Invalid patterns never match
+/// Pure function
+fn matches_glob_patterns(path: &Path, patterns: &[&String]) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    let options = glob::MatchOptions {
+        case_sensitive: false,
+        ..glob::MatchOptions::new()
+    };
+
+    patterns.iter().any(|pattern| {
+        glob::Pattern::new(pattern).is_ok_and(|pattern| pattern.matches_with(name, options))
+    })
+}
+
+/// Check if an extension-less file's magic bytes identify one of the allowed content types
+/// Files with an extension are left to the extension filter
+fn matches_content_type(path: &Path, content_types: &[String]) -> bool {
+    if content_types.is_empty() || path.extension().is_some() {
+        return false;
+    }
+
+    match infer::get_from_path(path) {
+        Ok(Some(kind)) => content_types.iter().any(|allowed| {
+            let allowed = allowed.trim().trim_start_matches('.');
+            allowed.eq_ignore_ascii_case(kind.mime_type()) || allowed.eq_ignore_ascii_case(kind.extension())
+        }),
+        _ => false,
+    }
 }
 
-/// Check if file was modified since the given date
+/// Check if file was modified since the given date, after applying the future mtime policy
+/// against `now`
 /// Pure function (except for file system access)
-fn matches_modification_date(path: &Path, modified_since: Option<DateTime<Utc>>) -> Result<bool> {
-    let Some(since) = modified_since else {
+fn matches_modification_date(
+    path: &Path,
+    modified_since: Option<DateTime<Utc>>,
+    future_mtime_policy: FutureMtimePolicy,
+    now: DateTime<Utc>,
+) -> Result<bool> {
+    if modified_since.is_none() && future_mtime_policy != FutureMtimePolicy::Reject {
         return Ok(true);
-    };
+    }
 
     let metadata = fs::metadata(path)
         .with_context(|| format!("Failed to read metadata for: {:?}", path))?;
@@ -67,7 +393,23 @@ fn matches_modification_date(path: &Path, modified_since: Option<DateTime<Utc>>)
         0
     ).unwrap_or_default();
 
-    Ok(modified_datetime >= since)
+    Ok(future_mtime_policy
+        .apply(modified_datetime, now)
+        .is_some_and(|modified| modified_since.is_none_or(|since| modified >= since)))
+}
+
+/// Check if file size falls within the optional bounds
+/// Pure function (except for file system access)
+fn matches_size(path: &Path, min_size: Option<u64>, max_size: Option<u64>) -> Result<bool> {
+    if min_size.is_none() && max_size.is_none() {
+        return Ok(true);
+    }
+
+    let size = fs::metadata(path)
+        .with_context(|| format!("Failed to read metadata for: {:?}", path))?
+        .len();
+
+    Ok(min_size.is_none_or(|min| size >= min) && max_size.is_none_or(|max| size <= max))
 }
 
 /// Copy file from source to destination
@@ -77,15 +419,47 @@ pub fn copy_file_safe<P: AsRef<Path>, Q: AsRef<Path>>(
     destination: Q,
     overwrite: bool,
 ) -> Result<bool> {
-    let src_path = source.as_ref();
-    let dest_path = destination.as_ref();
+    copy_file_safe_with(source, destination, overwrite, None)
+}
+
+/// Copy file from source to destination, with explicit reads and writes of `buffer_bytes`
+/// instead of `fs::copy` when set (faster on some network filesystems)
+/// The content is written to a `.part` file renamed into place once complete, so an
+/// interrupted copy never leaves a truncated file under the final name
+pub fn copy_file_safe_with<P: AsRef<Path>, Q: AsRef<Path>>(
+    source: P,
+    destination: Q,
+    overwrite: bool,
+    buffer_bytes: Option<usize>,
+) -> Result<bool> {
+    let copy = |src_path: &Path, part_path: &Path| match buffer_bytes {
+        Some(buffer_bytes) => copy_buffered(src_path, part_path, buffer_bytes),
+        None => fs::copy(src_path, part_path).map(drop),
+    };
+    copy_file_via(source.as_ref(), destination.as_ref(), overwrite, copy).map(|copied| copied.is_some())
+}
+
+/// `copy_file_safe_with` hashing the source as it is read, so verifying the copy does not
+/// read the source a second time
+/// Returns the SHA-256 of the copied content, or None when the destination exists and
+/// `overwrite` is off
+fn copy_file_hashed(src_path: &Path, dest_path: &Path, overwrite: bool, buffer_bytes: Option<usize>) -> Result<Option<[u8; 32]>> {
+    let buffer_bytes = buffer_bytes.unwrap_or(crate::models::DEFAULT_READ_BUFFER_SIZE);
+    copy_file_via(src_path, dest_path, overwrite, |src_path, part_path| copy_hashing(src_path, part_path, buffer_bytes))
+}
 
+/// Let `copy` write the source to the `.part` file of the destination, creating its directory
+/// Returns what `copy` returned, or None when the destination exists and `overwrite` is off
+fn copy_file_via<T, F>(src_path: &Path, dest_path: &Path, overwrite: bool, copy: F) -> Result<Option<T>>
+where
+    F: FnOnce(&Path, &Path) -> io::Result<T>,
+{
     if !src_path.exists() {
         anyhow::bail!("Source file does not exist: {:?}", src_path);
     }
 
     if dest_path.exists() && !overwrite {
-        return Ok(false); // File already exists, skip
+        return Ok(None); // File already exists, skip
     }
 
     // Create destination directory if it doesn't exist
@@ -94,12 +468,178 @@ pub fn copy_file_safe<P: AsRef<Path>, Q: AsRef<Path>>(
             .with_context(|| format!("Failed to create directory: {:?}", parent))?;
     }
 
-    fs::copy(src_path, dest_path)
+    let copied = write_via_part_file(dest_path, |part_path| copy(src_path, part_path))
         .with_context(|| format!("Failed to copy file from {:?} to {:?}", src_path, dest_path))?;
 
+    Ok(Some(copied))
+}
+
+/// Move file from source to destination, renaming it when both are on the same filesystem
+/// Falls back to a copy (through a `.part` file) and delete across filesystems
+/// Returns false, leaving the source in place, when the destination exists and `overwrite` is off
+pub fn move_file_safe<P: AsRef<Path>, Q: AsRef<Path>>(
+    source: P,
+    destination: Q,
+    overwrite: bool,
+) -> Result<bool> {
+    move_file_with(source.as_ref(), destination.as_ref(), overwrite, false, |from, to| fs::rename(from, to), |_, _| Ok(()))
+}
+
+/// `move_file_safe` with the rename operation supplied by the caller
+/// `check` runs on the destination once it is in place and, across filesystems, before the
+/// source is removed: when it fails the source is kept. It gets the SHA-256 of the copied
+/// content when the move fell back to a copy and `hash` is set; a rename leaves the content
+/// untouched and gives None
+fn move_file_with<R, C>(src_path: &Path, dest_path: &Path, overwrite: bool, hash: bool, rename: R, check: C) -> Result<bool>
+where
+    R: FnOnce(&Path, &Path) -> io::Result<()>,
+    C: FnOnce(&Path, Option<[u8; 32]>) -> Result<()>,
+{
+    if !src_path.exists() {
+        anyhow::bail!("Source file does not exist: {:?}", src_path);
+    }
+
+    if dest_path.exists() && !overwrite {
+        return Ok(false);
+    }
+
+    if let Some(parent) = dest_path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {:?}", parent))?;
+    }
+
+    match rename(src_path, dest_path) {
+        Ok(()) => return check(dest_path, None).map(|()| true),
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {}
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to move file from {:?} to {:?}", src_path, dest_path))
+        }
+    }
+
+    let copied_hash = write_via_part_file(dest_path, |part_path| {
+        if hash {
+            copy_hashing(src_path, part_path, crate::models::DEFAULT_READ_BUFFER_SIZE).map(Some)
+        } else {
+            fs::copy(src_path, part_path).map(|_| None)
+        }
+    })
+    .with_context(|| format!("Failed to copy file from {:?} to {:?}", src_path, dest_path))?;
+    check(dest_path, copied_hash)?;
+    fs::remove_file(src_path)
+        .with_context(|| format!("Copied {:?} to {:?} but failed to remove the source", src_path, dest_path))?;
+
     Ok(true)
 }
 
+/// Suffix of the temporary file a copy is written to before it is renamed into place
+pub const PART_FILE_SUFFIX: &str = ".part";
+
+/// Pure function naming the temporary file of a copy to `destination`, e.g. `a.txt.part`
+pub fn part_file_path(destination: &Path) -> PathBuf {
+    let mut name = destination.file_name().unwrap_or_default().to_os_string();
+    name.push(PART_FILE_SUFFIX);
+    destination.with_file_name(name)
+}
+
+/// Let `write` fill the `.part` file of `destination`, then rename it to `destination`
+/// A failed write leaves the `.part` file behind, as a crash would, for
+/// `remove_stale_part_files` to clean up on the next run
+fn write_via_part_file<T, F>(destination: &Path, write: F) -> io::Result<T>
+where
+    F: FnOnce(&Path) -> io::Result<T>,
+{
+    let part_path = part_file_path(destination);
+    let written = write(&part_path)?;
+    fs::rename(&part_path, destination)?;
+    Ok(written)
+}
+
+/// Remove the `.part` files interrupted copies left for the given destinations
+/// Only `part_file_path` of each destination is touched, so `.part` files this tool did not
+/// write, or that other jobs are still writing, are kept. Returns how many were removed
+pub fn remove_stale_part_files<'a, I>(destinations: I) -> io::Result<usize>
+where
+    I: IntoIterator<Item = &'a Path>,
+{
+    let mut removed = 0;
+    for destination in destinations {
+        match fs::remove_file(part_file_path(destination)) {
+            Ok(()) => removed += 1,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Copy file content through a buffer of the given size, keeping the source permissions
+fn copy_buffered(source: &Path, destination: &Path, buffer_bytes: usize) -> io::Result<()> {
+    use std::io::{BufReader, BufWriter, Write};
+
+    let buffer_bytes = buffer_bytes.max(1);
+    let mut reader = BufReader::with_capacity(buffer_bytes, fs::File::open(source)?);
+    let mut writer = BufWriter::with_capacity(buffer_bytes, fs::File::create(destination)?);
+    io::copy(&mut reader, &mut writer)?;
+    writer.flush()?;
+
+    fs::set_permissions(destination, fs::metadata(source)?.permissions())
+}
+
+/// `copy_buffered` that also returns the SHA-256 of the bytes it copied
+fn copy_hashing(source: &Path, destination: &Path, buffer_bytes: usize) -> io::Result<[u8; 32]> {
+    use sha2::{Digest, Sha256};
+    use std::io::{BufWriter, Read, Write};
+
+    let mut reader = fs::File::open(source)?;
+    let mut writer = BufWriter::with_capacity(buffer_bytes.max(1), fs::File::create(destination)?);
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; buffer_bytes.max(1)];
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        hasher.update(&buffer[..read]);
+        writer.write_all(&buffer[..read])?;
+    }
+    writer.flush()?;
+
+    fs::set_permissions(destination, fs::metadata(source)?.permissions())?;
+    Ok(hasher.finalize().into())
+}
+
+/// Copy the Windows file attributes of `source` onto `destination`
+/// `fs::copy` keeps the content but not bits such as read-only or archive
+#[cfg(windows)]
+pub fn preserve_file_attributes(source: &Path, destination: &Path) -> Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::fs::MetadataExt;
+    use windows_sys::Win32::Storage::FileSystem::SetFileAttributesW;
+
+    let attributes = fs::metadata(source)
+        .with_context(|| format!("Failed to read metadata for: {:?}", source))?
+        .file_attributes();
+    let wide_path: Vec<u16> = destination.as_os_str().encode_wide().chain(Some(0)).collect();
+
+    // SAFETY: `wide_path` is a NUL-terminated UTF-16 string that outlives the call
+    if unsafe { SetFileAttributesW(wide_path.as_ptr(), attributes) } == 0 {
+        return Err(io::Error::last_os_error())
+            .with_context(|| format!("Failed to set attributes on: {:?}", destination));
+    }
+
+    Ok(())
+}
+
+/// Copy the Windows file attributes of `source` onto `destination`
+/// No-op outside Windows
+#[cfg(not(windows))]
+pub fn preserve_file_attributes(_source: &Path, _destination: &Path) -> Result<()> {
+    Ok(())
+}
+
 /// Batch copy files with filtering
 /// Functional composition of copy operations
 pub fn copy_files_batch<P: AsRef<Path>, Q: AsRef<Path>>(
@@ -108,142 +648,1694 @@ pub fn copy_files_batch<P: AsRef<Path>, Q: AsRef<Path>>(
     days_back: Option<i64>,
     overwrite: bool,
 ) -> Result<Vec<CopyResult>> {
-    let modified_since = days_back.map(|days| Utc::now() - Duration::days(days));
-
-    let results: Vec<CopyResult> = mappings
+    let jobs: Vec<CopyJob> = mappings
         .iter()
-        .flat_map(|(source_dir, dest_dir)| {
-            copy_files_in_directory(source_dir, dest_dir, extensions, modified_since, overwrite)
-                .unwrap_or_else(|e| {
-                    vec![CopyResult::Error {
-                        source: source_dir.as_ref().to_path_buf(),
-                        destination: dest_dir.as_ref().to_path_buf(),
-                        error: e.to_string(),
-                    }]
-                })
+        .map(|(source_dir, dest_dir)| CopyJob {
+            look_back: days_back.map(Duration::days),
+            overwrite: overwrite.into(),
+            ..CopyJob::new(source_dir, dest_dir)
         })
         .collect();
 
-    Ok(results)
+    Ok(copy_jobs_batch(&jobs, extensions))
 }
 
-/// Copy all files from source directory to destination directory
-fn copy_files_in_directory<P: AsRef<Path>, Q: AsRef<Path>>(
-    source_dir: P,
-    dest_dir: Q,
-    extensions: &[String],
-    modified_since: Option<DateTime<Utc>>,
-    overwrite: bool,
-) -> Result<Vec<CopyResult>> {
-    let files = list_files_with_extensions(&source_dir, extensions, modified_since)?;
-    
-    let results: Vec<CopyResult> = files
-        .into_iter()
-        .map(|file_path| {
-            let file_name = file_path.file_name().unwrap_or_default();
-            let dest_path = dest_dir.as_ref().join(file_name);
-            
-            match copy_file_safe(&file_path, &dest_path, overwrite) {
-                Ok(true) => CopyResult::Success {
-                    source: file_path,
-                    destination: dest_path,
-                },
-                Ok(false) => CopyResult::Skipped {
-                    source: file_path,
-                    destination: dest_path,
-                    reason: "File already exists".to_string(),
-                },
-                Err(e) => CopyResult::Error {
-                    source: file_path,
-                    destination: dest_path,
-                    error: e.to_string(),
-                },
-            }
-        })
-        .collect();
+/// A single source to destination directory copy with its own filters
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CopyJob {
+    pub source_dir: PathBuf,
+    pub dest_dir: PathBuf,
+    /// Only copy files modified within this window (None copies regardless of age)
+    pub look_back: Option<Duration>,
+    pub overwrite: OverwriteMode,
+    /// Copy Windows file attributes (read-only, archive, ...) onto each copied file
+    pub preserve_attributes: bool,
+    /// How each copied file's destination path is built
+    pub dest_options: DestOptions,
+    /// Custom predicates every copied file must pass, alongside the extension and date filters
+    pub filters: Vec<FilePredicate>,
+    /// Judge the look-back window by the date in each file name instead of its modification time
+    pub filename_date: Option<FilenameDatePattern>,
+    /// Copy through a buffer of this many bytes instead of `fs::copy`
+    pub copy_buffer_bytes: Option<usize>,
+    /// Skip files whose content is already in the destination directory under another name
+    pub skip_duplicate_content: bool,
+    /// Decide every copy as usual but write nothing: copies that would happen are reported
+    /// as skipped with `SKIP_REASON_DRY_RUN`
+    pub dry_run: bool,
+    /// Copy files, or move them out of the source directory
+    pub transfer: TransferMode,
+    /// Re-hash each transferred file and report an error when it differs from the source
+    /// (local destinations only)
+    pub verify: bool,
+}
 
-    Ok(results)
+impl CopyJob {
+    /// Copy every file of `source_dir` into `dest_dir` regardless of age, skipping existing
+    /// destinations, with no other filter or option set
+    pub fn new<P: AsRef<Path>, Q: AsRef<Path>>(source_dir: P, dest_dir: Q) -> Self {
+        Self {
+            source_dir: source_dir.as_ref().to_path_buf(),
+            dest_dir: dest_dir.as_ref().to_path_buf(),
+            look_back: None,
+            overwrite: OverwriteMode::Never,
+            preserve_attributes: false,
+            dest_options: DestOptions::default(),
+            filters: Vec::new(),
+            filename_date: None,
+            copy_buffer_bytes: None,
+            skip_duplicate_content: false,
+            dry_run: false,
+            transfer: TransferMode::Copy,
+            verify: false,
+        }
+    }
 }
 
-/// Result of a file copy operation
-#[derive(Debug, Clone)]
-pub enum CopyResult {
-    Success {
-        source: PathBuf,
-        destination: PathBuf,
-    },
-    Skipped {
-        source: PathBuf,
-        destination: PathBuf,
-        reason: String,
-    },
-    Error {
-        source: PathBuf,
-        destination: PathBuf,
-        error: String,
-    },
+/// Whether transferred files stay in the source directory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransferMode {
+    #[default]
+    Copy,
+    /// Remove each file from the source directory once it is in place; files that are
+    /// skipped stay where they are
+    Move,
 }
 
-impl CopyResult {
-    pub fn is_success(&self) -> bool {
-        matches!(self, CopyResult::Success { .. })
-    }
+/// Default maximum file name length in bytes, the limit of most filesystems
+pub const DEFAULT_MAX_NAME_LEN: usize = 255;
 
-    pub fn is_error(&self) -> bool {
-        matches!(self, CopyResult::Error { .. })
+/// How a copied file's destination path is built from its source path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DestOptions {
+    /// Keep the source's subdirectories relative to this root; files are flattened when None
+    /// or when the source is not under the root
+    pub source_root: Option<PathBuf>,
+    /// Destination file name template with `{name}`, `{stem}` and `{ext}` placeholders
+    pub name_template: Option<String>,
+    /// What to do when the destination already exists
+    pub collision: CollisionPolicy,
+    /// Maximum length in bytes of a suffixed file name; the stem is shortened to fit
+    pub max_name_len: usize,
+}
+
+impl Default for DestOptions {
+    fn default() -> Self {
+        Self {
+            source_root: None,
+            name_template: None,
+            collision: CollisionPolicy::Keep,
+            max_name_len: DEFAULT_MAX_NAME_LEN,
+        }
     }
 }
 
-/// Functional utilities for file operations
-pub mod functional {
-    use super::*;
+/// Whether a copy replaces a destination file that already exists
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverwriteMode {
+    /// Skip existing destinations by name alone
+    #[default]
+    Never,
+    Always,
+    /// Replace existing destinations whose content differs from the source, repairing
+    /// stale or partial copies; SFTP destinations can only be compared by size
+    IfDestinationHashDiffers,
+}
 
-    /// Higher-order function that creates a file filter predicate
-    pub fn create_file_filter(
-        extensions: Vec<String>,
-        modified_since: Option<DateTime<Utc>>,
-    ) -> impl Fn(&Path) -> bool {
-        move |path: &Path| -> bool {
-            matches_extensions(path, &extensions) &&
-            matches_modification_date(path, modified_since).unwrap_or(false)
+impl From<bool> for OverwriteMode {
+    fn from(overwrite: bool) -> Self {
+        if overwrite {
+            Self::Always
+        } else {
+            Self::Never
         }
     }
+}
 
-    /// Map over file paths with a transformation function
-    pub fn map_files<F, T>(files: Vec<PathBuf>, f: F) -> Vec<T>
-    where
-        F: Fn(PathBuf) -> T,
-    {
-        files.into_iter().map(f).collect()
+impl OverwriteMode {
+    /// Whether an existing `destination` is replaced by `source`
+    /// Hashes both files only in `IfDestinationHashDiffers` mode, and only when their sizes match
+    pub fn replaces(self, source: &Path, destination: &Path) -> Result<bool> {
+        match self {
+            Self::Never => Ok(false),
+            Self::Always => Ok(true),
+            Self::IfDestinationHashDiffers => {
+                let size = |path: &Path| {
+                    fs::metadata(path)
+                        .map(|metadata| metadata.len())
+                        .with_context(|| format!("Failed to read metadata for: {:?}", path))
+                };
+                if size(source)? != size(destination)? {
+                    return Ok(true);
+                }
+                Ok(file_sha256(source)? != file_sha256(destination)?)
+            }
+        }
     }
 
-    /// Filter files using a predicate function
-    pub fn filter_files<F>(files: Vec<PathBuf>, predicate: F) -> Vec<PathBuf>
-    where
-        F: Fn(&PathBuf) -> bool,
-    {
-        files.into_iter().filter(predicate).collect()
+    /// Whether an existing remote destination of `remote_size` bytes is replaced by `source`
+    fn replaces_remote(self, source: &Path, remote_size: Option<u64>) -> bool {
+        match self {
+            Self::Never => false,
+            Self::Always => true,
+            Self::IfDestinationHashDiffers => fs::metadata(source).ok().map(|metadata| metadata.len()) != remote_size,
+        }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Handling of a destination path that already exists
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CollisionPolicy {
+    /// Keep the path; the copy then skips or overwrites per the job's `overwrite` setting
+    #[default]
+    Keep,
+    /// Append `_1`, `_2`, ... to the file stem until the path is free,
+    /// shortening the stem when the name would exceed `DestOptions::max_name_len`
+    RenameWithSuffix,
+}
 
-    #[test]
-    fn test_matches_extensions() {
-        let path = Path::new("test.txt");
-        let extensions = vec![".txt".to_string(), ".log".to_string()];
-        assert!(matches_extensions(path, &extensions));
+/// Compute where `source` is copied to inside `dest_dir`
+/// Checks the filesystem only for `CollisionPolicy::RenameWithSuffix`
+pub fn compute_destination(source: &Path, dest_dir: &Path, options: &DestOptions) -> PathBuf {
+    compute_destination_with(source, dest_dir, options, |path| path.exists())
+}
 
-        let extensions = vec![".pdf".to_string()];
+/// Pure function computing the destination path, with `exists` standing in for the filesystem
+pub fn compute_destination_with<F>(source: &Path, dest_dir: &Path, options: &DestOptions, exists: F) -> PathBuf
+where
+    F: Fn(&Path) -> bool,
+{
+    let relative_dir = options
+        .source_root
+        .as_deref()
+        .and_then(|root| source.parent()?.strip_prefix(root).ok())
+        .unwrap_or(Path::new(""));
+    let dir = dest_dir.join(relative_dir);
+
+    let file_name = source.file_name().unwrap_or_default().to_string_lossy();
+    let name = match &options.name_template {
+        Some(template) => render_name_template(template, &file_name),
+        None => file_name.to_string(),
+    };
+    let candidate = dir.join(&name);
+
+    match options.collision {
+        CollisionPolicy::RenameWithSuffix if exists(&candidate) => (1..)
+            .map(|n| dir.join(with_name_suffix(&name, n, options.max_name_len)))
+            .find(|path| !exists(path))
+            .unwrap_or(candidate),
+        _ => candidate,
+    }
+}
+
+/// Pure function filling the `{name}`, `{stem}` and `{ext}` placeholders of a name template
+fn render_name_template(template: &str, file_name: &str) -> String {
+    let path = Path::new(file_name);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path.extension().unwrap_or_default().to_string_lossy();
+
+    template
+        .replace("{name}", file_name)
+        .replace("{stem}", &stem)
+        .replace("{ext}", &ext)
+}
+
+/// Pure function inserting `_n` before a file name's extension
+/// The stem is truncated (on a character boundary) so the result fits in `max_len` bytes;
+/// the extension and suffix are always kept
+fn with_name_suffix(name: &str, n: u32, max_len: usize) -> String {
+    let path = Path::new(name);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let tail = match path.extension() {
+        Some(ext) => format!("_{}.{}", n, ext.to_string_lossy()),
+        None => format!("_{}", n),
+    };
+
+    let mut stem_len = stem.len().min(max_len.saturating_sub(tail.len()));
+    while !stem.is_char_boundary(stem_len) {
+        stem_len -= 1;
+    }
+
+    format!("{}{}", &stem[..stem_len], tail)
+}
+
+/// Run several copy jobs, each with its own look-back window and overwrite setting
+/// Results follow job order, then file name order within each job
+pub fn copy_jobs_batch(jobs: &[CopyJob], extensions: &[String]) -> Vec<CopyResult> {
+    copy_jobs_batch_with_limit(jobs, extensions, None, &SystemClock).results
+}
+
+/// Results of a copy batch that may have stopped early
+#[derive(Debug, Clone)]
+pub struct CopyBatch {
+    pub results: Vec<CopyResult>,
+    /// The batch stopped because the error limit was exceeded
+    pub aborted: bool,
+    /// The batch stopped because its deadline passed
+    pub time_limited: bool,
+    /// The batch stopped because its cancellation token was cancelled
+    pub cancelled: bool,
+}
+
+/// Run several copy jobs, stopping as soon as more than `max_errors` copies have failed
+/// Files are copied one at a time, so no copy is left half-done when the batch aborts
+/// Look-back windows are measured from `clock`
+pub fn copy_jobs_batch_with_limit<C: Clock + ?Sized>(
+    jobs: &[CopyJob],
+    extensions: &[String],
+    max_errors: Option<usize>,
+    clock: &C,
+) -> CopyBatch {
+    copy_jobs_batch_until(jobs, extensions, max_errors, None, &CancellationToken::new(), clock, |_| {})
+}
+
+/// Run several copy jobs like `copy_jobs_batch_with_limit`, also starting no new copy once
+/// `deadline` has passed or `cancel` is cancelled; the copy in progress then completes
+/// `on_result` sees each result as soon as its copy finishes, e.g. to report progress
+pub fn copy_jobs_batch_until<C, F>(
+    jobs: &[CopyJob],
+    extensions: &[String],
+    max_errors: Option<usize>,
+    deadline: Option<Deadline>,
+    cancel: &CancellationToken,
+    clock: &C,
+    mut on_result: F,
+) -> CopyBatch
+where
+    C: Clock + ?Sized,
+    F: FnMut(&CopyResult),
+{
+    let mut results = Vec::new();
+    let mut errors = 0;
+    let mut copies = jobs
+        .iter()
+        .flat_map(|job| copy_files_in_directory(job, extensions, modified_since_cutoff(job.look_back, clock), clock.now()));
+
+    loop {
+        if let Some(reason) = stop_reason(deadline, cancel) {
+            let (time_limited, cancelled) = (reason == StopReason::Deadline, reason == StopReason::Cancelled);
+            return CopyBatch { results, aborted: false, time_limited, cancelled };
+        }
+        let Some(result) = copies.next() else {
+            break;
+        };
+
+        errors += usize::from(result.is_error());
+        on_result(&result);
+        results.push(result);
+
+        if max_errors.is_some_and(|max| errors > max) {
+            return CopyBatch { results, aborted: true, time_limited: false, cancelled: false };
+        }
+    }
+
+    CopyBatch { results, aborted: false, time_limited: false, cancelled: false }
+}
+
+/// Longest look-back window accepted, about a century
+pub const MAX_LOOK_BACK_DAYS: i64 = 36_500;
+
+/// Pure function computing the oldest modification time a look-back window accepts
+/// A window reaching past the earliest representable time accepts every file
+pub fn modified_since_cutoff<C: Clock + ?Sized>(look_back: Option<Duration>, clock: &C) -> Option<DateTime<Utc>> {
+    look_back.map(|window| clock.now().checked_sub_signed(window).unwrap_or(DateTime::<Utc>::MIN_UTC))
+}
+
+/// Pure function parsing a look-back window such as `90m`, `6h`, `3d` or `1.5d`
+/// A bare number is a count of days, as `--days-back` always accepted; windows longer than
+/// `MAX_LOOK_BACK_DAYS` are rejected
+pub fn parse_look_back(raw: &str) -> Option<Duration> {
+    let raw = raw.trim();
+    let (amount, unit_seconds) = match raw.char_indices().last()? {
+        (index, 'm') => (&raw[..index], 60.0),
+        (index, 'h') => (&raw[..index], 3600.0),
+        (index, 'd') => (&raw[..index], 86400.0),
+        _ => (raw, 86400.0),
+    };
+    let amount: f64 = amount.parse().ok()?;
+
+    let max_seconds = (MAX_LOOK_BACK_DAYS * 86400) as f64;
+    let seconds = amount * unit_seconds;
+
+    (seconds.is_finite() && (0.0..=max_seconds).contains(&seconds))
+        .then(|| Duration::milliseconds((seconds * 1000.0).round() as i64))
+}
+
+/// Lazily copy all files from the job's source directory to its destination directory
+/// Files are copied in name order so results are deterministic; files the date or size
+/// filters exclude are reported as skipped with their reason
+fn copy_files_in_directory<'a>(
+    job: &'a CopyJob,
+    extensions: &[String],
+    modified_since: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> Box<dyn Iterator<Item = CopyResult> + 'a> {
+    // A file name date replaces the modification time check; undated files never pass it
+    let (modified_since, filename_date_filter) = match &job.filename_date {
+        Some(pattern) => {
+            let since = modified_since.map(|cutoff| cutoff.date_naive()).unwrap_or(chrono::NaiveDate::MIN);
+            (None, Some(pattern.since(since)))
+        }
+        None => (modified_since, None),
+    };
+    let options = ScanOptions {
+        extensions: extensions.to_vec(),
+        modified_since,
+        filters: job.filters.clone(),
+        now: Some(now),
+        ..ScanOptions::default()
+    };
+    let exclusion_reason = |path: &Path| {
+        options.exclusion_reason(path).or_else(|| {
+            filename_date_filter
+                .as_ref()
+                .is_some_and(|filter| !filter.matches(path))
+                .then_some(SKIP_REASON_DATE)
+        })
+    };
+
+    let job_error = |e: anyhow::Error| -> Box<dyn Iterator<Item = CopyResult> + 'a> {
+        Box::new(std::iter::once(CopyResult::Error {
+            source: job.source_dir.clone(),
+            destination: job.dest_dir.clone(),
+            error: format!("{:#}", e),
+        }))
+    };
+
+    let mut files = match list_files(&job.source_dir, options.selection()) {
+        Ok(files) => files,
+        Err(e) => return job_error(e),
+    };
+    files.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+    let files: Vec<(PathBuf, Option<&'static str>)> = files
+        .into_iter()
+        .map(|path| {
+            let reason = exclusion_reason(&path);
+            (path, reason)
+        })
+        .filter(|(path, reason)| reason.is_some() || options.filters.iter().all(|filter| filter.matches(path)))
+        .collect();
+    let excluded = move |source: PathBuf, reason: &str| CopyResult::Skipped {
+        destination: compute_destination_with(&source, &job.dest_dir, &job.dest_options, |_| false),
+        source,
+        reason: reason.to_string(),
+    };
+
+    // `sftp://` output folders are uploaded to over one session per job
+    match job.dest_dir.to_str().and_then(SftpTarget::parse) {
+        None => {
+            if !job.dry_run {
+                let destinations: Vec<PathBuf> = files
+                    .iter()
+                    .filter(|(_, reason)| reason.is_none())
+                    .map(|(file_path, _)| compute_destination(file_path, &job.dest_dir, &job.dest_options))
+                    .collect();
+                match remove_stale_part_files(destinations.iter().map(PathBuf::as_path)) {
+                    Ok(0) => {}
+                    Ok(removed) => tracing::info!("Removed {} interrupted copies from {:?}", removed, job.dest_dir),
+                    Err(e) => tracing::warn!("Failed to clean up interrupted copies in {:?}: {}", job.dest_dir, e),
+                }
+            }
+            let mut duplicates = DuplicateIndex::default();
+            Box::new(files.into_iter().map(move |(file_path, reason)| match reason {
+                Some(reason) => excluded(file_path, reason),
+                None => copy_file_for_job(job, file_path, &mut duplicates),
+            }))
+        }
+        Some(target) => {
+            if job.verify {
+                tracing::warn!("Copies to {:?} are not verified: verification needs a local destination", job.dest_dir);
+            }
+            let uploader = target.and_then(|target| {
+                let credentials = SftpCredentials::from_env(&target, |key| std::env::var(key).ok())?;
+                let uploader = SftpUploader::connect(&target, &credentials)?;
+                Ok((target, uploader))
+            });
+            match uploader {
+                Ok((target, uploader)) => Box::new(
+                    files.into_iter().map(move |(file_path, reason)| match reason {
+                        Some(reason) => excluded(file_path, reason),
+                        None => upload_file_for_job(job, &target, &uploader, file_path),
+                    }),
+                ),
+                Err(e) => job_error(e),
+            }
+        }
+    }
+}
+
+/// Upload a single file into the job's SFTP destination directory
+fn upload_file_for_job(job: &CopyJob, target: &SftpTarget, uploader: &SftpUploader, file_path: PathBuf) -> CopyResult {
+    let remote_path = compute_destination_with(&file_path, &target.remote_dir, &job.dest_options, |path| {
+        uploader.remote_size(path).is_some()
+    });
+    let existing_size = uploader.remote_size(&remote_path);
+    let destination = target.url_for(&remote_path);
+    let overwrite = job.overwrite.replaces_remote(&file_path, existing_size);
+
+    if job.dry_run && (existing_size.is_none() || overwrite) {
+        return CopyResult::Skipped {
+            source: file_path,
+            destination,
+            reason: SKIP_REASON_DRY_RUN.to_string(),
+        };
+    }
+
+    let uploaded = uploader.upload(&file_path, &remote_path, overwrite).and_then(|uploaded| {
+        if uploaded && job.transfer == TransferMode::Move {
+            fs::remove_file(&file_path)
+                .with_context(|| format!("Uploaded {:?} but failed to remove the source", file_path))?;
+        }
+        Ok(uploaded)
+    });
+
+    match uploaded {
+        Ok(true) if existing_size.is_some() => CopyResult::Overwritten { source: file_path, destination },
+        Ok(true) => CopyResult::Created { source: file_path, destination },
+        Ok(false) => CopyResult::Skipped {
+            reason: skip_reason_for_sizes(fs::metadata(&file_path).ok().map(|m| m.len()), existing_size).to_string(),
+            source: file_path,
+            destination,
+        },
+        Err(e) => CopyResult::Error {
+            source: file_path,
+            destination,
+            error: format!("{:#}", e),
+        },
+    }
+}
+
+/// Copy a single file into the job's destination directory
+/// `duplicates` holds what the job's earlier copies learned about the destination content
+fn copy_file_for_job(job: &CopyJob, file_path: PathBuf, duplicates: &mut DuplicateIndex) -> CopyResult {
+    copy_file_for_job_with(job, file_path, duplicates, |from, to| fs::rename(from, to), |_| Ok(()))
+}
+
+/// `copy_file_for_job` with the rename used by moves and a step run on the destination
+/// between the transfer and its verification
+fn copy_file_for_job_with<R, F>(
+    job: &CopyJob,
+    file_path: PathBuf,
+    duplicates: &mut DuplicateIndex,
+    rename: R,
+    after_transfer: F,
+) -> CopyResult
+where
+    R: FnOnce(&Path, &Path) -> io::Result<()>,
+    F: FnOnce(&Path) -> io::Result<()>,
+{
+    let dest_path = compute_destination(&file_path, &job.dest_dir, &job.dest_options);
+    let existed = dest_path.exists();
+
+    if job.skip_duplicate_content && !existed {
+        let duplicate = dest_path
+            .parent()
+            .map_or(Ok(None), |dir| duplicates.find(&file_path, dir));
+        match duplicate {
+            Ok(Some(duplicate)) => {
+                return CopyResult::Skipped {
+                    source: file_path,
+                    destination: duplicate,
+                    reason: SKIP_REASON_DUPLICATE.to_string(),
+                }
+            }
+            Ok(None) => {}
+            Err(e) => {
+                return CopyResult::Error {
+                    source: file_path,
+                    destination: dest_path,
+                    error: format!("{:#}", e),
+                }
+            }
+        }
+    }
+
+    let overwrite = if existed { job.overwrite.replaces(&file_path, &dest_path) } else { Ok(false) };
+    if job.dry_run {
+        return match overwrite {
+            Ok(false) if existed => CopyResult::Skipped {
+                reason: skip_reason(&file_path, &dest_path).to_string(),
+                source: file_path,
+                destination: dest_path,
+            },
+            Ok(_) => CopyResult::Skipped {
+                source: file_path,
+                destination: dest_path,
+                reason: SKIP_REASON_DRY_RUN.to_string(),
+            },
+            Err(e) => CopyResult::Error {
+                source: file_path,
+                destination: dest_path,
+                error: e.to_string(),
+            },
+        };
+    }
+
+    let copied = overwrite.and_then(|overwrite| {
+        // The source is hashed as it is copied, so verifying only reads the destination again
+        let check = |destination: &Path, source_hash: Option<[u8; 32]>| -> Result<()> {
+            after_transfer(destination)?;
+            if let Some(source_hash) = source_hash {
+                verify_copy(&file_path, &source_hash, destination)?;
+            }
+            Ok(())
+        };
+        let copied = match job.transfer {
+            TransferMode::Copy if job.verify => {
+                match copy_file_hashed(&file_path, &dest_path, overwrite, job.copy_buffer_bytes)? {
+                    Some(source_hash) => {
+                        check(&dest_path, Some(source_hash))?;
+                        true
+                    }
+                    None => false,
+                }
+            }
+            TransferMode::Copy => {
+                let copied = copy_file_safe_with(&file_path, &dest_path, overwrite, job.copy_buffer_bytes)?;
+                if copied {
+                    check(&dest_path, None)?;
+                }
+                copied
+            }
+            // Checked before a copied-over source is removed, so a bad copy keeps the source
+            TransferMode::Move => move_file_with(&file_path, &dest_path, overwrite, job.verify, rename, check)?,
+        };
+        if !copied {
+            return Ok(false);
+        }
+        // A rename keeps the file's attributes, so only copies need them carried over
+        if job.preserve_attributes && job.transfer == TransferMode::Copy {
+            preserve_file_attributes(&file_path, &dest_path)?;
+        }
+        Ok(true)
+    });
+    if matches!(copied, Ok(true)) {
+        duplicates.record(&dest_path);
+    }
+
+    match copied {
+        Ok(true) if existed => CopyResult::Overwritten {
+            source: file_path,
+            destination: dest_path,
+        },
+        Ok(true) => CopyResult::Created {
+            source: file_path,
+            destination: dest_path,
+        },
+        Ok(false) => CopyResult::Skipped {
+            reason: skip_reason(&file_path, &dest_path).to_string(),
+            source: file_path,
+            destination: dest_path,
+        },
+        Err(e) => CopyResult::Error {
+            source: file_path,
+            destination: dest_path,
+            error: e.to_string(),
+        },
+    }
+}
+
+/// Skip reason for a destination that already holds a copy of the source
+pub const SKIP_REASON_EXISTS: &str = "File already exists";
+/// Skip reason for a destination holding a different file under the same name
+pub const SKIP_REASON_COLLISION: &str = "Name collision with a different file";
+/// Skip reason for a source whose content is already in the destination directory under another name
+pub const SKIP_REASON_DUPLICATE: &str = "Duplicate content exists";
+/// Skip reason for a file a dry run would have copied
+pub const SKIP_REASON_DRY_RUN: &str = "dry-run";
+/// Skip reason for a file outside the look-back window, or dated in the future when rejected
+pub const SKIP_REASON_DATE: &str = "Excluded by the date filter";
+/// Skip reason for a file outside the size bounds
+pub const SKIP_REASON_SIZE: &str = "Excluded by the size filter";
+
+/// Files of the destination directories a job copies into, for `skip_duplicate_content`
+/// Each directory is listed once per job and grouped by size, and each file in it is hashed
+/// at most once, so a job costs one pass over its destination instead of one per source
+#[derive(Debug, Default)]
+struct DuplicateIndex {
+    /// Files directly inside each destination directory, by size
+    dirs: HashMap<PathBuf, HashMap<u64, Vec<PathBuf>>>,
+    /// SHA-256 of each destination file hashed so far
+    hashes: HashMap<PathBuf, [u8; 32]>,
+}
+
+impl DuplicateIndex {
+    /// Find a file directly inside `dir` with the same content as `source`
+    /// Only files of the same size are hashed, and the source at most once
+    fn find(&mut self, source: &Path, dir: &Path) -> Result<Option<PathBuf>> {
+        let len = fs::metadata(source)
+            .with_context(|| format!("Failed to read metadata for: {:?}", source))?
+            .len();
+        if !self.dirs.contains_key(dir) {
+            self.dirs.insert(dir.to_path_buf(), files_by_size(dir)?);
+        }
+        let Some(candidates) = self.dirs[dir].get(&len) else {
+            return Ok(None);
+        };
+
+        let source_hash = file_sha256(source)?;
+        for candidate in candidates {
+            let hash = match self.hashes.get(candidate) {
+                Some(hash) => *hash,
+                None => {
+                    let hash = file_sha256(candidate)?;
+                    self.hashes.insert(candidate.clone(), hash);
+                    hash
+                }
+            };
+            if hash == source_hash {
+                return Ok(Some(candidate.clone()));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Note a file just written to `destination`, so later sources with its content match it
+    fn record(&mut self, destination: &Path) {
+        let Some(by_size) = destination.parent().and_then(|dir| self.dirs.get_mut(dir)) else {
+            return;
+        };
+        // An overwritten file may have changed size and content
+        for files in by_size.values_mut() {
+            files.retain(|file| file != destination);
+        }
+        self.hashes.remove(destination);
+        if let Ok(metadata) = fs::metadata(destination) {
+            by_size.entry(metadata.len()).or_default().push(destination.to_path_buf());
+        }
+    }
+}
+
+/// Files directly inside `dir` grouped by size, in name order; a missing directory is empty
+fn files_by_size(dir: &Path) -> Result<HashMap<u64, Vec<PathBuf>>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read directory: {:?}", dir)),
+    };
+
+    let mut by_size: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    for entry in entries {
+        let path = entry?.path();
+        match fs::metadata(&path) {
+            Ok(metadata) if metadata.is_file() => by_size.entry(metadata.len()).or_default().push(path),
+            _ => {}
+        }
+    }
+    by_size.values_mut().for_each(|files| files.sort());
+
+    Ok(by_size)
+}
+
+/// Check that a copied file has the SHA-256 of the content read from its source
+/// A mismatching destination is left in place for inspection
+fn verify_copy(source: &Path, source_hash: &[u8; 32], destination: &Path) -> Result<()> {
+    let destination_hash = file_sha256(destination)?;
+    if destination_hash != *source_hash {
+        let hex = |hash: &[u8; 32]| hash.iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+        anyhow::bail!(
+            "Checksum mismatch after copy: {:?} has SHA-256 {} but {:?} has {}",
+            source,
+            hex(source_hash),
+            destination,
+            hex(&destination_hash)
+        );
+    }
+    Ok(())
+}
+
+/// SHA-256 of a file's content
+fn file_sha256(path: &Path) -> Result<[u8; 32]> {
+    use sha2::{Digest, Sha256};
+
+    let mut file = fs::File::open(path).with_context(|| format!("Failed to open file: {:?}", path))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher).with_context(|| format!("Failed to read file: {:?}", path))?;
+    Ok(hasher.finalize().into())
+}
+
+/// Tell an earlier copy of the source apart from an unrelated file with the same name
+/// Sizes are compared; a destination that cannot be read counts as an existing copy
+fn skip_reason(source: &Path, destination: &Path) -> &'static str {
+    let size = |path: &Path| fs::metadata(path).ok().map(|metadata| metadata.len());
+    skip_reason_for_sizes(size(source), size(destination))
+}
+
+/// Pure function choosing the skip reason from the source and destination sizes, when known
+fn skip_reason_for_sizes(source: Option<u64>, destination: Option<u64>) -> &'static str {
+    match (source, destination) {
+        (Some(src), Some(dest)) if src != dest => SKIP_REASON_COLLISION,
+        _ => SKIP_REASON_EXISTS,
+    }
+}
+
+/// Result of a file copy operation
+#[derive(Debug, Clone)]
+pub enum CopyResult {
+    /// Copied to a destination that did not exist yet
+    Created {
+        source: PathBuf,
+        destination: PathBuf,
+    },
+    /// Copied over an existing destination file
+    Overwritten {
+        source: PathBuf,
+        destination: PathBuf,
+    },
+    Skipped {
+        source: PathBuf,
+        destination: PathBuf,
+        reason: String,
+    },
+    Error {
+        source: PathBuf,
+        destination: PathBuf,
+        error: String,
+    },
+}
+
+impl CopyResult {
+    pub fn is_success(&self) -> bool {
+        matches!(self, CopyResult::Created { .. } | CopyResult::Overwritten { .. })
+    }
+
+    pub fn is_error(&self) -> bool {
+        matches!(self, CopyResult::Error { .. })
+    }
+}
+
+/// Functional utilities for file operations
+pub mod functional {
+    use super::*;
+
+    /// Higher-order function that creates a file filter predicate
+    pub fn create_file_filter(
+        extensions: Vec<String>,
+        modified_since: Option<DateTime<Utc>>,
+    ) -> impl Fn(&Path) -> bool {
+        move |path: &Path| -> bool {
+            matches_extensions(path, &extensions) &&
+            // Accepting future times leaves `now` unused
+            matches_modification_date(path, modified_since, FutureMtimePolicy::Accept, DateTime::<Utc>::MAX_UTC).unwrap_or(false)
+        }
+    }
+
+    /// Map over file paths with a transformation function
+    pub fn map_files<F, T>(files: Vec<PathBuf>, f: F) -> Vec<T>
+    where
+        F: Fn(PathBuf) -> T,
+    {
+        files.into_iter().map(f).collect()
+    }
+
+    /// Filter files using a predicate function
+    pub fn filter_files<F>(files: Vec<PathBuf>, predicate: F) -> Vec<PathBuf>
+    where
+        F: Fn(&PathBuf) -> bool,
+    {
+        files.into_iter().filter(predicate).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_extensions() {
+        let path = Path::new("test.txt");
+        let extensions = vec![".txt".to_string(), ".log".to_string()];
+        assert!(matches_extensions(path, &extensions));
+
+        let extensions = vec![".pdf".to_string()];
         assert!(!matches_extensions(path, &extensions));
     }
 
+    #[test]
+    fn test_file_scanner_is_lazy() {
+        let dir = tempfile::tempdir().unwrap();
+        let names = ["a.txt", "b.dat", "c.txt", "d.txt"];
+        for name in names {
+            fs::write(dir.path().join(name), "content").unwrap();
+        }
+
+        let pulled = std::cell::Cell::new(0);
+        let entries = names.iter().map(|name| {
+            pulled.set(pulled.get() + 1);
+            Ok(dir.path().join(name))
+        });
+        let options = ScanOptions {
+            extensions: vec![".txt".to_string()],
+            ..ScanOptions::default()
+        };
+        let mut scanner = FileScanner::from_entries(entries, options);
+
+        assert_eq!(scanner.next().unwrap().unwrap(), dir.path().join("a.txt"));
+        assert_eq!(pulled.get(), 1);
+        assert_eq!(scanner.next().unwrap().unwrap(), dir.path().join("c.txt"));
+        assert_eq!(pulled.get(), 3);
+    }
+
+    #[test]
+    fn test_file_scanner_filters() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("small.txt"), "1").unwrap();
+        fs::write(dir.path().join("large.txt"), "1234567890").unwrap();
+        fs::write(dir.path().join("large.dat"), "1234567890").unwrap();
+        fs::create_dir(dir.path().join("folder.txt")).unwrap();
+
+        let options = ScanOptions {
+            extensions: vec![".txt".to_string()],
+            modified_since: Some(Utc::now() - Duration::days(1)),
+            min_size: Some(5),
+            max_size: None,
+            ..ScanOptions::default()
+        };
+        let files: Vec<PathBuf> = FileScanner::new(dir.path(), options)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(files, vec![dir.path().join("large.txt")]);
+
+        let future_only = ScanOptions {
+            modified_since: Some(Utc::now() + Duration::days(1)),
+            ..ScanOptions::default()
+        };
+        assert_eq!(FileScanner::new(dir.path(), future_only).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_future_mtime_policies_in_scan() {
+        let dir = tempfile::tempdir().unwrap();
+        let skewed = dir.path().join("skewed.txt");
+        fs::write(&skewed, "from a server with a fast clock").unwrap();
+        let in_an_hour = std::time::SystemTime::now() + std::time::Duration::from_secs(3600);
+        fs::File::options().write(true).open(&skewed).unwrap().set_modified(in_an_hour).unwrap();
+        fs::write(dir.path().join("normal.txt"), "on time").unwrap();
+
+        let scan = |future_mtime_policy| {
+            let options = ScanOptions {
+                extensions: vec![".txt".to_string()],
+                modified_since: Some(Utc::now() - Duration::days(1)),
+                future_mtime_policy,
+                ..ScanOptions::default()
+            };
+            let mut files: Vec<PathBuf> = FileScanner::new(dir.path(), options).unwrap().map(Result::unwrap).collect();
+            files.sort();
+            files
+        };
+
+        let both = vec![dir.path().join("normal.txt"), skewed.clone()];
+        assert_eq!(scan(FutureMtimePolicy::Accept), both);
+        assert_eq!(scan(FutureMtimePolicy::ClampToNow), both);
+        assert_eq!(scan(FutureMtimePolicy::Reject), vec![dir.path().join("normal.txt")]);
+
+        // The future is judged against the scan's clock, not the system's
+        let two_hours_on = ScanOptions {
+            extensions: vec![".txt".to_string()],
+            future_mtime_policy: FutureMtimePolicy::Reject,
+            now: Some(Utc::now() + Duration::hours(2)),
+            ..ScanOptions::default()
+        };
+        assert_eq!(FileScanner::new(dir.path(), two_hours_on).unwrap().count(), 2);
+
+        let now = Utc::now();
+        let later = now + Duration::minutes(5);
+        assert_eq!(FutureMtimePolicy::Accept.apply(later, now), Some(later));
+        assert_eq!(FutureMtimePolicy::ClampToNow.apply(later, now), Some(now));
+        assert_eq!(FutureMtimePolicy::Reject.apply(later, now), None);
+        assert_eq!(FutureMtimePolicy::Reject.apply(now, now), Some(now));
+    }
+
+    #[test]
+    fn test_recursive_listing_walks_subdirectories() {
+        let dir = tempfile::tempdir().unwrap();
+        let day = dir.path().join("2024").join("01");
+        fs::create_dir_all(&day).unwrap();
+        fs::write(dir.path().join("top.txt"), "top").unwrap();
+        fs::write(dir.path().join("2024").join("month.txt"), "month").unwrap();
+        fs::write(day.join("day.txt"), "day").unwrap();
+        fs::write(day.join("day.dat"), "other extension").unwrap();
+
+        let extensions = vec![".txt".to_string()];
+        let sorted = |mut files: Vec<PathBuf>| {
+            files.sort();
+            files
+        };
+
+        assert_eq!(
+            list_files_with_extensions(dir.path(), &extensions, None).unwrap(),
+            vec![dir.path().join("top.txt")]
+        );
+        assert_eq!(
+            sorted(list_files_recursive(dir.path(), &extensions, None).unwrap()),
+            vec![day.join("day.txt"), dir.path().join("2024").join("month.txt"), dir.path().join("top.txt")]
+        );
+
+        // Each level of depth admits one more level of subdirectories
+        assert_eq!(
+            sorted(list_files_recursive_with_depth(dir.path(), &extensions, None, Some(1)).unwrap()),
+            vec![dir.path().join("2024").join("month.txt"), dir.path().join("top.txt")]
+        );
+        assert_eq!(list_files_recursive_with_depth(dir.path(), &extensions, None, Some(2)).unwrap().len(), 3);
+
+        // The date filter still applies to nested files
+        let future = Some(Utc::now() + Duration::days(1));
+        assert!(list_files_recursive(dir.path(), &extensions, future).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_max_depth_truncates_a_deeply_nested_tree_and_logs_it() {
+        use std::sync::Mutex;
+
+        /// In-memory log output
+        #[derive(Clone, Default)]
+        struct Logs(Arc<Mutex<Vec<u8>>>);
+
+        impl io::Write for Logs {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut level = dir.path().to_path_buf();
+        for depth in 0..=6 {
+            fs::write(level.join(format!("depth{}.txt", depth)), "x").unwrap();
+            level = level.join(format!("level{}", depth + 1));
+            fs::create_dir(&level).unwrap();
+        }
+
+        let logs = Logs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt().with_ansi(false).with_writer(move || writer.clone()).finish();
+        let mut files = tracing::subscriber::with_default(subscriber, || {
+            list_files_recursive_with_depth(dir.path(), &[".txt".to_string()], None, Some(2)).unwrap()
+        });
+        files.sort();
+
+        let names: Vec<String> = files.iter().map(|file| file.file_name().unwrap().to_string_lossy().to_string()).collect();
+        assert_eq!(names, vec!["depth0.txt", "depth1.txt", "depth2.txt"]);
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        let truncated = dir.path().join("level1").join("level2").join("level3");
+        assert!(logs.contains(&format!("Not scanning {:?}", truncated)), "{}", logs);
+        assert_eq!(logs.matches("Not scanning").count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_recursive_listing_skips_symlinked_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("nested");
+        fs::create_dir(&nested).unwrap();
+        fs::write(nested.join("a.txt"), "a").unwrap();
+        // A link back to the root would loop forever if followed
+        std::os::unix::fs::symlink(dir.path(), nested.join("loop")).unwrap();
+
+        let files = list_files_recursive(dir.path(), &[".txt".to_string()], None).unwrap();
+        assert_eq!(files, vec![nested.join("a.txt")]);
+    }
+
+    #[test]
+    fn test_file_scanner_take_stops_early() {
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..10 {
+            fs::write(dir.path().join(format!("{}.txt", i)), "content").unwrap();
+        }
+
+        let files: Vec<_> = FileScanner::new(dir.path(), ScanOptions::default())
+            .unwrap()
+            .take(3)
+            .collect();
+        assert_eq!(files.len(), 3);
+    }
+
+    #[test]
+    fn test_file_scanner_retries_flaky_listing() {
+        struct FlakyShare {
+            calls: std::cell::Cell<u32>,
+        }
+
+        impl FileSystem for FlakyShare {
+            fn read_dir(&self, directory: &Path) -> io::Result<DirEntries> {
+                self.calls.set(self.calls.get() + 1);
+                if self.calls.get() == 1 {
+                    return Err(io::Error::from(io::ErrorKind::TimedOut));
+                }
+                OsFileSystem.read_dir(directory)
+            }
+
+            fn stat(&self, path: &Path) -> io::Result<crate::utils::FileStat> {
+                OsFileSystem.stat(path)
+            }
+
+            fn read_to_string(&self, path: &Path) -> io::Result<String> {
+                OsFileSystem.read_to_string(path)
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "content").unwrap();
+        let share = FlakyShare {
+            calls: std::cell::Cell::new(0),
+        };
+        let options = ScanOptions {
+            retry: RetryPolicy {
+                attempts: 2,
+                delay: std::time::Duration::ZERO,
+            },
+            ..ScanOptions::default()
+        };
+
+        let files: Vec<PathBuf> = FileScanner::with_file_system(&share, dir.path(), options)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+
+        assert_eq!(files, vec![dir.path().join("a.txt")]);
+        assert_eq!(share.calls.get(), 2);
+    }
+
+    #[test]
+    fn test_copy_results_are_sorted_by_job_then_name() {
+        let root = tempfile::tempdir().unwrap();
+        let (in_a, in_b, out) = (root.path().join("in-a"), root.path().join("in-b"), root.path().join("out"));
+        for dir in [&in_a, &in_b] {
+            fs::create_dir(dir).unwrap();
+        }
+        for name in ["delta.txt", "alpha.txt", "charlie.txt", "bravo.txt"] {
+            fs::write(in_a.join(name), name).unwrap();
+        }
+        fs::write(in_b.join("zulu.txt"), "z").unwrap();
+        fs::write(in_b.join("echo.txt"), "e").unwrap();
+
+        // Mapping order is kept even though in-b's files sort before some of in-a's
+        let mappings = [(&in_b, &out.join("b")), (&in_a, &out.join("a"))];
+        let sources = |results: Vec<CopyResult>| -> Vec<String> {
+            results
+                .into_iter()
+                .map(|result| match result {
+                    CopyResult::Created { source, .. } | CopyResult::Overwritten { source, .. } => source.file_name().unwrap().to_string_lossy().to_string(),
+                    other => panic!("unexpected result: {:?}", other),
+                })
+                .collect()
+        };
+
+        let first = sources(copy_files_batch(&mappings, &[], None, true).unwrap());
+        let second = sources(copy_files_batch(&mappings, &[], None, true).unwrap());
+
+        assert_eq!(
+            first,
+            vec!["echo.txt", "zulu.txt", "alpha.txt", "bravo.txt", "charlie.txt", "delta.txt"]
+        );
+        assert_eq!(first, second);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_preserve_attributes_keeps_read_only() {
+        let root = tempfile::tempdir().unwrap();
+        let (input, output) = (root.path().join("in"), root.path().join("out"));
+        fs::create_dir(&input).unwrap();
+        let source = input.join("locked.txt");
+        fs::write(&source, "content").unwrap();
+        let mut permissions = fs::metadata(&source).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&source, permissions).unwrap();
+
+        let job = CopyJob {
+            preserve_attributes: true,
+            ..CopyJob::new(&input, &output)
+        };
+        let results = copy_jobs_batch(&[job], &[]);
+
+        assert!(results[0].is_success());
+        let copied = output.join("locked.txt");
+        assert!(fs::metadata(&copied).unwrap().permissions().readonly());
+
+        // Clear the bit again so the temp dir can be removed
+        for path in [&source, &copied] {
+            let mut permissions = fs::metadata(path).unwrap().permissions();
+            #[allow(clippy::permissions_set_readonly_false)]
+            permissions.set_readonly(false);
+            fs::set_permissions(path, permissions).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_extensionless_file_matched_by_magic_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("scan"), b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+        fs::write(dir.path().join("notes"), "plain text").unwrap();
+        fs::write(dir.path().join("a.txt"), "content").unwrap();
+
+        let scan = |content_types: Vec<String>| -> Vec<PathBuf> {
+            let options = ScanOptions {
+                extensions: vec![".txt".to_string()],
+                content_types,
+                ..ScanOptions::default()
+            };
+            let mut files: Vec<PathBuf> = FileScanner::new(dir.path(), options)
+                .unwrap()
+                .collect::<Result<_>>()
+                .unwrap();
+            files.sort();
+            files
+        };
+
+        assert_eq!(scan(Vec::new()), vec![dir.path().join("a.txt")]);
+        assert_eq!(
+            scan(vec!["image/png".to_string()]),
+            vec![dir.path().join("a.txt"), dir.path().join("scan")]
+        );
+        assert_eq!(
+            scan(vec![".PNG".to_string()]),
+            vec![dir.path().join("a.txt"), dir.path().join("scan")]
+        );
+    }
+
+    #[test]
+    fn test_copy_batch_aborts_after_error_limit() {
+        let root = tempfile::tempdir().unwrap();
+        let (input, output) = (root.path().join("in"), root.path().join("out"));
+        fs::create_dir(&input).unwrap();
+        for i in 0..5 {
+            let name = format!("{}.txt", i);
+            fs::write(input.join(&name), "content").unwrap();
+            // A directory in the way makes every copy fail, like a share gone read-only
+            fs::create_dir_all(output.join(&name)).unwrap();
+        }
+        let job = CopyJob {
+            overwrite: OverwriteMode::Always,
+            ..CopyJob::new(input, output)
+        };
+
+        let limited = copy_jobs_batch_with_limit(std::slice::from_ref(&job), &[], Some(2), &SystemClock);
+        assert!(limited.aborted);
+        assert_eq!(limited.results.len(), 3);
+        assert!(limited.results.iter().all(CopyResult::is_error));
+
+        let unlimited = copy_jobs_batch_with_limit(&[job], &[], None, &SystemClock);
+        assert!(!unlimited.aborted);
+        assert_eq!(unlimited.results.len(), 5);
+    }
+
+    #[test]
+    fn test_compute_destination_options() {
+        let source = Path::new("/in/2024/01/report.txt");
+        let dest = Path::new("/out");
+        let none_exist = |_: &Path| false;
+        let compute = |options: DestOptions| compute_destination_with(source, dest, &options, none_exist);
+
+        // Flattened by default
+        assert_eq!(compute(DestOptions::default()), Path::new("/out/report.txt"));
+
+        // Tree preserved relative to the root, flattened when outside it
+        let tree = DestOptions { source_root: Some(PathBuf::from("/in")), ..DestOptions::default() };
+        assert_eq!(compute(tree), Path::new("/out/2024/01/report.txt"));
+        let outside = DestOptions { source_root: Some(PathBuf::from("/elsewhere")), ..DestOptions::default() };
+        assert_eq!(compute(outside), Path::new("/out/report.txt"));
+
+        // Naming template
+        let template = DestOptions { name_template: Some("{stem}-copy.{ext}".to_string()), ..DestOptions::default() };
+        assert_eq!(compute(template), Path::new("/out/report-copy.txt"));
+        let prefixed = DestOptions { name_template: Some("vw_{name}".to_string()), ..DestOptions::default() };
+        assert_eq!(compute(prefixed), Path::new("/out/vw_report.txt"));
+
+        // Collision suffixes only when the path is taken
+        let rename = DestOptions { collision: CollisionPolicy::RenameWithSuffix, ..DestOptions::default() };
+        assert_eq!(compute(rename.clone()), Path::new("/out/report.txt"));
+        let taken = |path: &Path| path == Path::new("/out/report.txt") || path == Path::new("/out/report_1.txt");
+        assert_eq!(compute_destination_with(source, dest, &rename, taken), Path::new("/out/report_2.txt"));
+        let keep = DestOptions::default();
+        assert_eq!(compute_destination_with(source, dest, &keep, taken), Path::new("/out/report.txt"));
+
+        // All options combined, on a file without extension
+        let combined = DestOptions {
+            source_root: Some(PathBuf::from("/in")),
+            name_template: Some("vw_{stem}".to_string()),
+            collision: CollisionPolicy::RenameWithSuffix,
+            ..DestOptions::default()
+        };
+        let extensionless = Path::new("/in/sub/DATA");
+        let taken = |path: &Path| path == Path::new("/out/sub/vw_DATA");
+        assert_eq!(compute_destination_with(extensionless, dest, &combined, taken), Path::new("/out/sub/vw_DATA_1"));
+    }
+
+    #[test]
+    fn test_collision_suffix_stays_within_max_name_len() {
+        let long_name = format!("{}.txt", "a".repeat(DEFAULT_MAX_NAME_LEN - 4));
+        let source = Path::new("/in").join(&long_name);
+        let dest = Path::new("/out");
+        let rename = DestOptions { collision: CollisionPolicy::RenameWithSuffix, ..DestOptions::default() };
+
+        // Names up to _1 .. _10 are taken
+        let taken: std::collections::HashSet<PathBuf> = std::iter::once(dest.join(&long_name))
+            .chain((1..=10).map(|n| dest.join(with_name_suffix(&long_name, n, DEFAULT_MAX_NAME_LEN))))
+            .collect();
+        let destination = compute_destination_with(&source, dest, &rename, |path| taken.contains(path));
+        let name = destination.file_name().unwrap().to_string_lossy().to_string();
+
+        assert_eq!(name.len(), DEFAULT_MAX_NAME_LEN);
+        assert!(name.ends_with("_11.txt"));
+        assert!(!taken.contains(&destination));
+
+        // Multi-byte stems are cut on a character boundary
+        let truncated = with_name_suffix("ééééé.txt", 1, 10);
+        assert_eq!(truncated, "éé_1.txt");
+        assert!(truncated.len() <= 10);
+
+        // Short names are left alone
+        assert_eq!(with_name_suffix("report.txt", 2, DEFAULT_MAX_NAME_LEN), "report_2.txt");
+    }
+
+    #[test]
+    fn test_buffered_copy_matches_fs_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("in.bin");
+        let content: Vec<u8> = (0..200_000u32).map(|i| (i * 31 % 256) as u8).collect();
+        fs::write(&source, &content).unwrap();
+
+        let (plain, buffered, tiny) = (dir.path().join("plain.bin"), dir.path().join("buffered.bin"), dir.path().join("tiny.bin"));
+        assert!(copy_file_safe_with(&source, &plain, false, None).unwrap());
+        assert!(copy_file_safe_with(&source, &buffered, false, Some(64 * 1024)).unwrap());
+        assert!(copy_file_safe_with(&source, &tiny, false, Some(7)).unwrap());
+
+        assert_eq!(fs::read(&plain).unwrap(), content);
+        assert_eq!(fs::read(&buffered).unwrap(), fs::read(&plain).unwrap());
+        assert_eq!(fs::read(&tiny).unwrap(), fs::read(&plain).unwrap());
+
+        // Buffered copies honor overwrite like fs::copy
+        fs::write(&source, b"short").unwrap();
+        assert!(!copy_file_safe_with(&source, &buffered, false, Some(1024)).unwrap());
+        assert!(copy_file_safe_with(&source, &buffered, true, Some(1024)).unwrap());
+        assert_eq!(fs::read(&buffered).unwrap(), b"short");
+    }
+
+    #[test]
+    fn test_move_falls_back_to_copy_and_delete_across_filesystems() {
+        let root = tempfile::tempdir().unwrap();
+        let source = root.path().join("in").join("a.txt");
+        let destination = root.path().join("out").join("a.txt");
+        fs::create_dir_all(source.parent().unwrap()).unwrap();
+        fs::write(&source, "moved content").unwrap();
+
+        let cross_device = |_: &Path, _: &Path| Err(io::Error::from(io::ErrorKind::CrossesDevices));
+        assert!(move_file_with(&source, &destination, false, false, cross_device, |_, _| Ok(())).unwrap());
+
+        assert!(!source.exists());
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "moved content");
+        assert!(!part_file_path(&destination).exists());
+
+        // Other rename failures are errors and leave the source alone
+        fs::write(&source, "new content").unwrap();
+        let denied = |_: &Path, _: &Path| Err(io::Error::from(io::ErrorKind::PermissionDenied));
+        assert!(move_file_with(&source, &destination, true, false, denied, |_, _| Ok(())).is_err());
+        assert!(source.exists());
+
+        // An existing destination is kept unless overwriting
+        assert!(!move_file_safe(&source, &destination, false).unwrap());
+        assert!(source.exists());
+        assert!(move_file_safe(&source, &destination, true).unwrap());
+        assert!(!source.exists());
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "new content");
+    }
+
+    #[test]
+    fn test_interrupted_copy_leaves_only_a_part_file() {
+        let root = tempfile::tempdir().unwrap();
+        let (input, output) = (root.path().join("in"), root.path().join("out"));
+        fs::create_dir(&input).unwrap();
+        fs::create_dir(&output).unwrap();
+        fs::write(input.join("a.txt"), "complete content").unwrap();
+        let destination = output.join("a.txt");
+
+        // The writer dies halfway, like a copy killed mid-transfer
+        let interrupted: io::Result<()> = write_via_part_file(&destination, |part_path| {
+            fs::write(part_path, "compl")?;
+            Err(io::Error::other("connection reset"))
+        });
+
+        assert!(interrupted.is_err());
+        assert!(!destination.exists());
+        assert_eq!(fs::read_to_string(part_file_path(&destination)).unwrap(), "compl");
+
+        // The next run cleans the leftover up and copies the file in full
+        let job = CopyJob::new(&input, &output);
+        let results = copy_jobs_batch(&[job], &[]);
+
+        assert!(matches!(results.as_slice(), [CopyResult::Created { .. }]));
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "complete content");
+        assert!(!part_file_path(&destination).exists());
+    }
+
+    #[test]
+    fn test_stale_part_cleanup_keeps_part_files_of_other_copies() {
+        let root = tempfile::tempdir().unwrap();
+        let (input, output) = (root.path().join("in"), root.path().join("out"));
+        let nested = output.join("nested");
+        fs::create_dir(&input).unwrap();
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(input.join("a.txt"), "complete content").unwrap();
+        fs::write(output.join("a.txt.part"), "compl").unwrap();
+        // Not written by this job: an unrelated file and a copy in flight into a nested output folder
+        fs::write(output.join("foo.part"), "someone else's").unwrap();
+        fs::write(nested.join("b.txt.part"), "in flight").unwrap();
+
+        let job = CopyJob::new(&input, &output);
+        let results = copy_jobs_batch(&[job], &[]);
+
+        assert!(matches!(results.as_slice(), [CopyResult::Created { .. }]));
+        assert!(!output.join("a.txt.part").exists());
+        assert_eq!(fs::read_to_string(output.join("foo.part")).unwrap(), "someone else's");
+        assert_eq!(fs::read_to_string(nested.join("b.txt.part")).unwrap(), "in flight");
+    }
+
+    #[test]
+    fn test_hashed_copy_returns_the_sha256_of_the_source() {
+        let root = tempfile::tempdir().unwrap();
+        let source = root.path().join("a.txt");
+        fs::write(&source, "hashed while copied").unwrap();
+        let destination = root.path().join("out").join("a.txt");
+
+        let hash = copy_file_hashed(&source, &destination, false, Some(4)).unwrap();
+        assert_eq!(hash, Some(file_sha256(&source).unwrap()));
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "hashed while copied");
+        assert_eq!(copy_file_hashed(&source, &destination, false, None).unwrap(), None);
+    }
+
+    #[test]
+    fn test_verify_detects_destination_corrupted_after_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        let (input, output) = (dir.path().join("in"), dir.path().join("out"));
+        fs::create_dir(&input).unwrap();
+        fs::write(input.join("a.txt"), "original content\n").unwrap();
+        fs::write(input.join("b.txt"), "intact content\n").unwrap();
+        let job = CopyJob {
+            verify: true,
+            ..CopyJob::new(&input, &output)
+        };
+
+        // Something rewrites the destination between the copy and its verification
+        let corrupted = copy_file_for_job_with(&job, input.join("a.txt"), &mut DuplicateIndex::default(), |_, _| unreachable!(), |destination| {
+            fs::write(destination, "original c0ntent\n")
+        });
+
+        match corrupted {
+            CopyResult::Error { destination, error, .. } => {
+                assert_eq!(destination, output.join("a.txt"));
+                assert!(error.starts_with("Checksum mismatch after copy"), "{}", error);
+            }
+            other => panic!("expected a checksum error, got {:?}", other),
+        }
+        assert_eq!(fs::read_to_string(output.join("a.txt")).unwrap(), "original c0ntent\n");
+
+        let intact = copy_file_for_job(&job, input.join("b.txt"), &mut DuplicateIndex::default());
+        assert!(matches!(intact, CopyResult::Created { .. }), "{:?}", intact);
+
+        // A move across filesystems keeps its source when the copy does not verify
+        let job = CopyJob { transfer: TransferMode::Move, ..job };
+        fs::write(input.join("c.txt"), "moved content\n").unwrap();
+        let cross_device = |_: &Path, _: &Path| Err(io::Error::from(io::ErrorKind::CrossesDevices));
+        let corrupted = copy_file_for_job_with(&job, input.join("c.txt"), &mut DuplicateIndex::default(), cross_device, |destination| {
+            fs::write(destination, "moved c0ntent\n")
+        });
+
+        assert!(corrupted.is_error(), "{:?}", corrupted);
+        assert_eq!(fs::read_to_string(input.join("c.txt")).unwrap(), "moved content\n");
+        assert_eq!(fs::read_to_string(output.join("c.txt")).unwrap(), "moved c0ntent\n");
+    }
+
+    #[test]
+    fn test_duplicate_content_under_another_name_is_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let (input, output) = (dir.path().join("in"), dir.path().join("out"));
+        fs::create_dir(&input).unwrap();
+        fs::create_dir(&output).unwrap();
+        fs::write(input.join("report.txt"), "same content").unwrap();
+        fs::write(input.join("other.txt"), "different!!!").unwrap();
+        fs::write(output.join("report_renamed.txt"), "same content").unwrap();
+
+        let job = |skip_duplicate_content| CopyJob {
+            skip_duplicate_content,
+            ..CopyJob::new(&input, &output)
+        };
+
+        let results = copy_jobs_batch(&[job(true)], &[]);
+        assert_eq!(results.len(), 2);
+        assert!(matches!(&results[0], CopyResult::Created { source, .. } if source.ends_with("other.txt")));
+        match &results[1] {
+            CopyResult::Skipped { source, destination, reason } => {
+                assert!(source.ends_with("report.txt"));
+                assert_eq!(destination, &output.join("report_renamed.txt"));
+                assert_eq!(reason, SKIP_REASON_DUPLICATE);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(!output.join("report.txt").exists());
+
+        // Off by default: the renamed copy does not prevent another copy
+        let results = copy_jobs_batch(&[job(false)], &[]);
+        assert!(results.iter().any(|result| matches!(result, CopyResult::Created { source, .. } if source.ends_with("report.txt"))));
+    }
+
+    #[test]
+    fn test_duplicate_index_lists_the_destination_once_and_learns_each_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        let (input, output) = (dir.path().join("in"), dir.path().join("out"));
+        fs::create_dir(&input).unwrap();
+        fs::create_dir(&output).unwrap();
+        fs::write(input.join("a.txt"), "same content").unwrap();
+        fs::write(input.join("b.txt"), "same content").unwrap();
+        fs::write(output.join("x.txt"), "other conten").unwrap();
+        fs::write(output.join("y.txt"), "much longer other content").unwrap();
+
+        let job = CopyJob {
+            skip_duplicate_content: true,
+            ..CopyJob::new(&input, &output)
+        };
+        let mut duplicates = DuplicateIndex::default();
+        let first = copy_file_for_job(&job, input.join("a.txt"), &mut duplicates);
+        assert!(matches!(first, CopyResult::Created { .. }), "{:?}", first);
+
+        // The copy just made is found without listing the directory again
+        let second = copy_file_for_job(&job, input.join("b.txt"), &mut duplicates);
+        assert!(
+            matches!(&second, CopyResult::Skipped { destination, reason, .. } if destination == &output.join("a.txt") && reason == SKIP_REASON_DUPLICATE),
+            "{:?}",
+            second
+        );
+        // Only files of the source's size were hashed, each once
+        assert_eq!(duplicates.hashes.len(), 2);
+        assert!(!duplicates.hashes.contains_key(&output.join("y.txt")));
+    }
+
+    #[test]
+    fn test_stale_destination_is_recopied_when_hash_differs() {
+        let dir = tempfile::tempdir().unwrap();
+        let (input, output) = (dir.path().join("in"), dir.path().join("out"));
+        fs::create_dir(&input).unwrap();
+        fs::create_dir(&output).unwrap();
+        fs::write(input.join("stale.txt"), "complete data").unwrap();
+        fs::write(input.join("current.txt"), "already copied").unwrap();
+        // Same size as the source, so only the hash tells them apart
+        fs::write(output.join("stale.txt"), "complete \0\0\0\0").unwrap();
+        fs::write(output.join("current.txt"), "already copied").unwrap();
+
+        let job = |overwrite| CopyJob {
+            overwrite,
+            ..CopyJob::new(&input, &output)
+        };
+
+        // Name-only skipping leaves the stale copy in place
+        let results = copy_jobs_batch(&[job(OverwriteMode::Never)], &[]);
+        assert!(results.iter().all(|result| matches!(result, CopyResult::Skipped { .. })));
+
+        let results = copy_jobs_batch(&[job(OverwriteMode::IfDestinationHashDiffers)], &[]);
+        assert!(matches!(&results[0], CopyResult::Skipped { source, reason, .. }
+            if source.ends_with("current.txt") && reason == SKIP_REASON_EXISTS));
+        assert!(matches!(&results[1], CopyResult::Overwritten { source, .. } if source.ends_with("stale.txt")));
+        assert_eq!(fs::read_to_string(output.join("stale.txt")).unwrap(), "complete data");
+    }
+
+    #[test]
+    fn test_copy_renames_on_collision() {
+        let root = tempfile::tempdir().unwrap();
+        let (input, output) = (root.path().join("in"), root.path().join("out"));
+        fs::create_dir(&input).unwrap();
+        fs::create_dir(&output).unwrap();
+        fs::write(input.join("data.txt"), "fresh").unwrap();
+        fs::write(output.join("data.txt"), "stale").unwrap();
+
+        let job = CopyJob {
+            dest_options: DestOptions { collision: CollisionPolicy::RenameWithSuffix, ..DestOptions::default() },
+            ..CopyJob::new(input, &output)
+        };
+        let results = copy_jobs_batch(&[job], &[]);
+
+        assert!(results[0].is_success());
+        assert_eq!(fs::read_to_string(output.join("data.txt")).unwrap(), "stale");
+        assert_eq!(fs::read_to_string(output.join("data_1.txt")).unwrap(), "fresh");
+    }
+
+    #[test]
+    fn test_modified_since_cutoff_uses_clock() {
+        let now = DateTime::parse_from_rfc3339("2024-03-15T12:00:00Z").unwrap().with_timezone(&Utc);
+        let clock = crate::utils::FixedClock(now);
+
+        assert_eq!(
+            modified_since_cutoff(Some(Duration::days(15)), &clock),
+            Some(DateTime::parse_from_rfc3339("2024-02-29T12:00:00Z").unwrap().with_timezone(&Utc))
+        );
+        assert_eq!(modified_since_cutoff(None, &clock), None);
+    }
+
+    #[test]
+    fn test_parse_look_back() {
+        assert_eq!(parse_look_back("6h"), Some(Duration::hours(6)));
+        assert_eq!(parse_look_back("3d"), Some(Duration::days(3)));
+        assert_eq!(parse_look_back("90m"), Some(Duration::minutes(90)));
+        assert_eq!(parse_look_back("15"), Some(Duration::days(15)));
+        assert_eq!(parse_look_back("1.5d"), Some(Duration::hours(36)));
+        assert_eq!(parse_look_back("6x"), None);
+        assert_eq!(parse_look_back("-1d"), None);
+        assert_eq!(parse_look_back(""), None);
+    }
+
+    #[test]
+    fn test_look_back_cutoff_saturates_at_the_earliest_time() {
+        let clock = crate::utils::FixedClock(Utc::now());
+        assert_eq!(modified_since_cutoff(Some(Duration::MAX), &clock), Some(DateTime::<Utc>::MIN_UTC));
+        assert_eq!(modified_since_cutoff(Some(Duration::days(1)), &clock), Some(clock.0 - Duration::days(1)));
+    }
+
+    #[test]
+    fn test_copy_look_back_measured_from_clock() {
+        let root = tempfile::tempdir().unwrap();
+        let (input, output) = (root.path().join("in"), root.path().join("out"));
+        fs::create_dir(&input).unwrap();
+        fs::write(input.join("today.txt"), "content").unwrap();
+        let job = CopyJob {
+            look_back: Some(Duration::days(15)),
+            ..CopyJob::new(input, output)
+        };
+
+        // A month from now, today's file falls outside the 15-day window
+        let future = crate::utils::FixedClock(Utc::now() + Duration::days(30));
+        let results = copy_jobs_batch_with_limit(std::slice::from_ref(&job), &[], None, &future).results;
+        assert!(matches!(&results[..], [CopyResult::Skipped { reason, .. }] if reason == SKIP_REASON_DATE));
+        let results = copy_jobs_batch_with_limit(&[job], &[], None, &SystemClock).results;
+        assert!(matches!(&results[..], [CopyResult::Created { .. }]));
+    }
+
+    #[test]
+    fn test_exclusion_reason_names_date_and_size_filters() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.txt");
+        fs::write(&path, "12345").unwrap();
+
+        let too_old = ScanOptions {
+            modified_since: Some(Utc::now() + Duration::days(1)),
+            ..ScanOptions::default()
+        };
+        let too_small = ScanOptions {
+            min_size: Some(10),
+            ..ScanOptions::default()
+        };
+        let rejected_by_predicate = ScanOptions {
+            filters: vec![FilePredicate::new(|_| false)],
+            ..ScanOptions::default()
+        };
+
+        assert_eq!(too_old.exclusion_reason(&path), Some(SKIP_REASON_DATE));
+        assert_eq!(too_small.exclusion_reason(&path), Some(SKIP_REASON_SIZE));
+        assert_eq!(rejected_by_predicate.exclusion_reason(&path), None);
+        assert!(!rejected_by_predicate.passes_filters(&path));
+    }
+
+    #[test]
+    fn test_custom_predicate_applied_to_copy() {
+        let root = tempfile::tempdir().unwrap();
+        let (input, output) = (root.path().join("in"), root.path().join("out"));
+        fs::create_dir(&input).unwrap();
+        for name in ["daily_20240315.txt", "daily_20240314.txt", "notes_20240315.dat"] {
+            fs::write(input.join(name), "content").unwrap();
+        }
+        let job = CopyJob {
+            filters: vec![FilePredicate::new(|path| {
+                path.file_name().is_some_and(|name| name.to_string_lossy().contains("20240315"))
+            })],
+            ..CopyJob::new(input, &output)
+        };
+
+        let results = copy_jobs_batch(&[job], &[".txt".to_string()]);
+
+        // The custom rule and the extension filter both apply
+        assert_eq!(results.len(), 1);
+        assert!(output.join("daily_20240315.txt").exists());
+    }
+
+    #[test]
+    fn test_filename_date_replaces_modification_time_window() {
+        let root = tempfile::tempdir().unwrap();
+        let (input, output) = (root.path().join("in"), root.path().join("out"));
+        fs::create_dir(&input).unwrap();
+        // Files written today, but only one is named after a date inside the window
+        for name in ["export_20240315.txt", "export_20240301.txt", "export_latest.txt"] {
+            fs::write(input.join(name), "content").unwrap();
+        }
+        let job = CopyJob {
+            look_back: Some(Duration::days(1)),
+            filename_date: Some(FilenameDatePattern::new(r"_(\d{8})\.", "%Y%m%d").unwrap()),
+            ..CopyJob::new(input, &output)
+        };
+
+        let clock = crate::utils::FixedClock(DateTime::parse_from_rfc3339("2024-03-15T12:00:00Z").unwrap().with_timezone(&Utc));
+        let batch = copy_jobs_batch_with_limit(&[job], &[], None, &clock);
+        let copied: Vec<&CopyResult> = batch.results.iter().filter(|result| matches!(result, CopyResult::Created { .. })).collect();
+
+        assert_eq!(copied.len(), 1);
+        assert!(output.join("export_20240315.txt").exists());
+        // Undated and out-of-window names are reported as excluded by the date filter
+        assert!(batch.results.iter().all(|result| match result {
+            CopyResult::Skipped { reason, .. } => reason == SKIP_REASON_DATE,
+            _ => true,
+        }));
+        assert_eq!(batch.results.len(), 3);
+    }
+
     #[test]
     fn test_matches_extensions_case_insensitive() {
         let path = Path::new("test.TXT");
         let extensions = vec![".txt".to_string()];
         assert!(matches_extensions(path, &extensions));
     }
+
+    #[test]
+    fn test_matches_glob_extension_patterns() {
+        let extensions = vec!["*.txt.gz".to_string(), "report.*.csv".to_string()];
+        assert!(matches_extensions(Path::new("data.txt.gz"), &extensions));
+        assert!(matches_extensions(Path::new("/out/DATA.TXT.GZ"), &extensions));
+        assert!(matches_extensions(Path::new("report.2024.csv"), &extensions));
+        assert!(!matches_extensions(Path::new("data.gz"), &extensions));
+        assert!(!matches_extensions(Path::new("data.txt"), &extensions));
+        assert!(!matches_extensions(Path::new("summary.2024.csv"), &extensions));
+
+        // The literal dot in a pattern must be present in the name
+        let extensions = vec!["*.csv".to_string()];
+        assert!(matches_extensions(Path::new("foo.csv"), &extensions));
+        assert!(!matches_extensions(Path::new("foocsv"), &extensions));
+
+        // Plain extensions and patterns can be mixed
+        let extensions = vec![".log".to_string(), "data?.txt".to_string()];
+        assert!(matches_extensions(Path::new("app.log"), &extensions));
+        assert!(matches_extensions(Path::new("data1.txt"), &extensions));
+        assert!(!matches_extensions(Path::new("data12.txt"), &extensions));
+    }
 }
\ No newline at end of file
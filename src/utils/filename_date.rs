@@ -0,0 +1,83 @@
+use crate::utils::file_operations::FilePredicate;
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use regex::Regex;
+use std::path::Path;
+
+/// Where a date is embedded in file names, e.g. `export_(\d{8})` with format `%Y%m%d`
+#[derive(Debug, Clone)]
+pub struct FilenameDatePattern {
+    regex: Regex,
+    format: String,
+}
+
+impl FilenameDatePattern {
+    /// Compile the pattern; the date is the `date` named group, or else the first capture group
+    pub fn new(pattern: &str, format: &str) -> Result<Self> {
+        let regex = Regex::new(pattern).with_context(|| format!("Invalid filename date pattern: {}", pattern))?;
+        if regex.captures_len() < 2 {
+            anyhow::bail!("Filename date pattern needs a capture group around the date: {}", pattern);
+        }
+
+        Ok(Self {
+            regex,
+            format: format.to_string(),
+        })
+    }
+
+    /// Extract the date from a path's file name, if the pattern matches and the date parses
+    pub fn extract_date(&self, path: &Path) -> Option<NaiveDate> {
+        let name = path.file_name()?.to_str()?;
+        let captures = self.regex.captures(name)?;
+        let date = captures.name("date").or_else(|| captures.get(1))?;
+
+        NaiveDate::parse_from_str(date.as_str(), &self.format).ok()
+    }
+
+    /// Predicate accepting files whose file name date is on or after `since`
+    /// Files without a parseable date are rejected
+    pub fn since(&self, since: NaiveDate) -> FilePredicate {
+        let pattern = self.clone();
+        FilePredicate::new(move |path| pattern.extract_date(path).is_some_and(|date| date >= since))
+    }
+}
+
+impl PartialEq for FilenameDatePattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.regex.as_str() == other.regex.as_str() && self.format == other.format
+    }
+}
+
+impl Eq for FilenameDatePattern {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn test_extract_date_from_file_names() {
+        let compact = FilenameDatePattern::new(r"_(\d{8})\.", "%Y%m%d").unwrap();
+        let named = FilenameDatePattern::new(r"^(?<prefix>\w+?)-(?<date>\d{2}-\d{2}-\d{4})", "%d-%m-%Y").unwrap();
+
+        assert_eq!(compact.extract_date(Path::new("/in/export_20240115.txt")), Some(date(2024, 1, 15)));
+        assert_eq!(named.extract_date(Path::new("sales-15-01-2024.csv")), Some(date(2024, 1, 15)));
+        assert_eq!(compact.extract_date(Path::new("export.txt")), None);
+        assert_eq!(compact.extract_date(Path::new("export_20241345.txt")), None);
+        assert!(FilenameDatePattern::new(r"\d{8}", "%Y%m%d").is_err());
+    }
+
+    #[test]
+    fn test_filter_by_file_name_date_window() {
+        let pattern = FilenameDatePattern::new(r"_(\d{8})\.", "%Y%m%d").unwrap();
+        let filter = pattern.since(date(2024, 1, 15));
+
+        assert!(filter.matches(Path::new("export_20240115.txt")));
+        assert!(filter.matches(Path::new("export_20240116.txt")));
+        assert!(!filter.matches(Path::new("export_20240114.txt")));
+        assert!(!filter.matches(Path::new("export_latest.txt")));
+    }
+}
@@ -0,0 +1,189 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tracing::warn;
+
+/// Directory entries as produced by `read_dir`
+pub type DirEntries = Box<dyn Iterator<Item = io::Result<PathBuf>> + Send>;
+
+/// Size and timestamps of a file; timestamps are None where the platform does not support them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStat {
+    pub len: u64,
+    pub created: Option<SystemTime>,
+    pub modified: Option<SystemTime>,
+}
+
+/// Filesystem operations used while scanning, abstracted so tests can inject failures
+pub trait FileSystem {
+    /// List the entries of a directory
+    fn read_dir(&self, directory: &Path) -> io::Result<DirEntries>;
+
+    /// Read the size and timestamps of a file
+    fn stat(&self, path: &Path) -> io::Result<FileStat>;
+
+    /// Read a whole file as UTF-8 text
+    fn read_to_string(&self, path: &Path) -> io::Result<String>;
+}
+
+/// The real filesystem
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsFileSystem;
+
+impl FileSystem for OsFileSystem {
+    fn read_dir(&self, directory: &Path) -> io::Result<DirEntries> {
+        let entries = fs::read_dir(directory)?.map(|entry| entry.map(|entry| entry.path()));
+        Ok(Box::new(entries))
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<FileStat> {
+        let metadata = fs::metadata(path)?;
+        Ok(FileStat {
+            len: metadata.len(),
+            created: metadata.created().ok(),
+            modified: metadata.modified().ok(),
+        })
+    }
+
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+}
+
+/// Retry policy for filesystem calls that may fail transiently (e.g. on network shares)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one
+    pub attempts: u32,
+    /// Pause between attempts
+    pub delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            delay: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Check whether an I/O error kind is worth retrying
+/// Pure function
+pub fn is_transient_io_error(kind: io::ErrorKind) -> bool {
+    matches!(
+        kind,
+        io::ErrorKind::TimedOut | io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock
+    )
+}
+
+/// Call `read_dir`, retrying transient errors according to the policy
+/// Non-transient errors are returned immediately; the last error is returned once attempts run out.
+/// Sleeps the calling thread between attempts: async code must call it through `spawn_blocking`
+pub fn read_dir_with_retry<F: FileSystem + ?Sized>(
+    file_system: &F,
+    directory: &Path,
+    policy: &RetryPolicy,
+) -> io::Result<DirEntries> {
+    let attempts = policy.attempts.max(1);
+    let mut attempt = 1;
+
+    loop {
+        match file_system.read_dir(directory) {
+            Ok(entries) => return Ok(entries),
+            Err(e) if attempt < attempts && is_transient_io_error(e.kind()) => {
+                warn!(
+                    "Transient error listing {:?} (attempt {}/{}): {}",
+                    directory, attempt, attempts, e
+                );
+                std::thread::sleep(policy.delay);
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    /// Fails with the given error kind for the first `failures` calls
+    struct FlakyFileSystem {
+        kind: io::ErrorKind,
+        failures: u32,
+        calls: Cell<u32>,
+    }
+
+    impl FileSystem for FlakyFileSystem {
+        fn read_dir(&self, _directory: &Path) -> io::Result<DirEntries> {
+            self.calls.set(self.calls.get() + 1);
+            if self.calls.get() <= self.failures {
+                return Err(io::Error::from(self.kind));
+            }
+            Ok(Box::new(vec![Ok(PathBuf::from("/share/a.txt"))].into_iter()))
+        }
+
+        fn stat(&self, path: &Path) -> io::Result<FileStat> {
+            OsFileSystem.stat(path)
+        }
+
+        fn read_to_string(&self, path: &Path) -> io::Result<String> {
+            OsFileSystem.read_to_string(path)
+        }
+    }
+
+    fn policy(attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            attempts,
+            delay: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn test_retry_succeeds_after_transient_failures() {
+        let fs = FlakyFileSystem {
+            kind: io::ErrorKind::TimedOut,
+            failures: 2,
+            calls: Cell::new(0),
+        };
+
+        let entries: Vec<PathBuf> = read_dir_with_retry(&fs, Path::new("/share"), &policy(3))
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+
+        assert_eq!(entries, vec![PathBuf::from("/share/a.txt")]);
+        assert_eq!(fs.calls.get(), 3);
+    }
+
+    #[test]
+    fn test_retry_returns_last_error_when_exhausted() {
+        let fs = FlakyFileSystem {
+            kind: io::ErrorKind::Interrupted,
+            failures: 5,
+            calls: Cell::new(0),
+        };
+
+        let error = read_dir_with_retry(&fs, Path::new("/share"), &policy(2)).err().unwrap();
+
+        assert_eq!(error.kind(), io::ErrorKind::Interrupted);
+        assert_eq!(fs.calls.get(), 2);
+    }
+
+    #[test]
+    fn test_non_transient_errors_are_not_retried() {
+        let fs = FlakyFileSystem {
+            kind: io::ErrorKind::PermissionDenied,
+            failures: 1,
+            calls: Cell::new(0),
+        };
+
+        let error = read_dir_with_retry(&fs, Path::new("/share"), &policy(3)).err().unwrap();
+
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(fs.calls.get(), 1);
+    }
+}
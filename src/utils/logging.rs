@@ -0,0 +1,69 @@
+use tracing::Subscriber;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// Layer echoing WARN and ERROR events as one concise plain-text line each
+/// Meant for stderr, so operators see problems immediately whatever the main log output is
+pub fn alert_layer<S, W>(make_writer: W) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'writer> MakeWriter<'writer> + 'static,
+{
+    tracing_subscriber::fmt::layer()
+        .with_writer(make_writer)
+        .without_time()
+        .with_target(false)
+        .with_ansi(false)
+        .compact()
+        .with_filter(LevelFilter::WARN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    /// In-memory log output
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Capture {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    #[test]
+    fn test_alert_layer_receives_only_warnings_and_errors() {
+        let (main, alerts) = (Capture::default(), Capture::default());
+        let (main_writer, alert_writer) = (main.clone(), alerts.clone());
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_subscriber::fmt::layer().with_ansi(false).with_writer(move || main_writer.clone()))
+            .with(alert_layer(move || alert_writer.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("Scanning directory");
+            tracing::error!("Failed to process file: /out/a.txt");
+        });
+
+        let (main, alerts) = (main.contents(), alerts.contents());
+        assert!(main.contains("Scanning directory") && main.contains("Failed to process file"));
+        assert_eq!(alerts.lines().count(), 1);
+        assert!(alerts.contains("ERROR") && alerts.contains("Failed to process file: /out/a.txt"));
+    }
+}
@@ -1,3 +1,17 @@
+pub mod clock;
+pub mod error_log;
 pub mod file_operations;
+pub mod filename_date;
+pub mod filesystem;
+pub mod logging;
+pub mod output;
+pub mod sftp;
 
-pub use file_operations::*;
\ No newline at end of file
+pub use clock::*;
+pub use error_log::*;
+pub use file_operations::*;
+pub use filename_date::*;
+pub use filesystem::*;
+pub use logging::*;
+pub use output::*;
+pub use sftp::*;
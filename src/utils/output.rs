@@ -0,0 +1,75 @@
+use anyhow::{Context, Result};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Pure function returning the file actually written: `.gz` is appended when compressing
+pub fn output_path(path: &Path, compress: bool) -> PathBuf {
+    if compress {
+        let mut name = path.as_os_str().to_owned();
+        name.push(".gz");
+        PathBuf::from(name)
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// Create an output file, optionally gzip-compressed, and fill it with `write`
+/// The gzip stream is finalized and flushed before returning; returns the path written
+pub fn write_output<F>(path: &Path, compress: bool, write: F) -> Result<PathBuf>
+where
+    F: FnOnce(&mut dyn Write) -> Result<()>,
+{
+    let path = output_path(path, compress);
+    let file = File::create(&path).with_context(|| format!("Failed to create output file: {:?}", path))?;
+    let mut writer = BufWriter::new(file);
+
+    if compress {
+        let mut encoder = GzEncoder::new(writer, Compression::default());
+        write(&mut encoder)?;
+        writer = encoder.finish().context("Failed to finalize gzip stream")?;
+    } else {
+        write(&mut writer)?;
+    }
+
+    writer.flush().with_context(|| format!("Failed to flush output file: {:?}", path))?;
+    Ok(path)
+}
+
+/// Write a value as pretty-printed JSON, optionally gzip-compressed
+pub fn write_json_output<T: Serialize>(path: &Path, compress: bool, value: &T) -> Result<PathBuf> {
+    write_output(path, compress, |writer| {
+        serde_json::to_writer_pretty(&mut *writer, value).context("Failed to serialize output")?;
+        writeln!(writer)?;
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn test_compressed_report_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let report = serde_json::json!({ "files_saved": 3, "errors": ["a", "b"] });
+
+        let written = write_json_output(&dir.path().join("report.json"), true, &report).unwrap();
+        assert_eq!(written, dir.path().join("report.json.gz"));
+        assert!(!dir.path().join("report.json").exists());
+
+        let mut decompressed = String::new();
+        GzDecoder::new(File::open(&written).unwrap())
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&decompressed).unwrap(), report);
+
+        let plain = write_json_output(&dir.path().join("report.json"), false, &report).unwrap();
+        assert_eq!(std::fs::read_to_string(plain).unwrap(), decompressed);
+    }
+}
@@ -0,0 +1,329 @@
+use crate::utils::part_file_path;
+use anyhow::{Context, Result};
+use ssh2::{CheckResult, KnownHostFileKind, Session, Sftp};
+use std::fs::File;
+use std::io;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Default SSH port when an `sftp://` destination does not name one
+pub const DEFAULT_SFTP_PORT: u16 = 22;
+
+/// Limit on connecting to an SFTP host and on each blocking SSH call after that, so a
+/// server that stops responding fails the job instead of hanging its copy worker
+pub const SFTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Remote destination parsed from an `sftp://[user@]host[:port]/path` output folder
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SftpTarget {
+    pub host: String,
+    pub port: u16,
+    pub user: Option<String>,
+    pub remote_dir: PathBuf,
+}
+
+impl SftpTarget {
+    /// Pure function parsing an output folder
+    /// Returns None for local paths and an error for malformed `sftp://` URLs
+    pub fn parse(raw: &str) -> Option<Result<Self>> {
+        let rest = raw.strip_prefix("sftp://")?;
+        Some(Self::parse_authority_and_path(rest).with_context(|| format!("Invalid SFTP destination: {}", raw)))
+    }
+
+    fn parse_authority_and_path(rest: &str) -> Result<Self> {
+        let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+        let (user, host_port) = match authority.rsplit_once('@') {
+            Some((user, host_port)) => {
+                let user = urlencoding::decode(user).context("User is not valid URL encoding")?;
+                (Some(user.into_owned()), host_port)
+            }
+            None => (None, authority),
+        };
+
+        // Bracketed IPv6 hosts may contain ':' themselves
+        let (host, port) = match host_port.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => {
+                (host, port.parse().with_context(|| format!("Invalid port: {}", port))?)
+            }
+            _ => (host_port, DEFAULT_SFTP_PORT),
+        };
+        if host.is_empty() {
+            anyhow::bail!("Host not found");
+        }
+
+        Ok(Self {
+            host: host.trim_start_matches('[').trim_end_matches(']').to_string(),
+            port,
+            user: user.filter(|user| !user.is_empty()),
+            remote_dir: PathBuf::from(format!("/{}", path.trim_end_matches('/'))),
+        })
+    }
+
+    /// `sftp://` URL of a remote path on this target, used to report copy destinations
+    pub fn url_for(&self, remote_path: &Path) -> PathBuf {
+        let host = if self.host.contains(':') { format!("[{}]", self.host) } else { self.host.clone() };
+        PathBuf::from(format!("sftp://{}:{}{}", host, self.port, remote_path_string(remote_path)))
+    }
+}
+
+/// Login for an SFTP destination, read from the environment
+#[derive(Clone, Default)]
+pub struct SftpCredentials {
+    pub user: String,
+    pub password: Option<String>,
+    /// Private key file; `password` is then used as its passphrase
+    pub private_key: Option<PathBuf>,
+    /// OpenSSH known_hosts file the server's host key must be listed in
+    pub known_hosts: PathBuf,
+}
+
+impl std::fmt::Debug for SftpCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SftpCredentials")
+            .field("user", &self.user)
+            .field("password", &self.password.as_ref().map(|_| "***"))
+            .field("private_key", &self.private_key)
+            .field("known_hosts", &self.known_hosts)
+            .finish()
+    }
+}
+
+impl SftpCredentials {
+    /// Resolve credentials from an environment lookup
+    ///
+    /// The user comes from the URL, else `SFTP_USER`; `SFTP_PASSWORD` and
+    /// `SFTP_PRIVATE_KEY` are optional and the SSH agent is tried without either.
+    /// Host keys are checked against `SFTP_KNOWN_HOSTS`, else `~/.ssh/known_hosts`
+    pub fn from_env<F>(target: &SftpTarget, env: F) -> Result<Self>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let non_empty = |key: &str| env(key).filter(|value| !value.is_empty());
+        let user = target
+            .user
+            .clone()
+            .or_else(|| non_empty("SFTP_USER"))
+            .with_context(|| format!("No user for SFTP host {}: put it in the URL or set SFTP_USER", target.host))?;
+
+        let known_hosts = non_empty("SFTP_KNOWN_HOSTS")
+            .map(PathBuf::from)
+            .or_else(|| non_empty("HOME").map(|home| Path::new(&home).join(".ssh").join("known_hosts")))
+            .context("No known_hosts file to check SFTP host keys against: set SFTP_KNOWN_HOSTS")?;
+
+        Ok(Self {
+            user,
+            password: non_empty("SFTP_PASSWORD"),
+            private_key: non_empty("SFTP_PRIVATE_KEY").map(PathBuf::from),
+            known_hosts,
+        })
+    }
+}
+
+/// Open SFTP session uploading files to one target
+pub struct SftpUploader {
+    // Kept alive for the duration of the SFTP channel
+    _session: Session,
+    sftp: Sftp,
+}
+
+impl SftpUploader {
+    /// Connect and authenticate, with a private key, a password or else the SSH agent
+    /// Nothing is sent to the server before its host key matches `credentials.known_hosts`
+    pub fn connect(target: &SftpTarget, credentials: &SftpCredentials) -> Result<Self> {
+        let tcp = connect_tcp(target)
+            .with_context(|| format!("Failed to connect to SFTP host {}:{}", target.host, target.port))?;
+
+        let mut session = Session::new().context("Failed to create SSH session")?;
+        session.set_timeout(SFTP_TIMEOUT.as_millis() as u32);
+        session.set_tcp_stream(tcp);
+        session.handshake().context("SSH handshake failed")?;
+        verify_host_key(&session, target, &credentials.known_hosts)?;
+
+        let user = credentials.user.as_str();
+        let authenticated = match (&credentials.private_key, &credentials.password) {
+            (Some(key), passphrase) => session.userauth_pubkey_file(user, None, key, passphrase.as_deref()),
+            (None, Some(password)) => session.userauth_password(user, password),
+            (None, None) => session.userauth_agent(user),
+        };
+        authenticated.with_context(|| format!("SFTP authentication failed for {}@{}", user, target.host))?;
+
+        let sftp = session.sftp().context("Failed to open SFTP channel")?;
+
+        Ok(Self { _session: session, sftp })
+    }
+
+    /// Size of a remote file, or None when it does not exist
+    pub fn remote_size(&self, remote_path: &Path) -> Option<u64> {
+        self.sftp.stat(Path::new(&remote_path_string(remote_path))).ok()?.size
+    }
+
+    /// Upload `source` to `remote_path`, creating missing remote directories
+    /// Returns false without uploading when the remote file exists and `overwrite` is off.
+    /// The file is written to its `.part` name and renamed once complete, so a dropped
+    /// connection never leaves a partial file under the final name.
+    pub fn upload(&self, source: &Path, remote_path: &Path, overwrite: bool) -> Result<bool> {
+        let remote = PathBuf::from(remote_path_string(remote_path));
+        if !overwrite && self.remote_size(&remote).is_some() {
+            return Ok(false);
+        }
+
+        if let Some(parent) = remote.parent() {
+            self.create_dir_all(parent)?;
+        }
+
+        let part = part_file_path(&remote);
+        let mut local = File::open(source).with_context(|| format!("Failed to open file: {:?}", source))?;
+        let mut remote_file = self
+            .sftp
+            .create(&part)
+            .with_context(|| format!("Failed to create remote file: {:?}", part))?;
+        io::copy(&mut local, &mut remote_file)
+            .with_context(|| format!("Failed to upload {:?} to {:?}", source, part))?;
+        drop(remote_file);
+
+        // SFTP v3 servers such as OpenSSH refuse to rename over an existing file
+        if self.sftp.rename(&part, &remote, None).is_err() {
+            if overwrite {
+                let _ = self.sftp.unlink(&remote);
+            }
+            self.sftp
+                .rename(&part, &remote, None)
+                .with_context(|| format!("Failed to rename {:?} to {:?}", part, remote))?;
+        }
+
+        Ok(true)
+    }
+
+    fn create_dir_all(&self, dir: &Path) -> Result<()> {
+        let mut ancestors: Vec<&Path> = dir.ancestors().filter(|path| !path.as_os_str().is_empty()).collect();
+        ancestors.reverse();
+
+        for path in ancestors {
+            if self.sftp.stat(path).is_err() {
+                self.sftp
+                    .mkdir(path, 0o755)
+                    .with_context(|| format!("Failed to create remote directory: {:?}", path))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Open a TCP connection to the first reachable address of the target, within `SFTP_TIMEOUT` each
+fn connect_tcp(target: &SftpTarget) -> io::Result<TcpStream> {
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, "host resolved to no address");
+    for address in (target.host.as_str(), target.port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, SFTP_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+/// Fail unless the server's host key is listed for the target in the `known_hosts` file
+fn verify_host_key(session: &Session, target: &SftpTarget, known_hosts: &Path) -> Result<()> {
+    let (key, _) = session.host_key().context("SFTP server sent no host key")?;
+    let mut hosts = session.known_hosts().context("Failed to initialize known hosts")?;
+    hosts
+        .read_file(known_hosts, KnownHostFileKind::OpenSSH)
+        .with_context(|| format!("Failed to read known_hosts file {:?}", known_hosts))?;
+
+    host_key_check(hosts.check_port(&target.host, target.port, key), target, known_hosts)
+}
+
+/// Pure function turning a known_hosts lookup into the connection's go-ahead or error
+fn host_key_check(result: CheckResult, target: &SftpTarget, known_hosts: &Path) -> Result<()> {
+    match result {
+        CheckResult::Match => Ok(()),
+        CheckResult::Mismatch => anyhow::bail!(
+            "Host key of SFTP host {}:{} does not match {:?}: refusing to connect (man-in-the-middle or changed key)",
+            target.host,
+            target.port,
+            known_hosts
+        ),
+        CheckResult::NotFound => anyhow::bail!(
+            "SFTP host {}:{} is not in {:?}: add its host key (e.g. with ssh-keyscan) before uploading",
+            target.host,
+            target.port,
+            known_hosts
+        ),
+        CheckResult::Failure => anyhow::bail!("Failed to check the host key of SFTP host {}", target.host),
+    }
+}
+
+/// Remote paths always use '/' separators, whatever the local platform
+fn remote_path_string(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sftp_destinations() {
+        let target = SftpTarget::parse("sftp://uploader@files.example.com:2222/inbox/vw/").unwrap().unwrap();
+        assert_eq!(
+            target,
+            SftpTarget {
+                host: "files.example.com".to_string(),
+                port: 2222,
+                user: Some("uploader".to_string()),
+                remote_dir: PathBuf::from("/inbox/vw"),
+            }
+        );
+
+        let defaults = SftpTarget::parse("sftp://files.example.com").unwrap().unwrap();
+        assert_eq!((defaults.port, defaults.user, defaults.remote_dir), (22, None, PathBuf::from("/")));
+
+        let ipv6 = SftpTarget::parse("sftp://[::1]:22/out").unwrap().unwrap();
+        assert_eq!(ipv6.host, "::1");
+        assert_eq!(ipv6.url_for(Path::new("/out/a.txt")), PathBuf::from("sftp://[::1]:22/out/a.txt"));
+
+        assert!(SftpTarget::parse("/data/out").is_none());
+        assert!(SftpTarget::parse("sftp://host:port/out").unwrap().is_err());
+        assert!(SftpTarget::parse("sftp:///out").unwrap().is_err());
+    }
+
+    #[test]
+    fn test_credentials_from_env() {
+        let target = SftpTarget::parse("sftp://files.example.com/out").unwrap().unwrap();
+        let env = |key: &str| match key {
+            "SFTP_USER" => Some("env-user".to_string()),
+            "SFTP_PASSWORD" => Some("secret".to_string()),
+            "HOME" => Some("/home/vw".to_string()),
+            _ => None,
+        };
+
+        let credentials = SftpCredentials::from_env(&target, env).unwrap();
+        assert_eq!((credentials.user.as_str(), credentials.password.as_deref()), ("env-user", Some("secret")));
+        assert_eq!(credentials.known_hosts, PathBuf::from("/home/vw/.ssh/known_hosts"));
+        assert!(!format!("{:?}", credentials).contains("secret"));
+
+        let with_user = SftpTarget { user: Some("url-user".to_string()), ..target.clone() };
+        assert_eq!(SftpCredentials::from_env(&with_user, env).unwrap().user, "url-user");
+        assert!(SftpCredentials::from_env(&target, |_| None).is_err());
+
+        let pinned = |key: &str| match key {
+            "SFTP_KNOWN_HOSTS" => Some("/etc/vw/known_hosts".to_string()),
+            _ => env(key),
+        };
+        assert_eq!(SftpCredentials::from_env(&target, pinned).unwrap().known_hosts, PathBuf::from("/etc/vw/known_hosts"));
+    }
+
+    #[test]
+    fn test_host_key_check_only_accepts_a_listed_matching_key() {
+        let target = SftpTarget::parse("sftp://files.example.com/out").unwrap().unwrap();
+        let known_hosts = Path::new("/etc/vw/known_hosts");
+
+        assert!(host_key_check(CheckResult::Match, &target, known_hosts).is_ok());
+        let mismatch = host_key_check(CheckResult::Mismatch, &target, known_hosts).unwrap_err().to_string();
+        assert!(mismatch.contains("does not match"), "{}", mismatch);
+        let unknown = host_key_check(CheckResult::NotFound, &target, known_hosts).unwrap_err().to_string();
+        assert!(unknown.contains("is not in"), "{}", unknown);
+        assert!(host_key_check(CheckResult::Failure, &target, known_hosts).is_err());
+    }
+}
@@ -0,0 +1,73 @@
+//! Shared helpers for database integration tests
+//!
+//! Tests run against the database in `TEST_DATABASE_URL`, each one inside its own
+//! freshly migrated schema. When the variable is not set the tests are skipped.
+
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::PgPool;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Connect to an isolated, migrated schema on the test database
+/// Returns None when TEST_DATABASE_URL is not set
+pub async fn test_pool() -> Option<PgPool> {
+    let url = match std::env::var("TEST_DATABASE_URL") {
+        Ok(url) if !url.is_empty() => url,
+        _ => {
+            eprintln!("TEST_DATABASE_URL not set, skipping database test");
+            return None;
+        }
+    };
+
+    let schema = format!("vw_test_{}", uuid::Uuid::new_v4().simple());
+
+    let admin = PgPool::connect(&url)
+        .await
+        .expect("Failed to connect to TEST_DATABASE_URL");
+    sqlx::query(&format!("CREATE SCHEMA {}", schema))
+        .execute(&admin)
+        .await
+        .expect("Failed to create test schema");
+    admin.close().await;
+
+    let options = PgConnectOptions::from_str(&url)
+        .expect("Invalid TEST_DATABASE_URL")
+        .options([("search_path", schema.as_str())]);
+
+    let pool = PgPoolOptions::new()
+        .max_connections(5)
+        .connect_with(options)
+        .await
+        .expect("Failed to connect to test schema");
+
+    apply_migrations(&pool).await;
+
+    Some(pool)
+}
+
+/// Apply every migration in `migrations/` in file name order
+async fn apply_migrations(pool: &PgPool) {
+    let migrations_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("migrations");
+
+    let mut migrations: Vec<_> = std::fs::read_dir(&migrations_dir)
+        .expect("Failed to read migrations directory")
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "sql"))
+        .collect();
+    migrations.sort();
+
+    for migration in migrations {
+        apply_script(pool, &migration).await;
+    }
+}
+
+/// Run a SQL script, e.g. one of the opt-in scripts next to the migrations
+pub async fn apply_script(pool: &PgPool, script: &Path) {
+    let script = Path::new(env!("CARGO_MANIFEST_DIR")).join(script);
+    let sql = std::fs::read_to_string(&script).expect("Failed to read migration");
+    sqlx::raw_sql(&sql)
+        .execute(pool)
+        .await
+        .unwrap_or_else(|e| panic!("Failed to apply {:?}: {}", script, e));
+}
@@ -0,0 +1,506 @@
+mod common;
+
+use std::path::Path;
+use vw_file_discover::database::file_trace::{self, TraceFilter};
+use vw_file_discover::database::ConflictKey;
+use vw_file_discover::models::{create_file_trace_from_path, create_file_trace_from_path_with, FileProcessingConfig};
+use vw_file_discover::services::{copy_and_discover_pipelined, discover_from_file_list, parse_file_list};
+use vw_file_discover::utils::Deadline;
+use vw_file_discover::{
+    copy_files_for_revendas, discover_and_register_files, DbPool, FileCopyConfig, FileCopyReport, FileDiscoveryConfig,
+    FileDiscoveryReport, FileTraceStatus,
+};
+
+async fn insert_revenda(pool: &DbPool, extensao: &str, pasta_output: &Path) {
+    sqlx::query(
+        "INSERT INTO fvw_arq_diarios_ext (empresa, revenda, extensao, dn, pasta_input, pasta_output) VALUES (1, 1, $1, 0, '', $2)",
+    )
+    .bind(extensao)
+    .bind(pasta_output.to_string_lossy().to_string())
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn traces_share_the_run_id_of_their_discovery_run() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    let dir = tempfile::tempdir().unwrap();
+    insert_revenda(&pool, ".txt", dir.path()).await;
+
+    std::fs::write(dir.path().join("a.txt"), "first run a\n").unwrap();
+    std::fs::write(dir.path().join("b.txt"), "first run b\n").unwrap();
+    let first = discover_and_register_files(&pool, FileDiscoveryConfig::default()).await.unwrap();
+
+    std::fs::write(dir.path().join("c.txt"), "second run c\n").unwrap();
+    let second = discover_and_register_files(&pool, FileDiscoveryConfig::default()).await.unwrap();
+
+    let run_ids: Vec<(String, Option<String>)> = sqlx::query_as("SELECT name, run_id FROM fvw_file_trace ORDER BY name")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+
+    assert!(first.run_id.is_some());
+    assert_ne!(first.run_id, second.run_id);
+    assert_eq!(
+        run_ids,
+        vec![
+            ("a.txt".to_string(), first.run_id.clone()),
+            ("b.txt".to_string(), first.run_id.clone()),
+            ("c.txt".to_string(), second.run_id.clone()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn traces_carry_the_tenant_of_their_run() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    let dir = tempfile::tempdir().unwrap();
+    insert_revenda(&pool, ".txt", dir.path()).await;
+    let for_tenant = |tenant: &str| FileDiscoveryConfig {
+        tenant: Some(tenant.to_string()),
+        ..FileDiscoveryConfig::default()
+    };
+
+    std::fs::write(dir.path().join("a.txt"), "tenant one a\n").unwrap();
+    std::fs::write(dir.path().join("b.txt"), "tenant one b\n").unwrap();
+    let first = discover_and_register_files(&pool, for_tenant("tenant-one")).await.unwrap();
+
+    std::fs::write(dir.path().join("c.txt"), "tenant two c\n").unwrap();
+    discover_and_register_files(&pool, for_tenant("tenant-two")).await.unwrap();
+
+    let tenant_filter = |tenant: &str| TraceFilter {
+        tenant: Some(tenant.to_string()),
+        ..TraceFilter::default()
+    };
+    let tenant_one = file_trace::get_by_filter(
+        &pool,
+        &TraceFilter { status_fvw: Some(FileTraceStatus::Pending as i32), ..tenant_filter("tenant-one") },
+    )
+    .await
+    .unwrap();
+    let mut provenance: Vec<(&str, Option<&str>, Option<&str>)> = tenant_one
+        .iter()
+        .map(|trace| (trace.name.as_str(), trace.tenant.as_deref(), trace.run_id.as_deref()))
+        .collect();
+    provenance.sort();
+    assert_eq!(
+        provenance,
+        vec![
+            ("a.txt", Some("tenant-one"), first.run_id.as_deref()),
+            ("b.txt", Some("tenant-one"), first.run_id.as_deref()),
+        ]
+    );
+
+    let tenant_two = file_trace::get_by_filter(&pool, &tenant_filter("tenant-two")).await.unwrap();
+    assert_eq!(tenant_two.iter().map(|trace| trace.name.as_str()).collect::<Vec<_>>(), vec!["c.txt"]);
+    assert_eq!(file_trace::get_by_status(&pool, None, None, None).await.unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn same_content_is_registered_once_per_tenant_only_with_the_tenant_hash_key() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    let dir = tempfile::tempdir().unwrap();
+    insert_revenda(&pool, ".txt", dir.path()).await;
+    std::fs::write(dir.path().join("shared.txt"), "same content for every tenant\n").unwrap();
+    let run = |tenant: Option<&str>, conflict_key: ConflictKey| FileDiscoveryConfig {
+        tenant: tenant.map(str::to_string),
+        conflict_key,
+        ..FileDiscoveryConfig::default()
+    };
+    let tenants = |pool: DbPool| async move {
+        let mut tenants: Vec<Option<String>> = sqlx::query_scalar("SELECT tenant FROM fvw_file_trace")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        tenants.sort();
+        tenants
+    };
+
+    // With the default hash key, content registered by one tenant is skipped for every other
+    discover_and_register_files(&pool, run(Some("tenant-one"), ConflictKey::Hash)).await.unwrap();
+    let second = discover_and_register_files(&pool, run(Some("tenant-two"), ConflictKey::Hash)).await.unwrap();
+    assert_eq!(second.files_saved, 0);
+    assert_eq!(tenants(pool.clone()).await, vec![Some("tenant-one".to_string())]);
+
+    common::apply_script(&pool, Path::new(ConflictKey::TenantHash.setup_script().unwrap())).await;
+
+    let second = discover_and_register_files(&pool, run(Some("tenant-two"), ConflictKey::TenantHash)).await.unwrap();
+    assert_eq!(second.files_saved, 1);
+    let again = discover_and_register_files(&pool, run(Some("tenant-two"), ConflictKey::TenantHash)).await.unwrap();
+    assert_eq!(again.files_saved, 0);
+    // Untenanted runs share one trace per content as well
+    for _ in 0..2 {
+        discover_and_register_files(&pool, run(None, ConflictKey::TenantHash)).await.unwrap();
+    }
+
+    assert_eq!(
+        tenants(pool.clone()).await,
+        vec![None, Some("tenant-one".to_string()), Some("tenant-two".to_string())]
+    );
+}
+
+#[tokio::test]
+async fn delete_after_register_only_removes_saved_files() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    let dir = tempfile::tempdir().unwrap();
+    let new_file = dir.path().join("new.txt");
+    let conflicting_file = dir.path().join("conflicting.txt");
+    std::fs::write(&new_file, "new content\n").unwrap();
+    std::fs::write(&conflicting_file, "already registered\n").unwrap();
+
+    // Register the conflicting content beforehand under another path
+    let mut existing = create_file_trace_from_path(&conflicting_file).unwrap();
+    existing.path = "/elsewhere/conflicting.txt".to_string();
+    file_trace::save_batch(&pool, &[existing]).await.unwrap();
+
+    insert_revenda(&pool, ".txt", dir.path()).await;
+
+    let config = FileDiscoveryConfig {
+        delete_after_register: true,
+        ..FileDiscoveryConfig::default()
+    };
+    let report = discover_and_register_files(&pool, config).await.unwrap();
+
+    assert_eq!(report.files_saved, 1);
+    assert_eq!(report.files_deleted, 1);
+    assert!(!new_file.exists());
+    assert!(conflicting_file.exists());
+}
+
+#[tokio::test]
+async fn delete_conflicting_also_removes_already_registered_files() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    let dir = tempfile::tempdir().unwrap();
+    let conflicting_file = dir.path().join("conflicting.txt");
+    std::fs::write(&conflicting_file, "already registered\n").unwrap();
+    file_trace::save_batch(&pool, &[create_file_trace_from_path(&conflicting_file).unwrap()])
+        .await
+        .unwrap();
+
+    insert_revenda(&pool, ".txt", dir.path()).await;
+
+    let config = FileDiscoveryConfig {
+        delete_after_register: true,
+        delete_conflicting: true,
+        ..FileDiscoveryConfig::default()
+    };
+    let report = discover_and_register_files(&pool, config).await.unwrap();
+
+    assert_eq!(report.files_saved, 0);
+    assert_eq!(report.files_deleted, 1);
+    assert!(!conflicting_file.exists());
+}
+
+#[tokio::test]
+async fn report_lists_banned_and_requeued_traces() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    let dir = tempfile::tempdir().unwrap();
+    let banned_file = dir.path().join("skip_me.txt");
+    let errored_file = dir.path().join("retry.txt");
+    std::fs::write(&banned_file, "banned content\n").unwrap();
+    std::fs::write(&errored_file, "failed before\n").unwrap();
+
+    // The errored file was registered by an earlier run and marked as failed
+    let mut errored = create_file_trace_from_path(&errored_file).unwrap();
+    errored.status_fvw = FileTraceStatus::Error as i32;
+    file_trace::save_batch(&pool, &[errored]).await.unwrap();
+    let errored_id = file_trace::get_by_status(&pool, Some(FileTraceStatus::Error as i32), None, None)
+        .await
+        .unwrap()[0]
+        .id
+        .unwrap();
+
+    insert_revenda(&pool, ".txt", dir.path()).await;
+
+    let config = FileDiscoveryConfig {
+        banned_patterns: vec!["SKIP_*".to_string()],
+        requeue_errors: true,
+        ..FileDiscoveryConfig::default()
+    };
+    let report = discover_and_register_files(&pool, config).await.unwrap();
+
+    let banned = file_trace::get_by_status(&pool, Some(FileTraceStatus::Banned as i32), None, None)
+        .await
+        .unwrap();
+    assert_eq!(banned.len(), 1);
+    assert_eq!(banned[0].name, "skip_me.txt");
+
+    assert_eq!(report.files_saved, 1);
+    assert_eq!(report.files_banned, 1);
+    assert_eq!(
+        report.status_changes,
+        vec![
+            (banned[0].id.unwrap(), FileTraceStatus::Pending as i32, FileTraceStatus::Banned as i32),
+            (errored_id, FileTraceStatus::Error as i32, FileTraceStatus::Pending as i32),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn file_list_registers_only_existing_listed_files() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    let dir = tempfile::tempdir().unwrap();
+    let listed = dir.path().join("listed.txt");
+    let unlisted = dir.path().join("unlisted.txt");
+    std::fs::write(&listed, "listed\n").unwrap();
+    std::fs::write(&unlisted, "unlisted\n").unwrap();
+
+    let contents = format!(
+        "{}\n# skipped comment\n{}\n",
+        listed.display(),
+        dir.path().join("missing.txt").display()
+    );
+    let paths = parse_file_list(&contents);
+    assert_eq!(paths.len(), 2);
+
+    let report = discover_from_file_list(&pool, paths, FileDiscoveryConfig::default())
+        .await
+        .unwrap();
+
+    assert_eq!(report.files_discovered, 1);
+    assert_eq!(report.files_saved, 1);
+    assert_eq!(report.processing_errors, 0);
+
+    let traces = file_trace::get_by_status(&pool, None, None, None).await.unwrap();
+    assert_eq!(traces.len(), 1);
+    assert_eq!(traces[0].path, listed.to_string_lossy());
+}
+
+#[tokio::test]
+async fn path_rewrite_stores_canonical_path_for_scanned_snapshot() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    let snapshot = tempfile::tempdir().unwrap();
+    let scanned = snapshot.path().join("out").join("daily.txt");
+    std::fs::create_dir(snapshot.path().join("out")).unwrap();
+    std::fs::write(&scanned, "daily\n").unwrap();
+
+    let config = FileDiscoveryConfig {
+        path_rewrite: Some((
+            snapshot.path().to_string_lossy().to_string(),
+            "/mnt/prod/vw".to_string(),
+        )),
+        delete_after_register: true,
+        ..FileDiscoveryConfig::default()
+    };
+    let report = discover_from_file_list(&pool, vec![scanned.clone()], config)
+        .await
+        .unwrap();
+
+    assert_eq!(report.files_saved, 1);
+    let traces = file_trace::get_by_status(&pool, None, None, None).await.unwrap();
+    let stored = Path::new("/mnt/prod/vw").join("out").join("daily.txt");
+    assert_eq!(traces[0].path, stored.to_string_lossy());
+    assert_ne!(traces[0].path, scanned.to_string_lossy());
+
+    // Deletion acts on the scanned snapshot copy
+    assert_eq!(report.files_deleted, 1);
+    assert!(!scanned.exists());
+}
+
+#[tokio::test]
+async fn truncated_hash_collision_is_flagged() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    let dir = tempfile::tempdir().unwrap();
+    let colliding = dir.path().join("colliding.txt");
+    let duplicate = dir.path().join("duplicate.txt");
+    std::fs::write(&colliding, "new content\n").unwrap();
+    std::fs::write(&duplicate, "same content\n").unwrap();
+
+    let processing = FileProcessingConfig {
+        hash_truncate_len: Some(4),
+        ..FileProcessingConfig::default()
+    };
+
+    // A different, larger file registered earlier under the same truncated hash
+    let mut other = create_file_trace_from_path_with(colliding.clone(), &processing).unwrap();
+    other.path = "/elsewhere/other.txt".to_string();
+    other.size_bytes += 100;
+    // The same file registered earlier under another path is a genuine duplicate
+    let mut copy = create_file_trace_from_path_with(duplicate.clone(), &processing).unwrap();
+    copy.path = "/elsewhere/duplicate.txt".to_string();
+    file_trace::save_batch(&pool, &[other.clone(), copy]).await.unwrap();
+
+    insert_revenda(&pool, ".txt", dir.path()).await;
+
+    let config = FileDiscoveryConfig {
+        processing,
+        check_hash_collisions: true,
+        ..FileDiscoveryConfig::default()
+    };
+    let report = discover_and_register_files(&pool, config).await.unwrap();
+
+    assert_eq!(report.files_saved, 0);
+    assert_eq!(report.hash_collisions.len(), 1);
+    assert_eq!(report.hash_collisions[0].hash, other.hash);
+    assert_eq!(report.hash_collisions[0].path, colliding.to_string_lossy());
+    assert_eq!(report.hash_collisions[0].existing_path, "/elsewhere/other.txt");
+}
+
+#[tokio::test]
+async fn discovery_reports_stage_timings() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    let dir = tempfile::tempdir().unwrap();
+    insert_revenda(&pool, ".txt", dir.path()).await;
+    for index in 0..5 {
+        std::fs::write(dir.path().join(format!("timed-{}.txt", index)), format!("line {}\n", index)).unwrap();
+    }
+
+    let report = discover_and_register_files(&pool, FileDiscoveryConfig::default()).await.unwrap();
+    let timings = report.timings;
+
+    assert_eq!(report.files_saved, 5);
+    assert!(timings.scan_ms > 0.0 && timings.hash_ms > 0.0 && timings.save_ms > 0.0, "{:?}", timings);
+    // Hashing and saving run one after the other inside the run; scanning overlaps hashing
+    let run_ms = report.run_duration_ms as f64 + 1.0;
+    assert!(timings.hash_ms + timings.save_ms <= run_ms, "{:?} in {} ms", timings, run_ms);
+    assert!(timings.scan_ms <= timings.hash_ms + 1.0, "{:?}", timings);
+}
+
+#[tokio::test]
+async fn expired_deadline_queues_no_files_and_flags_the_report() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    let dir = tempfile::tempdir().unwrap();
+    insert_revenda(&pool, ".txt", dir.path()).await;
+    std::fs::write(dir.path().join("late.txt"), "too late\n").unwrap();
+
+    let config = FileDiscoveryConfig {
+        deadline: Some(Deadline::after(std::time::Duration::ZERO)),
+        ..FileDiscoveryConfig::default()
+    };
+    let report = discover_and_register_files(&pool, config).await.unwrap();
+
+    assert!(report.time_limited);
+    assert_eq!(report.files_discovered, 0);
+    assert_eq!(report.files_saved, 0);
+}
+
+#[tokio::test]
+async fn file_list_stops_at_the_deadline_or_on_cancellation() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    let dir = tempfile::tempdir().unwrap();
+    let listed = dir.path().join("listed.txt");
+    std::fs::write(&listed, "listed\n").unwrap();
+
+    let expired = FileDiscoveryConfig {
+        deadline: Some(Deadline::after(std::time::Duration::ZERO)),
+        ..FileDiscoveryConfig::default()
+    };
+    let report = discover_from_file_list(&pool, vec![listed.clone()], expired).await.unwrap();
+    assert!(report.time_limited);
+    assert_eq!((report.files_discovered, report.files_saved), (0, 0));
+
+    let cancelled = FileDiscoveryConfig::default();
+    cancelled.cancel.cancel();
+    let report = discover_from_file_list(&pool, vec![listed.clone()], cancelled).await.unwrap();
+    assert!(report.cancelled);
+    assert!(!report.time_limited);
+    assert_eq!(report.files_saved, 0);
+
+    let report = discover_from_file_list(&pool, vec![listed], FileDiscoveryConfig::default()).await.unwrap();
+    assert!(!report.time_limited && !report.cancelled);
+    assert_eq!(report.files_saved, 1);
+}
+
+#[tokio::test]
+async fn pipelined_run_reports_the_same_totals_as_sequential_phases() {
+    let Some(sequential_pool) = common::test_pool().await else {
+        return;
+    };
+    let pipelined_pool = common::test_pool().await.unwrap();
+
+    let root = tempfile::tempdir().unwrap();
+    for revenda in 1..=3 {
+        let input = root.path().join(format!("in{}", revenda));
+        std::fs::create_dir(&input).unwrap();
+        std::fs::write(input.join(format!("a{}.txt", revenda)), format!("revenda {} a\n", revenda)).unwrap();
+        std::fs::write(input.join(format!("b{}.csv", revenda)), format!("revenda {} b\n", revenda)).unwrap();
+        std::fs::write(input.join(format!("c{}.dat", revenda)), format!("revenda {} c\n", revenda)).unwrap();
+    }
+
+    for (mode, pool) in [("sequential", &sequential_pool), ("pipelined", &pipelined_pool)] {
+        for (revenda, extensao) in [(1, ".txt"), (2, ".csv"), (3, ".txt")] {
+            sqlx::query(
+                "INSERT INTO fvw_arq_diarios_ext (empresa, revenda, extensao, dn, pasta_input, pasta_output) VALUES (1, $1, $2, 0, $3, $4)",
+            )
+            .bind(revenda)
+            .bind(extensao)
+            .bind(root.path().join(format!("in{}", revenda)).to_string_lossy().to_string())
+            .bind(root.path().join(mode).join(format!("out{}", revenda)).to_string_lossy().to_string())
+            .execute(pool)
+            .await
+            .unwrap();
+        }
+    }
+
+    let sequential_copy = copy_files_for_revendas(&sequential_pool, FileCopyConfig::default()).await.unwrap();
+    let sequential_discovery = discover_and_register_files(&sequential_pool, FileDiscoveryConfig::default()).await.unwrap();
+    let (pipelined_copy, pipelined_discovery) =
+        copy_and_discover_pipelined(&pipelined_pool, FileCopyConfig::default(), FileDiscoveryConfig::default(), 2)
+            .await
+            .unwrap();
+
+    let totals = |copy: &FileCopyReport, discovery: &FileDiscoveryReport, mode: &str| {
+        let prefix = root.path().join(mode).to_string_lossy().to_string();
+        let sorted_relative = |paths: Vec<&String>| {
+            let mut paths: Vec<String> = paths.into_iter().map(|path| path.replace(&prefix, "")).collect();
+            paths.sort();
+            paths
+        };
+        (
+            (copy.successful_copies, copy.skipped_files(), copy.errors.len()),
+            sorted_relative(copy.copied_files.iter().map(|file| &file.destination).collect()),
+            (discovery.files_discovered, discovery.files_processed, discovery.files_saved, discovery.processing_errors),
+            sorted_relative(discovery.discovered_paths.iter().collect()),
+        )
+    };
+
+    let sequential = totals(&sequential_copy, &sequential_discovery, "sequential");
+    assert_eq!(sequential, totals(&pipelined_copy, &pipelined_discovery, "pipelined"));
+    assert_eq!(sequential.0, (6, 0, 0));
+    assert_eq!(sequential.2, (6, 6, 6, 0));
+
+    // Every unit of the pipelined run saves its traces under the same run id
+    let run_ids: Vec<Option<String>> = sqlx::query_scalar("SELECT DISTINCT run_id FROM fvw_file_trace")
+        .fetch_all(&pipelined_pool)
+        .await
+        .unwrap();
+    assert_eq!(run_ids, vec![pipelined_discovery.run_id.clone()]);
+    assert_eq!(pipelined_discovery.scanned_directories.len(), 3);
+}
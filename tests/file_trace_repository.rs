@@ -0,0 +1,435 @@
+mod common;
+
+use std::path::Path;
+use chrono::{TimeZone, Utc};
+use vw_file_discover::database::file_trace::{self, Page, StatusColumn, TraceFilter};
+use vw_file_discover::database::ConflictKey;
+use vw_file_discover::models::{create_file_trace_from_path, create_file_trace_from_path_with, DnExtractionSpec, FileProcessingConfig};
+use vw_file_discover::services::{backfill_dns, repair_traces};
+use vw_file_discover::{FileTrace, FileTraceStatus};
+
+fn fixture_trace(name: &str, hash: &str, dn: i32) -> FileTrace {
+    let timestamp = Utc.with_ymd_and_hms(2024, 1, 15, 10, 30, 0).unwrap();
+    FileTrace::new(
+        name.to_string(),
+        format!("/out/{}", name),
+        hash.to_string(),
+        100,
+        10,
+        timestamp,
+        timestamp,
+        timestamp,
+        dn,
+    )
+}
+
+#[tokio::test]
+async fn export_csv_writes_filtered_traces() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    let traces = vec![
+        fixture_trace("a.txt", "hash-a", 111),
+        fixture_trace("b.txt", "hash-b", 222),
+        fixture_trace("c.txt", "hash-c", 111),
+    ];
+    file_trace::save_batch(&pool, &traces).await.unwrap();
+
+    let filter = TraceFilter {
+        dn: Some(111),
+        ..TraceFilter::default()
+    };
+    let mut output = Vec::new();
+    let written = file_trace::export_csv(&pool, &filter, &mut output).await.unwrap();
+    assert_eq!(written, 2);
+
+    let mut reader = csv::Reader::from_reader(output.as_slice());
+    assert_eq!(reader.headers().unwrap(), file_trace::CSV_HEADER.as_slice());
+
+    let names: Vec<String> = reader
+        .records()
+        .map(|record| record.unwrap()[1].to_string())
+        .collect();
+    assert_eq!(names, vec!["a.txt", "c.txt"]);
+}
+
+#[tokio::test]
+async fn backfill_dns_updates_zero_dn_traces() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    let dir = tempfile::tempdir().unwrap();
+    let header = format!("FHI{}{}", "0".repeat(36), "12345 trailing");
+    let with_dn = dir.path().join("with-dn.txt");
+    std::fs::write(&with_dn, format!("{}\nrecord\n", header)).unwrap();
+    let without_dn = dir.path().join("without-dn.txt");
+    std::fs::write(&without_dn, "plain\n").unwrap();
+
+    let mut traces = vec![
+        fixture_trace("with-dn.txt", "hash-with", 0),
+        fixture_trace("without-dn.txt", "hash-without", 0),
+        fixture_trace("missing.txt", "hash-missing", 0),
+    ];
+    traces[0].path = with_dn.to_string_lossy().to_string();
+    traces[1].path = without_dn.to_string_lossy().to_string();
+    file_trace::save_batch(&pool, &traces).await.unwrap();
+
+    let report = backfill_dns(&pool, DnExtractionSpec::default()).await.unwrap();
+
+    assert_eq!(report.traces_examined, 3);
+    assert_eq!(report.traces_updated, 1);
+    assert_eq!(report.traces_unchanged, 1);
+    assert_eq!(report.missing_files, vec!["/out/missing.txt".to_string()]);
+    assert!(report.errors.is_empty());
+
+    let filter = TraceFilter {
+        dn: Some(12345),
+        ..TraceFilter::default()
+    };
+    let updated = file_trace::get_by_filter(&pool, &filter).await.unwrap();
+    assert_eq!(updated.len(), 1);
+    assert_eq!(updated[0].name, "with-dn.txt");
+}
+
+#[tokio::test]
+async fn repair_traces_rehashes_stale_traces_in_place() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    let dir = tempfile::tempdir().unwrap();
+    let header = format!("FHI{}{}", "0".repeat(36), "12345 trailing");
+    let changed = dir.path().join("changed.txt");
+    std::fs::write(&changed, format!("{}\nrecord 1\nrecord 2\n", header)).unwrap();
+    let intact = dir.path().join("intact.txt");
+    std::fs::write(&intact, "intact\n").unwrap();
+
+    let stale_hash = "5".repeat(64);
+    let mut stale = fixture_trace("changed.txt", &stale_hash, 0);
+    stale.path = changed.to_string_lossy().to_string();
+    let current = create_file_trace_from_path(&intact).unwrap();
+    let traces = vec![stale, current.clone(), fixture_trace("missing.txt", "hash-missing", 0)];
+    file_trace::save_batch(&pool, &traces).await.unwrap();
+
+    let (filter, processing) = (TraceFilter::default(), FileProcessingConfig::default());
+    let repair = |dry_run| repair_traces(&pool, &filter, &processing, ConflictKey::Hash, dry_run);
+    let preview = repair(true).await.unwrap();
+    assert_eq!(preview.repaired.len(), 1);
+    let unchanged = file_trace::get_by_filter(&pool, &TraceFilter::default()).await.unwrap();
+    assert!(unchanged.iter().any(|trace| trace.hash == stale_hash));
+
+    let report = repair(false).await.unwrap();
+
+    let expected = create_file_trace_from_path(&changed).unwrap();
+    assert_eq!(report.traces_examined, 3);
+    assert_eq!(report.traces_unchanged, 1);
+    assert_eq!(report.missing_files, vec!["/out/missing.txt".to_string()]);
+    assert!(report.errors.is_empty());
+    assert_eq!(report.repaired.len(), 1);
+    assert_eq!(report.repaired[0].stored_hash, stale_hash);
+    assert_eq!(report.repaired[0].file_hash, expected.hash);
+
+    let filter = TraceFilter {
+        hashes: Some(vec![expected.hash.clone()]),
+        ..TraceFilter::default()
+    };
+    let repaired = file_trace::get_by_filter(&pool, &filter).await.unwrap();
+    assert_eq!(repaired.len(), 1);
+    assert_eq!(repaired[0].id, Some(report.repaired[0].id));
+    assert_eq!(repaired[0].name, "changed.txt");
+    assert_eq!((repaired[0].size_bytes, repaired[0].total_lines, repaired[0].dn), (expected.size_bytes, 3, 12345));
+    assert_eq!(repaired[0].modified_at, expected.modified_at);
+}
+
+#[tokio::test]
+async fn repair_traces_uses_the_run_processing_settings() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("truncated.txt");
+    std::fs::write(&file, "line 1\nline 2\n").unwrap();
+    let truncated = FileProcessingConfig {
+        hash_truncate_len: Some(16),
+        ..FileProcessingConfig::default()
+    };
+    let mut stale = fixture_trace("truncated.txt", &"5".repeat(16), 0);
+    stale.path = file.to_string_lossy().to_string();
+    file_trace::save_batch(&pool, &[stale]).await.unwrap();
+
+    // Without the registering run's truncation every hash would change length
+    let mismatched = repair_traces(&pool, &TraceFilter::default(), &FileProcessingConfig::default(), ConflictKey::Hash, false)
+        .await
+        .unwrap();
+    assert!(mismatched.repaired.is_empty());
+    assert!(mismatched.errors[0].1.contains("Stored hash has 16 characters but the file hashes to 64"), "{:?}", mismatched.errors);
+
+    let report = repair_traces(&pool, &TraceFilter::default(), &truncated, ConflictKey::Hash, false).await.unwrap();
+    assert_eq!(report.repaired.len(), 1);
+    assert_eq!(report.repaired[0].file_hash, create_file_trace_from_path_with(&file, &truncated).unwrap().hash);
+}
+
+#[tokio::test]
+async fn update_content_checks_the_active_conflict_key() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    common::apply_script(&pool, Path::new(ConflictKey::TenantHash.setup_script().unwrap())).await;
+    let for_tenant = |name: &str, hash: &str, tenant: &str| FileTrace {
+        tenant: Some(tenant.to_string()),
+        ..fixture_trace(name, hash, 0)
+    };
+    let traces = vec![
+        for_tenant("a.txt", "hash-a", "tenant-one"),
+        for_tenant("b.txt", "hash-b", "tenant-one"),
+        for_tenant("a.txt", "hash-a", "tenant-two"),
+    ];
+    let saved = file_trace::save_batch_returning(&pool, &traces, ConflictKey::TenantHash).await.unwrap();
+    let id_of = |name: &str| saved.iter().find(|(_, _, path)| path.ends_with(name)).unwrap().0;
+    let b_id = id_of("b.txt");
+
+    // Another tenant's trace of the new content does not conflict, the same tenant's does
+    let content = FileTrace {
+        region: Some("07".to_string()),
+        ..fixture_trace("b.txt", "hash-c", 4)
+    };
+    assert_eq!(file_trace::update_content(&pool, b_id, &content, ConflictKey::TenantHash).await.unwrap(), 1);
+    let taken = fixture_trace("b.txt", "hash-a", 4);
+    assert_eq!(file_trace::update_content(&pool, b_id, &taken, ConflictKey::TenantHash).await.unwrap(), 0);
+
+    let filter = TraceFilter {
+        hashes: Some(vec!["hash-c".to_string()]),
+        ..TraceFilter::default()
+    };
+    let updated = file_trace::get_by_filter(&pool, &filter).await.unwrap();
+    assert_eq!(updated.len(), 1);
+    assert_eq!((updated[0].id, updated[0].dn, updated[0].region.as_deref()), (Some(b_id), 4, Some("07")));
+    assert_eq!(updated[0].tenant.as_deref(), Some("tenant-one"));
+}
+
+#[tokio::test]
+async fn mark_processed_updates_only_the_chosen_column() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    let traces = vec![
+        fixture_trace("a.txt", "hash-a", 1),
+        fixture_trace("b.txt", "hash-b", 1),
+        fixture_trace("c.txt", "hash-c", 1),
+    ];
+    file_trace::save_batch(&pool, &traces).await.unwrap();
+    let ids: Vec<i32> = file_trace::get_by_filter(&pool, &TraceFilter::default())
+        .await
+        .unwrap()
+        .iter()
+        .filter(|trace| trace.name != "c.txt")
+        .map(|trace| trace.id.unwrap())
+        .collect();
+
+    assert_eq!(file_trace::mark_processed(&pool, &ids, StatusColumn::Fnt).await.unwrap(), 2);
+    // Marking again is harmless
+    assert_eq!(file_trace::mark_processed(&pool, &ids, StatusColumn::Fnt).await.unwrap(), 2);
+
+    let processed = FileTraceStatus::Processed as i32;
+    let pending = FileTraceStatus::Pending as i32;
+    for trace in file_trace::get_by_filter(&pool, &TraceFilter::default()).await.unwrap() {
+        let expected_fnt = if trace.name == "c.txt" { pending } else { processed };
+        assert_eq!((trace.status_fvw, trace.status_fnt, trace.status_fa4), (pending, expected_fnt, pending));
+    }
+}
+
+#[tokio::test]
+async fn distinct_dns_lists_each_dn_once_in_order() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    assert!(file_trace::distinct_dns(&pool).await.unwrap().is_empty());
+
+    let traces = vec![
+        fixture_trace("a.txt", "hash-a", 300),
+        fixture_trace("b.txt", "hash-b", 100),
+        fixture_trace("c.txt", "hash-c", 300),
+        fixture_trace("d.txt", "hash-d", 0),
+        fixture_trace("e.txt", "hash-e", 200),
+        fixture_trace("f.txt", "hash-f", 100),
+    ];
+    file_trace::save_batch(&pool, &traces).await.unwrap();
+
+    assert_eq!(file_trace::distinct_dns(&pool).await.unwrap(), vec![0, 100, 200, 300]);
+}
+
+#[tokio::test]
+async fn paths_since_run_returns_only_later_runs() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    let in_run = |name: &str, run_id: &str, minute: u32| FileTrace {
+        run_id: Some(run_id.to_string()),
+        processed_at: Utc.with_ymd_and_hms(2024, 1, 15, 10, minute, 0).unwrap(),
+        ..fixture_trace(name, &format!("hash-{}", name), 1)
+    };
+    let traces = vec![
+        in_run("a.txt", "run-1", 0),
+        in_run("b.txt", "run-1", 1),
+        in_run("c.txt", "run-2", 5),
+        in_run("d.txt", "run-2", 6),
+    ];
+    file_trace::save_batch(&pool, &traces).await.unwrap();
+
+    assert_eq!(
+        file_trace::paths_since_run(&pool, "run-1").await.unwrap(),
+        vec!["/out/c.txt".to_string(), "/out/d.txt".to_string()]
+    );
+    assert!(file_trace::paths_since_run(&pool, "run-2").await.unwrap().is_empty());
+    assert!(file_trace::paths_since_run(&pool, "unknown-run").await.is_err());
+}
+
+#[tokio::test]
+async fn status_queries_page_and_count_matching_traces() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    let traces: Vec<FileTrace> = (0..5)
+        .map(|index| fixture_trace(&format!("page-{}.txt", index), &format!("hash-page-{}", index), 333))
+        .chain([fixture_trace("other.txt", "hash-other", 444)])
+        .collect();
+    file_trace::save_batch(&pool, &traces).await.unwrap();
+
+    let filter = TraceFilter {
+        dn: Some(333),
+        ..TraceFilter::default()
+    };
+    assert_eq!(file_trace::count_by_status(&pool, &filter).await.unwrap(), 5);
+
+    let page = file_trace::get_by_status_paged(&pool, &filter, Page { limit: Some(2), offset: 1 })
+        .await
+        .unwrap();
+    let names: Vec<&str> = page.iter().map(|trace| trace.name.as_str()).collect();
+    assert_eq!(names, vec!["page-1.txt", "page-2.txt"]);
+
+    let rest = file_trace::get_by_status_paged(&pool, &filter, Page { limit: None, offset: 3 })
+        .await
+        .unwrap();
+    assert_eq!(rest.len(), 2);
+}
+
+#[tokio::test]
+async fn partitioned_batch_insert_saves_every_dn_and_skips_conflicts() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    file_trace::save_batch(&pool, &[fixture_trace("old.txt", "hash-b", 222)]).await.unwrap();
+
+    let traces = vec![
+        fixture_trace("a.txt", "hash-a", 111),
+        fixture_trace("b.txt", "hash-b", 222),
+        fixture_trace("c.txt", "hash-c", 111),
+        fixture_trace("d.txt", "hash-d", 222),
+    ];
+    let saved = file_trace::save_batch_returning_by_dn(&pool, &traces, ConflictKey::Hash).await.unwrap();
+
+    let saved_hashes: Vec<&str> = saved.iter().map(|(_, hash, _)| hash.as_str()).collect();
+    assert_eq!(saved_hashes, vec!["hash-a", "hash-c", "hash-d"]);
+}
+
+#[tokio::test]
+async fn hash_dn_region_key_keeps_one_trace_per_content_dn_and_region() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    common::apply_script(&pool, Path::new(ConflictKey::HashDnRegion.setup_script().unwrap())).await;
+    let with_region = |name: &str, dn: i32, region: Option<&str>| FileTrace {
+        region: region.map(str::to_string),
+        ..fixture_trace(name, "hash-shared", dn)
+    };
+
+    let first = vec![
+        with_region("a.txt", 12345, Some("07")),
+        with_region("b.txt", 12345, Some("08")),
+        with_region("c.txt", 54321, Some("07")),
+        with_region("d.txt", 12345, None),
+    ];
+    let saved = file_trace::save_batch_returning(&pool, &first, ConflictKey::HashDnRegion).await.unwrap();
+    assert_eq!(saved.len(), 4);
+
+    // Same content, DN and region as a stored trace, including a missing region
+    let again = vec![with_region("a2.txt", 12345, Some("07")), with_region("d2.txt", 12345, None)];
+    let saved = file_trace::save_batch_returning(&pool, &again, ConflictKey::HashDnRegion).await.unwrap();
+    assert!(saved.is_empty(), "{:?}", saved);
+}
+
+#[tokio::test]
+async fn hash_algorithm_key_keeps_one_trace_per_content_and_algorithm() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    common::apply_script(&pool, Path::new(ConflictKey::HashAlgorithm.setup_script().unwrap())).await;
+    let hashed_with = |name: &str, hash_algorithm: &str| FileTrace {
+        hash_algorithm: hash_algorithm.to_string(),
+        ..fixture_trace(name, "hash-shared", 111)
+    };
+
+    let first = vec![hashed_with("full.txt", "sha256"), hashed_with("sampled.txt", "quick")];
+    let saved = file_trace::save_batch_returning(&pool, &first, ConflictKey::HashAlgorithm).await.unwrap();
+    assert_eq!(saved.len(), 2);
+    let again = vec![hashed_with("full2.txt", "sha256")];
+    assert!(file_trace::save_batch_returning(&pool, &again, ConflictKey::HashAlgorithm).await.unwrap().is_empty());
+
+    // A repair may move a trace to a hash another algorithm already stores, not one its own does
+    let other = FileTrace {
+        hash: "hash-other".to_string(),
+        ..hashed_with("other.txt", "md5")
+    };
+    let saved = file_trace::save_batch_returning(&pool, &[other], ConflictKey::HashAlgorithm).await.unwrap();
+    let other_id = saved[0].0;
+    let taken = hashed_with("other.txt", "sha256");
+    assert_eq!(file_trace::update_content(&pool, other_id, &taken, ConflictKey::HashAlgorithm).await.unwrap(), 0);
+    let free = hashed_with("other.txt", "md5");
+    assert_eq!(file_trace::update_content(&pool, other_id, &free, ConflictKey::HashAlgorithm).await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn requeue_errored_only_touches_the_given_tenant() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    common::apply_script(&pool, Path::new(ConflictKey::TenantHash.setup_script().unwrap())).await;
+    let errored = |name: &str, tenant: Option<&str>| FileTrace {
+        status_fvw: FileTraceStatus::Error as i32,
+        tenant: tenant.map(str::to_string),
+        ..fixture_trace(name, "hash-shared", 111)
+    };
+    let traces = vec![errored("a.txt", Some("tenant-one")), errored("b.txt", Some("tenant-two")), errored("c.txt", None)];
+    file_trace::save_batch_returning(&pool, &traces, ConflictKey::TenantHash).await.unwrap();
+
+    let hashes = vec!["hash-shared".to_string()];
+    let requeued = file_trace::requeue_errored(&pool, &hashes, Some("tenant-one")).await.unwrap();
+    assert_eq!(requeued.len(), 1);
+    let untenanted = file_trace::requeue_errored(&pool, &hashes, None).await.unwrap();
+    assert_eq!(untenanted.len(), 1);
+
+    let pending = TraceFilter {
+        status_fvw: Some(FileTraceStatus::Pending as i32),
+        ..TraceFilter::default()
+    };
+    let mut names: Vec<String> = file_trace::get_by_filter(&pool, &pending)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|trace| trace.name)
+        .collect();
+    names.sort();
+    assert_eq!(names, vec!["a.txt", "c.txt"]);
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use vw_file_discover::database::arq_vw_ext;
+
+#[tokio::test]
+async fn get_revendas_reads_copy_overrides() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    sqlx::raw_sql(
+        "INSERT INTO fvw_arq_diarios_ext (empresa, revenda, extensao, dn, pasta_input, pasta_output, days_back, overwrite)
+         VALUES (1, 1, '.txt', 10, '/in/a', '/out/a', 45, TRUE),
+                (1, 2, '.txt', 20, '/in/b', '/out/b', NULL, NULL)",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let mut revendas = arq_vw_ext::get_revendas(&pool).await.unwrap();
+    revendas.sort_by_key(|revenda| revenda.revenda);
+
+    assert_eq!(revendas[0].days_back, Some(45));
+    assert_eq!(revendas[0].overwrite, Some(true));
+    assert_eq!(revendas[1].days_back, None);
+    assert_eq!(revendas[1].overwrite, None);
+}
+
+#[tokio::test]
+async fn get_revendas_collapses_duplicate_revenda_rows() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    sqlx::raw_sql(
+        "INSERT INTO fvw_arq_diarios_ext (empresa, revenda, extensao, dn, pasta_input, pasta_output)
+         VALUES (1, 1, '.txt', 10, '/in/a', '/out/a'),
+                (1, 1, '.dat', 10, '/in/a', '/out/a')",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let revendas = arq_vw_ext::get_revendas(&pool).await.unwrap();
+
+    assert_eq!(revendas.len(), 1);
+    assert_eq!(revendas[0].extensions(), vec![".txt", ".dat"]);
+}
+
+#[tokio::test]
+async fn get_revendas_reads_long_extension_lists() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    sqlx::raw_sql(
+        "INSERT INTO fvw_arq_diarios_ext (empresa, revenda, extensao, dn, pasta_input, pasta_output)
+         VALUES (1, 1, '.txt,.dat,.fhi;.csv', 10, '/in/a', '/out/a')",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let revendas = arq_vw_ext::get_revendas(&pool).await.unwrap();
+
+    assert_eq!(revendas[0].extensions(), vec![".txt", ".dat", ".fhi", ".csv"]);
+}
+
+#[tokio::test]
+async fn get_revendas_with_query_maps_fixture_rows() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    let query = "SELECT * FROM (VALUES
+            (1, 1, '.txt'::varchar, 10, '/in/a'::varchar, '/out/a'::varchar, 30, FALSE),
+            (1, 2, NULL, 20, NULL, NULL, NULL, NULL)
+        ) AS fixture (empresa, revenda, extensao, dn, pasta_input, pasta_output, days_back, overwrite)";
+    let revendas = arq_vw_ext::get_revendas_with_query(&pool, query).await.unwrap();
+
+    assert_eq!(revendas.len(), 2);
+    assert_eq!(revendas[0].pasta_input, "/in/a");
+    assert_eq!((revendas[0].days_back, revendas[0].overwrite), (Some(30), Some(false)));
+    assert_eq!(revendas[1].extensao, "");
+    assert_eq!(revendas[1].pasta_output, "");
+    assert_eq!((revendas[1].days_back, revendas[1].overwrite), (None, None));
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use std::path::Path;
+use vw_file_discover::database::{health_check, ConflictKey};
+
+#[tokio::test]
+async fn health_check_passes_on_migrated_schema() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    health_check(&pool, ConflictKey::Hash).await.unwrap();
+}
+
+#[tokio::test]
+async fn health_check_fails_without_hash_unique_constraint() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    sqlx::query("ALTER TABLE fvw_file_trace DROP CONSTRAINT fvw_file_trace_hash_key")
+        .execute(&pool)
+        .await
+        .unwrap();
+    // A non-unique index does not make ON CONFLICT work
+    sqlx::query("CREATE INDEX fvw_file_trace_hash_idx ON fvw_file_trace (hash)")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let error = health_check(&pool, ConflictKey::Hash).await.unwrap_err().to_string();
+    assert!(error.contains("Missing unique constraint on fvw_file_trace(hash)"), "{}", error);
+}
+
+#[tokio::test]
+async fn health_check_for_tenant_hash_key_requires_its_setup_script() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let script = ConflictKey::TenantHash.setup_script().unwrap();
+
+    let error = health_check(&pool, ConflictKey::TenantHash).await.unwrap_err().to_string();
+    assert!(error.contains("Missing unique constraint on fvw_file_trace(tenant, hash)"), "{}", error);
+    assert!(error.contains(script), "{}", error);
+
+    common::apply_script(&pool, Path::new(script)).await;
+    health_check(&pool, ConflictKey::TenantHash).await.unwrap();
+
+    // Hash alone unique again would fail batches the tenant key lets through
+    sqlx::query("ALTER TABLE fvw_file_trace ADD CONSTRAINT fvw_file_trace_hash_key UNIQUE (hash)")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let error = health_check(&pool, ConflictKey::TenantHash).await.unwrap_err().to_string();
+    assert!(error.contains("still has a unique index on (hash)"), "{}", error);
+}
+
+#[tokio::test]
+async fn health_check_for_tenant_hash_key_requires_nulls_not_distinct() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+
+    sqlx::query("ALTER TABLE fvw_file_trace DROP CONSTRAINT fvw_file_trace_hash_key")
+        .execute(&pool)
+        .await
+        .unwrap();
+    // Untenanted rows never conflict on a plain unique index over the nullable tenant
+    sqlx::query("CREATE UNIQUE INDEX fvw_file_trace_tenant_hash_key ON fvw_file_trace (tenant, hash)")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let error = health_check(&pool, ConflictKey::TenantHash).await.unwrap_err().to_string();
+    assert!(error.contains("Missing unique constraint on fvw_file_trace(tenant, hash)"), "{}", error);
+}
+
+#[tokio::test]
+async fn conflict_key_setup_scripts_switch_between_keys() {
+    let Some(pool) = common::test_pool().await else {
+        return;
+    };
+    let setup = |key: ConflictKey| Path::new(key.setup_script().unwrap());
+
+    common::apply_script(&pool, setup(ConflictKey::TenantHash)).await;
+    let error = health_check(&pool, ConflictKey::HashDnRegion).await.unwrap_err().to_string();
+    assert!(error.contains("Missing unique constraint on fvw_file_trace(hash, dn, region)"), "{}", error);
+
+    common::apply_script(&pool, setup(ConflictKey::HashDnRegion)).await;
+    health_check(&pool, ConflictKey::HashDnRegion).await.unwrap();
+    assert!(health_check(&pool, ConflictKey::TenantHash).await.is_err());
+
+    common::apply_script(&pool, setup(ConflictKey::HashAlgorithm)).await;
+    health_check(&pool, ConflictKey::HashAlgorithm).await.unwrap();
+    assert!(health_check(&pool, ConflictKey::HashDnRegion).await.is_err());
+}
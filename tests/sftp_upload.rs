@@ -0,0 +1,56 @@
+//! Upload tests against the SFTP server in `TEST_SFTP_URL` (e.g. `sftp://tester@localhost:2222/upload`)
+//!
+//! Credentials come from `SFTP_USER`, `SFTP_PASSWORD` and `SFTP_PRIVATE_KEY`, and the host key is
+//! checked against `SFTP_KNOWN_HOSTS`, as in production.
+//! The tests are skipped when `TEST_SFTP_URL` is not set.
+
+use std::fs;
+use std::path::Path;
+use vw_file_discover::services::{copy_files_with_mappings, FileCopyConfig};
+use vw_file_discover::utils::{OverwriteMode, SftpCredentials, SftpTarget, SftpUploader};
+
+fn test_sftp_url() -> Option<String> {
+    match std::env::var("TEST_SFTP_URL") {
+        Ok(url) if !url.is_empty() => Some(url),
+        _ => {
+            eprintln!("TEST_SFTP_URL not set, skipping SFTP test");
+            None
+        }
+    }
+}
+
+#[test]
+fn copy_uploads_to_sftp_destination() {
+    let Some(url) = test_sftp_url() else {
+        return;
+    };
+
+    let root = tempfile::tempdir().unwrap();
+    let input = root.path().join("in");
+    fs::create_dir(&input).unwrap();
+    fs::write(input.join("a.txt"), "first file").unwrap();
+    fs::write(input.join("b.txt"), "second").unwrap();
+
+    // A fresh remote directory per run keeps reruns independent
+    let dest = format!("{}/vw-test-{}", url.trim_end_matches('/'), uuid::Uuid::new_v4().simple());
+    let mappings = vec![(input.to_string_lossy().to_string(), dest.clone())];
+
+    let report = copy_files_with_mappings(&mappings, &[], &FileCopyConfig::default());
+    assert!(report.errors.is_empty(), "{:?}", report.errors);
+    assert_eq!((report.successful_copies, report.overwritten_files), (2, 0));
+
+    let target = SftpTarget::parse(&dest).unwrap().unwrap();
+    let credentials = SftpCredentials::from_env(&target, |key| std::env::var(key).ok()).unwrap();
+    let uploader = SftpUploader::connect(&target, &credentials).unwrap();
+    assert_eq!(uploader.remote_size(&target.remote_dir.join("a.txt")), Some(10));
+    assert_eq!(uploader.remote_size(&target.remote_dir.join("b.txt")), Some(6));
+
+    // Existing remote files are skipped unless overwriting
+    let again = copy_files_with_mappings(&mappings, &[], &FileCopyConfig::default());
+    assert_eq!(again.skipped_files(), 2);
+
+    let overwrite = FileCopyConfig { overwrite: OverwriteMode::Always, ..FileCopyConfig::default() };
+    let replaced = copy_files_with_mappings(&mappings, &[], &overwrite);
+    assert_eq!(replaced.overwritten_files, 2);
+    assert!(replaced.copied_files.iter().all(|copied| Path::new(&copied.destination).starts_with("sftp://")));
+}